@@ -26,7 +26,7 @@ fn multi_threaded_in_batch(c: &mut Criterion) {
                     .map(|res| match res {
                         Ok(n) => Ok(n),
                         Err(Error::Inner(n)) => Ok(n),
-                        Err(Error::Rejected) => Err(0),
+                        Err(Error::Rejected(_)) => Err(0),
                     })
             });
 
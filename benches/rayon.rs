@@ -0,0 +1,34 @@
+#![deny(warnings)]
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rayon::prelude::*;
+
+use failsafe::{rayon::ParallelCall, Config, Error};
+
+fn par_iter(c: &mut Criterion) {
+    let circuit_breaker = Config::new().build();
+    let batch_size = 1000;
+
+    c.bench_function("par_iter", |b| {
+        b.iter(|| {
+            (0..batch_size)
+                .into_par_iter()
+                .call_each(&circuit_breaker, dangerous_call)
+                .for_each(|res| match res {
+                    Ok(_) | Err(Error::Inner(_)) => {}
+                    Err(err) => unreachable!("{:?}", err),
+                })
+        })
+    });
+}
+
+fn dangerous_call(n: usize) -> Result<usize, usize> {
+    if n % 5 == 0 {
+        black_box(Err(n))
+    } else {
+        black_box(Ok(n))
+    }
+}
+
+criterion_group!(benches, par_iter);
+criterion_main!(benches);
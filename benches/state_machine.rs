@@ -1,8 +1,10 @@
 #![deny(warnings)]
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
+use failsafe::failure_policy::SuccessRateOverTimeWindow;
 use failsafe::{backoff, clock, failure_policy, StateMachine};
 
 #[allow(clippy::unit_arg)]
@@ -23,8 +25,12 @@ fn consecutive_failures_policy(c: &mut Criterion) {
 #[allow(clippy::unit_arg)]
 fn success_rate_over_time_window_policy(c: &mut Criterion) {
     let backoff = backoff::constant(Duration::from_secs(5));
-    let policy =
-        failure_policy::success_rate_over_time_window(0.5, 0, Duration::from_secs(10), backoff);
+    let policy = SuccessRateOverTimeWindow::builder(backoff)
+        .required_success_rate(0.5)
+        .min_request_threshold(0)
+        .window(Duration::from_secs(10))
+        .build()
+        .unwrap();
     let state_machine = StateMachine::new(policy, ());
 
     clock::freeze(|time| {
@@ -39,9 +45,30 @@ fn success_rate_over_time_window_policy(c: &mut Criterion) {
     });
 }
 
+fn closed_call_permitted(c: &mut Criterion) {
+    let backoff = backoff::constant(Duration::from_secs(5));
+    let policy = failure_policy::consecutive_failures(3, backoff);
+    let state_machine = StateMachine::new(policy, ());
+
+    c.bench_function("closed_call_permitted", |b| {
+        b.iter(|| black_box(state_machine.is_call_permitted()))
+    });
+}
+
+// The baseline for `closed_call_permitted`, the closed state shouldn't cost more than this.
+fn atomic_load(c: &mut Criterion) {
+    let state = AtomicU64::new(0);
+
+    c.bench_function("atomic_load", |b| {
+        b.iter(|| black_box(state.load(Ordering::Acquire) == 0))
+    });
+}
+
 criterion_group!(
     benches,
     consecutive_failures_policy,
-    success_rate_over_time_window_policy
+    success_rate_over_time_window_policy,
+    closed_call_permitted,
+    atomic_load
 );
 criterion_main!(benches);
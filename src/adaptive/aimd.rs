@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use super::Limit;
+
+const DEFAULT_INITIAL_LIMIT: usize = 20;
+const DEFAULT_MAX_LIMIT: usize = 200;
+const DEFAULT_MIN_LIMIT: usize = 1;
+const DEFAULT_BACKOFF_RATIO: f64 = 0.5;
+
+/// Creates an additive-increase/multiplicative-decrease limit, which starts from `initial_limit`
+/// and never exceeds `max_limit`.
+///
+/// The limit grows by one on each successful call, which used the most of the current limit,
+/// and it's halved on each dropped call.
+///
+/// # Panics
+///
+/// When `initial_limit` is zero or greater than `max_limit`.
+pub fn aimd(initial_limit: usize, max_limit: usize) -> Aimd {
+    assert!(initial_limit > 0, "initial_limit must be > 0");
+    assert!(
+        max_limit >= initial_limit,
+        "max_limit must be greater then initial_limit: {} < {}",
+        max_limit,
+        initial_limit
+    );
+
+    Aimd {
+        limit: initial_limit,
+        min_limit: DEFAULT_MIN_LIMIT,
+        max_limit,
+        backoff_ratio: DEFAULT_BACKOFF_RATIO,
+        timeout: None,
+    }
+}
+
+impl Default for Aimd {
+    fn default() -> Self {
+        aimd(DEFAULT_INITIAL_LIMIT, DEFAULT_MAX_LIMIT)
+    }
+}
+
+/// A concurrency limit with additive increase and multiplicative decrease.
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    limit: usize,
+    min_limit: usize,
+    max_limit: usize,
+    backoff_ratio: f64,
+    timeout: Option<Duration>,
+}
+
+impl Aimd {
+    /// Sets the lowest possible limit, the default is `1`.
+    pub fn min_limit(mut self, min_limit: usize) -> Self {
+        self.min_limit = min_limit.max(1);
+        self
+    }
+
+    /// Sets the ratio the limit is multiplied by on a dropped call, the default is `0.5`.
+    ///
+    /// # Panics
+    ///
+    /// When `ratio` isn't in `[0.5, 1.0)` interval.
+    pub fn backoff_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            (0.5..1.0).contains(&ratio),
+            "backoff ratio must be [0.5, 1): {}",
+            ratio
+        );
+        self.backoff_ratio = ratio;
+        self
+    }
+
+    /// Treats successful calls which took longer than `timeout` as dropped.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Limit for Aimd {
+    #[inline]
+    fn limit(&self) -> usize {
+        self.limit
+    }
+
+    fn on_sample(&mut self, rtt: Duration, in_flight: usize, dropped: bool) {
+        let dropped = dropped || self.timeout.map_or(false, |timeout| rtt > timeout);
+
+        if dropped {
+            let limit = (self.limit as f64 * self.backoff_ratio) as usize;
+            self.limit = limit.max(self.min_limit);
+        } else if in_flight * 2 >= self.limit {
+            // Grow the limit only when the application uses it.
+            self.limit = (self.limit + 1).min(self.max_limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increase_additive() {
+        let mut limit = aimd(10, 12);
+
+        limit.on_sample(10.millis(), 10, false);
+        assert_eq!(11, limit.limit());
+
+        limit.on_sample(10.millis(), 11, false);
+        limit.on_sample(10.millis(), 12, false);
+        assert_eq!(12, limit.limit());
+
+        // App limited.
+        let mut limit = aimd(10, 12);
+        limit.on_sample(10.millis(), 1, false);
+        assert_eq!(10, limit.limit());
+    }
+
+    #[test]
+    fn decrease_multiplicative() {
+        let mut limit = aimd(10, 100).min_limit(3);
+
+        limit.on_sample(10.millis(), 10, true);
+        assert_eq!(5, limit.limit());
+
+        limit.on_sample(10.millis(), 5, true);
+        assert_eq!(3, limit.limit());
+    }
+
+    #[test]
+    fn decrease_on_timeout() {
+        let mut limit = aimd(10, 100).timeout(50.millis());
+
+        limit.on_sample(100.millis(), 10, false);
+        assert_eq!(5, limit.limit());
+    }
+
+    trait IntoDuration {
+        fn millis(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn millis(self) -> Duration {
+            Duration::from_millis(self)
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::super::clock;
+use super::super::error::{Error, Rejection, RejectionReason};
+use super::super::lock::{Lock, Mutex};
+
+/// Creates a controlled delay (CoDel) shedder.
+///
+/// * `target` - an acceptable time a call may wait before being executed.
+/// * `interval` - how long the waiting time must stay above `target` before the shedding starts,
+///   it should be about the worst expected execution time of a call.
+///
+/// See [Controlled Delay](https://queue.acm.org/detail.cfm?id=2209336).
+pub fn codel(target: Duration, interval: Duration) -> CoDel {
+    CoDel {
+        target,
+        interval,
+        state: Arc::new(Lock::new(State {
+            first_above: None,
+            dropping: false,
+            drop_next: clock::now(),
+            count: 0,
+        })),
+    }
+}
+
+/// A shedder which rejects calls when they wait for admission too long.
+///
+/// Calls usually wait in a queue (a worker pool, a bounded channel, a concurrency limiter) before
+/// being executed. When a dependency degrades, such a queue grows and never drains: every call
+/// waits and then fails anyway. CoDel tracks the waiting ("sojourn") time of each call, and when
+/// it has exceeded the `target` for at least an `interval`, starts rejecting calls with
+/// increasing frequency until the waiting time drops below the target again.
+///
+/// The shedder is cheap to clone, all clones share the same state.
+#[derive(Clone)]
+pub struct CoDel {
+    target: Duration,
+    interval: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    first_above: Option<Instant>,
+    dropping: bool,
+    drop_next: Instant,
+    count: u32,
+}
+
+impl CoDel {
+    /// Decides whether a call enqueued at `enqueued_at` should be executed.
+    ///
+    /// It returns `true` if the call is admitted, or `false` if it should be rejected.
+    pub fn admit(&self, enqueued_at: Instant) -> bool {
+        let now = clock::now();
+        let sojourn = now.saturating_duration_since(enqueued_at);
+        let target = self.target;
+        let interval = self.interval;
+
+        self.state.with(|state| {
+            let mut ok_to_drop = false;
+
+            if sojourn < target {
+                state.first_above = None;
+            } else {
+                match state.first_above {
+                    None => state.first_above = Some(now + interval),
+                    Some(first_above) => ok_to_drop = now >= first_above,
+                }
+            }
+
+            if state.dropping {
+                if !ok_to_drop {
+                    state.dropping = false;
+                } else if now >= state.drop_next {
+                    state.count += 1;
+                    state.drop_next = control_law(state.drop_next, interval, state.count);
+                    return false;
+                }
+            } else if ok_to_drop {
+                state.dropping = true;
+                // Start from the previous drop rate if the dropping state was left recently.
+                let recently = now.saturating_duration_since(state.drop_next) < interval * 8;
+                state.count = if state.count > 2 && recently {
+                    state.count - 2
+                } else {
+                    1
+                };
+                state.drop_next = control_law(now, interval, state.count);
+                return false;
+            }
+
+            true
+        })
+    }
+
+    /// Executes a given function enqueued at `enqueued_at` within the shedder.
+    ///
+    /// Returns `Error::Rejected` when the call has been waiting for too long.
+    pub fn call<F, E, R>(&self, enqueued_at: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        if !self.admit(enqueued_at) {
+            let rejection = Rejection::default().with_reason(RejectionReason::Shed);
+            return Err(Error::Rejected(rejection));
+        }
+        f().map_err(Error::Inner)
+    }
+}
+
+/// Returns the time of the next drop, the drop rate grows as a square root of drops count.
+#[inline]
+fn control_law(at: Instant, interval: Duration, count: u32) -> Instant {
+    at + interval.div_f64(f64::from(count).sqrt())
+}
+
+impl Debug for CoDel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dropping = self.state.with(|state| state.dropping);
+        f.debug_struct("CoDel")
+            .field("target", &self.target)
+            .field("interval", &self.interval)
+            .field("dropping", &dropping)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admit_when_below_target() {
+        clock::freeze(|time| {
+            let codel = codel(10.millis(), 100.millis());
+
+            for _ in 0..100 {
+                let enqueued_at = clock::now();
+                time.advance(5.millis());
+                assert!(codel.admit(enqueued_at));
+            }
+        });
+    }
+
+    #[test]
+    fn shed_standing_queue() {
+        clock::freeze(|time| {
+            let codel = codel(10.millis(), 100.millis());
+            let enqueued_at = clock::now();
+
+            // The sojourn time exceeds the target, but not for a whole interval yet.
+            time.advance(20.millis());
+            assert!(codel.admit(enqueued_at));
+            time.advance(50.millis());
+            assert!(codel.admit(enqueued_at));
+
+            // The standing queue persists for longer than the interval.
+            time.advance(60.millis());
+            assert!(!codel.admit(enqueued_at));
+            assert!(codel.admit(enqueued_at));
+
+            // The next drop happens after the interval, then after interval / sqrt(2).
+            time.advance(100.millis());
+            assert!(!codel.admit(enqueued_at));
+            time.advance(70.millis());
+            assert!(codel.admit(enqueued_at));
+            time.advance(1.millis());
+            match codel.call(enqueued_at, || Ok::<_, ()>(())) {
+                Err(Error::Rejected(rejection)) => {
+                    assert_eq!(RejectionReason::Shed, rejection.reason())
+                }
+                x => unreachable!("{:?}", x),
+            }
+
+            // The queue has drained.
+            let enqueued_at = clock::now();
+            time.advance(1.millis());
+            assert!(codel.admit(enqueued_at));
+            match codel.call(enqueued_at, || Ok::<_, ()>(())) {
+                Ok(()) => {}
+                x => unreachable!("{:?}", x),
+            }
+        });
+    }
+
+    trait IntoDuration {
+        fn millis(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn millis(self) -> Duration {
+            Duration::from_millis(self)
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::time::Duration;
+
+use super::super::percentile::Percentile;
+use super::Limit;
+
+const DEFAULT_INITIAL_LIMIT: usize = 20;
+const DEFAULT_MAX_LIMIT: usize = 200;
+const DEFAULT_MIN_LIMIT: usize = 1;
+const DEFAULT_TOLERANCE: f64 = 1.5;
+const DEFAULT_SMOOTHING: f64 = 0.2;
+const DEFAULT_LONG_WINDOW: u32 = 600;
+const MIN_GRADIENT: f64 = 0.5;
+
+/// Creates a latency gradient limit, which starts from `initial_limit` and never exceeds
+/// `max_limit`.
+///
+/// The limit tracks a long term exponential moving average of RTT and compares it with the RTT
+/// of each completed call. While the current RTT stays close to the long term one, the limit
+/// grows by a queue allowance of `sqrt(limit)`; when the RTT grows, which means calls are
+/// queueing up on the backend side, the limit shrinks proportionally (at most by half per
+/// sample). See [Netflix concurrency limits](https://github.com/Netflix/concurrency-limits).
+///
+/// # Panics
+///
+/// When `initial_limit` is zero or greater than `max_limit`.
+pub fn gradient(initial_limit: usize, max_limit: usize) -> Gradient {
+    assert!(initial_limit > 0, "initial_limit must be > 0");
+    assert!(
+        max_limit >= initial_limit,
+        "max_limit must be greater then initial_limit: {} < {}",
+        max_limit,
+        initial_limit
+    );
+
+    Gradient {
+        limit: initial_limit as f64,
+        min_limit: DEFAULT_MIN_LIMIT,
+        max_limit,
+        tolerance: DEFAULT_TOLERANCE,
+        smoothing: DEFAULT_SMOOTHING,
+        long_window: DEFAULT_LONG_WINDOW,
+        long_rtt: None,
+        batch: None,
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        gradient(DEFAULT_INITIAL_LIMIT, DEFAULT_MAX_LIMIT)
+    }
+}
+
+/// A concurrency limit based on the gradient of RTT.
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    limit: f64,
+    min_limit: usize,
+    max_limit: usize,
+    tolerance: f64,
+    smoothing: f64,
+    long_window: u32,
+    long_rtt: Option<f64>,
+    batch: Option<Batch>,
+}
+
+impl Gradient {
+    /// Sets the lowest possible limit, the default is `1`.
+    pub fn min_limit(mut self, min_limit: usize) -> Self {
+        self.min_limit = min_limit.max(1);
+        self
+    }
+
+    /// Sets how much the current RTT may exceed the long term RTT before the limit starts
+    /// decreasing, the default is `1.5`.
+    ///
+    /// # Panics
+    ///
+    /// When `tolerance` is less than `1.0`.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance >= 1.0, "tolerance must be >= 1: {}", tolerance);
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Sets how fast the limit follows its estimation, the default is `0.2`.
+    ///
+    /// # Panics
+    ///
+    /// When `smoothing` isn't in `(0.0, 1.0]` interval.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        assert!(
+            smoothing > 0.0 && smoothing <= 1.0,
+            "smoothing must be (0, 1]: {}",
+            smoothing
+        );
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Sets the number of samples the long term RTT is averaged over, the default is `600`.
+    pub fn long_window(mut self, samples: u32) -> Self {
+        self.long_window = samples.max(1);
+        self
+    }
+
+    /// Compares the percentile `p` of RTTs of every `samples` calls with the long term RTT,
+    /// e.g. the median of 10 calls, instead of the RTT of each call, so sporadic slow calls
+    /// don't shrink the limit. The limit is adjusted once per batch of samples, or at once on
+    /// a dropped call.
+    ///
+    /// # Panics
+    ///
+    /// When `p` isn't in `[0.0, 1.0]` interval.
+    pub fn rtt_percentile(mut self, p: f64, samples: u32) -> Self {
+        self.batch = Some(Batch {
+            rtts: Percentile::new(p),
+            size: u64::from(samples.max(1)),
+        });
+        self
+    }
+}
+
+/// RTTs of recent calls, which percentile is compared with the long term RTT, see
+/// `Gradient::rtt_percentile`.
+#[derive(Debug, Clone)]
+struct Batch {
+    rtts: Percentile,
+    size: u64,
+}
+
+impl Batch {
+    /// Adds the `rtt`, returns the percentile of the batch once it's complete and starts the
+    /// next one.
+    #[inline]
+    fn observe(&mut self, rtt: f64) -> Option<f64> {
+        self.rtts.observe(rtt);
+        if self.rtts.count() < self.size {
+            return None;
+        }
+
+        let percentile = self.rtts.estimate();
+        self.rtts.reset();
+        percentile
+    }
+}
+
+impl Limit for Gradient {
+    #[inline]
+    fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    fn on_sample(&mut self, rtt: Duration, in_flight: usize, dropped: bool) {
+        let rtt = rtt_nanos(rtt);
+        let long_rtt = match self.long_rtt {
+            Some(long_rtt) => {
+                let factor = 1.0 / f64::from(self.long_window);
+                long_rtt * (1.0 - factor) + rtt * factor
+            }
+            None => rtt,
+        };
+        self.long_rtt = Some(long_rtt);
+
+        let rtt = match self.batch.as_mut() {
+            Some(batch) if !dropped => match batch.observe(rtt) {
+                Some(percentile) => percentile,
+                None => return,
+            },
+            _ => rtt,
+        };
+
+        // Don't grow the limit when the application doesn't use it.
+        let app_limited = (in_flight as f64) < self.limit / 2.0;
+
+        let gradient = if dropped {
+            MIN_GRADIENT
+        } else {
+            (self.tolerance * long_rtt / rtt).clamp(MIN_GRADIENT, 1.0)
+        };
+
+        if app_limited && gradient >= 1.0 {
+            return;
+        }
+
+        let queue_size = self.limit.sqrt();
+        let estimated = self.limit * gradient + queue_size;
+        let limit = self.limit * (1.0 - self.smoothing) + estimated * self.smoothing;
+
+        self.limit = limit.max(self.min_limit as f64).min(self.max_limit as f64);
+    }
+}
+
+/// Returns RTT in nanoseconds, a zero RTT is rounded up to avoid division by zero.
+#[inline]
+fn rtt_nanos(rtt: Duration) -> f64 {
+    (rtt.as_secs() as f64 * 1e9 + f64::from(rtt.subsec_nanos())).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grow_on_stable_rtt() {
+        let mut limit = gradient(10, 100);
+
+        for _ in 0..20 {
+            let in_flight = limit.limit();
+            limit.on_sample(10.millis(), in_flight, false);
+        }
+
+        assert!(limit.limit() > 10, "limit={}", limit.limit());
+        assert!(limit.limit() <= 100);
+    }
+
+    #[test]
+    fn keep_when_app_limited() {
+        let mut limit = gradient(10, 100);
+
+        for _ in 0..20 {
+            limit.on_sample(10.millis(), 1, false);
+        }
+
+        assert_eq!(10, limit.limit());
+    }
+
+    #[test]
+    fn shrink_on_growing_rtt() {
+        let mut limit = gradient(50, 100);
+
+        for _ in 0..10 {
+            limit.on_sample(10.millis(), 50, false);
+        }
+        let before = limit.limit();
+
+        for _ in 0..20 {
+            limit.on_sample(100.millis(), 50, false);
+        }
+
+        assert!(limit.limit() < before, "{} >= {}", limit.limit(), before);
+    }
+
+    #[test]
+    fn shrink_on_drops() {
+        let mut limit = gradient(50, 100).min_limit(5);
+
+        for _ in 0..100 {
+            limit.on_sample(10.millis(), 50, true);
+        }
+
+        assert_eq!(5, limit.limit());
+    }
+
+    #[test]
+    fn ignore_sporadic_slow_calls() {
+        let mut each = gradient(50, 100);
+        let mut median = gradient(50, 100).rtt_percentile(0.5, 10);
+
+        let mut shrunk = false;
+        for i in 0..100 {
+            let rtt = if i % 5 == 4 { 500 } else { 8 + i * 7 % 5 }.millis();
+            let before = each.limit();
+            each.on_sample(rtt, 50, false);
+            shrunk |= each.limit() < before;
+
+            let before = median.limit();
+            median.on_sample(rtt, 50, false);
+            assert!(median.limit() >= before, "{} < {}", median.limit(), before);
+        }
+
+        assert!(shrunk);
+        assert!(median.limit() > 50, "limit={}", median.limit());
+    }
+
+    trait IntoDuration {
+        fn millis(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn millis(self) -> Duration {
+            Duration::from_millis(self)
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Adaptive concurrency limiting.
+//!
+//! A `Limiter` restricts the number of in-flight calls to a backend. The limit isn't fixed, it's
+//! adjusted by a `Limit` algorithm based on the observed round trip time (RTT) of calls, so the
+//! limiter finds the backend's saturation point by itself. It may be used on its own or combined
+//! with a circuit breaker for saturation protection.
+//!
+//! Available limits:
+//!
+//! * `gradient` - adjusts the limit by the gradient of RTT.
+//! * `aimd` - a simpler additive-increase/multiplicative-decrease limit.
+//!
+//! Calls which wait for admission too long may be shed by the `codel` shedder, to prevent
+//! standing queues behind a degraded dependency.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{adaptive, CircuitBreaker, Config, Error};
+//!
+//! let limiter = adaptive::Limiter::new(adaptive::gradient(20, 200));
+//! let circuit_breaker = Config::new().build();
+//!
+//! let result = limiter.call(|| circuit_breaker.call(|| Ok::<_, ()>(42)));
+//! match result {
+//!   Ok(42) => {}
+//!   Err(Error::Rejected(_)) => eprintln!("too many calls in flight"),
+//!   Err(Error::Inner(Error::Rejected(_))) => eprintln!("circuit breaker is open"),
+//!   x => unreachable!("{:?}", x),
+//! }
+//! ```
+
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::clock;
+use super::error::{Error, Rejection, RejectionReason};
+use super::failure_predicate::{self, FailurePredicate};
+use super::instrument::Instrument;
+use super::lock::{Lock, Mutex};
+
+mod aimd;
+mod codel;
+mod gradient;
+
+pub use self::aimd::{aimd, Aimd};
+pub use self::codel::{codel, CoDel};
+pub use self::gradient::{gradient, Gradient};
+
+/// An algorithm which computes the concurrency limit from observed samples.
+pub trait Limit {
+    /// Returns the current concurrency limit.
+    fn limit(&self) -> usize;
+
+    /// Updates the limit with a sample of a completed call.
+    ///
+    /// * `rtt` - the round trip time of the call.
+    /// * `in_flight` - the number of in-flight calls when the call was started, including itself.
+    /// * `dropped` - `true` if the call failed or timed out, which usually means an overload.
+    fn on_sample(&mut self, rtt: Duration, in_flight: usize, dropped: bool);
+}
+
+struct Inner<LIMIT, INSTRUMENT> {
+    limit: Mutex<LIMIT>,
+    in_flight: AtomicUsize,
+    instrument: INSTRUMENT,
+}
+
+/// A concurrency limiter driven by a `Limit` algorithm.
+///
+/// The limiter is cheap to clone, all clones share the same limit and in-flight counter.
+pub struct Limiter<LIMIT, INSTRUMENT = ()> {
+    inner: Arc<Inner<LIMIT, INSTRUMENT>>,
+}
+
+/// A permission to make a call obtained from a `Limiter`.
+///
+/// The call's outcome should be reported via `success` or `dropped`, otherwise when the token
+/// goes out of scope it releases the slot without updating the limit.
+#[must_use = "the token releases the slot when dropped"]
+pub struct Token<LIMIT, INSTRUMENT = ()> {
+    limiter: Limiter<LIMIT, INSTRUMENT>,
+    started_at: Instant,
+    in_flight: usize,
+    released: bool,
+}
+
+impl<LIMIT> Limiter<LIMIT, ()>
+where
+    LIMIT: Limit,
+{
+    /// Creates a new limiter with the given limit algorithm.
+    pub fn new(limit: LIMIT) -> Self {
+        Self::with_instrument(limit, ())
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Limiter<LIMIT, INSTRUMENT>
+where
+    LIMIT: Limit,
+    INSTRUMENT: Instrument,
+{
+    /// Creates a new limiter with the given limit algorithm and instrument. The instrument is
+    /// notified about rejected calls.
+    pub fn with_instrument(limit: LIMIT, instrument: INSTRUMENT) -> Self {
+        Limiter {
+            inner: Arc::new(Inner {
+                limit: Lock::new(limit),
+                in_flight: AtomicUsize::new(0),
+                instrument,
+            }),
+        }
+    }
+
+    /// Returns the current concurrency limit.
+    pub fn limit(&self) -> usize {
+        self.inner.limit.with(|limit| limit.limit())
+    }
+
+    /// Returns the number of calls in flight.
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Requests permission to call.
+    ///
+    /// It returns `Some(Token)` if a call is allowed, or `None` if the limit is reached.
+    pub fn try_acquire(&self) -> Option<Token<LIMIT, INSTRUMENT>> {
+        let limit = self.limit();
+        let mut current = self.inner.in_flight.load(Ordering::Acquire);
+
+        loop {
+            if current >= limit {
+                self.inner.instrument.on_call_rejected();
+                return None;
+            }
+
+            match self.inner.in_flight.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+
+        Some(Token {
+            limiter: self.clone(),
+            started_at: clock::now(),
+            in_flight: current + 1,
+            released: false,
+        })
+    }
+
+    /// Executes a given function within the limiter.
+    ///
+    /// Returns `Error::Rejected` when the limit is reached.
+    #[inline]
+    pub fn call<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.call_with(failure_predicate::Any, f)
+    }
+
+    /// Executes a given function within the limiter.
+    ///
+    /// It checks error by the provided predicate. If the predicate returns `true` for the
+    /// error, the call is recorded as dropped.
+    pub fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        let token = match self.try_acquire() {
+            Some(token) => token,
+            None => {
+                let rejection = Rejection::default().with_reason(RejectionReason::Limited);
+                return Err(Error::Rejected(rejection));
+            }
+        };
+
+        match f() {
+            Ok(ok) => {
+                token.success();
+                Ok(ok)
+            }
+            Err(err) => {
+                if predicate.is_err(&err) {
+                    token.dropped();
+                } else {
+                    token.success();
+                }
+                Err(Error::Inner(err))
+            }
+        }
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Token<LIMIT, INSTRUMENT>
+where
+    LIMIT: Limit,
+{
+    /// Records a successful call and releases the slot.
+    pub fn success(mut self) {
+        self.release(Some(false));
+    }
+
+    /// Records a dropped call (failed or timed out) and releases the slot.
+    pub fn dropped(mut self) {
+        self.release(Some(true));
+    }
+
+    /// Releases the slot without updating the limit.
+    pub fn ignore(mut self) {
+        self.release(None);
+    }
+
+    fn release(&mut self, dropped: Option<bool>) {
+        if self.released {
+            return;
+        }
+        self.released = true;
+
+        if let Some(dropped) = dropped {
+            let rtt = clock::now() - self.started_at;
+            let in_flight = self.in_flight;
+            self.limiter
+                .inner
+                .limit
+                .with(|limit| limit.on_sample(rtt, in_flight, dropped));
+        }
+
+        self.limiter.inner.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Drop for Token<LIMIT, INSTRUMENT> {
+    fn drop(&mut self) {
+        if !self.released {
+            self.released = true;
+            self.limiter.inner.in_flight.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Clone for Limiter<LIMIT, INSTRUMENT> {
+    fn clone(&self) -> Self {
+        Limiter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Debug for Limiter<LIMIT, INSTRUMENT>
+where
+    LIMIT: Limit,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = self.inner.limit.with(|limit| limit.limit());
+        f.debug_struct("Limiter")
+            .field("limit", &limit)
+            .field("in_flight", &self.inner.in_flight.load(Ordering::Acquire))
+            .finish()
+    }
+}
+
+impl<LIMIT, INSTRUMENT> Debug for Token<LIMIT, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_when_limit_reached() {
+        let limiter = Limiter::new(Fixed(2));
+
+        let first = limiter.try_acquire().unwrap();
+        let second = limiter.try_acquire().unwrap();
+        assert_eq!(2, limiter.in_flight());
+        assert!(limiter.try_acquire().is_none());
+
+        first.success();
+        assert_eq!(1, limiter.in_flight());
+        assert!(limiter.try_acquire().is_some());
+
+        drop(second);
+        assert_eq!(0, limiter.in_flight());
+    }
+
+    #[test]
+    fn call_records_samples() {
+        let limiter = Limiter::new(Fixed(1));
+
+        assert_eq!(Ok(1), limiter.call(|| Ok::<_, ()>(1)).map_err(|_| ()));
+        match limiter.call(|| Err::<(), _>(())) {
+            Err(Error::Inner(())) => {}
+            x => unreachable!("{:?}", x),
+        }
+
+        let _token = limiter.try_acquire().unwrap();
+        match limiter.call(|| Ok::<_, ()>(())) {
+            Err(Error::Rejected(rejection)) => {
+                assert_eq!(RejectionReason::Limited, rejection.reason())
+            }
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[derive(Debug)]
+    struct Fixed(usize);
+
+    impl Limit for Fixed {
+        fn limit(&self) -> usize {
+            self.0
+        }
+
+        fn on_sample(&mut self, _rtt: Duration, _in_flight: usize, _dropped: bool) {}
+    }
+}
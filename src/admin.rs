@@ -0,0 +1,269 @@
+//! A framework agnostic HTTP handler for operational tooling, requires the `admin` feature.
+//!
+//! The handler works on the method and the path of a request, so it's trivial to mount it into
+//! any HTTP server. The following routes are served:
+//!
+//! * `GET /breakers` - snapshots of all registered circuit breakers as a JSON array.
+//! * `GET /breakers/{name}` - the snapshot of the circuit breaker as a JSON object.
+//! * `POST /breakers/{name}/force-open` - opens the circuit breaker until reset.
+//! * `POST /breakers/{name}/force-closed` - closes the circuit breaker until reset.
+//! * `POST /breakers/{name}/reset` - resets the circuit breaker to the closed state.
+//! * `GET /metrics` - snapshots in the Prometheus text exposition format.
+//!
+//! Actions respond with the snapshot of the circuit breaker after the action. Names are
+//! percent-decoded, a malformed escape responds with `400 Bad Request`.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{admin, registry::Registry, Config};
+//!
+//! let registry = Registry::new();
+//! registry.register("redis", Config::new().build());
+//!
+//! // Within the request handler of a HTTP server.
+//! let response = admin::handle(&registry, "POST", "/breakers/redis/force-open");
+//! assert_eq!(200, response.status);
+//! assert!(response.body.contains("\"state\":\"open\""));
+//! ```
+
+use std::fmt::{self, Write};
+
+use super::clock;
+use super::prometheus;
+use super::registry::{Managed, Registry};
+use super::snapshot::Snapshot;
+
+const JSON: &str = "application/json";
+const TEXT: &str = "text/plain; charset=utf-8";
+const PROMETHEUS: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// A response of the admin handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The value of the `Content-Type` header.
+    pub content_type: &'static str,
+    /// The response body.
+    pub body: String,
+}
+
+/// Handles a request with the given `method` and `path`.
+///
+/// The `path` must be relative to the mount point of the handler, a query string is ignored.
+/// Segments are percent-decoded, so names like `pool[2]` of `Group` members are served at
+/// `/breakers/pool%5B2%5D`.
+pub fn handle(registry: &Registry, method: &str, path: &str) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let decoded: Option<Vec<_>> = path
+        .split('/')
+        .filter(|it| !it.is_empty())
+        .map(percent_decode)
+        .collect();
+    let decoded = match decoded {
+        Some(decoded) => decoded,
+        None => return respond(400, TEXT, "malformed path".into()),
+    };
+    let segments: Vec<_> = decoded.iter().map(String::as_str).collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["breakers"]) => {
+            let mut body = String::from("[");
+            for (idx, (name, snapshot)) in registry.snapshots().iter().enumerate() {
+                if idx > 0 {
+                    body.push(',');
+                }
+                write_snapshot(&mut body, name, snapshot).expect("writing to a String never fails");
+            }
+            body.push(']');
+            respond(200, JSON, body)
+        }
+        ("GET", ["breakers", name]) => with_breaker(registry, name, |_| {}),
+        ("POST", ["breakers", name, "force-open"]) => {
+            with_breaker(registry, name, |breaker| breaker.force_open())
+        }
+        ("POST", ["breakers", name, "force-closed"]) => {
+            with_breaker(registry, name, |breaker| breaker.force_closed())
+        }
+        ("POST", ["breakers", name, "reset"]) => {
+            with_breaker(registry, name, |breaker| breaker.reset())
+        }
+        ("GET", ["metrics"]) => respond(200, PROMETHEUS, prometheus::render(registry.snapshots())),
+        (_, ["breakers"])
+        | (_, ["breakers", _])
+        | (_, ["breakers", _, "force-open"])
+        | (_, ["breakers", _, "force-closed"])
+        | (_, ["breakers", _, "reset"])
+        | (_, ["metrics"]) => respond(405, TEXT, "method not allowed".into()),
+        _ => not_found(),
+    }
+}
+
+fn with_breaker<F>(registry: &Registry, name: &str, action: F) -> Response
+where
+    F: FnOnce(&dyn Managed),
+{
+    match registry.get(name) {
+        Some(breaker) => {
+            action(&*breaker);
+            let mut body = String::new();
+            write_snapshot(&mut body, name, &breaker.snapshot())
+                .expect("writing to a String never fails");
+            respond(200, JSON, body)
+        }
+        None => not_found(),
+    }
+}
+
+#[inline]
+fn respond(status: u16, content_type: &'static str, body: String) -> Response {
+    Response {
+        status,
+        content_type,
+        body,
+    }
+}
+
+/// Decodes `%XX` escapes of a path segment, returns `None` when an escape is malformed or the
+/// result isn't UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|it| it.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[inline]
+fn not_found() -> Response {
+    respond(404, TEXT, "not found".into())
+}
+
+fn write_snapshot<W: Write>(out: &mut W, name: &str, snapshot: &Snapshot) -> fmt::Result {
+    let now = clock::now();
+    let in_state = now.saturating_duration_since(snapshot.since);
+
+    write!(
+        out,
+        "{{\"name\":\"{}\",\"state\":\"{}\",\"in_state_ms\":{},\"open_for_ms\":",
+        Escaped(name),
+        snapshot.state.as_str(),
+        in_state.as_millis()
+    )?;
+    match snapshot.open_until {
+        Some(until) => write!(out, "{}", until.saturating_duration_since(now).as_millis())?,
+        None => out.write_str("null")?,
+    }
+    write!(
+        out,
+        ",\"successes\":{},\"failures\":{},\"rejections\":{},\"opened\":{},\"last_error\":",
+        snapshot.successes, snapshot.failures, snapshot.rejections, snapshot.opened
+    )?;
+    match &snapshot.last_error {
+        Some(last_error) => write!(
+            out,
+            "{{\"message\":\"{}\",\"ago_ms\":{}}}}}",
+            Escaped(&last_error.message),
+            now.saturating_duration_since(last_error.at).as_millis()
+        ),
+        None => out.write_str("null}"),
+    }
+}
+
+/// Escapes a JSON string.
+struct Escaped<'a>(&'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ch in self.0.chars() {
+            match ch {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+                ch => f.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::Config;
+    use super::*;
+
+    #[test]
+    fn serve_routes() {
+        clock::freeze(|time| {
+            let registry = Registry::new();
+            let redis = Config::new().build();
+            registry.register("redis", redis.clone());
+            registry.register("pg", Config::new().build());
+
+            time.advance(Duration::from_millis(1500));
+            let response = handle(&registry, "GET", "/breakers/redis");
+            assert_eq!(200, response.status);
+            assert_eq!(
+                "{\"name\":\"redis\",\"state\":\"closed\",\"in_state_ms\":1500,\"open_for_ms\":null,\
+                 \"successes\":0,\"failures\":0,\"rejections\":0,\"opened\":0,\"last_error\":null}",
+                response.body
+            );
+
+            let response = handle(&registry, "POST", "/breakers/redis/force-open");
+            assert_eq!(200, response.status);
+            assert!(response.body.contains("\"state\":\"open\""));
+            assert!(!redis.is_call_permitted());
+
+            let response = handle(&registry, "GET", "/breakers?pretty");
+            assert_eq!(JSON, response.content_type);
+            assert!(response.body.starts_with("[{\"name\":\"pg\""));
+
+            handle(&registry, "POST", "/breakers/redis/reset");
+            assert!(redis.is_call_permitted());
+
+            let response = handle(&registry, "GET", "/metrics");
+            assert!(response
+                .body
+                .contains("failsafe_opened_total{breaker=\"redis\"} 1"));
+
+            assert_eq!(404, handle(&registry, "GET", "/breakers/mysql").status);
+            assert_eq!(404, handle(&registry, "GET", "/unknown").status);
+            assert_eq!(405, handle(&registry, "DELETE", "/breakers/redis").status);
+        })
+    }
+
+    #[test]
+    fn decode_names() {
+        let registry = Registry::new();
+        let member = Config::new().build();
+        registry.register("pool[2]", member.clone());
+
+        let response = handle(&registry, "POST", "/breakers/pool%5B2%5D/force-open");
+        assert_eq!(200, response.status);
+        assert!(response.body.starts_with("{\"name\":\"pool[2]\""));
+        assert!(!member.is_call_permitted());
+
+        assert_eq!(400, handle(&registry, "GET", "/breakers/pool%5").status);
+        assert_eq!(400, handle(&registry, "GET", "/breakers/%zz").status);
+        assert_eq!(
+            404,
+            handle(&registry, "GET", "/breakers/pool%5B3%5D").status
+        );
+    }
+}
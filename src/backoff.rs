@@ -1,12 +1,15 @@
 //! Contains various backoff strategies.
 //!
-//! Strategies are defined as `Iterator<Item=Duration>`.
+//! Strategies are defined as `Iterator<Item=Duration>`. A strategy which needs a feedback from
+//! the failure policy (e.g. the current failure rate or the number of consecutive open cycles)
+//! implements the `WithFeedback` trait instead, which is implemented for all iterators.
 
 use std::iter::{self, Iterator};
 use std::time::Duration;
 
 use rand::prelude::thread_rng;
 pub use rand::prelude::ThreadRng;
+use rand::{Rng, RngCore};
 
 const MAX_RETRIES: u32 = 30;
 
@@ -93,12 +96,182 @@ pub fn full_jittered(start: Duration, max: Duration) -> FullJittered {
     }
 }
 
+/// Defines how `jittered` randomizes delays of a backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jitter {
+    /// A delay is random between 0 and the delay of the inner backoff.
+    Full,
+    /// A delay keeps half of the delay of the inner backoff, and jitters the other half.
+    Equal,
+    /// A delay is random between the first delay of the inner backoff and three times the
+    /// previous delay, bounded by the delay of the inner backoff.
+    Decorrelated,
+}
+
+/// Creates a backoff which randomizes delays of the `inner` backoff, e.g. retry delays, so many
+/// clients don't retry in lockstep.
+///
+/// Unlike `full_jittered` and `equal_jittered`, it wraps any backoff, and works with
+/// milliseconds.
+///
+/// See https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/.
+pub fn jittered<B>(inner: B, jitter: Jitter) -> Jittered<B>
+where
+    B: Iterator<Item = Duration>,
+{
+    Jittered {
+        inner,
+        jitter,
+        first: None,
+        prev: None,
+        rng: ThreadLocalGenRange,
+    }
+}
+
+/// A feedback passed by a failure policy to a backoff when the next delay is requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Feedback {
+    /// The number of consecutive open cycles since the backoff was reset, including the upcoming
+    /// one, so it starts from `1`.
+    pub open_cycles: u32,
+    /// The failure rate in `[0.0, 1.0]` observed by the failure policy, if it tracks one.
+    pub failure_rate: Option<f64>,
+}
+
+/// A backoff strategy, which takes a feedback into account when producing the next delay.
+pub trait WithFeedback {
+    /// Returns the next delay.
+    fn next_with(&mut self, feedback: &Feedback) -> Option<Duration>;
+}
+
+/// Plain iterators ignore the feedback.
+impl<T> WithFeedback for T
+where
+    T: Iterator<Item = Duration>,
+{
+    #[inline]
+    fn next_with(&mut self, _feedback: &Feedback) -> Option<Duration> {
+        self.next()
+    }
+}
+
+/// Creates a backoff which multiplies delays of the `inner` backoff by `factor` for each
+/// consecutive open cycle after the first one, capped by `max`.
+///
+/// Unlike the exponential backoff, which is driven by its own attempts, the escalation is driven
+/// by the failure policy's open cycles, so repeated failed recoveries escalate faster.
+///
+/// # Panics
+///
+/// When `factor` is less than `1.0`.
+pub fn escalating<B>(inner: B, factor: f64, max: Duration) -> Escalating<B>
+where
+    B: WithFeedback,
+{
+    assert!(factor >= 1.0, "factor must be >= 1: {}", factor);
+    Escalating { inner, factor, max }
+}
+
+/// Returns the next `n` delays of the `backoff` without advancing it, e.g. to log how long the
+/// following open states would last.
+///
+/// Delays are taken from a clone, as if the open cycles followed one another. A clone of a
+/// jittered backoff randomizes delays anew, unless its generator is seeded, see
+/// `EqualJittered::with_rng`.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::backoff;
+///
+/// let backoff = backoff::exponential(Duration::from_secs(10), Duration::from_secs(60));
+/// let delays = backoff::preview(&backoff, 3);
+/// assert_eq!(
+///     vec![Duration::from_secs(10), Duration::from_secs(20), Duration::from_secs(40)],
+///     delays
+/// );
+/// ```
+pub fn preview<B>(backoff: &B, n: usize) -> Vec<Duration>
+where
+    B: WithFeedback + Clone,
+{
+    let mut backoff = backoff.clone();
+    let mut feedback = Feedback::default();
+    (0..n)
+        .map_while(|_| {
+            feedback.open_cycles = feedback.open_cycles.saturating_add(1);
+            backoff.next_with(&feedback)
+        })
+        .collect()
+}
+
+/// Creates a backoff from a closure, which computes the next delay from the feedback.
+pub fn from_feedback<F>(f: F) -> FromFeedback<F>
+where
+    F: FnMut(&Feedback) -> Option<Duration>,
+{
+    FromFeedback(f)
+}
+
+/// A backoff which escalates delays of the inner backoff on consecutive open cycles.
+#[derive(Clone, Debug)]
+pub struct Escalating<B> {
+    inner: B,
+    factor: f64,
+    max: Duration,
+}
+
+impl<B> WithFeedback for Escalating<B>
+where
+    B: WithFeedback,
+{
+    fn next_with(&mut self, feedback: &Feedback) -> Option<Duration> {
+        let delay = self.inner.next_with(feedback)?;
+        let exp = feedback.open_cycles.saturating_sub(1).min(MAX_RETRIES) as i32;
+        let secs = delay.as_secs_f64() * self.factor.powi(exp);
+        Some(Duration::from_secs_f64(secs.min(self.max.as_secs_f64())))
+    }
+}
+
+/// A backoff defined by a closure, see `from_feedback`.
+#[derive(Clone)]
+pub struct FromFeedback<F>(F);
+
+impl<F> WithFeedback for FromFeedback<F>
+where
+    F: FnMut(&Feedback) -> Option<Duration>,
+{
+    #[inline]
+    fn next_with(&mut self, feedback: &Feedback) -> Option<Duration> {
+        (self.0)(feedback)
+    }
+}
+
+impl<F> std::fmt::Debug for FromFeedback<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("FromFeedback").finish()
+    }
+}
+
 /// Random generator.
+///
+/// It's implemented for all `rand` generators, so jittered backoffs may use a seeded one, see
+/// `EqualJittered::with_rng`.
 pub trait GenRange {
     /// Generates a random value within range low and high.
     fn gen_range(&mut self, low: u64, high: u64) -> u64;
 }
 
+impl<T> GenRange for T
+where
+    T: RngCore,
+{
+    #[inline]
+    fn gen_range(&mut self, low: u64, high: u64) -> u64 {
+        Rng::gen_range(self, low..high)
+    }
+}
+
 /// Thread local random generator, invokes `rand::thread_rng`.
 #[derive(Debug, Clone)]
 pub struct ThreadLocalGenRange;
@@ -106,8 +279,7 @@ pub struct ThreadLocalGenRange;
 impl GenRange for ThreadLocalGenRange {
     #[inline]
     fn gen_range(&mut self, low: u64, high: u64) -> u64 {
-        use rand::Rng;
-        thread_rng().gen_range(low..high)
+        Rng::gen_range(&mut thread_rng(), low..high)
     }
 }
 
@@ -149,9 +321,9 @@ pub struct FullJittered<R = ThreadLocalGenRange> {
     rng: R,
 }
 
-#[cfg(test)]
 impl<R> FullJittered<R> {
-    fn with_rng<T: GenRange>(self, rng: T) -> FullJittered<T> {
+    /// Replaces the random generator, see `EqualJittered::with_rng`.
+    pub fn with_rng<T: GenRange>(self, rng: T) -> FullJittered<T> {
         FullJittered {
             rng,
             start: self.start,
@@ -188,9 +360,25 @@ pub struct EqualJittered<R = ThreadLocalGenRange> {
     rng: R,
 }
 
-#[cfg(test)]
 impl<R> EqualJittered<R> {
-    fn with_rng<T: GenRange>(self, rng: T) -> EqualJittered<T> {
+    /// Replaces the random generator, by default delays are randomized by `rand::thread_rng`.
+    ///
+    /// A seeded generator makes delays reproducible, e.g. in tests and simulations.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use failsafe::backoff;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let delays = || {
+    ///     backoff::equal_jittered(Duration::from_secs(5), Duration::from_secs(300))
+    ///         .with_rng(StdRng::seed_from_u64(42))
+    ///         .take(5)
+    ///         .collect::<Vec<_>>()
+    /// };
+    /// assert_eq!(delays(), delays());
+    /// ```
+    pub fn with_rng<T: GenRange>(self, rng: T) -> EqualJittered<T> {
         EqualJittered {
             rng,
             start: self.start,
@@ -215,6 +403,54 @@ impl<R: GenRange> Iterator for EqualJittered<R> {
     }
 }
 
+/// A backoff which randomizes delays of the inner backoff, see `jittered`.
+#[derive(Clone, Debug)]
+pub struct Jittered<B, R = ThreadLocalGenRange> {
+    inner: B,
+    jitter: Jitter,
+    first: Option<u64>,
+    prev: Option<u64>,
+    rng: R,
+}
+
+impl<B, R> Jittered<B, R> {
+    /// Replaces the random generator, see `EqualJittered::with_rng`.
+    pub fn with_rng<T: GenRange>(self, rng: T) -> Jittered<B, T> {
+        Jittered {
+            rng,
+            inner: self.inner,
+            jitter: self.jitter,
+            first: self.first,
+            prev: self.prev,
+        }
+    }
+}
+
+impl<B, R> Iterator for Jittered<B, R>
+where
+    B: Iterator<Item = Duration>,
+    R: GenRange,
+{
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let delay = self.inner.next()?.as_millis() as u64;
+        let millis = match self.jitter {
+            Jitter::Full => self.rng.gen_range(0, delay + 1),
+            Jitter::Equal => (delay / 2) + self.rng.gen_range(0, (delay - delay / 2) + 1),
+            Jitter::Decorrelated => {
+                let first = *self.first.get_or_insert(delay);
+                let high = self.prev.map_or(first, |prev| prev.saturating_mul(3));
+                let millis = self.rng.gen_range(first, high.max(first) + 1).min(delay);
+                self.prev = Some(millis);
+                millis
+            }
+        };
+
+        Some(Duration::from_millis(millis))
+    }
+}
+
 fn exponential_backoff_seconds(attempt: u32, base: Duration, max: Duration) -> u64 {
     ((1_u64 << attempt) * base.as_secs()).min(max.as_secs())
 }
@@ -222,23 +458,13 @@ fn exponential_backoff_seconds(attempt: u32, base: Duration, max: Duration) -> u
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rand::{RngCore, SeedableRng};
+    use rand::SeedableRng;
     use rand_xorshift::XorShiftRng;
 
     const SEED: &[u8; 16] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 8, 7, 6, 5, 4, 3, 2];
-    struct TestGenRage<T>(T);
 
-    impl Default for TestGenRage<XorShiftRng> {
-        fn default() -> Self {
-            TestGenRage(XorShiftRng::from_seed(*SEED))
-        }
-    }
-
-    impl<T: RngCore> GenRange for TestGenRage<T> {
-        fn gen_range(&mut self, low: u64, high: u64) -> u64 {
-            use rand::Rng;
-            self.0.gen_range(low..high)
-        }
+    fn seeded() -> XorShiftRng {
+        XorShiftRng::from_seed(*SEED)
     }
 
     #[test]
@@ -252,8 +478,8 @@ mod tests {
 
     #[test]
     fn full_jittered_growth() {
-        let backoff = full_jittered(Duration::from_secs(10), Duration::from_secs(300))
-            .with_rng(TestGenRage::default());
+        let backoff =
+            full_jittered(Duration::from_secs(10), Duration::from_secs(300)).with_rng(seeded());
 
         let actual = backoff.take(10).map(|it| it.as_secs()).collect::<Vec<_>>();
         let expected = vec![0, 0, 33, 53, 80, 6, 132, 121, 234, 79];
@@ -262,14 +488,76 @@ mod tests {
 
     #[test]
     fn equal_jittered_growth() {
-        let backoff = equal_jittered(Duration::from_secs(5), Duration::from_secs(300))
-            .with_rng(TestGenRage::default());
+        let backoff =
+            equal_jittered(Duration::from_secs(5), Duration::from_secs(300)).with_rng(seeded());
 
         let actual = backoff.take(10).map(|it| it.as_secs()).collect::<Vec<_>>();
         let expected = vec![2, 5, 10, 37, 63, 133, 225, 153, 216, 170];
         assert_eq!(expected, actual)
     }
 
+    #[test]
+    fn jittered_bounds() {
+        let delays = || exponential(Duration::from_secs(1), Duration::from_secs(60)).take(10);
+        let jitter = |jitter| {
+            jittered(delays(), jitter)
+                .with_rng(seeded())
+                .zip(delays())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(jitter(Jitter::Full).iter().all(|(it, max)| it <= max));
+        assert!(jitter(Jitter::Equal)
+            .iter()
+            .all(|(it, max)| *it >= *max / 2 && it <= max));
+
+        let decorrelated = jitter(Jitter::Decorrelated);
+        assert!(decorrelated
+            .iter()
+            .all(|(it, max)| *it >= Duration::from_secs(1) && it <= max));
+        assert!(decorrelated
+            .windows(2)
+            .all(|pair| pair[1].0 <= pair[0].0 * 3));
+        // Delays differ from the inner backoff.
+        assert_ne!(
+            delays().collect::<Vec<_>>(),
+            decorrelated.iter().map(|it| it.0).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn preview_delays() {
+        let mut backoff = escalating(
+            constant(Duration::from_secs(10)),
+            2.0,
+            Duration::from_secs(60),
+        );
+        let delays = preview(&backoff, 4)
+            .iter()
+            .map(|it| it.as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![10, 20, 40, 60], delays);
+
+        // The backoff itself isn't advanced.
+        let feedback = Feedback {
+            open_cycles: 1,
+            ..Feedback::default()
+        };
+        assert_eq!(Some(Duration::from_secs(10)), backoff.next_with(&feedback));
+
+        let jittered =
+            full_jittered(Duration::from_secs(10), Duration::from_secs(300)).with_rng(seeded());
+        assert_eq!(preview(&jittered, 5), preview(&jittered, 5));
+        assert_eq!(
+            2,
+            preview(
+                &exponential(Duration::from_secs(1), Duration::from_secs(1)).take(2),
+                5
+            )
+            .len()
+        );
+    }
+
     #[test]
     fn constant_growth() {
         let backoff = constant(Duration::from_secs(3));
@@ -0,0 +1,216 @@
+//! Picking of endpoints which circuit breakers permit calls, for client side load balancing.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{balance::{Picker, Strategy}, Config};
+//!
+//! let picker = Picker::new(Strategy::RoundRobin)
+//!     .endpoint("10.0.0.1", Config::new().build())
+//!     .endpoint("10.0.0.2", Config::new().build());
+//!
+//! let result = picker.call(|addr| Ok::<_, ()>(format!("fetched from {}", addr)));
+//! assert!(result.is_ok());
+//! ```
+
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::circuit_breaker::CircuitBreaker;
+use super::error::{Error, Rejection, RejectionReason};
+use super::failure_policy::FailurePolicy;
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// Defines how `Picker` chooses among endpoints which permit calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Endpoints are taken in turn.
+    RoundRobin,
+    /// The endpoint with the least number of calls in flight is taken, see `Picked`.
+    LeastLoaded,
+}
+
+struct Slot<T, POLICY, INSTRUMENT> {
+    endpoint: T,
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+    in_flight: AtomicUsize,
+}
+
+/// Picks an endpoint which circuit breaker currently permits calls, skipping open ones.
+pub struct Picker<T, POLICY, INSTRUMENT> {
+    slots: Vec<Slot<T, POLICY, INSTRUMENT>>,
+    strategy: Strategy,
+    next: AtomicUsize,
+}
+
+/// An endpoint picked by `Picker`, it counts as a call in flight until dropped.
+pub struct Picked<'a, T, POLICY, INSTRUMENT> {
+    slot: &'a Slot<T, POLICY, INSTRUMENT>,
+}
+
+impl<T, POLICY, INSTRUMENT> Picker<T, POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Creates a picker without endpoints.
+    pub fn new(strategy: Strategy) -> Self {
+        Picker {
+            slots: Vec::new(),
+            strategy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds the `endpoint` guarded by the `state_machine`.
+    pub fn endpoint(
+        mut self,
+        endpoint: T,
+        state_machine: StateMachine<POLICY, INSTRUMENT>,
+    ) -> Self {
+        self.slots.push(Slot {
+            endpoint,
+            state_machine,
+            in_flight: AtomicUsize::new(0),
+        });
+        self
+    }
+
+    /// Returns an endpoint which circuit breaker permits calls, or `None` when all are open.
+    ///
+    /// The check has no side effects, see `StateMachine::peek_permitted`, so the call may still
+    /// be rejected, e.g. by a half open circuit breaker.
+    pub fn pick(&self) -> Option<Picked<'_, T, POLICY, INSTRUMENT>> {
+        let len = self.slots.len();
+        if len == 0 {
+            return None;
+        }
+
+        // The rotating start spreads calls among equally loaded endpoints as well.
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut healthy = (0..len)
+            .map(|n| &self.slots[(start + n) % len])
+            .filter(|slot| slot.state_machine.peek_permitted());
+
+        let slot = match self.strategy {
+            Strategy::RoundRobin => healthy.next(),
+            Strategy::LeastLoaded => {
+                healthy.min_by_key(|slot| slot.in_flight.load(Ordering::Relaxed))
+            }
+        }?;
+
+        slot.in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(Picked { slot })
+    }
+
+    /// Picks an endpoint and executes a given function with it within its circuit breaker.
+    ///
+    /// Returns `Error::Rejected` when no endpoint permits calls.
+    pub fn call<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce(&T) -> Result<R, E>,
+    {
+        match self.pick() {
+            Some(picked) => picked.state_machine().call(|| f(picked.endpoint())),
+            None => Err(Error::Rejected(
+                Rejection::default().with_reason(RejectionReason::NoEndpoint),
+            )),
+        }
+    }
+}
+
+impl<'a, T, POLICY, INSTRUMENT> Picked<'a, T, POLICY, INSTRUMENT> {
+    /// Returns the picked endpoint.
+    pub fn endpoint(&self) -> &'a T {
+        &self.slot.endpoint
+    }
+
+    /// Returns the circuit breaker of the picked endpoint.
+    pub fn state_machine(&self) -> &'a StateMachine<POLICY, INSTRUMENT> {
+        &self.slot.state_machine
+    }
+}
+
+impl<'a, T, POLICY, INSTRUMENT> Drop for Picked<'a, T, POLICY, INSTRUMENT> {
+    fn drop(&mut self) {
+        self.slot.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T, POLICY, INSTRUMENT> Debug for Picker<T, POLICY, INSTRUMENT>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Picker")
+            .field("strategy", &self.strategy)
+            .field(
+                "endpoints",
+                &self.slots.iter().map(|it| &it.endpoint).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<'a, T, POLICY, INSTRUMENT> Debug for Picked<'a, T, POLICY, INSTRUMENT>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Picked").field(&self.slot.endpoint).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    fn picker(strategy: Strategy) -> Picker<u32, impl FailurePolicy, ()> {
+        let backoff = backoff::constant(Duration::from_secs(30));
+        (0..3).fold(Picker::new(strategy), |picker, n| {
+            let policy = failure_policy::consecutive_failures(1, backoff.clone());
+            picker.endpoint(n, Config::new().failure_policy(policy).build())
+        })
+    }
+
+    #[test]
+    fn skip_open_endpoints() {
+        let picker = picker(Strategy::RoundRobin);
+        let _ = picker.call(|_| Err::<(), _>(()));
+
+        let picked = (0..4)
+            .map(|_| *picker.pick().unwrap().endpoint())
+            .collect::<Vec<_>>();
+        assert!(picked.contains(&1) && picked.contains(&2) && !picked.contains(&0));
+
+        let _ = picker.call(|_| Err::<(), _>(()));
+        let _ = picker.call(|_| Err::<(), _>(()));
+        assert!(picker.pick().is_none());
+        match picker.call(|_| Ok::<_, ()>(())) {
+            Err(Error::Rejected(rejection)) => {
+                assert_eq!(RejectionReason::NoEndpoint, rejection.reason())
+            }
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn least_loaded() {
+        let picker = picker(Strategy::LeastLoaded);
+
+        let first = picker.pick().unwrap();
+        let second = picker.pick().unwrap();
+        let third = picker.pick().unwrap();
+        assert_ne!(first.endpoint(), second.endpoint());
+        assert_ne!(second.endpoint(), third.endpoint());
+        assert_ne!(first.endpoint(), third.endpoint());
+
+        let endpoint = *second.endpoint();
+        drop(second);
+        assert_eq!(endpoint, *picker.pick().unwrap().endpoint());
+    }
+}
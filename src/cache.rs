@@ -0,0 +1,129 @@
+//! Degrading to cached results while the circuit breaker is open, requires the `cache` feature.
+//!
+//! `Cached` stores successful results of calls in a [moka](https://docs.rs/moka) cache, and
+//! serves them when the circuit breaker rejects a call with the same key.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use failsafe::{cache::Cached, Config};
+//!
+//! let cached = Cached::new(Config::new().build(), 1024, Duration::from_secs(60));
+//!
+//! match cached.call_cached("user:1", || Ok::<_, ()>("Alice".to_owned())) {
+//!     Ok(name) => println!("{}", name),
+//!     Err(_) => eprintln!("unavailable"),
+//! }
+//! ```
+
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::time::Duration;
+
+use moka::sync::Cache;
+
+use super::circuit_breaker::CircuitBreaker;
+use super::error::Error;
+
+/// A circuit breaker which serves cached results of successful calls when it rejects a call.
+///
+/// Only rejected calls are served from the cache, errors of permitted calls are returned as is.
+/// Entries expire by the cache policy, which uses the system time rather than `clock`.
+pub struct Cached<BREAKER, K, V> {
+    circuit_breaker: BREAKER,
+    cache: Cache<K, V>,
+}
+
+impl<BREAKER, K, V> Cached<BREAKER, K, V>
+where
+    BREAKER: CircuitBreaker,
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a cache of at most `capacity` results, which expire `ttl` after they are stored.
+    pub fn new(circuit_breaker: BREAKER, capacity: u64, ttl: Duration) -> Self {
+        let cache = Cache::builder()
+            .max_capacity(capacity)
+            .time_to_live(ttl)
+            .build();
+        Self::with_cache(circuit_breaker, cache)
+    }
+
+    /// Creates an instance with a preconfigured cache.
+    pub fn with_cache(circuit_breaker: BREAKER, cache: Cache<K, V>) -> Self {
+        Cached {
+            circuit_breaker,
+            cache,
+        }
+    }
+
+    /// Executes `f` within the circuit breaker and stores its successful result for the `key`.
+    ///
+    /// When the circuit breaker rejects the call, the cached result for the `key` is returned if
+    /// it's present, otherwise `Error::Rejected`.
+    pub fn call_cached<F, E>(&self, key: K, f: F) -> Result<V, Error<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self.circuit_breaker.call(f) {
+            Ok(value) => {
+                self.cache.insert(key, value.clone());
+                Ok(value)
+            }
+            Err(Error::Rejected(rejection)) => {
+                self.cache.get(&key).ok_or(Error::Rejected(rejection))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the underlying circuit breaker.
+    pub fn get_ref(&self) -> &BREAKER {
+        &self.circuit_breaker
+    }
+
+    /// Returns the cache of results.
+    pub fn cache(&self) -> &Cache<K, V> {
+        &self.cache
+    }
+}
+
+impl<BREAKER, K, V> Debug for Cached<BREAKER, K, V>
+where
+    BREAKER: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("entries", &self.cache.entry_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn serve_cached_when_open() {
+        let backoff = backoff::constant(Duration::from_secs(30));
+        let policy = failure_policy::consecutive_failures(1, backoff);
+        let circuit_breaker = Config::new().failure_policy(policy).build();
+        let cached = Cached::new(circuit_breaker, 16, Duration::from_secs(60));
+
+        assert_eq!(1, cached.call_cached("a", || Ok::<_, ()>(1)).unwrap());
+        // A failed call isn't served from the cache, it opens the circuit breaker.
+        match cached.call_cached("a", || Err(())) {
+            Err(Error::Inner(())) => {}
+            x => unreachable!("{:?}", x),
+        }
+
+        assert_eq!(1, cached.call_cached("a", || Ok::<_, ()>(2)).unwrap());
+        match cached.call_cached("b", || Ok::<_, ()>(2)) {
+            Err(Error::Rejected(_)) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
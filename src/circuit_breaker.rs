@@ -1,8 +1,14 @@
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::clock;
+use super::config::Config;
 use super::error::Error;
-use super::failure_policy::FailurePolicy;
-use super::failure_predicate::{self, FailurePredicate};
+use super::failure_policy::{DefaultPolicy, FailurePolicy};
+use super::failure_predicate::{self, FailureClassifier, FailurePredicate};
 use super::instrument::Instrument;
-use super::state_machine::StateMachine;
+use super::state_machine::{Failure, StateMachine, WAIT_INTERVAL, WAIT_PRECISION};
 
 /// A circuit breaker's public interface.
 pub trait CircuitBreaker {
@@ -31,6 +37,115 @@ pub trait CircuitBreaker {
     where
         P: FailurePredicate<E>,
         F: FnOnce() -> Result<R, E>;
+
+    /// Executes a given function within circuit breaker.
+    ///
+    /// Depending on function result value, the call will be recorded as success or failure.
+    /// It sorts errors by the provided classifier. If the classifier returns `Some(class)`,
+    /// the call is recorded as failure of that class, see `failure_policy::per_class`,
+    /// otherwise considered this error as a success.
+    ///
+    /// The default implementation records classified errors as plain failures by `call_with`.
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.call_with(|err: &E| classifier.classify(err).is_some(), f)
+    }
+
+    /// Executes a given function within circuit breaker, waiting for the permission.
+    ///
+    /// Instead of rejecting the call at once, it blocks the current thread until the circuit
+    /// breaker permits the call or the `deadline` expires, which suits batch and worker
+    /// workloads that prefer waiting to failing. Depending on function result value, the call
+    /// will be recorded as success or failure.
+    ///
+    /// The default implementation retries `call` until it isn't rejected, sleeping for the
+    /// delay the rejection suggests.
+    fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        let mut remaining = deadline.saturating_duration_since(clock::now());
+        let mut f = Some(f);
+        loop {
+            // A rejected call leaves the function untouched for the next attempt.
+            let res = self.call(|| f.take().expect("the function is called once")());
+            match res {
+                Err(Error::Rejected(rejection)) if remaining > Duration::from_secs(0) => {
+                    let wait = rejection
+                        .retry_after()
+                        .map_or(WAIT_INTERVAL, |retry_after| retry_after + WAIT_PRECISION)
+                        .min(remaining);
+                    clock::sleep(wait);
+                    remaining -= wait;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Executes a given function within circuit breaker, which result weighs `cost` calls.
+    ///
+    /// Depending on function result value, the call will be recorded as success or failure of
+    /// the given `cost`, so one expensive call, e.g. a bulk request, counts as much as many
+    /// small ones when the failure policy computes failure rates.
+    ///
+    /// The default implementation ignores the `cost` and records the call by `call`.
+    fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        let _ = cost;
+        self.call(f)
+    }
+
+    /// Executes a given function within circuit breaker, reporting errors to the instrument.
+    ///
+    /// Depending on function result value, the call will be recorded as success or failure.
+    /// The error of a failed call is passed to `Instrument::on_failure_reason`, so instruments
+    /// can log what kind of failure has opened the circuit breaker.
+    ///
+    /// The default implementation records the call by `call` without reporting the error.
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        self.call(f)
+    }
+
+    /// Executes a given function for each of the `inputs` within circuit breaker, returns
+    /// results in the order of inputs.
+    ///
+    /// Each call is recorded as success or failure like by `call`. Once a call is rejected, the
+    /// rejection is the last result and the remaining inputs are left unprocessed, so the
+    /// number of results tells where to resume the batch.
+    ///
+    /// ```
+    /// use failsafe::{CircuitBreaker, Config};
+    ///
+    /// let circuit_breaker = Config::new().build();
+    /// let results = circuit_breaker.call_each(1..=3, |n| Ok::<_, ()>(n * 2));
+    /// assert_eq!(3, results.len());
+    /// ```
+    fn call_each<I, F, E, R>(&self, inputs: I, mut f: F) -> Vec<Result<R, Error<E>>>
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> Result<R, E>,
+    {
+        let mut results = Vec::new();
+        for input in inputs {
+            let res = self.call(|| f(input));
+            let rejected = matches!(res, Err(Error::Rejected(_)));
+            results.push(res);
+            if rejected {
+                break;
+            }
+        }
+        results
+    }
 }
 
 impl<POLICY, INSTRUMENT> CircuitBreaker for StateMachine<POLICY, INSTRUMENT>
@@ -48,25 +163,343 @@ where
         P: FailurePredicate<E>,
         F: FnOnce() -> Result<R, E>,
     {
-        if !self.is_call_permitted() {
-            return Err(Error::Rejected);
-        }
+        self.permit().map_err(Error::Rejected)?;
+
+        call_permitted(self, predicate, f)
+    }
 
-        match f() {
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit().map_err(Error::Rejected)?;
+
+        let started = clock::now();
+        let res = f();
+        let latency = clock::now() - started;
+
+        match res {
             Ok(ok) => {
-                self.on_success();
+                self.record_success(1, Some(latency));
                 Ok(ok)
             }
             Err(err) => {
-                if predicate.is_err(&err) {
-                    self.on_error();
-                } else {
-                    self.on_success();
+                match classifier.classify(&err) {
+                    Some(class) => {
+                        self.record_error(Failure::Classified(class), Some(latency), Some(&class))
+                    }
+                    None => self.record_success(1, Some(latency)),
                 }
                 Err(Error::Inner(err))
             }
         }
     }
+
+    fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.wait_permitted(deadline).map_err(Error::Rejected)?;
+
+        call_permitted(self, failure_predicate::Any, f)
+    }
+
+    fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit().map_err(Error::Rejected)?;
+
+        let started = clock::now();
+        let res = f();
+        let latency = clock::now() - started;
+
+        match res {
+            Ok(ok) => {
+                self.record_success(cost, Some(latency));
+                Ok(ok)
+            }
+            Err(err) => {
+                self.record_error(Failure::Weighted(cost), Some(latency), None);
+                Err(Error::Inner(err))
+            }
+        }
+    }
+
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        self.permit().map_err(Error::Rejected)?;
+
+        let started = clock::now();
+        let res = f();
+        let latency = clock::now() - started;
+
+        match res {
+            Ok(ok) => {
+                self.record_success(1, Some(latency));
+                Ok(ok)
+            }
+            Err(err) => {
+                self.record_error(Failure::Plain, Some(latency), Some(&err));
+                Err(Error::Inner(err))
+            }
+        }
+    }
+}
+
+// References and `Arc`s of circuit breakers are circuit breakers too, so a shared circuit breaker
+// may be passed where `impl CircuitBreaker` is expected.
+macro_rules! delegate_circuit_breaker {
+    () => {
+        #[inline]
+        fn is_call_permitted(&self) -> bool {
+            (**self).is_call_permitted()
+        }
+
+        #[inline]
+        fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+        where
+            P: FailurePredicate<E>,
+            F: FnOnce() -> Result<R, E>,
+        {
+            (**self).call_with(predicate, f)
+        }
+
+        #[inline]
+        fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+        where
+            C: FailureClassifier<E>,
+            F: FnOnce() -> Result<R, E>,
+        {
+            (**self).call_classified(classifier, f)
+        }
+
+        #[inline]
+        fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+        where
+            F: FnOnce() -> Result<R, E>,
+        {
+            (**self).call_or_wait(deadline, f)
+        }
+
+        #[inline]
+        fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+        where
+            F: FnOnce() -> Result<R, E>,
+        {
+            (**self).call_weighted(cost, f)
+        }
+
+        #[inline]
+        fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+        where
+            F: FnOnce() -> Result<R, E>,
+            E: Display,
+        {
+            (**self).call_reported(f)
+        }
+    };
+}
+
+impl<T> CircuitBreaker for &T
+where
+    T: CircuitBreaker + ?Sized,
+{
+    delegate_circuit_breaker!();
+}
+
+impl<T> CircuitBreaker for Arc<T>
+where
+    T: CircuitBreaker + ?Sized,
+{
+    delegate_circuit_breaker!();
+}
+
+/// A circuit breaker to share across functions and tasks.
+///
+/// `StateMachine` is already cheap to clone, `SharedCircuitBreaker` is a concrete type with
+/// inherent `call` methods for signatures and struct fields, so the `CircuitBreaker` trait needn't
+/// be imported, and the default failure policy needn't be spelled out.
+///
+/// ```
+/// use failsafe::SharedCircuitBreaker;
+///
+/// fn fetch(circuit_breaker: &SharedCircuitBreaker) -> Result<u32, failsafe::Error<()>> {
+///     circuit_breaker.call(|| Ok(42))
+/// }
+///
+/// let circuit_breaker = SharedCircuitBreaker::new();
+/// let worker = circuit_breaker.clone();
+/// std::thread::spawn(move || fetch(&worker)).join().unwrap().unwrap();
+/// assert_eq!(42, fetch(&circuit_breaker).unwrap());
+/// ```
+pub struct SharedCircuitBreaker<POLICY = DefaultPolicy, INSTRUMENT = ()> {
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+}
+
+impl SharedCircuitBreaker {
+    /// Creates a circuit breaker with the default configuration, see `Config::new`.
+    pub fn new() -> Self {
+        Config::new().build().into()
+    }
+}
+
+impl Default for SharedCircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<POLICY, INSTRUMENT> SharedCircuitBreaker<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Requests permission to call, see `CircuitBreaker::is_call_permitted`.
+    #[inline]
+    pub fn is_call_permitted(&self) -> bool {
+        self.state_machine.is_call_permitted()
+    }
+
+    /// Executes a given function within circuit breaker, see `CircuitBreaker::call`.
+    #[inline]
+    pub fn call<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        CircuitBreaker::call(&self.state_machine, f)
+    }
+
+    /// Executes a given function within circuit breaker, which errors are checked by the
+    /// `predicate`, see `CircuitBreaker::call_with`.
+    #[inline]
+    pub fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        CircuitBreaker::call_with(&self.state_machine, predicate, f)
+    }
+
+    /// Returns the underlying state machine, e.g. for snapshots and operational actions.
+    #[inline]
+    pub fn state_machine(&self) -> &StateMachine<POLICY, INSTRUMENT> {
+        &self.state_machine
+    }
+}
+
+impl<POLICY, INSTRUMENT> From<StateMachine<POLICY, INSTRUMENT>>
+    for SharedCircuitBreaker<POLICY, INSTRUMENT>
+{
+    fn from(state_machine: StateMachine<POLICY, INSTRUMENT>) -> Self {
+        SharedCircuitBreaker { state_machine }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Clone for SharedCircuitBreaker<POLICY, INSTRUMENT> {
+    fn clone(&self) -> Self {
+        SharedCircuitBreaker {
+            state_machine: self.state_machine.clone(),
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Debug for SharedCircuitBreaker<POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SharedCircuitBreaker")
+            .field(&self.state_machine)
+            .finish()
+    }
+}
+
+impl<POLICY, INSTRUMENT> CircuitBreaker for SharedCircuitBreaker<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    #[inline]
+    fn is_call_permitted(&self) -> bool {
+        self.state_machine.is_call_permitted()
+    }
+
+    #[inline]
+    fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.state_machine.call_with(predicate, f)
+    }
+
+    #[inline]
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.state_machine.call_classified(classifier, f)
+    }
+
+    #[inline]
+    fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.state_machine.call_or_wait(deadline, f)
+    }
+
+    #[inline]
+    fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.state_machine.call_weighted(cost, f)
+    }
+
+    #[inline]
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        self.state_machine.call_reported(f)
+    }
+}
+
+/// Executes the already permitted call and records its result.
+pub(crate) fn call_permitted<POLICY, INSTRUMENT, P, F, E, R>(
+    state_machine: &StateMachine<POLICY, INSTRUMENT>,
+    predicate: P,
+    f: F,
+) -> Result<R, Error<E>>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    P: FailurePredicate<E>,
+    F: FnOnce() -> Result<R, E>,
+{
+    let started = clock::now();
+    let res = f();
+    let latency = clock::now() - started;
+
+    match res {
+        Ok(ok) => {
+            state_machine.on_success_after(latency);
+            Ok(ok)
+        }
+        Err(err) => {
+            if predicate.is_err(&err) {
+                state_machine.on_error_after(latency);
+            } else {
+                state_machine.on_success_after(latency);
+            }
+            Err(Error::Inner(err))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -75,7 +508,7 @@ mod tests {
 
     use super::super::backoff;
     use super::super::config::Config;
-    use super::super::failure_policy::consecutive_failures;
+    use super::super::failure_policy::{self, consecutive_failures};
     use super::*;
 
     #[test]
@@ -117,12 +550,228 @@ mod tests {
         assert!(!circuit_breaker.is_call_permitted());
 
         match circuit_breaker.call(|| Err::<(), _>(())) {
-            Err(Error::Rejected) => {}
+            Err(Error::Rejected(_)) => {}
             x => unreachable!("{:?}", x),
         }
         assert!(!circuit_breaker.is_call_permitted());
     }
 
+    #[test]
+    fn shared() {
+        fn call<CB: CircuitBreaker>(circuit_breaker: CB) -> Result<(), Error<()>> {
+            circuit_breaker.call(|| Err(()))
+        }
+        fn assert_send_sync<T: Send + Sync + Clone>(_: &T) {}
+
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let policy = consecutive_failures(1, backoff);
+        let circuit_breaker =
+            SharedCircuitBreaker::from(Config::new().failure_policy(policy).build());
+        assert_send_sync(&circuit_breaker);
+        let arc = Arc::new(circuit_breaker.clone());
+
+        assert!(circuit_breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(call(&arc).is_err());
+        assert!(!circuit_breaker.is_call_permitted());
+        match call(arc) {
+            Err(Error::Rejected(_)) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn call_classified() {
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let policy = failure_policy::per_class(|| consecutive_failures(2, backoff.clone()));
+        let circuit_breaker = Config::new().failure_policy(policy).build();
+        let classify = |err: &u8| match err {
+            0 => None,
+            1 => Some("timeout"),
+            _ => Some("serialization"),
+        };
+
+        for err in &[0, 1, 2] {
+            match circuit_breaker.call_classified(classify, || Err::<(), _>(*err)) {
+                Err(Error::Inner(_)) => {}
+                x => unreachable!("{:?}", x),
+            }
+            assert!(circuit_breaker.is_call_permitted());
+        }
+
+        let _ = circuit_breaker.call_classified(classify, || Err::<(), _>(2));
+        assert!(!circuit_breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn call_weighted() {
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let policy = consecutive_failures(5, backoff);
+        let circuit_breaker = Config::new().failure_policy(policy).build();
+
+        let _ = circuit_breaker.call_weighted(3, || Err::<(), _>(()));
+        assert!(circuit_breaker.is_call_permitted());
+
+        // Cheap calls add a unit each.
+        let _ = circuit_breaker.call(|| Err::<(), _>(()));
+        assert!(circuit_breaker.is_call_permitted());
+        let _ = circuit_breaker.call(|| Err::<(), _>(()));
+        assert!(!circuit_breaker.is_call_permitted());
+
+        circuit_breaker.reset();
+        let _ = circuit_breaker.call_weighted(5, || Err::<(), _>(()));
+        assert!(!circuit_breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn call_reported() {
+        #[derive(Debug, Default)]
+        struct Log(std::sync::Mutex<Vec<String>>);
+
+        impl Instrument for Log {
+            fn on_open(&self) {
+                self.0.lock().unwrap().push("open".to_string());
+            }
+
+            fn on_failure_reason(&self, reason: &dyn Display) {
+                self.0.lock().unwrap().push(reason.to_string());
+            }
+        }
+
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let policy = consecutive_failures(2, backoff);
+        let circuit_breaker = Config::new()
+            .failure_policy(policy)
+            .instrument(Log::default())
+            .build();
+
+        let _ = circuit_breaker.call_classified(|_: &()| Some("timeout"), || Err::<(), _>(()));
+        let _ = circuit_breaker.call_reported(|| Err::<(), _>("connection refused"));
+        assert!(!circuit_breaker.is_call_permitted());
+
+        assert_eq!(
+            vec!["timeout", "connection refused", "open"],
+            *circuit_breaker.instrument().0.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn call_or_wait() {
+        clock::freeze(|_| {
+            let backoff = backoff::constant(Duration::from_millis(50));
+            let policy = consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+            let started = clock::now();
+
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            assert!(!circuit_breaker.is_call_permitted());
+
+            // The deadline expires before the circuit breaker becomes half open.
+            let deadline = clock::now() + Duration::from_millis(5);
+            match circuit_breaker.call_or_wait(deadline, || Ok::<_, ()>(())) {
+                Err(Error::Rejected(_)) => {}
+                x => unreachable!("{:?}", x),
+            }
+            assert_eq!(deadline, clock::now());
+
+            // Waiting sleeps until the open state expires.
+            let deadline = clock::now() + Duration::from_secs(5);
+            circuit_breaker
+                .call_or_wait(deadline, || Ok::<_, ()>(()))
+                .unwrap();
+            assert!(clock::now() - started < Duration::from_millis(60));
+            assert!(circuit_breaker.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn call_each() {
+        let circuit_breaker = new_circuit_breaker();
+        let mut called = Vec::new();
+
+        let results = circuit_breaker.call_each(vec![1, 2, 3, 4], |n| {
+            called.push(n);
+            if n == 2 {
+                Err(n)
+            } else {
+                Ok(n)
+            }
+        });
+
+        assert_eq!(vec![1, 2], called);
+        assert_eq!(3, results.len());
+        assert!(matches!(results[0], Ok(1)));
+        assert!(matches!(results[1], Err(Error::Inner(2))));
+        assert!(matches!(results[2], Err(Error::Rejected(_))));
+    }
+
+    /// Implements only the required methods of the trait.
+    struct Minimal<CB>(CB);
+
+    impl<CB: CircuitBreaker> CircuitBreaker for Minimal<CB> {
+        fn is_call_permitted(&self) -> bool {
+            self.0.is_call_permitted()
+        }
+
+        fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+        where
+            P: FailurePredicate<E>,
+            F: FnOnce() -> Result<R, E>,
+        {
+            self.0.call_with(predicate, f)
+        }
+    }
+
+    #[test]
+    fn provided_call_classified() {
+        let circuit_breaker = Minimal(new_circuit_breaker());
+        let classify = |err: &u8| if *err == 0 { None } else { Some("timeout") };
+
+        let _ = circuit_breaker.call_classified(classify, || Err::<(), _>(0));
+        assert!(circuit_breaker.is_call_permitted());
+        let _ = circuit_breaker.call_classified(classify, || Err::<(), _>(1));
+        assert!(!circuit_breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn provided_call_or_wait() {
+        clock::freeze(|_| {
+            let circuit_breaker = Minimal(new_circuit_breaker());
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+
+            let deadline = clock::now() + Duration::from_secs(1);
+            match circuit_breaker.call_or_wait(deadline, || Ok::<_, ()>(())) {
+                Err(Error::Rejected(_)) => {}
+                x => unreachable!("{:?}", x),
+            }
+            assert_eq!(deadline, clock::now());
+
+            let deadline = clock::now() + Duration::from_secs(10);
+            circuit_breaker
+                .call_or_wait(deadline, || Ok::<_, ()>(()))
+                .unwrap();
+            assert!(clock::now() < deadline);
+        });
+    }
+
+    #[test]
+    fn provided_call_weighted() {
+        let circuit_breaker = Minimal(new_circuit_breaker());
+
+        assert!(circuit_breaker
+            .call_weighted(10, || Ok::<_, ()>(()))
+            .is_ok());
+        let _ = circuit_breaker.call_weighted(10, || Err::<(), _>(()));
+        assert!(!circuit_breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn provided_call_reported() {
+        let circuit_breaker = Minimal(new_circuit_breaker());
+
+        let _ = circuit_breaker.call_reported(|| Err::<(), _>("connection refused"));
+        assert!(!circuit_breaker.is_call_permitted());
+    }
+
     fn new_circuit_breaker() -> impl CircuitBreaker {
         let backoff = backoff::constant(Duration::from_secs(5));
         let policy = consecutive_failures(1, backoff);
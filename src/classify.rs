@@ -0,0 +1,179 @@
+//! Judging of calls by their result and latency, e.g. to count slow successful calls as failures.
+//!
+//! A `Classifier` sees the result of a call along with its latency, which `call` and `call_async`
+//! measure, so a backend which still answers but too slowly opens the circuit breaker.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use failsafe::{classify, Config};
+//!
+//! let circuit_breaker = Config::new().build();
+//! let slow = classify::slower_than(Duration::from_millis(200));
+//!
+//! // A result slower than 200ms is returned as is, but recorded as failure.
+//! let result = classify::call(&circuit_breaker, slow, || Ok::<_, ()>("fetched"));
+//! assert!(result.is_ok());
+//! ```
+
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::time::Duration;
+
+use super::clock;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate::Outcome;
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// Judges a completed call by its result and latency.
+pub trait Classifier<T, E> {
+    /// Returns how the call is recorded by the circuit breaker.
+    fn classify(&self, res: &Result<T, E>, latency: Duration) -> Outcome;
+}
+
+impl<F, T, E> Classifier<T, E> for F
+where
+    F: Fn(&Result<T, E>, Duration) -> Outcome,
+{
+    #[inline]
+    fn classify(&self, res: &Result<T, E>, latency: Duration) -> Outcome {
+        self(res, latency)
+    }
+}
+
+/// Returns a classifier which records errors and calls slower than `limit` as failures.
+pub fn slower_than(limit: Duration) -> SlowerThan {
+    SlowerThan { limit }
+}
+
+/// A classifier which counts slow calls as failures, see `slower_than`.
+#[derive(Debug, Clone, Copy)]
+pub struct SlowerThan {
+    limit: Duration,
+}
+
+impl<T, E> Classifier<T, E> for SlowerThan {
+    #[inline]
+    fn classify(&self, res: &Result<T, E>, latency: Duration) -> Outcome {
+        if res.is_err() || latency > self.limit {
+            Outcome::Failure
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+/// Executes a given function within the circuit breaker, the result is judged by the
+/// `classifier` along with the measured latency.
+///
+/// The result is returned as is unless the call is rejected.
+pub fn call<POLICY, INSTRUMENT, C, F, E, R>(
+    state_machine: &StateMachine<POLICY, INSTRUMENT>,
+    classifier: C,
+    f: F,
+) -> Result<R, Error<E>>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    C: Classifier<R, E>,
+    F: FnOnce() -> Result<R, E>,
+{
+    state_machine.permit().map_err(Error::Rejected)?;
+
+    let started = clock::now();
+    let res = f();
+    record(state_machine, &classifier, &res, clock::now() - started);
+    res.map_err(Error::Inner)
+}
+
+/// Executes a given future within the circuit breaker, see `call`.
+///
+/// The latency is measured from the first poll.
+#[cfg(feature = "async")]
+pub async fn call_async<POLICY, INSTRUMENT, C, F, E, R>(
+    state_machine: &StateMachine<POLICY, INSTRUMENT>,
+    classifier: C,
+    f: F,
+) -> Result<R, Error<E>>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    C: Classifier<R, E>,
+    F: Future<Output = Result<R, E>>,
+{
+    state_machine.permit().map_err(Error::Rejected)?;
+
+    let started = clock::now();
+    let res = f.await;
+    record(state_machine, &classifier, &res, clock::now() - started);
+    res.map_err(Error::Inner)
+}
+
+fn record<POLICY, INSTRUMENT, C, E, R>(
+    state_machine: &StateMachine<POLICY, INSTRUMENT>,
+    classifier: &C,
+    res: &Result<R, E>,
+    latency: Duration,
+) where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    C: Classifier<R, E>,
+{
+    match classifier.classify(res, latency) {
+        Outcome::Success => state_machine.on_success_after(latency),
+        Outcome::Failure => state_machine.on_error_after(latency),
+        Outcome::Ignored => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{backoff, clock, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn slow_calls_are_failures() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(2, backoff);
+            let state_machine = Config::new().failure_policy(policy).build();
+            let slow = slower_than(Duration::from_millis(100));
+
+            let mut fetch = |latency| {
+                call(&state_machine, slow, || {
+                    time.advance(latency);
+                    Ok::<_, ()>(())
+                })
+            };
+
+            assert!(fetch(Duration::from_millis(50)).is_ok());
+            assert!(fetch(Duration::from_millis(150)).is_ok());
+            assert!(fetch(Duration::from_millis(150)).is_ok());
+            assert!(matches!(
+                fetch(Duration::from_millis(50)),
+                Err(Error::Rejected(_))
+            ));
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn call_async_with_closure() {
+        let state_machine = Config::new().build();
+        let ignore_errors = |res: &Result<(), ()>, _: Duration| match res {
+            Ok(_) => Outcome::Success,
+            Err(_) => Outcome::Ignored,
+        };
+
+        let res = futures::executor::block_on(call_async(
+            &state_machine,
+            ignore_errors,
+            futures::future::ready(Err(())),
+        ));
+        assert!(matches!(res, Err(Error::Inner(()))));
+        assert_eq!(0, state_machine.snapshot().failures);
+    }
+}
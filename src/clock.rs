@@ -1,24 +1,30 @@
 use std::cell::Cell;
+use std::thread;
 use std::time::{Duration, Instant};
 
 thread_local!(static CLOCK: Cell<Option<*const MockClock>> = const { Cell::new(None) });
 
 #[derive(Debug)]
-pub struct MockClock(Instant);
+pub struct MockClock(Cell<Instant>);
 
 impl MockClock {
     fn new() -> MockClock {
-        MockClock(Instant::now())
+        MockClock(Cell::new(Instant::now()))
     }
 
     #[inline]
     pub fn now(&self) -> Instant {
-        self.0
+        self.0.get()
     }
 
     #[inline]
     pub fn advance(&mut self, diff: Duration) {
-        self.0 += diff
+        self.pass(diff)
+    }
+
+    #[inline]
+    fn pass(&self, diff: Duration) {
+        self.0.set(self.0.get() + diff)
     }
 }
 
@@ -52,10 +58,59 @@ where
     })
 }
 
+/// Runs `f` with the current time of this thread set to `instant`, so outcomes replayed later
+/// are attributed to the time they happened.
+pub fn at<F, R>(instant: Instant, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    CLOCK.with(|cell| {
+        let clock = MockClock(Cell::new(instant));
+
+        // Restores the previous clock, which may be a frozen one in tests.
+        struct Restore<'a>(&'a Cell<Option<*const MockClock>>, Option<*const MockClock>);
+
+        impl<'a> Drop for Restore<'a> {
+            fn drop(&mut self) {
+                self.0.set(self.1);
+            }
+        }
+
+        let _restore = Restore(cell, cell.get());
+
+        cell.set(Some(&clock as *const MockClock));
+
+        f()
+    })
+}
+
 #[inline]
 pub fn now() -> Instant {
     CLOCK.with(|current| match current.get() {
         Some(ptr) => unsafe { (*ptr).now() },
-        None => Instant::now(),
+        None => system_now(),
+    })
+}
+
+/// Blocks the current thread for the `duration`, a frozen clock is advanced instead, so waiting
+/// calls complete at once in tests.
+pub fn sleep(duration: Duration) {
+    CLOCK.with(|current| match current.get() {
+        Some(ptr) => unsafe { (*ptr).pass(duration) },
+        None => thread::sleep(duration),
     })
 }
+
+/// Returns the current time of the tokio runtime, which follows `tokio::time::pause` and
+/// `tokio::time::advance`, or the system time outside of a paused runtime.
+#[cfg(feature = "tokio-time")]
+#[inline]
+fn system_now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+#[cfg(not(feature = "tokio-time"))]
+#[inline]
+fn system_now() -> Instant {
+    Instant::now()
+}
@@ -0,0 +1,333 @@
+//! Composite circuit breakers, which protect calls traversing multiple resources.
+
+use std::fmt::{self, Debug, Display};
+use std::time::{Duration, Instant};
+
+use super::circuit_breaker::CircuitBreaker;
+use super::clock;
+use super::error::{Error, Rejection};
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate::{FailureClassifier, FailurePredicate};
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// An object safe interface of a circuit breaker, which takes part in a composite one.
+pub trait Member {
+    /// Requests permission to call, see `StateMachine::is_call_permitted`.
+    fn is_call_permitted(&self) -> bool;
+
+    /// Blocks until a call is permitted or the `deadline` expires.
+    fn wait_permitted(&self, deadline: Instant) -> bool;
+
+    /// Records the latency of a completed call, see `StateMachine::on_latency`.
+    fn on_latency(&self, latency: Duration);
+
+    /// Records a successful call, see `StateMachine::on_success_after`.
+    fn on_success_after(&self, latency: Duration);
+
+    /// Records a failed call, see `StateMachine::on_error_after`.
+    fn on_error_after(&self, latency: Duration);
+
+    /// Records a failed call with an error of the given class, see `StateMachine::on_error_in`.
+    fn on_error_in(&self, class: &'static str);
+
+    /// Records a successful call of the given cost, see `StateMachine::on_success_weighted`.
+    fn on_success_weighted(&self, cost: u32);
+
+    /// Records a failed call of the given cost, see `StateMachine::on_error_weighted`.
+    fn on_error_weighted(&self, cost: u32);
+
+    /// Returns details of a call rejected by the member.
+    fn rejection(&self) -> Rejection;
+
+    /// Requests permission to call, the rejection carries the reason decided along with it.
+    fn permit(&self) -> Result<(), Rejection> {
+        if self.is_call_permitted() {
+            Ok(())
+        } else {
+            Err(self.rejection())
+        }
+    }
+
+    /// Blocks until a call is permitted or the `deadline` expires, see `permit`.
+    fn wait_permit(&self, deadline: Instant) -> Result<(), Rejection> {
+        if self.wait_permitted(deadline) {
+            Ok(())
+        } else {
+            Err(self.rejection())
+        }
+    }
+
+    /// Records a failed call which error is the `reason`, see `StateMachine::on_error_with`.
+    fn on_error_with(&self, reason: &dyn Display);
+}
+
+impl<POLICY, INSTRUMENT> Member for StateMachine<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    #[inline]
+    fn is_call_permitted(&self) -> bool {
+        StateMachine::is_call_permitted(self)
+    }
+
+    #[inline]
+    fn wait_permitted(&self, deadline: Instant) -> bool {
+        StateMachine::wait_permitted(self, deadline).is_ok()
+    }
+
+    #[inline]
+    fn on_latency(&self, latency: Duration) {
+        StateMachine::on_latency(self, latency)
+    }
+
+    #[inline]
+    fn on_success_after(&self, latency: Duration) {
+        StateMachine::on_success_after(self, latency)
+    }
+
+    #[inline]
+    fn on_error_after(&self, latency: Duration) {
+        StateMachine::on_error_after(self, latency)
+    }
+
+    #[inline]
+    fn on_error_in(&self, class: &'static str) {
+        StateMachine::on_error_in(self, class)
+    }
+
+    #[inline]
+    fn on_success_weighted(&self, cost: u32) {
+        StateMachine::on_success_weighted(self, cost)
+    }
+
+    #[inline]
+    fn on_error_weighted(&self, cost: u32) {
+        StateMachine::on_error_weighted(self, cost)
+    }
+
+    #[inline]
+    fn rejection(&self) -> Rejection {
+        StateMachine::current_rejection(self)
+    }
+
+    #[inline]
+    fn permit(&self) -> Result<(), Rejection> {
+        StateMachine::permit(self)
+    }
+
+    #[inline]
+    fn wait_permit(&self, deadline: Instant) -> Result<(), Rejection> {
+        StateMachine::wait_permitted(self, deadline)
+    }
+
+    #[inline]
+    fn on_error_with(&self, reason: &dyn Display) {
+        StateMachine::on_error_with(self, reason)
+    }
+}
+
+/// Creates a circuit breaker which permits a call only when every member permits it, and
+/// records the result into all of them.
+///
+/// Members are asked in order and asking stops at the first rejection, so a permission given
+/// by preceding members, e.g. a probe in the half open state, is left unused.
+///
+/// ```
+/// use failsafe::{composite, CircuitBreaker, Config};
+///
+/// let database = Config::new().build();
+/// let cache = Config::new().build();
+///
+/// let result = composite::all_of(&[&database, &cache]).call(|| Ok::<_, ()>(42));
+/// assert_eq!(42, result.unwrap());
+/// ```
+pub fn all_of<'a>(members: &'a [&'a dyn Member]) -> AllOf<'a> {
+    AllOf { members }
+}
+
+/// A circuit breaker composed of members, see `all_of`.
+#[derive(Clone, Copy)]
+pub struct AllOf<'a> {
+    members: &'a [&'a dyn Member],
+}
+
+impl<'a> AllOf<'a> {
+    /// Asks members in order, the rejection names the first member which refused.
+    fn permit<P, E>(&self, permit: P) -> Result<(), Error<E>>
+    where
+        P: Fn(&dyn Member) -> Result<(), Rejection>,
+    {
+        self.members
+            .iter()
+            .try_for_each(|member| permit(*member))
+            .map_err(Error::Rejected)
+    }
+
+    fn run<F, E, R, S, FL>(&self, f: F, on_success: S, on_failure: FL) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        S: Fn(&dyn Member, Duration),
+        FL: Fn(&dyn Member, &E, Duration),
+    {
+        let started = clock::now();
+        let res = f();
+        let latency = clock::now() - started;
+
+        match res {
+            Ok(ok) => {
+                for member in self.members {
+                    on_success(*member, latency);
+                }
+                Ok(ok)
+            }
+            Err(err) => {
+                for member in self.members {
+                    on_failure(*member, &err, latency);
+                }
+                Err(Error::Inner(err))
+            }
+        }
+    }
+}
+
+impl<'a> CircuitBreaker for AllOf<'a> {
+    fn is_call_permitted(&self) -> bool {
+        self.members.iter().all(|member| member.is_call_permitted())
+    }
+
+    fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit(|member| member.permit())?;
+
+        self.run(
+            f,
+            |member, latency| member.on_success_after(latency),
+            |member, err, latency| {
+                if predicate.is_err(err) {
+                    member.on_error_after(latency)
+                } else {
+                    member.on_success_after(latency)
+                }
+            },
+        )
+    }
+
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit(|member| member.permit())?;
+
+        self.run(
+            f,
+            |member, latency| member.on_success_after(latency),
+            |member, err, latency| match classifier.classify(err) {
+                Some(class) => {
+                    member.on_latency(latency);
+                    member.on_error_in(class)
+                }
+                None => member.on_success_after(latency),
+            },
+        )
+    }
+
+    fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit(|member| member.wait_permit(deadline))?;
+
+        self.run(
+            f,
+            |member, latency| member.on_success_after(latency),
+            |member, _, latency| member.on_error_after(latency),
+        )
+    }
+
+    fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.permit(|member| member.permit())?;
+
+        self.run(
+            f,
+            |member, latency| {
+                member.on_latency(latency);
+                member.on_success_weighted(cost);
+            },
+            |member, _, latency| {
+                member.on_latency(latency);
+                member.on_error_weighted(cost);
+            },
+        )
+    }
+
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        self.permit(|member| member.permit())?;
+
+        self.run(
+            f,
+            |member, latency| member.on_success_after(latency),
+            |member, err, latency| {
+                member.on_latency(latency);
+                member.on_error_with(err);
+            },
+        )
+    }
+}
+
+impl<'a> Debug for AllOf<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AllOf")
+            .field("members", &self.members.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn permit_when_all_permit() {
+        let new_circuit_breaker = |name| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            Config::new().failure_policy(policy).name(name).build()
+        };
+        let database = new_circuit_breaker("database");
+        let cache = new_circuit_breaker("cache");
+
+        all_of(&[&database, &cache])
+            .call(|| Ok::<_, ()>(()))
+            .unwrap();
+        assert_eq!(1, database.snapshot().successes);
+        assert_eq!(1, cache.snapshot().successes);
+
+        // The failure is recorded into every member.
+        match all_of(&[&database, &cache]).call(|| Err::<(), _>(())) {
+            Err(Error::Inner(())) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert!(!database.is_call_permitted());
+        assert!(!cache.is_call_permitted());
+
+        cache.reset();
+        match all_of(&[&cache, &database]).call(|| Ok::<_, ()>(())) {
+            Err(Error::Rejected(rejection)) => assert_eq!(Some("database"), rejection.name()),
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
@@ -1,31 +1,163 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use super::backoff;
-use super::failure_policy::{self, ConsecutiveFailures, FailurePolicy, SuccessRateOverTimeWindow};
-use super::instrument::Instrument;
-use super::state_machine::StateMachine;
+use super::classify::{self, SlowerThan};
+use super::failure_policy::{
+    self, ConsecutiveFailures, DefaultPolicy, FailurePolicy, SuccessRateOverTimeWindow,
+};
+use super::instrument::{Instrument, Transition};
+use super::state_machine::{
+    CallContext, Decision, Interceptor, ProbeSelection, Recovery, Schedule, Scheduled, Settings,
+    StateMachine, TransitionFn, TransitionHook,
+};
+
+/// Tunings for common kinds of dependencies, see `Config::preset`.
+///
+/// A preset tunes the failure policy and the recovery, its classifier is applied per call only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Preset {
+    /// A remote HTTP service: tolerates occasional errors, backs off for up to 2 minutes, and
+    /// admits probes of many callers gradually.
+    HttpClient,
+    /// A database: trips quickly on a few failures, backs off briefly, since connections are
+    /// restored fast, and ramps the load up after recovery, so warming caches aren't flooded.
+    Database,
+    /// A message broker: tolerates a high error rate of retried deliveries over a long window,
+    /// and backs off for up to 5 minutes.
+    MessageQueue,
+}
+
+impl Preset {
+    /// Returns a classifier which records errors and calls slower than typical for the kind of
+    /// dependency as failures, to use with `classify::call`.
+    ///
+    /// The limits are 10s for HTTP services, 2s for databases, and 30s for message brokers.
+    pub fn classifier(&self) -> SlowerThan {
+        let limit = match self {
+            Preset::HttpClient => Duration::from_secs(10),
+            Preset::Database => Duration::from_secs(2),
+            Preset::MessageQueue => Duration::from_secs(30),
+        };
+        classify::slower_than(limit)
+    }
+}
 
 /// A `CircuitBreaker`'s configuration.
-#[derive(Debug)]
+///
+/// It's `Clone` when the failure policy and the instrument are, so one configuration may serve
+/// as a template for many independent circuit breakers, see `build_fresh`.
+#[derive(Debug, Clone)]
 pub struct Config<POLICY, INSTRUMENT> {
     pub(crate) failure_policy: POLICY,
     pub(crate) instrument: INSTRUMENT,
+    pub(crate) settings: Settings,
 }
 
 impl Config<(), ()> {
     /// Creates a new circuit breaker's default configuration.
     #[allow(clippy::new_ret_no_self)]
-    pub fn new() -> Config<
-        failure_policy::OrElse<
-            SuccessRateOverTimeWindow<backoff::EqualJittered>,
-            ConsecutiveFailures<backoff::EqualJittered>,
-        >,
-        (),
-    > {
+    pub fn new() -> Config<DefaultPolicy, ()> {
         let failure_policy =
             SuccessRateOverTimeWindow::default().or_else(ConsecutiveFailures::default());
 
         Config {
             failure_policy,
             instrument: (),
+            settings: Settings::default(),
+        }
+    }
+}
+
+impl<INSTRUMENT> Config<DefaultPolicy, INSTRUMENT> {
+    /// Tunes thresholds of the default failure policy, keeping its default backoff.
+    ///
+    /// * `required_success_rate` - a success rate that must be met over the `window`,
+    ///   the default is `0.8`.
+    /// * `window` - window over which the success rate is tracked, the default is `30s`.
+    /// * `consecutive_failures` - number of consecutive failures, the default is `5`.
+    ///
+    /// # Panics
+    ///
+    /// When `required_success_rate` isn't in `[0.0, 1.0]` interval.
+    pub fn with_default_thresholds(
+        self,
+        required_success_rate: f64,
+        window: Duration,
+        consecutive_failures: u32,
+    ) -> Self {
+        let failure_policy = failure_policy::success_rate_over_time_window(
+            required_success_rate,
+            failure_policy::DEFAULT_MINIMUM_REQUEST_THRESHOLD,
+            window,
+            failure_policy::default_backoff(),
+        )
+        .or_else(failure_policy::consecutive_failures(
+            consecutive_failures,
+            failure_policy::default_backoff(),
+        ));
+
+        Config {
+            failure_policy,
+            instrument: self.instrument,
+            settings: self.settings,
+        }
+    }
+}
+
+impl<INSTRUMENT> Config<DefaultPolicy, INSTRUMENT> {
+    /// Tunes the default failure policy, its backoff and the recovery for a common kind of
+    /// dependency.
+    ///
+    /// The preset's classifier isn't applied by the circuit breaker: `CircuitBreaker::call`
+    /// records every error as a failure and every `Ok` as a success, however slow the call is.
+    /// Slow calls count as failures only when they are made by `classify::call` with
+    /// `Preset::classifier`, as below, and errors are filtered per call, e.g. by
+    /// `sqlx::Infrastructure`.
+    ///
+    /// | preset         | success rate | window | consecutive failures | backoff     |
+    /// |----------------|--------------|--------|----------------------|-------------|
+    /// | `HttpClient`   | 0.8          | 30s    | 5                    | 10s to 2m   |
+    /// | `Database`     | 0.9          | 10s    | 3                    | 2s to 30s   |
+    /// | `MessageQueue` | 0.5          | 60s    | 10                   | 5s to 5m    |
+    ///
+    /// ```
+    /// use failsafe::{classify, Config, Preset};
+    ///
+    /// let circuit_breaker = Config::new().preset(Preset::Database).name("users-db").build();
+    /// let result = classify::call(&circuit_breaker, Preset::Database.classifier(), || {
+    ///     Ok::<_, ()>("row")
+    /// });
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn preset(self, preset: Preset) -> Self {
+        let (required_success_rate, window, consecutive_failures, start, max) = match preset {
+            Preset::HttpClient => (0.8, 30, 5, 10, 120),
+            Preset::Database => (0.9, 10, 3, 2, 30),
+            Preset::MessageQueue => (0.5, 60, 10, 5, 300),
+        };
+        let backoff = backoff::equal_jittered(Duration::from_secs(start), Duration::from_secs(max));
+        let failure_policy = failure_policy::success_rate_over_time_window(
+            required_success_rate,
+            failure_policy::DEFAULT_MINIMUM_REQUEST_THRESHOLD,
+            Duration::from_secs(window),
+            backoff.clone(),
+        )
+        .or_else(failure_policy::consecutive_failures(
+            consecutive_failures,
+            backoff,
+        ));
+
+        let config = Config {
+            failure_policy,
+            instrument: self.instrument,
+            settings: self.settings,
+        };
+        match preset {
+            Preset::HttpClient => config.half_open_jitter(Duration::from_secs(1)),
+            Preset::Database => config.gradual_recovery(&[0.2, 0.5], Duration::from_secs(5)),
+            Preset::MessageQueue => config,
         }
     }
 }
@@ -39,6 +171,7 @@ impl<POLICY, INSTRUMENT> Config<POLICY, INSTRUMENT> {
         Config {
             failure_policy,
             instrument: self.instrument,
+            settings: self.settings,
         }
     }
 
@@ -50,7 +183,281 @@ impl<POLICY, INSTRUMENT> Config<POLICY, INSTRUMENT> {
         Config {
             failure_policy: self.failure_policy,
             instrument,
+            settings: self.settings,
+        }
+    }
+
+    /// Names the circuit breaker.
+    ///
+    /// The name is a part of rejection errors and the debug output, so logs from shared call
+    /// paths identify which dependency rejected the call.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.settings.name = Some(Arc::from(name.into()));
+        self
+    }
+
+    /// Spreads the transition to the half open state over the `jitter` interval.
+    ///
+    /// When many threads see the open state's deadline expire simultaneously, they all probe the
+    /// recovering backend at once. With a jitter the open state is extended by a random part of
+    /// the interval, and after the transition the callers are admitted gradually: the chance to
+    /// pass grows from 0% to 100% during the interval.
+    pub fn half_open_jitter(mut self, jitter: Duration) -> Self {
+        self.settings.half_open_jitter = Some(jitter);
+        self
+    }
+
+    /// Falls back to the open state when no probe arrives within the `timeout` after the
+    /// transition to the half open state.
+    ///
+    /// An idle service sends no probes, and the circuit breaker would stay half open
+    /// indefinitely. With a timeout it opens again with the previous delay, counted from the
+    /// moment the timeout expired. A probe which never reports its result, e.g. when its future
+    /// is dropped, doesn't keep it half open either, so the timeout should be longer than calls
+    /// take. The timeout is checked when calls request the permission, and when the state is
+    /// observed by `StateMachine::state` and `StateMachine::snapshot`.
+    pub fn half_open_timeout(mut self, timeout: Duration) -> Self {
+        self.settings.half_open_timeout = Some(timeout);
+        self
+    }
+
+    /// Enables the gradual recovery after a successful probe in the half open state.
+    ///
+    /// Instead of snapping fully closed, the circuit breaker admits only a share of calls given
+    /// by `steps`, each step lasts for `step_duration`. When all steps have passed, the circuit
+    /// breaker admits all calls. For example, `&[0.1, 0.5]` admits 10%, then 50% and then 100% of
+    /// calls. A failure during the recovery is handled by the failure policy as usual.
+    ///
+    /// # Panics
+    ///
+    /// When any of `steps` isn't in `[0.0, 1.0]` interval.
+    pub fn gradual_recovery(mut self, steps: &[f64], step_duration: Duration) -> Self {
+        for step in steps {
+            assert!(
+                (0.0..=1.0).contains(step),
+                "recovery step must be [0, 1]: {}",
+                step
+            );
+        }
+
+        self.settings.recovery = Some(Recovery {
+            steps: steps.to_vec(),
+            step_duration,
+        });
+        self
+    }
+
+    /// Permits a share of calls while the circuit breaker is open ("canary traffic").
+    ///
+    /// The circuit breaker continuously samples the backend health instead of waiting for the
+    /// full backoff: a successful canary call closes the circuit breaker (or starts the gradual
+    /// recovery), a failed one keeps it open.
+    ///
+    /// Only outcomes of measured calls started after the circuit breaker has opened count as
+    /// canaries, so ones made by `call`, or recorded by `on_success_after` and `on_error_after`.
+    /// Calls admitted before the opening, which complete later, don't affect it.
+    ///
+    /// # Panics
+    ///
+    /// When `ratio` isn't in `[0.0, 1.0]` interval.
+    pub fn canary_traffic(mut self, ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "canary ratio must be [0, 1]: {}",
+            ratio
+        );
+
+        self.settings.canary_ratio = Some(ratio);
+        self
+    }
+
+    /// Records successes in the closed state in batches of `rate`, while failures are recorded
+    /// at once.
+    ///
+    /// It cuts the lock traffic of very hot circuit breakers, as a success only counts up an
+    /// atomic until the batch is full. Pending successes are recorded before the next failure
+    /// as of the moment of the last one, so the failure policy sees every success in the same
+    /// order, e.g. they end streaks of failures, and rate based policies see the same success
+    /// rate. Counters of the snapshot and the instrument see every success at once.
+    ///
+    /// # Panics
+    ///
+    /// When `rate` is zero.
+    pub fn success_sampling(mut self, rate: u32) -> Self {
+        assert!(rate > 0, "success sampling rate must be positive");
+
+        self.settings.success_sampling = Some(rate);
+        self
+    }
+
+    /// Defines which callers probe the backend in the half open state, see `ProbeSelection`.
+    ///
+    /// # Panics
+    ///
+    /// When the ratio of `ProbeSelection::Sampled` isn't in `[0.0, 1.0]` interval.
+    pub fn probe_selection(mut self, selection: ProbeSelection) -> Self {
+        if let ProbeSelection::Sampled(ratio) = selection {
+            assert!(
+                (0.0..=1.0).contains(&ratio),
+                "probe ratio must be [0, 1]: {}",
+                ratio
+            );
         }
+
+        self.settings.probe_selection = selection;
+        self
+    }
+
+    /// Sets a callback which returns the active scheduled window, e.g. planned maintenance of
+    /// the backend, during which the circuit breaker is forced open or into the shadow mode.
+    ///
+    /// It's called on every request for permission, so it should be cheap. Scheduled windows
+    /// don't change the state of the circuit breaker, only which calls are permitted.
+    ///
+    /// ```
+    /// use std::time::{SystemTime, UNIX_EPOCH};
+    /// use failsafe::{Config, Scheduled};
+    ///
+    /// // The backend is under maintenance from 2:00 to 3:00 UTC every day.
+    /// let circuit_breaker = Config::new()
+    ///     .schedule(|| {
+    ///         let secs = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    ///         let hour = secs % 86_400 / 3_600;
+    ///         if hour == 2 {
+    ///             Some(Scheduled::ForcedOpen)
+    ///         } else {
+    ///             None
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn schedule<F>(mut self, schedule: F) -> Self
+    where
+        F: Fn() -> Option<Scheduled> + Send + Sync + 'static,
+    {
+        self.settings.schedule = Some(Schedule(Arc::new(schedule)));
+        self
+    }
+
+    /// Lets `interceptor` override decisions for calls, e.g. to inject failures in tests or by
+    /// a chaos tool, without changing call sites.
+    ///
+    /// The interceptor is asked when a call requests the permission, where `Decision::Permit`
+    /// or `Decision::Reject` overrides the state, and when the outcome of a completed call is
+    /// recorded, where `Decision::Record` replaces the outcome. `None` keeps the decision of the
+    /// circuit breaker. Calls in the closed state take the lock when an interceptor is set.
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    /// use failsafe::{CallContext, CircuitBreaker, Config, Decision, Error};
+    ///
+    /// let outage = Arc::new(AtomicBool::new(false));
+    /// let chaos = outage.clone();
+    /// let circuit_breaker = Config::new()
+    ///     .name("redis")
+    ///     .interceptor(move |call: &CallContext| {
+    ///         if chaos.load(Ordering::Relaxed) && call.name == Some("redis") {
+    ///             Some(Decision::Reject)
+    ///         } else {
+    ///             None
+    ///         }
+    ///     })
+    ///     .build();
+    ///
+    /// outage.store(true, Ordering::Relaxed);
+    /// let result = circuit_breaker.call(|| Ok::<_, ()>("pong"));
+    /// assert!(matches!(result, Err(Error::Rejected(_))));
+    /// ```
+    pub fn interceptor<F>(mut self, interceptor: F) -> Self
+    where
+        F: Fn(&CallContext) -> Option<Decision> + Send + Sync + 'static,
+    {
+        self.settings.interceptor = Some(Interceptor(Arc::new(interceptor)));
+        self
+    }
+
+    /// Keeps user traffic away from the backend until a health check passes, instead of using
+    /// calls as probes in the half open state.
+    ///
+    /// The circuit breaker stays open once the delay has expired, and only
+    /// `StateMachine::on_health_check` closes it. With the `async` feature, `futures::health_check`
+    /// runs a user supplied check on schedule.
+    pub fn background_probing(mut self) -> Self {
+        self.settings.background_probing = true;
+        self
+    }
+
+    /// Keeps the reason of the failure which caused the most recent open transition, see
+    /// `Snapshot::last_error`.
+    ///
+    /// The reason is known for calls made by `CircuitBreaker::call_reported` or
+    /// `call_classified`, otherwise the last error is cleared.
+    pub fn capture_last_error(mut self) -> Self {
+        self.settings.capture_last_error = true;
+        self
+    }
+
+    /// Keeps counters of at most `max` labels, see `StateMachine::labeled`.
+    ///
+    /// Calls of other labels are counted under the `"other"` label, which takes one more
+    /// entry, so labels taken from requests, e.g. tenants, don't grow the memory unboundedly.
+    pub fn max_labels(mut self, max: usize) -> Self {
+        self.settings.max_labels = Some(max);
+        self
+    }
+
+    /// Measures the time spent in the instrument and the transition callback, see
+    /// `Snapshot::instrument_overhead`.
+    ///
+    /// Callbacks run on the path of every call, so a slow instrument, e.g. a blocking logger or
+    /// a network exporter, adds its latency to calls. Measuring takes two readings of the clock
+    /// per notification.
+    ///
+    /// ```
+    /// use failsafe::Config;
+    ///
+    /// let circuit_breaker = Config::new().measure_instrument().build();
+    /// circuit_breaker.on_success();
+    ///
+    /// let overhead = circuit_breaker.snapshot().instrument_overhead.unwrap();
+    /// assert_eq!(1, overhead.calls);
+    /// ```
+    pub fn measure_instrument(mut self) -> Self {
+        self.settings.measure_instrument = true;
+        self
+    }
+
+    /// Calls `f` on every transition, along with the instrument.
+    ///
+    /// It's a shortcut for the common case of reacting to state changes, without implementing
+    /// `Instrument`. Circuit breakers built from clones of the configuration share the callback,
+    /// and it isn't serialized: transitions of different circuit breakers, or concurrent ones of
+    /// the same circuit breaker, call it from several threads at once, so state it keeps needs
+    /// its own synchronization.
+    ///
+    /// It's called without locks of the circuit breaker, so it may act on any circuit breaker,
+    /// e.g. force open a sibling. Acting on the same circuit breaker, e.g. recording a call,
+    /// may cause a nested transition, which calls `f` again before the outer call returns.
+    ///
+    /// ```
+    /// use failsafe::{instrument::State, Config};
+    ///
+    /// let circuit_breaker = Config::new()
+    ///     .on_transition(|transition| {
+    ///         if transition.to == State::Open {
+    ///             eprintln!("circuit breaker is open: {:?}", transition.cause);
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn on_transition<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Transition) + Send + Sync + 'static,
+    {
+        let hook: Arc<TransitionFn> = Arc::new(f);
+        self.settings.transition_hook = Some(TransitionHook(hook));
+        self
     }
 
     /// Builds a new circuit breaker instance.
@@ -59,6 +466,236 @@ impl<POLICY, INSTRUMENT> Config<POLICY, INSTRUMENT> {
         POLICY: FailurePolicy,
         INSTRUMENT: Instrument,
     {
-        StateMachine::new(self.failure_policy, self.instrument)
+        StateMachine::with_settings(self.failure_policy, self.instrument, self.settings)
+    }
+
+    /// Builds a new circuit breaker instance from a copy of the configuration.
+    ///
+    /// Each circuit breaker gets its own clone of the failure policy, so they don't share any
+    /// history, e.g. a circuit breaker per endpoint or tenant.
+    pub fn build_fresh(&self) -> StateMachine<POLICY, INSTRUMENT>
+    where
+        POLICY: FailurePolicy + Clone,
+        INSTRUMENT: Instrument + Clone,
+    {
+        self.clone().build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backoff, clock};
+
+    #[test]
+    fn with_default_thresholds() {
+        clock::freeze(|_| {
+            let state_machine = Config::new()
+                .with_default_thresholds(0.5, Duration::from_secs(10), 2)
+                .build();
+
+            state_machine.on_error();
+            assert!(state_machine.is_call_permitted());
+            state_machine.on_error();
+            assert!(!state_machine.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn build_fresh() {
+        clock::freeze(|_| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let template = Config::new().failure_policy(policy);
+
+            let first = template.build_fresh();
+            let second = template.build_fresh();
+
+            first.on_error();
+            assert!(!first.is_call_permitted());
+            assert!(second.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn success_sampling() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        /// Counts successes, and ones seen before the last failure.
+        #[derive(Debug, Default)]
+        struct Recorded(Arc<(AtomicU32, AtomicU32)>);
+
+        impl FailurePolicy for Recorded {
+            fn record_success(&mut self) {
+                self.0 .0.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+                let successes = self.0 .0.load(Ordering::Relaxed);
+                self.0 .1.store(successes, Ordering::Relaxed);
+                None
+            }
+
+            fn revived(&mut self) {}
+        }
+
+        let recorded = Arc::default();
+        let state_machine = Config::new()
+            .failure_policy(Recorded(Arc::clone(&recorded)))
+            .success_sampling(16)
+            .build();
+        let successes = || recorded.0.load(Ordering::Relaxed);
+
+        for _ in 0..1605 {
+            state_machine.on_success();
+        }
+        assert_eq!(1600, successes());
+        assert_eq!(1605, state_machine.snapshot().successes);
+
+        // Pending successes are recorded before a failure.
+        state_machine.on_error();
+        assert_eq!(1605, recorded.1.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn success_sampling_keeps_healthy_breakers_closed() {
+        clock::freeze(|time| {
+            for sampling in &[None, Some(16)] {
+                let config = Config::new();
+                let config = match sampling {
+                    Some(rate) => config.success_sampling(*rate),
+                    None => config,
+                };
+                let circuit_breaker = config.build();
+
+                // A backend at 90% of successes.
+                for i in 0..20_000 {
+                    time.advance(Duration::from_millis(10));
+                    assert!(circuit_breaker.is_call_permitted(), "{:?}", sampling);
+                    if i % 10 == 9 {
+                        circuit_breaker.on_error();
+                    } else {
+                        circuit_breaker.on_success();
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn interceptor() {
+        use crate::{CircuitBreaker, Error, Outcome};
+
+        clock::freeze(|_| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .interceptor(|call: &CallContext| match call.outcome {
+                    // Every successful call is recorded as failure.
+                    Some(Outcome::Success) => Some(Decision::Record(Outcome::Failure)),
+                    // Calls are let through the open circuit breaker.
+                    None if call.state == crate::State::Open => Some(Decision::Permit),
+                    _ => None,
+                })
+                .build();
+
+            assert!(circuit_breaker.call(|| Ok::<_, ()>(())).is_ok());
+            let snapshot = circuit_breaker.snapshot();
+            assert_eq!((0, 1), (snapshot.successes, snapshot.failures));
+            assert_eq!(crate::State::Open, snapshot.state);
+
+            assert!(!matches!(
+                circuit_breaker.call(|| Err::<(), _>(())),
+                Err(Error::Rejected(_))
+            ));
+            assert_eq!(0, circuit_breaker.snapshot().rejections);
+        });
+    }
+
+    #[test]
+    fn presets() {
+        clock::freeze(|_| {
+            let http = Config::new().preset(Preset::HttpClient).build();
+            for _ in 0..4 {
+                http.on_error();
+            }
+            assert!(http.is_call_permitted());
+            http.on_error();
+            assert!(!http.is_call_permitted());
+
+            let database = Config::new().preset(Preset::Database).build();
+            for _ in 0..2 {
+                database.on_error();
+            }
+            assert!(database.is_call_permitted());
+            database.on_error();
+            assert!(!database.is_call_permitted());
+
+            let queue = Config::new().preset(Preset::MessageQueue).build();
+            for _ in 0..9 {
+                queue.on_error();
+            }
+            assert!(queue.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn on_transition() {
+        use std::sync::Mutex;
+
+        use crate::instrument::State;
+
+        clock::freeze(|time| {
+            let states = Arc::new(Mutex::new(Vec::new()));
+            let sink = states.clone();
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let state_machine = Config::new()
+                .failure_policy(policy)
+                .on_transition(move |transition| {
+                    let mut states = sink.lock().unwrap();
+                    states.push((transition.to, transition.tripped_by));
+                })
+                .build();
+
+            state_machine.on_error();
+            time.advance(Duration::from_secs(10));
+            assert!(state_machine.is_call_permitted());
+            state_machine.on_success();
+
+            assert_eq!(
+                vec![
+                    (State::Open, Some("ConsecutiveFailures")),
+                    (State::HalfOpen, None),
+                    (State::Closed, None)
+                ],
+                *states.lock().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn on_transition_acts_on_siblings() {
+        use std::sync::Mutex;
+
+        use crate::instrument::State;
+
+        let sibling = Arc::new(Mutex::new(None::<StateMachine<DefaultPolicy, ()>>));
+        let slot = sibling.clone();
+        let template = Config::new().on_transition(move |transition| {
+            let sibling = slot.lock().unwrap().clone();
+            match sibling {
+                // Opening the sibling calls the shared callback again.
+                Some(sibling) if transition.to == State::Open => sibling.force_open(),
+                _ => {}
+            }
+        });
+        let first = template.clone().build();
+        *sibling.lock().unwrap() = Some(template.build());
+
+        first.force_open();
+        let sibling = sibling.lock().unwrap().clone().unwrap();
+        assert_eq!(State::Open, sibling.state());
     }
 }
@@ -2,7 +2,7 @@
 /// given window on a user-defined clock.
 ///
 /// Ema requires monotonic timestamps
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Ema {
     window: u64,
     timestamp: u64,
@@ -22,12 +22,15 @@ impl Ema {
     }
 
     /// `true` if `Ema` contains no values.
-    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.timestamp == 0
     }
 
-    /// Updates the average with observed value. and return the new average.
+    /// Updates the average with observed value of the given `weight` and return the new
+    /// average.
+    ///
+    /// It counts as `weight` observations of the value, each one after the same time as
+    /// passed since the last observation, so the step of the average is `1 - w^weight`.
     ///
     /// Since `update` requires monotonic timestamps. it is up to the caller to
     /// ensure that calls to update do not race.
@@ -35,7 +38,7 @@ impl Ema {
     /// # Panics
     ///
     /// When timestamp isn't monotonic.
-    pub fn update(&mut self, timestamp: u64, value: f64) -> f64 {
+    pub fn update(&mut self, timestamp: u64, value: f64, weight: u32) -> f64 {
         if self.timestamp == 0 {
             self.timestamp = timestamp;
             self.ema = value;
@@ -50,7 +53,7 @@ impl Ema {
             let w = if self.window == 0 {
                 0_f64
             } else {
-                (-(time_diff as f64) / self.window as f64).exp()
+                (-(time_diff as f64) * f64::from(weight) / self.window as f64).exp()
             };
 
             self.ema = value * (1_f64 - w) + self.ema * w;
@@ -60,11 +63,21 @@ impl Ema {
     }
 
     /// Returns the last observation.
-    #[allow(dead_code)]
     pub fn last(&self) -> f64 {
         self.ema
     }
 
+    /// Returns the timestamp of the last observation.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Restores the average `ema` observed at the `timestamp`, e.g. saved by another process.
+    pub fn restore(&mut self, timestamp: u64, ema: f64) {
+        self.timestamp = timestamp;
+        self.ema = ema;
+    }
+
     /// Resets the average to 0 and erase all observations.
     pub fn reset(&mut self) {
         self.timestamp = 0;
@@ -97,7 +110,7 @@ mod tests {
             .iter()
             .enumerate()
             .map(|(i, x)| ((i + 1) as u64, x))
-            .map(|(i, x)| round_to(ema.update(i, *x), 2))
+            .map(|(i, x)| round_to(ema.update(i, *x, 1), 2))
             .collect::<Vec<_>>();
 
         assert_eq!(EMA_10_OVER_DAYS.to_vec(), result);
@@ -107,8 +120,8 @@ mod tests {
     #[should_panic]
     fn non_monotonic_timestamp() {
         let mut ema = Ema::new(10);
-        ema.update(10, 10.0);
-        ema.update(1, 10.0);
+        ema.update(10, 10.0, 1);
+        ema.update(1, 10.0, 1);
     }
 
     #[test]
@@ -116,33 +129,49 @@ mod tests {
         let mut a = Ema::new(1000);
         let mut b = Ema::new(1000);
 
-        assert_eq!(10.0, a.update(10, 10.0));
-        assert_eq!(10.0, a.update(20, 10.0));
-        assert_eq!(10.0, a.update(30, 10.0));
+        assert_eq!(10.0, a.update(10, 10.0, 1));
+        assert_eq!(10.0, a.update(20, 10.0, 1));
+        assert_eq!(10.0, a.update(30, 10.0, 1));
 
-        assert_eq!(10.0, b.update(10, 10.0));
-        assert_eq!(10.0, b.update(30, 10.0));
+        assert_eq!(10.0, b.update(10, 10.0, 1));
+        assert_eq!(10.0, b.update(30, 10.0, 1));
 
-        assert_eq!(a.update(40, 5.0), b.update(40, 5.0));
-        assert!(a.update(50, 5.0) > b.update(60, 5.0));
+        assert_eq!(a.update(40, 5.0, 1), b.update(40, 5.0, 1));
+        assert!(a.update(50, 5.0, 1) > b.update(60, 5.0, 1));
 
         assert_eq!(
-            round_to(a.update(60, 5.0), 4),
-            round_to(b.update(60, 5.0), 4)
+            round_to(a.update(60, 5.0, 1), 4),
+            round_to(b.update(60, 5.0, 1), 4)
         );
     }
 
+    #[test]
+    fn weighted_updates() {
+        let mut a = Ema::new(1000);
+        let mut b = Ema::new(1000);
+
+        a.update(10, 10.0, 1);
+        b.update(10, 10.0, 1);
+
+        // A weighted observation counts as several equally spaced ones.
+        a.update(20, 5.0, 1);
+        a.update(30, 5.0, 1);
+        a.update(40, 5.0, 1);
+        b.update(20, 5.0, 3);
+        assert_eq!(round_to(a.last(), 4), round_to(b.last(), 4));
+    }
+
     #[test]
     fn reset() {
         let mut ema = Ema::new(5);
 
-        assert_eq!(3.0, ema.update(1, 3.0));
+        assert_eq!(3.0, ema.update(1, 3.0, 1));
 
         ema.reset();
 
         assert!(ema.is_empty());
         assert_eq!(0.0, ema.last());
-        assert_eq!(5.0, ema.update(2, 5.0));
+        assert_eq!(5.0, ema.update(2, 5.0, 1));
     }
 
     fn round_to(x: f64, power: i32) -> f64 {
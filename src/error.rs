@@ -1,5 +1,7 @@
 use std::error::Error as StdError;
 use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// A `CircuitBreaker`'s error.
 #[derive(Debug)]
@@ -7,16 +9,217 @@ pub enum Error<E> {
     /// An error from inner call.
     Inner(E),
     /// An error when call was rejected.
-    Rejected,
+    Rejected(Rejection),
 }
 
+/// The result of a call within a circuit breaker.
+pub type CallResult<R, E> = Result<R, Error<E>>;
+
+impl<E> Error<E> {
+    /// Converts the error into an application error, which is convertible from both the inner
+    /// error and the rejection.
+    ///
+    /// It lets applications with their own error types use `?` with `map_err`, see also
+    /// `impl_from_error!`.
+    ///
+    /// ```
+    /// use failsafe::{CircuitBreaker, Config, Error, Rejection};
+    ///
+    /// #[derive(Debug)]
+    /// enum AppError {
+    ///     Parse(std::num::ParseIntError),
+    ///     Unavailable(Rejection),
+    /// }
+    /// # impl From<std::num::ParseIntError> for AppError {
+    /// #     fn from(err: std::num::ParseIntError) -> Self { AppError::Parse(err) }
+    /// # }
+    /// # impl From<Rejection> for AppError {
+    /// #     fn from(rejection: Rejection) -> Self { AppError::Unavailable(rejection) }
+    /// # }
+    ///
+    /// fn parse(circuit_breaker: &impl CircuitBreaker) -> Result<u32, AppError> {
+    ///     let n = circuit_breaker
+    ///         .call(|| "42".parse::<u32>())
+    ///         .map_err(Error::flatten::<AppError>)?;
+    ///     Ok(n)
+    /// }
+    ///
+    /// assert_eq!(42, parse(&Config::new().build()).unwrap());
+    /// ```
+    pub fn flatten<T>(self) -> T
+    where
+        T: From<E> + From<Rejection>,
+    {
+        match self {
+            Error::Inner(err) => T::from(err),
+            Error::Rejected(rejection) => T::from(rejection),
+        }
+    }
+}
+
+/// Implements `From<failsafe::Error<INNER>>` for an application error type, which is
+/// convertible from both `INNER` and `failsafe::Rejection`, so `?` works on results of calls.
+///
+/// ```
+/// use failsafe::{impl_from_error, CircuitBreaker, Config, Rejection};
+///
+/// #[derive(Debug)]
+/// enum AppError {
+///     Io(std::io::Error),
+///     Unavailable(Rejection),
+/// }
+///
+/// impl From<std::io::Error> for AppError {
+///     fn from(err: std::io::Error) -> Self {
+///         AppError::Io(err)
+///     }
+/// }
+///
+/// impl From<Rejection> for AppError {
+///     fn from(rejection: Rejection) -> Self {
+///         AppError::Unavailable(rejection)
+///     }
+/// }
+///
+/// impl_from_error!(AppError, std::io::Error);
+///
+/// fn read(circuit_breaker: &impl CircuitBreaker) -> Result<String, AppError> {
+///     Ok(circuit_breaker.call(|| std::fs::read_to_string("/nonexistent"))?)
+/// }
+///
+/// assert!(matches!(read(&Config::new().build()), Err(AppError::Io(_))));
+/// ```
+#[macro_export]
+macro_rules! impl_from_error {
+    ($app:ty, $inner:ty) => {
+        impl ::std::convert::From<$crate::Error<$inner>> for $app {
+            fn from(err: $crate::Error<$inner>) -> Self {
+                err.flatten()
+            }
+        }
+    };
+}
+
+/// Why a call was rejected, see `Rejection::reason`.
+///
+/// New reasons may be added along with new kinds of limits, so matches need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// The circuit breaker is open, calls are rejected until its delay expires.
+    Open,
+    /// The circuit breaker is half open or recovering, and admits only some calls, the others
+    /// are rejected while the probes are in flight.
+    ProbesExhausted,
+    /// The concurrency limit of `adaptive::Limiter` is reached.
+    Limited,
+    /// The call has waited for admission too long, and `adaptive::CoDel` shed it.
+    Shed,
+    /// The quota of `governor::Governor` is exhausted, `Rejection::retry_after` tells when it
+    /// allows the next call.
+    RateLimited,
+    /// No endpoint of `balance::Picker` permits calls.
+    NoEndpoint,
+}
+
+impl Default for RejectionReason {
+    fn default() -> Self {
+        RejectionReason::Open
+    }
+}
+
+/// Details of a rejected call.
+///
+/// It keeps the name of the circuit breaker which rejected the call, if the circuit breaker
+/// has one, so logs from shared call paths identify the dependency, see `Config::name`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rejection {
+    name: Option<Arc<str>>,
+    retry_after: Option<Duration>,
+    reason: RejectionReason,
+}
+
+impl Rejection {
+    pub(crate) fn new(name: Option<Arc<str>>) -> Self {
+        Rejection {
+            name,
+            retry_after: None,
+            reason: RejectionReason::Open,
+        }
+    }
+
+    pub(crate) fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    pub(crate) fn with_reason(mut self, reason: RejectionReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// Returns the name of the circuit breaker which rejected the call.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns how long until the circuit breaker permits a probe call, so a retry policy
+    /// waits exactly that long rather than sleeping blindly and retrying into an open circuit
+    /// breaker.
+    ///
+    /// It's `None` when the time is unknown, e.g. the circuit breaker is forced open or the
+    /// rejection came from a half open circuit breaker which admits only some calls.
+    ///
+    /// ```
+    /// use std::{thread, time::Duration};
+    /// use failsafe::{CircuitBreaker, Config, Error};
+    ///
+    /// let circuit_breaker = Config::new().build();
+    ///
+    /// for _ in 0..3 {
+    ///     match circuit_breaker.call(|| Ok::<_, ()>(42)) {
+    ///         Err(Error::Rejected(rejection)) => match rejection.retry_after() {
+    ///             // The delay is too long to wait for, give up.
+    ///             Some(wait) if wait > Duration::from_secs(1) => break,
+    ///             Some(wait) => thread::sleep(wait),
+    ///             None => thread::sleep(Duration::from_millis(100)),
+    ///         },
+    ///         Err(Error::Inner(_)) => thread::sleep(Duration::from_millis(100)),
+    ///         Ok(_) => break,
+    ///     }
+    /// }
+    /// ```
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Returns why the call was rejected.
+    ///
+    /// A call rejected by a half open circuit breaker may be retried shortly, once the probes
+    /// complete, while an open one calls for backing off until `retry_after`.
+    pub fn reason(&self) -> RejectionReason {
+        self.reason
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "call was rejected by circuit breaker `{}`", name),
+            None => write!(f, "call was rejected"),
+        }
+    }
+}
+
+impl StdError for Rejection {}
+
 impl<E> Display for Error<E>
 where
     E: Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Rejected => write!(f, "call was rejected"),
+            Error::Rejected(rejection) => write!(f, "{}", rejection),
             Error::Inner(err) => write!(f, "{}", err),
         }
     }
@@ -33,3 +236,55 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum AppError {
+        Inner(u32),
+        Rejected(Option<String>),
+    }
+
+    impl From<u32> for AppError {
+        fn from(err: u32) -> Self {
+            AppError::Inner(err)
+        }
+    }
+
+    impl From<Rejection> for AppError {
+        fn from(rejection: Rejection) -> Self {
+            AppError::Rejected(rejection.name().map(str::to_owned))
+        }
+    }
+
+    impl_from_error!(AppError, u32);
+
+    #[test]
+    fn into_app_error() {
+        fn call(res: CallResult<(), u32>) -> Result<(), AppError> {
+            res?;
+            Ok(())
+        }
+
+        assert_eq!(Err(AppError::Inner(7)), call(Err(Error::Inner(7))));
+        let rejection = Rejection::new(Some(Arc::from("redis")));
+        assert_eq!(
+            Err(AppError::Rejected(Some("redis".to_owned()))),
+            call(Err(Error::Rejected(rejection)))
+        );
+    }
+
+    #[test]
+    fn display_name() {
+        let anonymous = Error::<String>::Rejected(Rejection::default());
+        assert_eq!("call was rejected", anonymous.to_string());
+
+        let named = Error::<String>::Rejected(Rejection::new(Some(Arc::from("redis"))));
+        assert_eq!(
+            "call was rejected by circuit breaker `redis`",
+            named.to_string()
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use std::mem;
+use std::time::{Duration, Instant};
+
+use super::super::backoff::{self, WithFeedback};
+use super::super::clock;
+use super::super::windowed_adder::WindowedAdder;
+use super::{
+    default_backoff, BackoffReset, BackoffState, Cadence, FailurePolicy,
+    DEFAULT_MINIMUM_REQUEST_THRESHOLD, MILLIS_PER_SECOND,
+};
+
+const DEFAULT_SENSITIVITY: f64 = 3.0;
+const DEFAULT_MIN_DEVIATION: f64 = 0.05;
+const DEFAULT_LEARNING_RATE: f64 = 0.3;
+const DEFAULT_WINDOW_SECONDS: u64 = 30;
+
+/// Returns a policy which learns a seasonal baseline of the failure rate and trips on a
+/// statistically significant deviation from it.
+///
+/// The `period` (e.g. a day) is split into `slots` (e.g. 24 hours). For each slot the policy
+/// maintains an exponentially weighted mean and variance of the failure rate observed in this
+/// slot during previous periods. The current failure rate, computed over a sliding window, is
+/// compared with the baseline of the current slot, and if it exceeds the mean by more than
+/// `sensitivity` standard deviations, `mark_dead_on_failure` will return `Some(Duration)`.
+///
+/// The policy never trips until the baseline for the current slot is learned, so it's usually
+/// combined with a simpler policy via `or_else`. A slot in which the policy has tripped isn't
+/// learned, so an outage doesn't become the new normal.
+///
+/// Only the failure rate is modelled, latencies are ignored, slow calls may be limited with
+/// `latency_budget`.
+///
+/// * `period` - the length of a season.
+/// * `slots` - the number of slots the season is split into.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `mark_dead_on_failure`
+///
+/// # Panics
+///
+/// When `slots` is zero.
+pub fn seasonal_anomaly<BACKOFF>(
+    period: Duration,
+    slots: u32,
+    backoff: BACKOFF,
+) -> SeasonalAnomaly<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    assert!(slots > 0, "slots must be > 0");
+
+    let window = Duration::from_secs(DEFAULT_WINDOW_SECONDS);
+    let period_millis = period.as_secs() * MILLIS_PER_SECOND + u64::from(period.subsec_millis());
+
+    SeasonalAnomaly {
+        slot_millis: (period_millis / u64::from(slots)).max(1),
+        baselines: vec![Baseline::default(); slots as usize],
+        started_at: clock::now(),
+        slot: 0,
+        slot_requests: 0,
+        slot_failures: 0,
+        slot_tripped: false,
+        requests: WindowedAdder::new(window, 5),
+        failures: WindowedAdder::new(window, 5),
+        sensitivity: DEFAULT_SENSITIVITY,
+        min_deviation: DEFAULT_MIN_DEVIATION,
+        learning_rate: DEFAULT_LEARNING_RATE,
+        min_request_threshold: DEFAULT_MINIMUM_REQUEST_THRESHOLD,
+        backoff: BackoffState::new(backoff),
+        cadence: Cadence::default(),
+    }
+}
+
+impl Default for SeasonalAnomaly<backoff::EqualJittered> {
+    fn default() -> Self {
+        let backoff = default_backoff();
+        seasonal_anomaly(Duration::from_secs(24 * 60 * 60), 24, backoff)
+    }
+}
+
+/// A policy which trips on a deviation of the failure rate from a learned seasonal baseline.
+#[derive(Debug, Clone)]
+pub struct SeasonalAnomaly<BACKOFF> {
+    slot_millis: u64,
+    baselines: Vec<Baseline>,
+    started_at: Instant,
+    slot: u64,
+    slot_requests: u64,
+    slot_failures: u64,
+    /// Whether the policy has tripped in the current slot, then it's an anomaly to not learn.
+    slot_tripped: bool,
+    requests: WindowedAdder,
+    failures: WindowedAdder,
+    sensitivity: f64,
+    min_deviation: f64,
+    learning_rate: f64,
+    min_request_threshold: u32,
+    backoff: BackoffState<BACKOFF>,
+    cadence: Cadence,
+}
+
+/// The learned failure rate of a slot.
+#[derive(Debug, Clone, Default)]
+struct Baseline {
+    mean: f64,
+    variance: f64,
+    seasons: u32,
+}
+
+impl<BACKOFF> SeasonalAnomaly<BACKOFF> {
+    /// Sets how many standard deviations above the mean are considered an anomaly, the default
+    /// is `3.0`.
+    pub fn sensitivity(mut self, sensitivity: f64) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    /// Sets the lowest standard deviation used for the comparison, so a perfectly stable
+    /// baseline doesn't make the policy trip on a single failure, the default is `0.05`.
+    pub fn min_deviation(mut self, min_deviation: f64) -> Self {
+        self.min_deviation = min_deviation;
+        self
+    }
+
+    /// Sets the weight of the latest season in the baseline, the default is `0.3`.
+    ///
+    /// # Panics
+    ///
+    /// When `learning_rate` isn't in `(0.0, 1.0]` interval.
+    pub fn learning_rate(mut self, learning_rate: f64) -> Self {
+        assert!(
+            learning_rate > 0.0 && learning_rate <= 1.0,
+            "learning_rate must be (0, 1]: {}",
+            learning_rate
+        );
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    /// Sets the window over which the current failure rate is computed, the default is `30s`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.requests = WindowedAdder::new(window, 5);
+        self.failures = WindowedAdder::new(window, 5);
+        self
+    }
+
+    /// Sets the minimum number of requests in the window (and in a slot to learn from it), the
+    /// default is `5`.
+    pub fn min_request_threshold(mut self, min_request_threshold: u32) -> Self {
+        self.min_request_threshold = min_request_threshold;
+        self
+    }
+
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+
+    /// Compares the failure rate with the baseline at most once per `interval`, driven lazily
+    /// by failures, instead of on every failure.
+    pub fn evaluation_interval(mut self, interval: Duration) -> Self {
+        self.cadence = Cadence::new(interval);
+        self
+    }
+
+    /// Moves to the current slot, the completed slot is folded into the baseline unless the
+    /// policy has tripped in it.
+    fn advance(&mut self) {
+        let diff = clock::now() - self.started_at;
+        let elapsed = diff.as_secs() * MILLIS_PER_SECOND + u64::from(diff.subsec_millis());
+        let slot = elapsed / self.slot_millis;
+
+        if slot == self.slot {
+            return;
+        }
+
+        if !self.slot_tripped && self.slot_requests >= u64::from(self.min_request_threshold) {
+            let rate = self.slot_failures as f64 / self.slot_requests as f64;
+            let index = (self.slot % self.baselines.len() as u64) as usize;
+            self.baselines[index].learn(rate, self.learning_rate);
+        }
+
+        self.slot = slot;
+        self.slot_requests = 0;
+        self.slot_failures = 0;
+        self.slot_tripped = false;
+    }
+
+    /// Returns the current failure rate if it's an anomaly.
+    fn anomaly(&mut self) -> Option<f64> {
+        let index = (self.slot % self.baselines.len() as u64) as usize;
+        let baseline = &self.baselines[index];
+        if baseline.seasons == 0 {
+            return None;
+        }
+
+        let requests = self.requests.sum();
+        if requests < i64::from(self.min_request_threshold) || requests <= 0 {
+            return None;
+        }
+
+        let rate = self.failures.sum() as f64 / requests as f64;
+        let deviation = baseline.variance.sqrt().max(self.min_deviation);
+        Some(rate).filter(|rate| *rate > baseline.mean + self.sensitivity * deviation)
+    }
+}
+
+impl Baseline {
+    /// Updates exponentially weighted mean and variance with the observed rate.
+    fn learn(&mut self, rate: f64, alpha: f64) {
+        if self.seasons == 0 {
+            self.mean = rate;
+            self.variance = 0.0;
+        } else {
+            let diff = rate - self.mean;
+            let increment = alpha * diff;
+            self.mean += increment;
+            self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        }
+        self.seasons = self.seasons.saturating_add(1);
+    }
+}
+
+impl<BACKOFF> FailurePolicy for SeasonalAnomaly<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    #[inline]
+    fn record_success(&mut self) {
+        self.advance();
+        self.slot_requests += 1;
+        self.requests.add(1);
+        self.backoff.on_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.advance();
+        self.slot_requests += 1;
+        self.slot_failures += 1;
+        self.requests.add(1);
+        self.failures.add(1);
+        self.backoff.on_failure();
+
+        if !self.cadence.is_due() {
+            return None;
+        }
+        let rate = self.anomaly()?;
+        self.slot_tripped = true;
+        Some(self.backoff.next(Some(rate)))
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.baselines.capacity() * mem::size_of::<Baseline>()
+            + self.requests.heap_size()
+            + self.failures.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        // The baseline is kept, it's a long-horizon history.
+        self.requests.reset();
+        self.failures.reset();
+        self.cadence.reset();
+        self.backoff.revived();
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trip_on_deviation_from_baseline() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = seasonal_anomaly(4.seconds(), 2, backoff).window(1.seconds());
+
+            // The first season: the slot #0 has 10% of failures, the slot #1 has 50%.
+            assert!(!observe(&mut policy, 1));
+            time.advance(2.seconds());
+            assert!(!observe(&mut policy, 5));
+            time.advance(2.seconds());
+
+            // The second season: 50% of failures is an anomaly for the slot #0.
+            assert!(observe(&mut policy, 5));
+            time.advance(2.seconds());
+
+            // But it's normal for the slot #1.
+            policy.revived();
+            assert!(!observe(&mut policy, 5));
+        });
+    }
+
+    #[test]
+    fn dont_learn_tripped_slots() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = seasonal_anomaly(4.seconds(), 2, backoff).window(1.seconds());
+
+            assert!(!observe(&mut policy, 1));
+            time.advance(4.seconds());
+
+            // The anomaly in the slot #0 doesn't shift its baseline, so it trips again.
+            for _ in 0..3 {
+                policy.revived();
+                assert!(observe(&mut policy, 5));
+                time.advance(4.seconds());
+            }
+        });
+    }
+
+    /// Performs 10 requests, the last `failures` of them fail. Returns `true` if tripped.
+    fn observe<BACKOFF>(policy: &mut SeasonalAnomaly<BACKOFF>, failures: usize) -> bool
+    where
+        BACKOFF: WithFeedback + Clone,
+    {
+        let mut tripped = false;
+        for i in 0..10 {
+            if i >= 10 - failures {
+                tripped |= policy.mark_dead_on_failure().is_some();
+            } else {
+                policy.record_success();
+            }
+        }
+        tripped
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use super::super::backoff::WithFeedback;
+use super::{
+    new_success_rate_over_time_window, SuccessRateOverTimeWindow,
+    DEFAULT_MINIMUM_REQUEST_THRESHOLD, DEFAULT_SUCCESS_RATE_THRESHOLD,
+    DEFAULT_SUCCESS_RATE_WINDOW_SECONDS,
+};
+
+const DEFAULT_SLICES: u8 = 5;
+const MIN_SLICES: u8 = 2;
+const MAX_SLICES: u8 = 10;
+
+/// An error returned when a policy is built with invalid parameters.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The required success rate isn't in `[0.0, 1.0]` interval.
+    InvalidSuccessRate(f64),
+    /// The window is shorter than one second.
+    WindowTooShort(Duration),
+    /// The number of window slices isn't in `[2, 10]` interval.
+    InvalidSlices(u8),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::InvalidSuccessRate(rate) => {
+                write!(f, "required success rate must be [0, 1]: {}", rate)
+            }
+            BuildError::WindowTooShort(window) => {
+                write!(f, "window must be at least 1s: {:?}", window)
+            }
+            BuildError::InvalidSlices(slices) => write!(
+                f,
+                "slices must be [{}, {}]: {}",
+                MIN_SLICES, MAX_SLICES, slices
+            ),
+        }
+    }
+}
+
+impl StdError for BuildError {}
+
+/// A builder of the `SuccessRateOverTimeWindow` policy.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{backoff, failure_policy::SuccessRateOverTimeWindow};
+///
+/// let policy = SuccessRateOverTimeWindow::builder(backoff::constant(Duration::from_secs(5)))
+///     .required_success_rate(0.5)
+///     .window(Duration::from_secs(10))
+///     .min_request_threshold(20)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SuccessRateBuilder<BACKOFF> {
+    required_success_rate: f64,
+    min_request_threshold: u32,
+    window: Duration,
+    slices: u8,
+    evaluation_interval: Option<Duration>,
+    backoff: BACKOFF,
+}
+
+impl<BACKOFF> SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    /// Creates a builder with the given backoff and default parameters.
+    pub fn builder(backoff: BACKOFF) -> SuccessRateBuilder<BACKOFF> {
+        SuccessRateBuilder {
+            required_success_rate: DEFAULT_SUCCESS_RATE_THRESHOLD,
+            min_request_threshold: DEFAULT_MINIMUM_REQUEST_THRESHOLD,
+            window: Duration::from_secs(DEFAULT_SUCCESS_RATE_WINDOW_SECONDS),
+            slices: DEFAULT_SLICES,
+            evaluation_interval: None,
+            backoff,
+        }
+    }
+}
+
+impl<BACKOFF> SuccessRateBuilder<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    /// Sets a success rate that must be met, the default is `0.8`.
+    pub fn required_success_rate(mut self, required_success_rate: f64) -> Self {
+        self.required_success_rate = required_success_rate;
+        self
+    }
+
+    /// Sets a minimum number of requests in the past window for the policy to trip, the default
+    /// is `5`. Zero means the policy may trip on the very first failure after the window.
+    pub fn min_request_threshold(mut self, min_request_threshold: u32) -> Self {
+        self.min_request_threshold = min_request_threshold;
+        self
+    }
+
+    /// Sets a window over which the success rate is tracked, the default is `30s`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets a number of slices the request counter's window is split into, the default is `5`.
+    /// A higher number of slices means finer granularity but also more memory consumption.
+    pub fn slices(mut self, slices: u8) -> Self {
+        self.slices = slices;
+        self
+    }
+
+    /// Sets how often the success rate is evaluated, see
+    /// `SuccessRateOverTimeWindow::evaluation_interval`. By default it's evaluated on every
+    /// failure.
+    pub fn evaluation_interval(mut self, interval: Duration) -> Self {
+        self.evaluation_interval = Some(interval);
+        self
+    }
+
+    /// Validates parameters and builds the policy.
+    pub fn build(self) -> Result<SuccessRateOverTimeWindow<BACKOFF>, BuildError> {
+        if !(0.0..=1.0).contains(&self.required_success_rate) {
+            return Err(BuildError::InvalidSuccessRate(self.required_success_rate));
+        }
+
+        if self.window < Duration::from_secs(1) {
+            return Err(BuildError::WindowTooShort(self.window));
+        }
+
+        if !(MIN_SLICES..=MAX_SLICES).contains(&self.slices) {
+            return Err(BuildError::InvalidSlices(self.slices));
+        }
+
+        let policy = new_success_rate_over_time_window(
+            self.required_success_rate,
+            self.min_request_threshold,
+            self.window,
+            self.slices,
+            self.backoff,
+        );
+        Ok(match self.evaluation_interval {
+            Some(interval) => policy.evaluation_interval(interval),
+            None => policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock};
+    use super::super::FailurePolicy;
+    use super::*;
+
+    #[test]
+    fn validate_parameters() {
+        let builder =
+            || SuccessRateOverTimeWindow::builder(backoff::constant(Duration::from_secs(1)));
+
+        assert!(builder().build().is_ok());
+        assert_eq!(
+            Some(BuildError::InvalidSuccessRate(1.5)),
+            builder().required_success_rate(1.5).build().err()
+        );
+        assert_eq!(
+            Some(BuildError::WindowTooShort(Duration::from_millis(500))),
+            builder().window(Duration::from_millis(500)).build().err()
+        );
+        assert_eq!(
+            Some(BuildError::InvalidSlices(11)),
+            builder().slices(11).build().err()
+        );
+    }
+
+    #[test]
+    fn keep_fractional_window() {
+        clock::freeze(|time| {
+            let mut policy =
+                SuccessRateOverTimeWindow::builder(backoff::constant(Duration::from_secs(5)))
+                    .required_success_rate(0.9)
+                    .min_request_threshold(1)
+                    .window(Duration::from_millis(1900))
+                    .build()
+                    .unwrap();
+
+            // The policy doesn't trip until the whole window has passed.
+            time.advance(Duration::from_millis(1500));
+            assert_eq!(None, policy.mark_dead_on_failure());
+            time.advance(Duration::from_millis(400));
+            assert_eq!(Some(Duration::from_secs(5)), policy.mark_dead_on_failure());
+        });
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use super::super::backoff::WithFeedback;
+use super::super::windowed_adder::WindowedAdder;
+use super::{BackoffReset, BackoffState, FailurePolicy, MILLIS_PER_SECOND};
+
+/// Returns a policy based on the instantaneous failure rate, measured in failures per second
+/// over a short sliding `window`. If the rate exceeds `max_failures_per_second`,
+/// `mark_dead_on_failure` will return `Some(Duration)`.
+///
+/// Unlike `success_rate_over_time_window` it ignores successes, so a sudden burst of errors
+/// is caught even when the window is dominated by prior successes.
+///
+/// * `max_failures_per_second` - the highest tolerated failure rate.
+/// * `window` - short window over which the rate is measured, e.g. one second.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `mark_dead_on_failure`
+///
+/// # Panics
+///
+/// When `window` is shorter than 10 milliseconds.
+pub fn failure_burst<BACKOFF>(
+    max_failures_per_second: f64,
+    window: Duration,
+    backoff: BACKOFF,
+) -> FailureBurst<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    assert!(
+        window >= Duration::from_millis(10),
+        "window must be at least 10 milliseconds"
+    );
+
+    let window_millis = window.as_secs() * MILLIS_PER_SECOND + u64::from(window.subsec_millis());
+
+    FailureBurst {
+        max_failures_per_second,
+        window_seconds: window_millis as f64 / MILLIS_PER_SECOND as f64,
+        failures: WindowedAdder::new(window, 5),
+        backoff: BackoffState::new(backoff),
+    }
+}
+
+/// A policy based on the failures per second rate over a short window.
+#[derive(Debug, Clone)]
+pub struct FailureBurst<BACKOFF> {
+    max_failures_per_second: f64,
+    window_seconds: f64,
+    failures: WindowedAdder,
+    backoff: BackoffState<BACKOFF>,
+}
+
+impl<BACKOFF> FailureBurst<BACKOFF> {
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+
+    /// Returns the current failure rate in failures per second.
+    pub fn failures_per_second(&mut self) -> f64 {
+        self.failures.sum() as f64 / self.window_seconds
+    }
+}
+
+impl<BACKOFF> FailurePolicy for FailureBurst<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    #[inline]
+    fn record_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.mark_dead_on_failure_weighted(1)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        self.failures.add(i64::from(cost));
+        self.backoff.on_failure();
+
+        if self.failures_per_second() > self.max_failures_per_second {
+            Some(self.backoff.next(None))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.failures.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.failures.reset();
+        self.backoff.revived();
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock};
+    use super::*;
+
+    #[test]
+    fn fail_on_burst() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = failure_burst(4.0, 1.seconds(), backoff);
+
+            // A slow trickle of failures never trips the policy.
+            for _ in 0..20 {
+                assert_eq!(None, policy.mark_dead_on_failure());
+                time.advance(1.seconds());
+            }
+
+            for _ in 0..4 {
+                assert_eq!(None, policy.mark_dead_on_failure());
+            }
+            assert_eq!(4.0, policy.failures_per_second());
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+
+            policy.revived();
+            assert_eq!(0.0, policy.failures_per_second());
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "window must be at least 10 milliseconds")]
+    fn reject_tiny_window() {
+        failure_burst(
+            1.0,
+            Duration::from_millis(1),
+            backoff::constant(5.seconds()),
+        );
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+    }
+}
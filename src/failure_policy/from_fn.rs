@@ -0,0 +1,129 @@
+use std::fmt::{self, Debug};
+use std::time::Duration;
+
+use super::FailurePolicy;
+
+/// Creates a policy from closures over a user defined `state`.
+///
+/// It's a shortcut for simple bespoke policies, which avoids defining a struct and implementing
+/// the `FailurePolicy` trait manually. Each closure corresponds to the trait's method and
+/// receives a mutable reference to the state.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{failure_policy, Config};
+///
+/// // Trips on every third failure, whatever happens between them.
+/// let policy = failure_policy::from_fn(
+///     0_u32,
+///     |_failures| {},
+///     |failures| {
+///         *failures += 1;
+///         if *failures % 3 == 0 {
+///             Some(Duration::from_secs(5))
+///         } else {
+///             None
+///         }
+///     },
+///     |failures| *failures = 0,
+/// );
+///
+/// let circuit_breaker = Config::new().failure_policy(policy).build();
+/// ```
+pub fn from_fn<S, RS, MD, RV>(
+    state: S,
+    record_success: RS,
+    mark_dead_on_failure: MD,
+    revived: RV,
+) -> FromFn<S, RS, MD, RV>
+where
+    RS: FnMut(&mut S),
+    MD: FnMut(&mut S) -> Option<Duration>,
+    RV: FnMut(&mut S),
+{
+    FromFn {
+        state,
+        record_success,
+        mark_dead_on_failure,
+        revived,
+    }
+}
+
+/// A policy defined by closures, see `from_fn`.
+#[derive(Clone)]
+pub struct FromFn<S, RS, MD, RV> {
+    state: S,
+    record_success: RS,
+    mark_dead_on_failure: MD,
+    revived: RV,
+}
+
+impl<S, RS, MD, RV> FromFn<S, RS, MD, RV> {
+    /// Returns a reference to the user defined state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+}
+
+impl<S, RS, MD, RV> FailurePolicy for FromFn<S, RS, MD, RV>
+where
+    RS: FnMut(&mut S),
+    MD: FnMut(&mut S) -> Option<Duration>,
+    RV: FnMut(&mut S),
+{
+    #[inline]
+    fn record_success(&mut self) {
+        (self.record_success)(&mut self.state)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        (self.mark_dead_on_failure)(&mut self.state)
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        (self.revived)(&mut self.state)
+    }
+}
+
+impl<S, RS, MD, RV> Debug for FromFn<S, RS, MD, RV>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FromFn")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delegate_to_closures() {
+        let mut policy = from_fn(
+            (0, 0),
+            |(successes, _)| *successes += 1,
+            |(_, failures)| {
+                *failures += 1;
+                if *failures >= 2 {
+                    Some(Duration::from_secs(1))
+                } else {
+                    None
+                }
+            },
+            |state| *state = (0, 0),
+        );
+
+        policy.record_success();
+        assert_eq!(None, policy.mark_dead_on_failure());
+        assert_eq!(Some(Duration::from_secs(1)), policy.mark_dead_on_failure());
+        assert_eq!(&(1, 2), policy.state());
+
+        policy.revived();
+        assert_eq!(&(0, 0), policy.state());
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use super::super::backoff::WithFeedback;
+use super::super::windowed_adder::WindowedAdder;
+use super::{BackoffReset, BackoffState, FailurePolicy, MILLIS_PER_SECOND};
+
+/// Returns a policy based on the cumulative latency of calls over a time window. If the time
+/// spent waiting on the backend within the `window` exceeds the `budget`,
+/// `record_latency` will return `Some(Duration)`.
+///
+/// It approximates "the backend consumes our worker threads" and trips even when calls
+/// eventually succeed. Failures themselves never trip this policy, so combine it with another
+/// one using `or_else`.
+///
+/// * `budget` - total time which may be spent in calls within the window.
+/// * `window` - window over which latencies are summed.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `record_latency`
+///
+/// # Panics
+///
+/// When `window` is shorter than 10 milliseconds.
+pub fn latency_budget<BACKOFF>(
+    budget: Duration,
+    window: Duration,
+    backoff: BACKOFF,
+) -> LatencyBudget<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    assert!(
+        window >= Duration::from_millis(10),
+        "window must be at least 10 milliseconds"
+    );
+
+    LatencyBudget {
+        budget: millis(budget),
+        spent: WindowedAdder::new(window, 5),
+        backoff: BackoffState::new(backoff),
+    }
+}
+
+/// A policy based on the sum of call latencies within a time window.
+#[derive(Debug, Clone)]
+pub struct LatencyBudget<BACKOFF> {
+    budget: u64,
+    spent: WindowedAdder,
+    backoff: BackoffState<BACKOFF>,
+}
+
+impl<BACKOFF> LatencyBudget<BACKOFF> {
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+
+    /// Returns the time spent in calls within the current window.
+    pub fn spent(&mut self) -> Duration {
+        Duration::from_millis(self.spent.sum().max(0) as u64)
+    }
+}
+
+impl<BACKOFF> FailurePolicy for LatencyBudget<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    #[inline]
+    fn record_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.backoff.on_failure();
+        None
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.spent.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.spent.reset();
+        self.backoff.revived();
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+
+    #[inline]
+    fn record_latency(&mut self, latency: Duration) -> Option<Duration> {
+        self.spent.add(millis(latency) as i64);
+
+        if self.spent.sum() as u64 > self.budget {
+            Some(self.backoff.next(None))
+        } else {
+            None
+        }
+    }
+}
+
+#[inline]
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * MILLIS_PER_SECOND + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock, Config};
+    use super::*;
+
+    #[test]
+    fn exceed_budget() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = latency_budget(3.seconds(), 10.seconds(), backoff);
+
+            assert_eq!(None, policy.record_latency(2.seconds()));
+            assert_eq!(None, policy.mark_dead_on_failure());
+
+            // The first call left the window.
+            time.advance(10.seconds());
+            assert_eq!(None, policy.record_latency(2.seconds()));
+            assert_eq!(2.seconds(), policy.spent());
+
+            assert_eq!(Some(5.seconds()), policy.record_latency(1001.millis()));
+
+            policy.revived();
+            assert_eq!(Duration::from_secs(0), policy.spent());
+        })
+    }
+
+    #[test]
+    fn open_on_slow_successes() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = latency_budget(3.seconds(), 10.seconds(), backoff);
+            let state_machine = Config::new().failure_policy(policy).build();
+
+            for _ in 0..2 {
+                assert!(state_machine.is_call_permitted());
+                time.advance(2.seconds());
+                state_machine.on_latency(2.seconds());
+                state_machine.on_success();
+            }
+
+            assert!(!state_machine.is_call_permitted());
+        })
+    }
+
+    #[test]
+    fn open_on_slow_calls() {
+        use super::super::super::{CircuitBreaker, Error};
+
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = latency_budget(3.seconds(), 10.seconds(), backoff);
+            let state_machine = Config::new().failure_policy(policy).build();
+            let mut slow = |result: Result<(), ()>| {
+                state_machine.call(|| {
+                    time.advance(2.seconds());
+                    result
+                })
+            };
+
+            // The latency is recorded along with the outcome of measured calls.
+            assert!(slow(Ok(())).is_ok());
+            assert!(matches!(slow(Err(())), Err(Error::Inner(()))));
+            assert!(matches!(slow(Ok(())), Err(Error::Rejected(_))));
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "window must be at least 10 milliseconds")]
+    fn reject_tiny_window() {
+        latency_budget(3.seconds(), 4.millis(), backoff::constant(5.seconds()));
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+        fn millis(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+
+        fn millis(self) -> Duration {
+            Duration::from_millis(self)
+        }
+    }
+}
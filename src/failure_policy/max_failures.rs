@@ -0,0 +1,182 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::super::backoff::WithFeedback;
+use super::super::windowed_adder::WindowedAdder;
+use super::{BackoffReset, BackoffState, FailurePolicy, MaxFailuresInWindowState, Persist};
+
+/// Returns a policy based on an absolute number of failures within a sliding time window. If
+/// `num_failures` occur within the `window`, regardless of the number of successes,
+/// `mark_dead_on_failure` will return `Some(Duration)`.
+///
+/// It's simpler to reason about than a rate for low-volume traffic, such as batch jobs.
+///
+/// * `num_failures` - number of failures within the window.
+/// * `window` - window over which failures are counted.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `mark_dead_on_failure`
+///
+/// # Panics
+///
+/// When `window` is shorter than 10 milliseconds.
+pub fn max_failures_in_window<BACKOFF>(
+    num_failures: u32,
+    window: Duration,
+    backoff: BACKOFF,
+) -> MaxFailuresInWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    assert!(
+        window >= Duration::from_millis(10),
+        "window must be at least 10 milliseconds"
+    );
+
+    MaxFailuresInWindow {
+        num_failures,
+        failures: WindowedAdder::new(window, 5),
+        backoff: BackoffState::new(backoff),
+    }
+}
+
+/// A policy based on a maximum number of failures within a time window.
+#[derive(Debug, Clone)]
+pub struct MaxFailuresInWindow<BACKOFF> {
+    num_failures: u32,
+    failures: WindowedAdder,
+    backoff: BackoffState<BACKOFF>,
+}
+
+impl<BACKOFF> MaxFailuresInWindow<BACKOFF> {
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+}
+
+impl<BACKOFF> FailurePolicy for MaxFailuresInWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "max_failures_in_window({})", self.num_failures)
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        self.backoff.on_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.mark_dead_on_failure_weighted(1)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        self.failures.add(i64::from(cost));
+        self.backoff.on_failure();
+
+        if self.failures.sum() >= i64::from(self.num_failures) {
+            Some(self.backoff.next(None))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.failures.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.failures.reset();
+        self.backoff.revived();
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+}
+
+impl<BACKOFF> Persist for MaxFailuresInWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    type State = MaxFailuresInWindowState;
+
+    fn save(&self) -> Self::State {
+        MaxFailuresInWindowState {
+            failures: self.failures.save(),
+            backoff: self.backoff.save(),
+        }
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.failures.restore(&state.failures);
+        self.backoff.restore(&state.backoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock};
+    use super::*;
+
+    #[test]
+    fn fail_on_nth_failure_within_window() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = max_failures_in_window(3, 10.seconds(), backoff);
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+            time.advance(4.seconds());
+            policy.record_success();
+            assert_eq!(None, policy.mark_dead_on_failure());
+
+            // The first failure left the window.
+            time.advance(6.seconds());
+            assert_eq!(None, policy.mark_dead_on_failure());
+
+            // Successes don't compensate failures.
+            for _ in 0..100 {
+                policy.record_success();
+            }
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+
+            policy.revived();
+            assert_eq!(None, policy.mark_dead_on_failure());
+        })
+    }
+
+    #[test]
+    fn weighted_failures() {
+        clock::freeze(|_| {
+            let backoff = backoff::constant(5.seconds());
+            let mut policy = max_failures_in_window(10, 10.seconds(), backoff);
+
+            assert_eq!(None, policy.mark_dead_on_failure_weighted(6));
+            assert_eq!(None, policy.mark_dead_on_failure_weighted(3));
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "window must be at least 10 milliseconds")]
+    fn reject_tiny_window() {
+        max_failures_in_window(3, Duration::from_millis(4), backoff::constant(5.seconds()));
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+    }
+}
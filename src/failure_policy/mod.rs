@@ -0,0 +1,1542 @@
+//! Contains various failure accrual policies, which are used for the failure rate detection.
+//!
+//! Policies of this module don't allocate when they record calls or open the circuit breaker,
+//! restarting a backoff clones its initial state, which built-in backoffs keep inline.
+//! `per_class` is the exception, it allocates once for each class it sees the first time.
+
+use std::any;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::backoff::{self, Feedback, WithFeedback};
+use super::clock;
+use super::ema::Ema;
+use super::windowed_adder::WindowedAdder;
+
+mod anomaly;
+mod builder;
+mod burst;
+mod from_fn;
+mod latency_budget;
+mod max_failures;
+mod per_class;
+mod persist;
+
+pub use self::anomaly::{seasonal_anomaly, SeasonalAnomaly};
+pub use self::builder::{BuildError, SuccessRateBuilder};
+pub use self::burst::{failure_burst, FailureBurst};
+pub use self::from_fn::{from_fn, FromFn};
+pub use self::latency_budget::{latency_budget, LatencyBudget};
+pub use self::max_failures::{max_failures_in_window, MaxFailuresInWindow};
+pub use self::per_class::{per_class, PerClass};
+pub use self::persist::{
+    BackoffProgress, ConsecutiveFailuresState, MaxFailuresInWindowState, Persist, SuccessRateState,
+};
+
+static DEFAULT_BACKOFF: Duration = Duration::from_secs(300);
+
+const SUCCESS: f64 = 1.0;
+const FAILURE: f64 = 0.0;
+const MILLIS_PER_SECOND: u64 = 1_000;
+const DEFAULT_SUCCESS_RATE_THRESHOLD: f64 = 0.8;
+const DEFAULT_SUCCESS_RATE_WINDOW_SECONDS: u64 = 30;
+const DEFAULT_SUCCESS_RATE_SLICES: u8 = 5;
+const DEFAULT_CONSECUTIVE_FAILURES: u32 = 5;
+pub(crate) const DEFAULT_MINIMUM_REQUEST_THRESHOLD: u32 = 5;
+
+/// A `FailurePolicy` is used to determine whether or not the backend died.
+pub trait FailurePolicy {
+    /// Invoked when a request is successful.
+    fn record_success(&mut self);
+
+    /// Invoked when a non-probing request fails.  If it returns `Some(Duration)`,
+    /// the backend will mark as the dead for the specified `Duration`.
+    fn mark_dead_on_failure(&mut self) -> Option<Duration>;
+
+    /// Invoked  when a backend is revived after probing. Used to reset any history.
+    fn revived(&mut self);
+
+    /// Invoked when a non-probing request fails with an error of the given `class`.
+    ///
+    /// The default implementation ignores the class and calls `mark_dead_on_failure`.
+    #[inline]
+    fn mark_dead_on_failure_in(&mut self, _class: &'static str) -> Option<Duration> {
+        self.mark_dead_on_failure()
+    }
+
+    /// Invoked when a request of the given `cost` is successful.
+    ///
+    /// The default implementation counts it as `cost` successful requests.
+    #[inline]
+    fn record_success_weighted(&mut self, cost: u32) {
+        for _ in 0..cost {
+            self.record_success();
+        }
+    }
+
+    /// Invoked when a non-probing request of the given `cost` fails. If it returns
+    /// `Some(Duration)`, the backend will mark as the dead for the specified `Duration`.
+    ///
+    /// The default implementation counts it as `cost` failed requests, until one of them
+    /// marks the backend as dead.
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        for _ in 0..cost {
+            if let Some(duration) = self.mark_dead_on_failure() {
+                return Some(duration);
+            }
+        }
+        None
+    }
+
+    /// Invoked with the latency of a completed call, before its outcome is recorded.
+    /// If it returns `Some(Duration)`, the backend will mark as the dead for the specified
+    /// `Duration`.
+    ///
+    /// The default implementation ignores latencies.
+    #[inline]
+    fn record_latency(&mut self, _latency: Duration) -> Option<Duration> {
+        None
+    }
+
+    /// Invoked when a request is successful, with details of the call.
+    ///
+    /// It's what the state machine calls, policies which need the moment, the latency, or the
+    /// weight of calls override it instead of the specific methods. The default implementation
+    /// calls `record_success`, or `record_success_weighted` for a weight other than one.
+    #[inline]
+    fn record_success_with(&mut self, record: &CallRecord) {
+        if record.weight == 1 {
+            self.record_success();
+        } else {
+            self.record_success_weighted(record.weight);
+        }
+    }
+
+    /// Invoked when a non-probing request fails, with details of the call. If it returns
+    /// `Some(Duration)`, the backend will mark as the dead for the specified `Duration`.
+    ///
+    /// It's what the state machine calls, see `record_success_with`. The default
+    /// implementation calls `mark_dead_on_failure_in` for a classified failure,
+    /// `mark_dead_on_failure_weighted` for a weight other than one, or `mark_dead_on_failure`.
+    #[inline]
+    fn mark_dead_on_failure_with(&mut self, record: &CallRecord) -> Option<Duration> {
+        match record.class {
+            Some(class) => self.mark_dead_on_failure_in(class),
+            None if record.weight == 1 => self.mark_dead_on_failure(),
+            None => self.mark_dead_on_failure_weighted(record.weight),
+        }
+    }
+
+    /// Returns the approximate number of bytes the policy allocates on the heap, e.g. for
+    /// windows of counters, it's a part of `StateMachine::memory_footprint`.
+    ///
+    /// The default implementation returns zero, as for policies which don't allocate.
+    #[inline]
+    fn heap_size(&self) -> usize {
+        0
+    }
+
+    /// Writes the kind of the policy with its key thresholds, e.g. `consecutive_failures(5)`,
+    /// it's used by the `Display` output of the state machine.
+    ///
+    /// The default implementation writes the name of the type.
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(short_type_name(any::type_name::<Self>()))
+    }
+
+    /// Returns the name of the policy which has marked the backend dead most recently, e.g.
+    /// `ConsecutiveFailures`, it's reported by `Transition::tripped_by`.
+    ///
+    /// Combinators return the name of the policy among theirs which has tripped. The default
+    /// implementation returns the name of the type.
+    #[inline]
+    fn tripped_by(&self) -> &'static str {
+        short_type_name(any::type_name::<Self>())
+    }
+
+    /// Invoked right after the policy has returned a delay, which a combinator has discarded,
+    /// e.g. `and_also` when the other policy hasn't tripped. The policy takes back the step of
+    /// its backoff, so the next delay isn't escalated by trips which didn't open the circuit
+    /// breaker.
+    ///
+    /// The default implementation does nothing.
+    #[inline]
+    fn discard_trip(&mut self) {}
+
+    /// Creates a `FailurePolicy` which uses both `self` and `rhs`.
+    ///
+    /// When both policies trip on the same failure, the one with the longer delay opens the
+    /// circuit breaker, `self` on a tie, see `tripped_by`.
+    fn or_else<R>(self, rhs: R) -> OrElse<Self, R>
+    where
+        Self: Sized,
+    {
+        OrElse {
+            left: self,
+            right: rhs,
+            tripped: Side::Left,
+            stepped: (false, false),
+        }
+    }
+
+    /// Creates a `FailurePolicy` which trips only when both `self` and `rhs` trip.
+    ///
+    /// It's useful to combine a policy over a short window, which reacts quickly, with a policy
+    /// over a long window, which detects a sustained problem. Short error bursts trip only the
+    /// former and don't open the circuit breaker, nor escalate its backoff, see `discard_trip`.
+    /// The policy with the longer delay opens the circuit breaker, `self` on a tie.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use failsafe::{backoff, failure_policy::{self, FailurePolicy}};
+    ///
+    /// let backoff = backoff::constant(Duration::from_secs(30));
+    /// let short = failure_policy::success_rate_over_time_window(
+    ///     0.5, 10, Duration::from_secs(10), backoff.clone(),
+    /// );
+    /// let long = failure_policy::success_rate_over_time_window(
+    ///     0.9, 100, Duration::from_secs(300), backoff,
+    /// );
+    /// let policy = short.and_also(long);
+    /// ```
+    fn and_also<R>(self, rhs: R) -> AndAlso<Self, R>
+    where
+        Self: Sized,
+    {
+        AndAlso {
+            left: self,
+            right: rhs,
+            tripped: Side::Left,
+        }
+    }
+}
+
+/// Details of a completed call recorded by a failure policy, see
+/// `FailurePolicy::record_success_with` and `FailurePolicy::mark_dead_on_failure_with`.
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use failsafe::failure_policy::CallRecord;
+///
+/// let record = CallRecord::new(Instant::now())
+///     .with_latency(Duration::from_millis(30))
+///     .with_class("timeout");
+/// assert_eq!(1, record.weight);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CallRecord {
+    /// The moment the call has completed, it's in the past for replayed calls.
+    pub at: Instant,
+    /// The latency of the call, if it was measured. It's passed to
+    /// `FailurePolicy::record_latency` beforehand.
+    pub latency: Option<Duration>,
+    /// The cost of the call, it counts as `weight` calls.
+    pub weight: u32,
+    /// The class of the error of a failed call, if it's classified.
+    pub class: Option<&'static str>,
+}
+
+impl CallRecord {
+    /// Creates a record of a call of weight one completed `at` the given moment.
+    pub fn new(at: Instant) -> Self {
+        CallRecord {
+            at,
+            latency: None,
+            weight: 1,
+            class: None,
+        }
+    }
+
+    /// Sets the latency of the call.
+    pub fn with_latency(mut self, latency: impl Into<Option<Duration>>) -> Self {
+        self.latency = latency.into();
+        self
+    }
+
+    /// Sets the cost of the call.
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Sets the class of the error of the call.
+    pub fn with_class(mut self, class: &'static str) -> Self {
+        self.class = Some(class);
+        self
+    }
+}
+
+/// Returns a policy based on an exponentially-weighted moving average success
+/// rate over a time window. A moving average is used so the success rate
+/// calculation is biased towards more recent requests.
+///
+/// If the computed weighted success rate is less than the required success rate,
+/// `mark_dead_on_failure` will return `Some(Duration)`.
+///
+/// See `ema::Ema` for how the success rate is computed.
+///
+/// * `required_success_rate` - a success rate that must be met.
+/// * `min_request_threshold` - minimum number of requests in the past `window`
+///   for `mark_dead_on_failure` to return a duration.
+/// * `window` - window over which the success rate is tracked.  `mark_dead_on_failure`
+///   will return None, until we get requests for a duration of at least `window`.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `mark_dead_on_failure`
+///
+/// Use `SuccessRateOverTimeWindow::builder` to get validation errors instead of panics.
+///
+/// # Panics
+///
+/// When `required_success_rate` isn't in `[0.0, 1.0]` interval.
+pub fn success_rate_over_time_window<BACKOFF>(
+    required_success_rate: f64,
+    min_request_threshold: u32,
+    window: Duration,
+    backoff: BACKOFF,
+) -> SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    assert!(
+        (0.0..=1.0).contains(&required_success_rate),
+        "required_success_rate must be [0, 1]: {}",
+        required_success_rate
+    );
+
+    new_success_rate_over_time_window(
+        required_success_rate,
+        min_request_threshold,
+        window,
+        DEFAULT_SUCCESS_RATE_SLICES,
+        backoff,
+    )
+}
+
+fn new_success_rate_over_time_window<BACKOFF>(
+    required_success_rate: f64,
+    min_request_threshold: u32,
+    window: Duration,
+    slices: u8,
+    backoff: BACKOFF,
+) -> SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    let window_millis = window.as_secs() * MILLIS_PER_SECOND + u64::from(window.subsec_millis());
+    let request_counter = WindowedAdder::new(window, slices);
+
+    SuccessRateOverTimeWindow {
+        required_success_rate,
+        min_request_threshold,
+        ema: Ema::new(window_millis),
+        now: clock::now(),
+        window_millis,
+        backoff: BackoffState::new(backoff),
+        request_counter,
+        cadence: Cadence::default(),
+    }
+}
+
+/// A policy based on a maximum number of consecutive failures. If `num_failures`
+/// occur consecutively, `mark_dead_on_failure` will return a Some(Duration) to
+/// mark an endpoint dead for.
+///
+/// * `num_failures` - number of consecutive failures.
+/// * `backoff` - stream of durations to use for the next duration
+///   returned from `mark_dead_on_failure`
+pub fn consecutive_failures<BACKOFF>(
+    num_failures: u32,
+    backoff: BACKOFF,
+) -> ConsecutiveFailures<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    ConsecutiveFailures {
+        num_failures,
+        consecutive_failures: 0,
+        backoff: BackoffState::new(backoff),
+        idle_reset: None,
+        last_call: None,
+    }
+}
+
+/// Returns the name of the type without the module path and generic parameters.
+pub(crate) fn short_type_name(name: &str) -> &str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Returns the backoff used by default policies.
+pub(crate) fn default_backoff() -> backoff::EqualJittered {
+    backoff::equal_jittered(Duration::from_secs(10), Duration::from_secs(300))
+}
+
+impl Default for SuccessRateOverTimeWindow<backoff::EqualJittered> {
+    fn default() -> Self {
+        let backoff = default_backoff();
+        let window = Duration::from_secs(DEFAULT_SUCCESS_RATE_WINDOW_SECONDS);
+        success_rate_over_time_window(
+            DEFAULT_SUCCESS_RATE_THRESHOLD,
+            DEFAULT_MINIMUM_REQUEST_THRESHOLD,
+            window,
+            backoff,
+        )
+    }
+}
+
+impl Default for ConsecutiveFailures<backoff::EqualJittered> {
+    fn default() -> Self {
+        let backoff = default_backoff();
+        consecutive_failures(DEFAULT_CONSECUTIVE_FAILURES, backoff)
+    }
+}
+
+/// A policy based on an exponentially-weighted moving average success
+/// rate over a time window. A moving average is used so the success rate
+/// calculation is biased towards more recent requests.
+///
+/// A call of some cost moves the average as that many calls would, see
+/// `ema::Ema::update`, and counts as that many requests for the threshold.
+#[derive(Debug, Clone)]
+pub struct SuccessRateOverTimeWindow<BACKOFF> {
+    required_success_rate: f64,
+    min_request_threshold: u32,
+    ema: Ema,
+    now: Instant,
+    window_millis: u64,
+    backoff: BackoffState<BACKOFF>,
+    request_counter: WindowedAdder,
+    cadence: Cadence,
+}
+
+impl<BACKOFF> SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: Clone,
+{
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+
+    /// Evaluates the success rate at most once per `interval`, driven lazily by failures,
+    /// instead of on every failure.
+    ///
+    /// It reduces the work per call and makes decisions more stable under bursty traffic.
+    /// Calls in between are still recorded.
+    pub fn evaluation_interval(mut self, interval: Duration) -> Self {
+        self.cadence = Cadence::new(interval);
+        self
+    }
+
+    /// Returns seconds since instance was created.
+    fn elapsed_millis(&self) -> u64 {
+        let diff = clock::now() - self.now;
+        (diff.as_secs() * MILLIS_PER_SECOND) + u64::from(diff.subsec_millis())
+    }
+
+    /// Returns the timestamp of an observation for the average, which needs monotonic ones, so
+    /// a replayed observation counts as the latest one.
+    fn ema_timestamp(&self) -> u64 {
+        self.elapsed_millis().max(self.ema.timestamp())
+    }
+
+    /// We can trigger failure accrual if the `window` has passed, success rate is below
+    /// `required_success_rate`.
+    fn can_remove(&mut self, success_rate: f64) -> bool {
+        self.elapsed_millis() >= self.window_millis
+            && success_rate < self.required_success_rate
+            && self.request_counter.sum() >= i64::from(self.min_request_threshold)
+    }
+}
+
+impl<BACKOFF> FailurePolicy for SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "success_rate_over_time_window({}, {}, {:?})",
+            self.required_success_rate,
+            self.min_request_threshold,
+            Duration::from_millis(self.window_millis)
+        )
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        self.record_success_weighted(1)
+    }
+
+    #[inline]
+    fn record_success_weighted(&mut self, cost: u32) {
+        let timestamp = self.ema_timestamp();
+        self.ema.update(timestamp, SUCCESS, cost);
+        self.request_counter.add(i64::from(cost));
+        self.backoff.on_successes(cost);
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.mark_dead_on_failure_weighted(1)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        self.request_counter.add(i64::from(cost));
+        self.backoff.on_failure();
+
+        let timestamp = self.ema_timestamp();
+        let success_rate = self.ema.update(timestamp, FAILURE, cost);
+
+        if self.cadence.is_due() && self.can_remove(success_rate) {
+            let duration = self.backoff.next(Some(1.0 - success_rate));
+            Some(duration)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.request_counter.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.now = clock::now();
+        self.ema.reset();
+        self.request_counter.reset();
+        self.cadence.reset();
+        self.backoff.revived();
+    }
+}
+
+/// A policy based on a maximum number of consecutive failure
+///
+/// A failed call of some cost extends the failure streak by the cost, so an expensive call
+/// may trip it alone, while a successful call of any cost ends the streak and counts as that
+/// many successes for `BackoffReset::AfterSuccesses`.
+#[derive(Debug, Clone)]
+pub struct ConsecutiveFailures<BACKOFF> {
+    num_failures: u32,
+    consecutive_failures: u32,
+    backoff: BackoffState<BACKOFF>,
+    idle_reset: Option<Duration>,
+    last_call: Option<Instant>,
+}
+
+impl<BACKOFF> ConsecutiveFailures<BACKOFF> {
+    /// Returns the current number of consecutive failures.
+    #[inline]
+    pub fn failure_streak(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Returns the configured number of consecutive failures, which trips the policy.
+    #[inline]
+    pub fn threshold(&self) -> u32 {
+        self.num_failures
+    }
+
+    /// Configures when the backoff restarts from the beginning, see `BackoffReset`.
+    pub fn backoff_reset(mut self, reset: BackoffReset) -> Self {
+        self.backoff.reset = reset;
+        self
+    }
+
+    /// Resets the failure streak when there were no calls for the `idle` period.
+    ///
+    /// A failure streak accumulated over hours of sparse traffic shouldn't trip the circuit
+    /// breaker the same way a rapid burst does.
+    pub fn idle_reset(mut self, idle: Duration) -> Self {
+        self.idle_reset = Some(idle);
+        self
+    }
+
+    /// Resets the failure streak if the policy has been idle for too long.
+    #[inline]
+    fn expire_idle(&mut self) {
+        if let Some(idle) = self.idle_reset {
+            let now = clock::now();
+            if self
+                .last_call
+                .map_or(false, |last_call| now - last_call >= idle)
+            {
+                self.consecutive_failures = 0;
+            }
+            self.last_call = Some(now);
+        }
+    }
+}
+
+impl<BACKOFF> FailurePolicy for ConsecutiveFailures<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "consecutive_failures({})", self.num_failures)
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        self.record_success_weighted(1)
+    }
+
+    #[inline]
+    fn record_success_weighted(&mut self, cost: u32) {
+        self.expire_idle();
+        self.consecutive_failures = 0;
+        self.backoff.on_successes(cost);
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.backoff.step_back();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.mark_dead_on_failure_weighted(1)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        self.expire_idle();
+        self.consecutive_failures = self.consecutive_failures.saturating_add(cost);
+        self.backoff.on_failure();
+
+        if self.consecutive_failures >= self.num_failures {
+            let duration = self.backoff.next(None);
+            Some(duration)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.consecutive_failures = 0;
+        self.backoff.revived();
+    }
+}
+
+/// Defines when a policy restarts its backoff from the beginning.
+///
+/// A premature reset causes open/close flapping: a backend, which is still unstable, passes a
+/// probe, fails again shortly after, and the circuit breaker is opened for the shortest delay
+/// again instead of an escalated one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackoffReset {
+    /// Restart the backoff on the first success, when the backend is revived after probing.
+    /// This is the default behaviour.
+    FirstSuccess,
+    /// Restart the backoff after the given number of successful calls since the backend was
+    /// revived. A failure in between starts the count over.
+    AfterSuccesses(u32),
+    /// Restart the backoff when the given period has passed without failures since the backend
+    /// was revived.
+    AfterQuietPeriod(Duration),
+}
+
+impl Default for BackoffReset {
+    fn default() -> Self {
+        BackoffReset::FirstSuccess
+    }
+}
+
+/// Limits how often a policy evaluates its trip condition, see `evaluation_interval` of rate
+/// based policies.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cadence {
+    interval: Option<Duration>,
+    evaluated_at: Option<Instant>,
+}
+
+impl Cadence {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Cadence {
+            interval: Some(interval),
+            evaluated_at: None,
+        }
+    }
+
+    /// Tells whether the trip condition should be evaluated now, and if so, starts the next
+    /// interval. Without an interval it's evaluated on every failure.
+    #[inline]
+    pub(crate) fn is_due(&mut self) -> bool {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return true,
+        };
+
+        let now = clock::now();
+        match self.evaluated_at {
+            Some(at) if now - at < interval => false,
+            _ => {
+                self.evaluated_at = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Forgets the last evaluation, so the first failure after reviving is evaluated.
+    #[inline]
+    pub(crate) fn reset(&mut self) {
+        self.evaluated_at = None;
+    }
+}
+
+/// A backoff used by a policy, it keeps the initial state of the backoff to restart it, and
+/// counts open cycles to provide feedback.
+#[derive(Debug, Clone)]
+pub(crate) struct BackoffState<BACKOFF> {
+    current: BACKOFF,
+    fresh: BACKOFF,
+    open_cycles: u32,
+    pub(crate) reset: BackoffReset,
+    /// A pending reset, which waits for successes or a quiet period since the given moment.
+    pending: Option<(u32, Instant)>,
+    /// The backoff before the last step, until the next call is recorded, see `step_back`.
+    previous: Option<BACKOFF>,
+}
+
+impl<BACKOFF> BackoffState<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    pub(crate) fn new(backoff: BACKOFF) -> Self {
+        BackoffState {
+            current: backoff.clone(),
+            fresh: backoff,
+            open_cycles: 0,
+            reset: BackoffReset::default(),
+            pending: None,
+            previous: None,
+        }
+    }
+
+    /// Returns the next delay, `failure_rate` is the rate observed by the policy if it tracks one.
+    #[inline]
+    pub(crate) fn next(&mut self, failure_rate: Option<f64>) -> Duration {
+        self.previous = Some(self.current.clone());
+        self.open_cycles = self.open_cycles.saturating_add(1);
+        let feedback = Feedback {
+            open_cycles: self.open_cycles,
+            failure_rate,
+        };
+        self.current.next_with(&feedback).unwrap_or(DEFAULT_BACKOFF)
+    }
+
+    /// Takes back the last step, unless another call has been recorded since, see
+    /// `FailurePolicy::discard_trip`.
+    #[inline]
+    pub(crate) fn step_back(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            self.current = previous;
+            self.open_cycles = self.open_cycles.saturating_sub(1);
+        }
+    }
+
+    /// Invoked when the backend is revived, restarts the backoff or schedules the restart.
+    #[inline]
+    pub(crate) fn revived(&mut self) {
+        self.previous = None;
+        match self.reset {
+            BackoffReset::FirstSuccess => self.restart(),
+            _ => self.pending = Some((0, clock::now())),
+        }
+    }
+
+    /// Invoked on each success.
+    #[inline]
+    pub(crate) fn on_success(&mut self) {
+        self.on_successes(1)
+    }
+
+    /// Invoked on a success which counts as `count` ones, e.g. of a weighted call.
+    #[inline]
+    pub(crate) fn on_successes(&mut self, count: u32) {
+        self.previous = None;
+        let (successes, since) = match self.pending.as_mut() {
+            Some(pending) => {
+                pending.0 = pending.0.saturating_add(count);
+                *pending
+            }
+            None => return,
+        };
+
+        let restart = match self.reset {
+            BackoffReset::FirstSuccess => true,
+            BackoffReset::AfterSuccesses(n) => successes >= n,
+            BackoffReset::AfterQuietPeriod(period) => clock::now() - since >= period,
+        };
+
+        if restart {
+            self.restart();
+        }
+    }
+
+    /// Invoked on each failure, starts a pending reset over.
+    #[inline]
+    pub(crate) fn on_failure(&mut self) {
+        self.previous = None;
+        if let Some(pending) = self.pending.as_mut() {
+            *pending = (0, clock::now());
+        }
+    }
+
+    /// Restarts the backoff from the beginning.
+    #[inline]
+    fn restart(&mut self) {
+        self.current = self.fresh.clone();
+        self.open_cycles = 0;
+        self.pending = None;
+        self.previous = None;
+    }
+}
+
+/// The failure policy of `Config::new`.
+pub type DefaultPolicy = OrElse<
+    SuccessRateOverTimeWindow<backoff::EqualJittered>,
+    ConsecutiveFailures<backoff::EqualJittered>,
+>;
+
+/// A combinator used for join two policies into new one.
+#[derive(Debug, Clone)]
+pub struct OrElse<LEFT, RIGHT> {
+    left: LEFT,
+    right: RIGHT,
+    tripped: Side,
+    /// Which policies have tripped on the last call, their steps are taken back together.
+    stepped: (bool, bool),
+}
+
+/// Which policy of a combinator has tripped most recently.
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl<LEFT, RIGHT> OrElse<LEFT, RIGHT> {
+    /// Returns the delay of the policy which has tripped, and remembers which one it was.
+    #[inline]
+    fn pick(&mut self, left: Option<Duration>, right: Option<Duration>) -> Option<Duration> {
+        self.stepped = (left.is_some(), right.is_some());
+        let (side, delay) = match (left, right) {
+            (Some(l), Some(r)) if r > l => (Side::Right, r),
+            (Some(l), _) => (Side::Left, l),
+            (None, Some(r)) => (Side::Right, r),
+            (None, None) => return None,
+        };
+        self.tripped = side;
+        Some(delay)
+    }
+}
+
+impl<LEFT, RIGHT> FailurePolicy for OrElse<LEFT, RIGHT>
+where
+    LEFT: FailurePolicy,
+    RIGHT: FailurePolicy,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.left.describe(f)?;
+        f.write_str(" or ")?;
+        self.right.describe(f)
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        self.left.record_success();
+        self.right.record_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure();
+        let right = self.right.mark_dead_on_failure();
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_in(&mut self, class: &'static str) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_in(class);
+        let right = self.right.mark_dead_on_failure_in(class);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn record_success_weighted(&mut self, cost: u32) {
+        self.left.record_success_weighted(cost);
+        self.right.record_success_weighted(cost);
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_weighted(cost);
+        let right = self.right.mark_dead_on_failure_weighted(cost);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn record_success_with(&mut self, record: &CallRecord) {
+        self.left.record_success_with(record);
+        self.right.record_success_with(record);
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_with(&mut self, record: &CallRecord) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_with(record);
+        let right = self.right.mark_dead_on_failure_with(record);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.left.heap_size() + self.right.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.left.revived();
+        self.right.revived();
+    }
+
+    #[inline]
+    fn tripped_by(&self) -> &'static str {
+        match self.tripped {
+            Side::Left => self.left.tripped_by(),
+            Side::Right => self.right.tripped_by(),
+        }
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        let (left, right) = self.stepped;
+        if left {
+            self.left.discard_trip();
+        }
+        if right {
+            self.right.discard_trip();
+        }
+        self.stepped = (false, false);
+    }
+
+    #[inline]
+    fn record_latency(&mut self, latency: Duration) -> Option<Duration> {
+        let left = self.left.record_latency(latency);
+        let right = self.right.record_latency(latency);
+
+        self.pick(left, right)
+    }
+}
+
+/// A combinator used for join two policies into new one, which trips when both of them trip.
+#[derive(Debug, Clone)]
+pub struct AndAlso<LEFT, RIGHT> {
+    left: LEFT,
+    right: RIGHT,
+    tripped: Side,
+}
+
+impl<LEFT, RIGHT> AndAlso<LEFT, RIGHT>
+where
+    LEFT: FailurePolicy,
+    RIGHT: FailurePolicy,
+{
+    /// Returns the longer delay when both policies have tripped, and remembers which one it was,
+    /// a policy which has tripped alone takes back the step of its backoff.
+    #[inline]
+    fn pick(&mut self, left: Option<Duration>, right: Option<Duration>) -> Option<Duration> {
+        match (left, right) {
+            (Some(l), Some(r)) => {
+                self.tripped = if r > l { Side::Right } else { Side::Left };
+                Some(l.max(r))
+            }
+            (Some(_), None) => {
+                self.left.discard_trip();
+                None
+            }
+            (None, Some(_)) => {
+                self.right.discard_trip();
+                None
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+impl<LEFT, RIGHT> FailurePolicy for AndAlso<LEFT, RIGHT>
+where
+    LEFT: FailurePolicy,
+    RIGHT: FailurePolicy,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.left.describe(f)?;
+        f.write_str(" and ")?;
+        self.right.describe(f)
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        self.left.record_success();
+        self.right.record_success();
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        // Both policies must observe the failure, so don't short circuit here.
+        let left = self.left.mark_dead_on_failure();
+        let right = self.right.mark_dead_on_failure();
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_in(&mut self, class: &'static str) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_in(class);
+        let right = self.right.mark_dead_on_failure_in(class);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn record_success_weighted(&mut self, cost: u32) {
+        self.left.record_success_weighted(cost);
+        self.right.record_success_weighted(cost);
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_weighted(&mut self, cost: u32) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_weighted(cost);
+        let right = self.right.mark_dead_on_failure_weighted(cost);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn record_success_with(&mut self, record: &CallRecord) {
+        self.left.record_success_with(record);
+        self.right.record_success_with(record);
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_with(&mut self, record: &CallRecord) -> Option<Duration> {
+        let left = self.left.mark_dead_on_failure_with(record);
+        let right = self.right.mark_dead_on_failure_with(record);
+
+        self.pick(left, right)
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.left.heap_size() + self.right.heap_size()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        self.left.revived();
+        self.right.revived();
+    }
+
+    #[inline]
+    fn tripped_by(&self) -> &'static str {
+        match self.tripped {
+            Side::Left => self.left.tripped_by(),
+            Side::Right => self.right.tripped_by(),
+        }
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        self.left.discard_trip();
+        self.right.discard_trip();
+    }
+
+    #[inline]
+    fn record_latency(&mut self, latency: Duration) -> Option<Duration> {
+        let left = self.left.record_latency(latency);
+        let right = self.right.record_latency(latency);
+
+        self.pick(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::backoff;
+    use super::super::clock;
+
+    mod consecutive_failures {
+        use super::*;
+
+        #[test]
+        fn fail_on_nth_attempt() {
+            let mut policy = consecutive_failures(3, constant_backoff());
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn reset_to_zero_on_revived() {
+            let mut policy = consecutive_failures(3, constant_backoff());
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+
+            policy.revived();
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn reset_to_zero_on_success() {
+            let mut policy = consecutive_failures(3, constant_backoff());
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+
+            policy.record_success();
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn weighted_calls() {
+            let mut policy = consecutive_failures(3, constant_backoff());
+
+            assert_eq!(None, policy.mark_dead_on_failure_weighted(2));
+            policy.record_success_weighted(5);
+            assert_eq!(0, policy.failure_streak());
+
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure_weighted(3));
+        }
+
+        #[test]
+        fn expose_failure_streak() {
+            let mut policy = consecutive_failures(3, constant_backoff());
+            assert_eq!(3, policy.threshold());
+
+            policy.mark_dead_on_failure();
+            policy.mark_dead_on_failure();
+            assert_eq!(2, policy.failure_streak());
+
+            policy.record_success();
+            assert_eq!(0, policy.failure_streak());
+        }
+
+        #[test]
+        fn reset_when_idle() {
+            clock::freeze(|time| {
+                let mut policy =
+                    consecutive_failures(3, constant_backoff()).idle_reset(60.seconds());
+
+                assert_eq!(None, policy.mark_dead_on_failure());
+                time.advance(30.seconds());
+                assert_eq!(None, policy.mark_dead_on_failure());
+
+                time.advance(60.seconds());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(1, policy.failure_streak());
+
+                time.advance(59.seconds());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            })
+        }
+
+        #[test]
+        fn iterates_over_backoff() {
+            let exp_backoff = exp_backoff();
+            let mut policy = consecutive_failures(1, exp_backoff.clone());
+
+            for i in exp_backoff.take(6) {
+                assert_eq!(Some(i), policy.mark_dead_on_failure());
+            }
+        }
+    }
+
+    mod success_rate_over_time_window {
+        use super::*;
+
+        #[test]
+        fn fail_when_success_rate_not_met() {
+            clock::freeze(|time| {
+                let exp_backoff = exp_backoff();
+                let success_rate_duration = 30.seconds();
+                let mut policy = success_rate_over_time_window(
+                    0.5,
+                    1,
+                    success_rate_duration,
+                    exp_backoff.clone(),
+                );
+
+                assert_eq!(None, policy.mark_dead_on_failure());
+
+                // Advance the time with 'success_rate_duration'.
+                // All mark_dead_on_failure calls should now return Some(Duration),
+                // and should iterate over expBackoffList.
+                time.advance(success_rate_duration);
+
+                for i in exp_backoff.take(6) {
+                    assert_eq!(Some(i), policy.mark_dead_on_failure());
+                }
+            })
+        }
+
+        #[test]
+        fn respects_rps_threshold() {
+            clock::freeze(|time| {
+                let exp_backoff = exp_backoff();
+                let mut policy = success_rate_over_time_window(1.0, 5, 30.seconds(), exp_backoff);
+
+                time.advance(30.seconds());
+
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            });
+        }
+
+        #[test]
+        fn revived_resets_failures() {
+            clock::freeze(|time| {
+                let exp_backoff = constant_backoff();
+                let success_rate_duration = 30.seconds();
+                let mut policy = success_rate_over_time_window(
+                    0.5,
+                    1,
+                    success_rate_duration,
+                    exp_backoff.clone(),
+                );
+
+                time.advance(success_rate_duration);
+                for i in exp_backoff.take(6) {
+                    assert_eq!(Some(i), policy.mark_dead_on_failure());
+                }
+
+                policy.revived();
+
+                // Make sure the failure status has been reset.
+                // This will also be registered as the timestamp of the first request.
+                assert_eq!(None, policy.mark_dead_on_failure());
+
+                // One failure after 'success_rate_duration' should mark the node dead again.
+                time.advance(success_rate_duration);
+                assert!(policy.mark_dead_on_failure().is_some())
+            })
+        }
+
+        #[test]
+        fn fractional_success_rate() {
+            clock::freeze(|time| {
+                let exp_backoff = exp_backoff();
+                let success_rate_duration = 100.seconds();
+                let mut policy = success_rate_over_time_window(
+                    0.5,
+                    1,
+                    success_rate_duration,
+                    exp_backoff.clone(),
+                );
+
+                for _i in 0..100 {
+                    time.advance(1.seconds());
+                    policy.record_success();
+                }
+
+                // With a window of 100 seconds, it will take 100 * ln(2) + 1 = 70 seconds of failures
+                // for the success rate to drop below 0.5 (half-life).
+                for _i in 0..69 {
+                    time.advance(1.seconds());
+                    assert_eq!(None, policy.mark_dead_on_failure(), "n={}", _i);
+                }
+
+                // 70th failure should make markDeadOnFailure() return Some(_)
+                time.advance(1.seconds());
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            })
+        }
+
+        #[test]
+        fn weighted_failure_moves_success_rate() {
+            clock::freeze(|time| {
+                let policy = success_rate_over_time_window(0.5, 1, 10.seconds(), exp_backoff());
+                let mut cheap = policy.clone();
+                let mut expensive = policy;
+
+                for _ in 0..100 {
+                    time.advance(1.seconds());
+                    cheap.record_success();
+                    expensive.record_success();
+                }
+
+                // The same number of calls, but one of them weighs as ten.
+                time.advance(1.seconds());
+                assert_eq!(None, cheap.mark_dead_on_failure());
+                assert!(expensive.mark_dead_on_failure_weighted(10).is_some());
+            })
+        }
+
+        #[test]
+        fn evaluation_interval() {
+            clock::freeze(|time| {
+                let mut policy = success_rate_over_time_window(0.5, 1, 30.seconds(), exp_backoff())
+                    .evaluation_interval(1.seconds());
+
+                time.advance(30.seconds());
+                assert!(policy.mark_dead_on_failure().is_some());
+                assert_eq!(None, policy.mark_dead_on_failure());
+
+                time.advance(1.seconds());
+                assert!(policy.mark_dead_on_failure().is_some());
+
+                // The first failure after reviving is evaluated at once.
+                policy.revived();
+                time.advance(30.seconds());
+                assert!(policy.mark_dead_on_failure().is_some());
+            })
+        }
+    }
+
+    mod backoff_feedback {
+        use super::*;
+
+        #[test]
+        fn escalate_on_open_cycles() {
+            let backoff = backoff::escalating(constant_backoff(), 2.0, 30.seconds());
+            let mut policy = consecutive_failures(1, backoff);
+
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            assert_eq!(Some(10.seconds()), policy.mark_dead_on_failure());
+            assert_eq!(Some(20.seconds()), policy.mark_dead_on_failure());
+            assert_eq!(Some(30.seconds()), policy.mark_dead_on_failure());
+
+            policy.revived();
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn pass_failure_rate() {
+            clock::freeze(|time| {
+                let backoff = backoff::from_feedback(|feedback: &Feedback| {
+                    let rate = feedback.failure_rate.unwrap();
+                    Some(Duration::from_secs((rate * 100.0) as u64))
+                });
+                let mut policy = success_rate_over_time_window(0.5, 1, 10.seconds(), backoff);
+
+                time.advance(10.seconds());
+                assert_eq!(Some(100.seconds()), policy.mark_dead_on_failure());
+            })
+        }
+    }
+
+    mod backoff_reset {
+        use super::*;
+
+        #[test]
+        fn reset_after_successes() {
+            let backoff = exp_backoff();
+            let mut policy =
+                consecutive_failures(1, backoff).backoff_reset(BackoffReset::AfterSuccesses(2));
+
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            policy.revived();
+            policy.record_success();
+            assert_eq!(Some(10.seconds()), policy.mark_dead_on_failure());
+
+            policy.revived();
+            policy.record_success();
+            policy.record_success();
+            assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn reset_after_quiet_period() {
+            clock::freeze(|time| {
+                let backoff = exp_backoff();
+                let mut policy = consecutive_failures(1, backoff)
+                    .backoff_reset(BackoffReset::AfterQuietPeriod(60.seconds()));
+
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+                policy.revived();
+                time.advance(30.seconds());
+                policy.record_success();
+                assert_eq!(Some(10.seconds()), policy.mark_dead_on_failure());
+
+                policy.revived();
+                time.advance(60.seconds());
+                policy.record_success();
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+            })
+        }
+    }
+
+    mod or_else {
+        use super::*;
+
+        #[test]
+        fn compose_policies() {
+            let mut policy = consecutive_failures(3, constant_backoff()).or_else(
+                success_rate_over_time_window(0.5, 100, 10.seconds(), constant_backoff()),
+            );
+
+            policy.record_success();
+            assert_eq!(None, policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn report_tripped_policy() {
+            clock::freeze(|_| {
+                let mut policy = consecutive_failures(3, constant_backoff()).or_else(
+                    max_failures_in_window(2, 10.seconds(), backoff::constant(10.seconds())),
+                );
+
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(Some(10.seconds()), policy.mark_dead_on_failure());
+                assert_eq!("MaxFailuresInWindow", policy.tripped_by());
+
+                // Both trip, the longer delay takes precedence.
+                let mut policy = max_failures_in_window(1, 10.seconds(), constant_backoff())
+                    .or_else(consecutive_failures(1, backoff::constant(30.seconds())));
+                assert_eq!(Some(30.seconds()), policy.mark_dead_on_failure());
+                assert_eq!("ConsecutiveFailures", policy.tripped_by());
+            })
+        }
+    }
+
+    mod and_also {
+        use super::*;
+
+        #[test]
+        fn trip_when_both_trip() {
+            let mut policy = consecutive_failures(2, constant_backoff())
+                .and_also(consecutive_failures(4, backoff::constant(10.seconds())));
+
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(None, policy.mark_dead_on_failure());
+            assert_eq!(Some(10.seconds()), policy.mark_dead_on_failure());
+
+            policy.revived();
+            assert_eq!(None, policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn lone_trips_dont_escalate_backoff() {
+            clock::freeze(|_| {
+                let mut policy = consecutive_failures(1, exp_backoff()).and_also(
+                    max_failures_in_window(3, 60.seconds(), backoff::constant(1.seconds())),
+                );
+
+                // The streak trips alone twice, its backoff starts from the beginning.
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+                assert_eq!("ConsecutiveFailures", policy.tripped_by());
+
+                // The longer delay of the window opens the circuit breaker.
+                let mut policy = consecutive_failures(1, backoff::constant(1.seconds()))
+                    .and_also(max_failures_in_window(2, 60.seconds(), constant_backoff()));
+                assert_eq!(None, policy.mark_dead_on_failure());
+                assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure());
+                assert_eq!("MaxFailuresInWindow", policy.tripped_by());
+            });
+        }
+    }
+
+    mod call_record {
+        use super::super::super::Config;
+        use super::*;
+
+        /// Trips on a failure slower than a second, ignoring faster ones.
+        #[derive(Debug)]
+        struct SlowFailures;
+
+        impl FailurePolicy for SlowFailures {
+            fn record_success(&mut self) {}
+
+            fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+                None
+            }
+
+            fn revived(&mut self) {}
+
+            fn mark_dead_on_failure_with(&mut self, record: &CallRecord) -> Option<Duration> {
+                record
+                    .latency
+                    .filter(|it| *it > 1.seconds())
+                    .map(|_| 5.seconds())
+            }
+        }
+
+        #[test]
+        fn adapt_to_specific_methods() {
+            let now = clock::now();
+            let mut policy = consecutive_failures(3, constant_backoff());
+
+            assert_eq!(
+                None,
+                policy.mark_dead_on_failure_with(&CallRecord::new(now))
+            );
+            assert_eq!(
+                Some(5.seconds()),
+                policy.mark_dead_on_failure_with(&CallRecord::new(now).with_weight(2))
+            );
+
+            policy.revived();
+            policy.mark_dead_on_failure_with(&CallRecord::new(now).with_weight(2));
+            policy.record_success_with(&CallRecord::new(now));
+            assert_eq!(None, policy.mark_dead_on_failure());
+        }
+
+        #[test]
+        fn receive_details_of_calls() {
+            let policy = SlowFailures.or_else(consecutive_failures(3, constant_backoff()));
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+
+            circuit_breaker.on_error_after(Duration::from_millis(10));
+            assert!(circuit_breaker.is_call_permitted());
+            circuit_breaker.on_error_after(2.seconds());
+            assert!(!circuit_breaker.is_call_permitted());
+        }
+    }
+
+    fn constant_backoff() -> backoff::Constant {
+        backoff::constant(5.seconds())
+    }
+
+    fn exp_backoff() -> backoff::Exponential {
+        backoff::exponential(5.seconds(), 60.seconds())
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+    }
+}
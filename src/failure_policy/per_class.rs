@@ -0,0 +1,301 @@
+use std::fmt::{self, Debug};
+use std::mem;
+use std::time::Duration;
+
+use super::FailurePolicy;
+
+/// Creates a policy which accrues failures of each class independently.
+///
+/// Every failure class gets its own policy created by `new_policy`, so, for example, timeouts
+/// may trip the circuit breaker independently of serialization errors. Failures recorded without
+/// a class are accrued by a policy of their own. Classes come from a `FailureClassifier` passed
+/// to `CircuitBreaker::call_classified`, or from `StateMachine::on_error_in`.
+///
+/// Classes are accrued independently, but they share one circuit breaker: when any class trips,
+/// the whole circuit breaker opens and rejects calls which would fail with other classes, or
+/// not fail at all, until it's revived. `tripped_class` tells which class was the offending one,
+/// and `tripped_by` names its policy. To reject only calls of the offending class, keep a
+/// circuit breaker per class instead, e.g. by `partition::Partitioned`.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{backoff, failure_policy, CircuitBreaker, Config};
+///
+/// #[derive(Debug)]
+/// enum Error {
+///     Timeout,
+///     Serialization,
+/// }
+///
+/// let policy = failure_policy::per_class(|| {
+///     failure_policy::consecutive_failures(3, backoff::constant(Duration::from_secs(5)))
+/// });
+/// let circuit_breaker = Config::new().failure_policy(policy).build();
+///
+/// let classify = |err: &Error| match err {
+///     Error::Timeout => Some("timeout"),
+///     Error::Serialization => Some("serialization"),
+/// };
+///
+/// for _ in 0..2 {
+///     let _ = circuit_breaker.call_classified(classify, || Err::<(), _>(Error::Timeout));
+///     let _ = circuit_breaker.call_classified(classify, || Err::<(), _>(Error::Serialization));
+/// }
+///
+/// assert!(circuit_breaker.is_call_permitted());
+/// ```
+pub fn per_class<F, P>(new_policy: F) -> PerClass<F, P>
+where
+    F: FnMut() -> P,
+    P: FailurePolicy,
+{
+    PerClass {
+        new_policy,
+        classes: Vec::new(),
+        tripped_class: None,
+        max_classes: None,
+    }
+}
+
+/// A policy which accrues failures per class, see `per_class`.
+#[derive(Clone)]
+pub struct PerClass<F, P> {
+    new_policy: F,
+    classes: Vec<(Option<&'static str>, P)>,
+    tripped_class: Option<&'static str>,
+    max_classes: Option<usize>,
+}
+
+impl<F, P> PerClass<F, P>
+where
+    F: FnMut() -> P,
+    P: FailurePolicy,
+{
+    /// Keeps policies of at most `max` classes, failures of other classes are accrued by the
+    /// policy of failures without a class.
+    ///
+    /// It bounds the memory when classes come from an open set, e.g. error codes of a backend.
+    pub fn max_classes(mut self, max: usize) -> Self {
+        self.max_classes = Some(max);
+        self
+    }
+
+    /// Returns the class of the failure which tripped the policy last time, if any.
+    pub fn tripped_class(&self) -> Option<&'static str> {
+        self.tripped_class
+    }
+
+    /// Returns the policy accruing failures of the given class, if it has seen any.
+    pub fn class(&self, class: &'static str) -> Option<&P> {
+        self.classes
+            .iter()
+            .find(|(it, _)| *it == Some(class))
+            .map(|(_, policy)| policy)
+    }
+
+    fn mark_dead(&mut self, class: Option<&'static str>) -> Option<Duration> {
+        let known = self.classes.iter().any(|(it, _)| *it == class);
+        let classes = self.classes.iter().filter(|(it, _)| it.is_some()).count();
+        let class = match self.max_classes {
+            Some(max) if class.is_some() && !known && classes >= max => None,
+            _ => class,
+        };
+
+        let idx = match self.classes.iter().position(|(it, _)| *it == class) {
+            Some(idx) => idx,
+            None => {
+                let policy = (self.new_policy)();
+                self.classes.push((class, policy));
+                self.classes.len() - 1
+            }
+        };
+
+        let res = self.classes[idx].1.mark_dead_on_failure();
+        if res.is_some() {
+            self.tripped_class = class;
+        }
+        res
+    }
+}
+
+impl<F, P> FailurePolicy for PerClass<F, P>
+where
+    F: FnMut() -> P,
+    P: FailurePolicy,
+{
+    fn describe(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Policies of classes are alike, they are created by the same function.
+        f.write_str("per_class(")?;
+        match self.classes.first() {
+            Some((_, policy)) => policy.describe(f)?,
+            None => f.write_str("..")?,
+        }
+        f.write_str(")")
+    }
+
+    #[inline]
+    fn record_success(&mut self) {
+        for (_, policy) in self.classes.iter_mut() {
+            policy.record_success();
+        }
+    }
+
+    #[inline]
+    fn mark_dead_on_failure(&mut self) -> Option<Duration> {
+        self.mark_dead(None)
+    }
+
+    #[inline]
+    fn mark_dead_on_failure_in(&mut self, class: &'static str) -> Option<Duration> {
+        self.mark_dead(Some(class))
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.classes.capacity() * mem::size_of::<(Option<&'static str>, P)>()
+            + self
+                .classes
+                .iter()
+                .map(|(_, policy)| policy.heap_size())
+                .sum::<usize>()
+    }
+
+    #[inline]
+    fn revived(&mut self) {
+        for (_, policy) in self.classes.iter_mut() {
+            policy.revived();
+        }
+        self.tripped_class = None;
+    }
+
+    #[inline]
+    fn tripped_by(&self) -> &'static str {
+        self.classes
+            .iter()
+            .find(|(class, _)| *class == self.tripped_class)
+            .map_or("PerClass", |(_, policy)| policy.tripped_by())
+    }
+
+    #[inline]
+    fn discard_trip(&mut self) {
+        // Only the policy which has tripped on the last call has a step to take back.
+        for (_, policy) in self.classes.iter_mut() {
+            policy.discard_trip();
+        }
+    }
+
+    #[inline]
+    fn record_latency(&mut self, latency: Duration) -> Option<Duration> {
+        self.classes
+            .iter_mut()
+            .filter_map(|(_, policy)| policy.record_latency(latency))
+            .fold(None, |acc: Option<Duration>, it| {
+                Some(acc.map_or(it, |acc| acc.max(it)))
+            })
+    }
+}
+
+impl<F, P> Debug for PerClass<F, P>
+where
+    P: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PerClass")
+            .field("classes", &self.classes)
+            .field("tripped_class", &self.tripped_class)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::backoff;
+    use super::super::consecutive_failures;
+    use super::*;
+
+    #[test]
+    fn trip_independently() {
+        let mut policy = per_class(|| consecutive_failures(2, backoff::constant(5.seconds())));
+
+        assert_eq!(None, policy.mark_dead_on_failure_in("timeout"));
+        assert_eq!(None, policy.mark_dead_on_failure_in("serialization"));
+        assert_eq!(None, policy.mark_dead_on_failure());
+        assert_eq!(None, policy.tripped_class());
+
+        assert_eq!(Some(5.seconds()), policy.mark_dead_on_failure_in("timeout"));
+        assert_eq!(Some("timeout"), policy.tripped_class());
+        assert_eq!(
+            Some(2),
+            policy.class("timeout").map(|it| it.failure_streak())
+        );
+        assert_eq!(
+            Some(1),
+            policy.class("serialization").map(|it| it.failure_streak())
+        );
+
+        policy.revived();
+        assert_eq!(None, policy.tripped_class());
+        assert_eq!(None, policy.mark_dead_on_failure_in("timeout"));
+
+        policy.record_success();
+        assert_eq!(None, policy.mark_dead_on_failure_in("serialization"));
+    }
+
+    #[test]
+    fn bound_classes() {
+        let mut policy =
+            per_class(|| consecutive_failures(2, backoff::constant(5.seconds()))).max_classes(1);
+
+        assert_eq!(None, policy.mark_dead_on_failure_in("timeout"));
+        assert_eq!(None, policy.mark_dead_on_failure_in("http-502"));
+        assert!(policy.class("http-502").is_none());
+
+        // Failures of classes over the limit are accrued together with unclassified ones.
+        assert_eq!(
+            Some(5.seconds()),
+            policy.mark_dead_on_failure_in("http-503")
+        );
+        assert_eq!(None, policy.tripped_class());
+        assert!(policy.heap_size() > 0);
+    }
+
+    #[test]
+    fn open_whole_circuit_breaker() {
+        use super::super::super::{CircuitBreaker, Config, Error};
+
+        let policy = per_class(|| consecutive_failures(2, backoff::constant(5.seconds())));
+        let circuit_breaker = Config::new().failure_policy(policy).build();
+        assert!(circuit_breaker.to_string().contains("policy per_class(..)"));
+
+        circuit_breaker.on_error_in("timeout");
+        circuit_breaker.on_error_in("timeout");
+
+        // Calls which would fail with another class, or succeed, are rejected too.
+        assert!(matches!(
+            circuit_breaker.call_classified(|_: &()| Some("serialization"), || Ok(())),
+            Err(Error::Rejected(_))
+        ));
+        assert!(circuit_breaker
+            .to_string()
+            .contains("policy per_class(consecutive_failures(2))"));
+    }
+
+    #[test]
+    fn report_tripped_policy() {
+        let mut policy = per_class(|| consecutive_failures(1, backoff::constant(5.seconds())));
+        assert_eq!("PerClass", policy.tripped_by());
+
+        assert!(policy.mark_dead_on_failure_in("timeout").is_some());
+        assert_eq!("ConsecutiveFailures", policy.tripped_by());
+    }
+
+    trait IntoDuration {
+        fn seconds(self) -> Duration;
+    }
+
+    impl IntoDuration for u64 {
+        fn seconds(self) -> Duration {
+            Duration::from_secs(self)
+        }
+    }
+}
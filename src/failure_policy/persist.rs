@@ -0,0 +1,279 @@
+use std::time::{Duration, Instant};
+
+use super::super::clock;
+use super::super::windowed_adder::WindowState;
+use super::{
+    AndAlso, BackoffState, ConsecutiveFailures, OrElse, SuccessRateOverTimeWindow, WithFeedback,
+};
+
+/// A failure policy which internal state may be saved and restored, e.g. to keep the failure
+/// history over a restart, to share it between processes, or to inspect it while debugging.
+///
+/// Moments are saved as ages relative to the moment of saving, so a state restored in another
+/// process keeps its windows sliding. With the `serde` feature saved states are serializable.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{backoff, failure_policy::{self, FailurePolicy, Persist}};
+///
+/// let backoff = backoff::constant(Duration::from_secs(30));
+/// let mut policy = failure_policy::consecutive_failures(3, backoff.clone());
+/// policy.mark_dead_on_failure();
+/// policy.mark_dead_on_failure();
+///
+/// let mut restored = failure_policy::consecutive_failures(3, backoff);
+/// restored.restore(&policy.save());
+/// assert_eq!(Some(Duration::from_secs(30)), restored.mark_dead_on_failure());
+/// ```
+pub trait Persist {
+    /// The saved state of the policy.
+    type State;
+
+    /// Returns the current state of counters and windows.
+    fn save(&self) -> Self::State;
+
+    /// Restores the `state` returned by `save` of a policy with the same configuration.
+    fn restore(&mut self, state: &Self::State);
+}
+
+/// A saved position of the backoff of a policy.
+///
+/// The backoff is restarted on restore and advanced by the number of open cycles, so a jittered
+/// backoff may produce other delays than the original one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct BackoffProgress {
+    /// The number of open cycles since the backoff was restarted.
+    pub open_cycles: u32,
+    /// A pending restart of the backoff, with the number of successes and the time since it's
+    /// pending, see `BackoffReset`.
+    pub pending_reset: Option<(u32, Duration)>,
+}
+
+/// A saved state of `ConsecutiveFailures`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ConsecutiveFailuresState {
+    /// The number of consecutive failures.
+    pub failure_streak: u32,
+    /// The time since the last call, if the policy tracks idle periods.
+    pub idle: Option<Duration>,
+    /// The position of the backoff.
+    pub backoff: BackoffProgress,
+}
+
+/// A saved state of `MaxFailuresInWindow`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct MaxFailuresInWindowState {
+    /// Failures within the window.
+    pub failures: WindowState,
+    /// The position of the backoff.
+    pub backoff: BackoffProgress,
+}
+
+/// A saved state of `SuccessRateOverTimeWindow`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct SuccessRateState {
+    /// The time since the policy started observing calls, or was revived.
+    pub elapsed: Duration,
+    /// The moving average of the success rate.
+    pub success_rate: f64,
+    /// When the last call was observed, relative to the start, if there were calls.
+    pub observed_at: Option<Duration>,
+    /// Calls within the window.
+    pub requests: WindowState,
+    /// The position of the backoff.
+    pub backoff: BackoffProgress,
+}
+
+/// Returns the time elapsed since the moment `at`.
+#[inline]
+pub(crate) fn age(at: Instant) -> Duration {
+    clock::now().saturating_duration_since(at)
+}
+
+/// Returns the moment which was `age` ago.
+#[inline]
+pub(crate) fn ago(age: Duration) -> Instant {
+    let now = clock::now();
+    now.checked_sub(age).unwrap_or(now)
+}
+
+impl<BACKOFF> BackoffState<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    pub(crate) fn save(&self) -> BackoffProgress {
+        BackoffProgress {
+            open_cycles: self.open_cycles,
+            pending_reset: self
+                .pending
+                .map(|(successes, since)| (successes, age(since))),
+        }
+    }
+
+    pub(crate) fn restore(&mut self, progress: &BackoffProgress) {
+        self.restart();
+        for _ in 0..progress.open_cycles {
+            self.next(None);
+        }
+        self.previous = None;
+        self.pending = progress
+            .pending_reset
+            .map(|(successes, since)| (successes, ago(since)));
+    }
+}
+
+impl<BACKOFF> Persist for ConsecutiveFailures<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    type State = ConsecutiveFailuresState;
+
+    fn save(&self) -> Self::State {
+        ConsecutiveFailuresState {
+            failure_streak: self.consecutive_failures,
+            idle: self.last_call.map(age),
+            backoff: self.backoff.save(),
+        }
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.consecutive_failures = state.failure_streak;
+        self.last_call = state.idle.map(ago);
+        self.backoff.restore(&state.backoff);
+    }
+}
+
+impl<BACKOFF> Persist for SuccessRateOverTimeWindow<BACKOFF>
+where
+    BACKOFF: WithFeedback + Clone,
+{
+    type State = SuccessRateState;
+
+    fn save(&self) -> Self::State {
+        let observed_at = if self.ema.is_empty() {
+            None
+        } else {
+            Some(Duration::from_millis(self.ema.timestamp()))
+        };
+        SuccessRateState {
+            elapsed: age(self.now),
+            success_rate: self.ema.last(),
+            observed_at,
+            requests: self.request_counter.save(),
+            backoff: self.backoff.save(),
+        }
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.now = ago(state.elapsed);
+        match state.observed_at {
+            Some(at) => self.ema.restore(at.as_millis() as u64, state.success_rate),
+            None => self.ema.reset(),
+        }
+        self.request_counter.restore(&state.requests);
+        self.cadence.reset();
+        self.backoff.restore(&state.backoff);
+    }
+}
+
+impl<LEFT, RIGHT> Persist for OrElse<LEFT, RIGHT>
+where
+    LEFT: Persist,
+    RIGHT: Persist,
+{
+    type State = (LEFT::State, RIGHT::State);
+
+    fn save(&self) -> Self::State {
+        (self.left.save(), self.right.save())
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.left.restore(&state.0);
+        self.right.restore(&state.1);
+    }
+}
+
+impl<LEFT, RIGHT> Persist for AndAlso<LEFT, RIGHT>
+where
+    LEFT: Persist,
+    RIGHT: Persist,
+{
+    type State = (LEFT::State, RIGHT::State);
+
+    fn save(&self) -> Self::State {
+        (self.left.save(), self.right.save())
+    }
+
+    fn restore(&mut self, state: &Self::State) {
+        self.left.restore(&state.0);
+        self.right.restore(&state.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock};
+    use super::super::{consecutive_failures, max_failures_in_window, FailurePolicy};
+    use super::*;
+
+    #[test]
+    fn restore_progress() {
+        clock::freeze(|time| {
+            let backoff = backoff::exponential(Duration::from_secs(10), Duration::from_secs(60));
+            let new_policy = || {
+                consecutive_failures(2, backoff.clone()).or_else(max_failures_in_window(
+                    5,
+                    Duration::from_secs(10),
+                    backoff.clone(),
+                ))
+            };
+
+            let mut policy = new_policy();
+            policy.mark_dead_on_failure();
+            assert_eq!(Some(Duration::from_secs(10)), policy.mark_dead_on_failure());
+            time.advance(Duration::from_secs(3));
+
+            let mut restored = new_policy();
+            restored.restore(&policy.save());
+            assert_eq!(policy.save(), restored.save());
+
+            // Both the streak and the backoff continue where they stopped.
+            assert_eq!(
+                Some(Duration::from_secs(20)),
+                restored.mark_dead_on_failure()
+            );
+            restored.revived();
+            assert_eq!(None, restored.mark_dead_on_failure());
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_state() {
+        use super::super::success_rate_over_time_window;
+
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(30));
+            let new_policy =
+                || success_rate_over_time_window(0.5, 2, Duration::from_secs(10), backoff.clone());
+
+            let mut policy = new_policy();
+            policy.record_success();
+            time.advance(Duration::from_secs(1));
+            policy.mark_dead_on_failure();
+
+            let json = serde_json::to_string(&policy.save()).unwrap();
+            let mut restored = new_policy();
+            restored.restore(&serde_json::from_str(&json).unwrap());
+            assert_eq!(policy.save(), restored.save());
+        });
+    }
+}
@@ -25,6 +25,37 @@ impl<ERROR> FailurePredicate<ERROR> for Any {
     }
 }
 
+/// Sorts errors into failure classes, which are accrued independently by class aware failure
+/// policies, see `failure_policy::per_class`.
+pub trait FailureClassifier<ERROR> {
+    /// Must return the class of the failure, or `None` if the error shouldn't count as a failure.
+    fn classify(&self, err: &ERROR) -> Option<&'static str>;
+}
+
+impl<F, ERROR> FailureClassifier<ERROR> for F
+where
+    F: Fn(&ERROR) -> Option<&'static str>,
+{
+    #[inline]
+    fn classify(&self, err: &ERROR) -> Option<&'static str> {
+        self(err)
+    }
+}
+
+/// How a call is recorded by the circuit breaker, see `futures::CircuitBreaker::call_with_outcome`.
+///
+/// `futures::stream::RetryStream::with_outcome` judges items the same way, a failure recreates
+/// the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The call is recorded as success.
+    Success,
+    /// The call is recorded as failure.
+    Failure,
+    /// The call isn't recorded at all.
+    Ignored,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::{FailurePolicy, Instrument, WeakStateMachine};
+
+/// Runs the `check` on schedule while the circuit breaker is open, and records its result, see
+/// `Config::background_probing`.
+///
+/// The check runs once the delay of the open state has expired, and the circuit breaker closes
+/// only when it passes. While the circuit breaker isn't open, its state is looked up every
+/// `interval`, slept by `sleep`, see the timers section of the `futures` module.
+///
+/// The returned future completes once the circuit breaker is dropped, so it's usually spawned.
+///
+/// ```
+/// # async {
+/// use std::time::Duration;
+/// use failsafe::{futures::health_check, Config};
+///
+/// let circuit_breaker = Config::new().background_probing().build();
+///
+/// tokio::spawn(health_check(
+///     circuit_breaker.downgrade(),
+///     Duration::from_secs(1),
+///     || async { true },
+///     tokio::time::sleep,
+/// ));
+/// # };
+/// ```
+pub async fn health_check<POLICY, INSTRUMENT, CHECK, PASSED, SLEEP, SLEPT>(
+    state_machine: WeakStateMachine<POLICY, INSTRUMENT>,
+    interval: Duration,
+    mut check: CHECK,
+    mut sleep: SLEEP,
+) where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    CHECK: FnMut() -> PASSED,
+    PASSED: Future<Output = bool>,
+    SLEEP: FnMut(Duration) -> SLEPT,
+    SLEPT: Future<Output = ()>,
+{
+    loop {
+        // Strong references aren't kept while waiting, so the circuit breaker may be dropped.
+        let delay = match state_machine.upgrade() {
+            Some(state_machine) => state_machine.health_check_delay(),
+            None => return,
+        };
+
+        match delay {
+            Some(delay) if delay == Duration::from_secs(0) => {
+                let passed = check().await;
+                match state_machine.upgrade() {
+                    Some(state_machine) => state_machine.on_health_check(passed),
+                    None => return,
+                }
+            }
+            Some(delay) => sleep(delay).await,
+            None => sleep(interval).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::time::Duration;
+
+    use futures::future;
+
+    use super::*;
+    use crate::{backoff, clock, failure_policy, instrument::State, Config};
+
+    #[test]
+    fn close_when_passed() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .background_probing()
+                .build();
+            circuit_breaker.on_error();
+
+            let weak = circuit_breaker.downgrade();
+            let circuit_breaker = RefCell::new(Some(circuit_breaker));
+            let checks = Cell::new(0);
+            let closed = Cell::new(false);
+
+            futures::executor::block_on(health_check(
+                weak,
+                Duration::from_secs(1),
+                || {
+                    checks.set(checks.get() + 1);
+                    future::ready(checks.get() > 1)
+                },
+                |duration| {
+                    time.advance(duration);
+                    // Drop the circuit breaker once it's closed, so the loop completes.
+                    let mut circuit_breaker = circuit_breaker.borrow_mut();
+                    if let Some(State::Closed) = circuit_breaker.as_ref().map(|it| it.state()) {
+                        closed.set(true);
+                        circuit_breaker.take();
+                    }
+                    future::ready(())
+                },
+            ));
+
+            assert!(closed.get());
+            assert_eq!(2, checks.get());
+        });
+    }
+}
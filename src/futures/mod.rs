@@ -29,21 +29,41 @@
 //! let result = future.await;
 //!
 //! # }; // async
+//! ```
+//!
+//! # Timers
+//!
+//! The crate has no timer, so adapters which wait take a `sleep` function, which returns a
+//! future completing after the given duration, e.g. `tokio::time::sleep`. These are
+//! `stream::BreakerStream::wait_while_open`, `stream::RetryStream`, `health_check` and
+//! `tower::Guarded::wait_while_open`.
+//!
+//! # Paused time
+//!
+//! With the `tokio-time` feature circuit breakers read time from tokio, so tests running with
+//! `#[tokio::test(start_paused = true)]` may `tokio::time::advance` past the delay of the open
+//! state instantly, instead of sleeping.
 
-use std::future::Future;
+use std::convert::Infallible;
+use std::future::{self, Future};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
 use futures_core::future::TryFuture;
 
+use super::clock;
 use super::error::Error;
 use super::failure_policy::FailurePolicy;
-use super::failure_predicate::{self, FailurePredicate};
+use super::failure_predicate::{self, FailurePredicate, Outcome};
 use super::instrument::Instrument;
 use super::state_machine::StateMachine;
 
+mod health_check;
 pub mod stream;
 
+pub use self::health_check::health_check;
+
 /// A futures aware circuit breaker's public interface.
 pub trait CircuitBreaker {
     #[doc(hidden)]
@@ -83,6 +103,32 @@ pub trait CircuitBreaker {
     where
         F: TryFuture,
         P: FailurePredicate<F::Error>;
+
+    /// Executes a given future, which output isn't a `Result`, within circuit breaker.
+    ///
+    /// The output is judged by the provided function, e.g. an HTTP response with the status 503
+    /// may be recorded as failure, and returned as is unless the call is rejected.
+    #[inline]
+    fn call_with_outcome<F, J>(
+        &self,
+        judge: J,
+        f: F,
+    ) -> OutcomeFuture<F, Self::FailurePolicy, Self::Instrument, J>
+    where
+        F: Future,
+        J: FnMut(&F::Output) -> Outcome,
+    {
+        // The state machine is taken from a call which is never polled, so it requests no
+        // permission, and implementations of `call_with` provide it.
+        let ResponseFuture { state_machine, .. } =
+            self.call_with(failure_predicate::Any, future::ready(Ok::<(), ()>(())));
+        OutcomeFuture {
+            future: f,
+            state_machine,
+            judge,
+            started: None,
+        }
+    }
 }
 
 impl<POLICY, INSTRUMENT> CircuitBreaker for StateMachine<POLICY, INSTRUMENT>
@@ -112,7 +158,7 @@ where
             future: f,
             state_machine: self.clone(),
             predicate,
-            ask: false,
+            started: None,
         }
     }
 }
@@ -125,7 +171,7 @@ pin_project_lite::pin_project! {
         future: FUTURE,
         state_machine: StateMachine<POLICY, INSTRUMENT>,
         predicate: PREDICATE,
-        ask: bool,
+        started: Option<Instant>,
     }
 }
 
@@ -142,23 +188,27 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let this = self.project();
 
-        if !*this.ask {
-            *this.ask = true;
-            if !this.state_machine.is_call_permitted() {
-                return Poll::Ready(Err(Error::Rejected));
+        let started = match *this.started {
+            Some(started) => started,
+            None => {
+                if let Err(rejection) = this.state_machine.permit() {
+                    return Poll::Ready(Err(Error::Rejected(rejection)));
+                }
+                *this.started.get_or_insert(clock::now())
             }
-        }
+        };
 
         match this.future.try_poll(cx) {
             Poll::Ready(Ok(ok)) => {
-                this.state_machine.on_success();
+                this.state_machine.on_success_after(clock::now() - started);
                 Poll::Ready(Ok(ok))
             }
             Poll::Ready(Err(err)) => {
+                let latency = clock::now() - started;
                 if this.predicate.is_err(&err) {
-                    this.state_machine.on_error();
+                    this.state_machine.on_error_after(latency);
                 } else {
-                    this.state_machine.on_success();
+                    this.state_machine.on_success_after(latency);
                 }
                 Poll::Ready(Err(Error::Inner(err)))
             }
@@ -167,6 +217,55 @@ where
     }
 }
 
+pin_project_lite::pin_project! {
+    /// A circuit breaker's future, which output is judged by a function.
+    #[allow(missing_debug_implementations)]
+    pub struct OutcomeFuture<FUTURE, POLICY, INSTRUMENT, JUDGE> {
+        #[pin]
+        future: FUTURE,
+        state_machine: StateMachine<POLICY, INSTRUMENT>,
+        judge: JUDGE,
+        started: Option<Instant>,
+    }
+}
+
+impl<FUTURE, POLICY, INSTRUMENT, JUDGE> Future for OutcomeFuture<FUTURE, POLICY, INSTRUMENT, JUDGE>
+where
+    FUTURE: Future,
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    JUDGE: FnMut(&FUTURE::Output) -> Outcome,
+{
+    type Output = Result<FUTURE::Output, Error<Infallible>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+
+        let started = match *this.started {
+            Some(started) => started,
+            None => {
+                if let Err(rejection) = this.state_machine.permit() {
+                    return Poll::Ready(Err(Error::Rejected(rejection)));
+                }
+                *this.started.get_or_insert(clock::now())
+            }
+        };
+
+        match this.future.poll(cx) {
+            Poll::Ready(output) => {
+                let latency = clock::now() - started;
+                match (this.judge)(&output) {
+                    Outcome::Success => this.state_machine.on_success_after(latency),
+                    Outcome::Failure => this.state_machine.on_error_after(latency),
+                    Outcome::Ignored => {}
+                }
+                Poll::Ready(Ok(output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -203,7 +302,7 @@ mod tests {
         let future = delay_for(Duration::from_secs(1));
         let future = circuit_breaker.call(future);
         match future.await {
-            Err(Error::Rejected) => {}
+            Err(Error::Rejected(_)) => {}
             err => unreachable!("{:?}", err),
         }
         assert!(!circuit_breaker.is_call_permitted());
@@ -233,6 +332,47 @@ mod tests {
         assert!(!circuit_breaker.is_call_permitted());
     }
 
+    #[tokio::test]
+    async fn call_with_outcome() {
+        let circuit_breaker = new_circuit_breaker();
+        let judge = |status: &u16| match status {
+            503 => Outcome::Failure,
+            404 => Outcome::Ignored,
+            _ => Outcome::Success,
+        };
+
+        for status in &[200, 404] {
+            let future = circuit_breaker.call_with_outcome(judge, future::ready(*status));
+            assert_eq!(*status, future.await.unwrap());
+            assert!(circuit_breaker.is_call_permitted());
+        }
+
+        let future = circuit_breaker.call_with_outcome(judge, future::ready(503));
+        assert_eq!(503, future.await.unwrap());
+        assert!(!circuit_breaker.is_call_permitted());
+
+        let future = circuit_breaker.call_with_outcome(judge, future::ready(200));
+        match future.await {
+            Err(Error::Rejected(_)) => {}
+            err => unreachable!("{:?}", err),
+        }
+    }
+
+    #[cfg(feature = "tokio-time")]
+    #[tokio::test(start_paused = true)]
+    async fn paused_time() {
+        let circuit_breaker = new_circuit_breaker();
+
+        let future = circuit_breaker.call(future::err::<(), ()>(()));
+        assert!(future.await.is_err());
+        assert!(!circuit_breaker.is_call_permitted());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let future = circuit_breaker.call(delay_for(Duration::from_secs(1)));
+        future.await.unwrap();
+        assert!(circuit_breaker.is_call_permitted());
+    }
+
     fn new_circuit_breaker() -> impl CircuitBreaker {
         let backoff = backoff::constant(Duration::from_secs(5));
         let policy = failure_policy::consecutive_failures(1, backoff);
@@ -1,24 +1,46 @@
 //! calls CircuitBreaker in a Stream that can be polled with `next()`
+use std::convert::Infallible;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
 use std::task;
+use std::time::Duration;
 
-use futures_core::Stream;
+use futures_core::{ready, Stream};
 
-use crate::{failure_predicate, FailurePolicy, FailurePredicate, StateMachine};
+use crate::failure_predicate::{self, FailurePredicate, Outcome};
+use crate::state_machine::{WAIT_INTERVAL, WAIT_PRECISION};
+use crate::{FailurePolicy, Instrument, Rejection, StateMachine};
 
 pin_project_lite::pin_project! {
     /// Stream that holds `StateMachine` and calls stream future
+    ///
+    /// While the circuit breaker is open, every poll returns a rejection, unless the stream waits
+    /// instead, see `wait_while_open`. Items are judged by `Err` and the predicate, or by a
+    /// function, see `with_outcome`.
     #[derive(Debug, Clone)]
-    pub struct BreakerStream<S, P, Pol, Ins> {
+    pub struct BreakerStream<S, P, Pol, Ins, W = (), M = ByPredicate> {
         breaker: StateMachine<Pol, Ins>,
         #[pin]
         stream: S,
         predicate: P,
+        #[pin]
+        wait: W,
+        mode: PhantomData<M>,
     }
 }
 
-impl<T, E, S, Pol, Ins> BreakerStream<S, failure_predicate::Any, Pol, Ins>
+/// Marks a `BreakerStream` which judges `Result` items by `Err` and the predicate.
+#[derive(Debug, Clone, Copy)]
+pub struct ByPredicate;
+
+/// Marks a `BreakerStream` which judges items by a function, see `BreakerStream::with_outcome`.
+#[derive(Debug, Clone, Copy)]
+pub struct ByOutcome;
+
+impl<S, Pol, Ins> BreakerStream<S, failure_predicate::Any, Pol, Ins>
 where
-    S: Stream<Item = Result<T, E>>,
+    S: Stream,
 {
     /// create new circuit breaker stream
     pub fn new(breaker: StateMachine<Pol, Ins>, stream: S) -> Self {
@@ -26,6 +48,8 @@ where
             breaker,
             stream,
             predicate: crate::failure_predicate::Any,
+            wait: (),
+            mode: PhantomData,
         }
     }
 }
@@ -41,20 +65,157 @@ where
             breaker,
             stream,
             predicate,
+            wait: (),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<S, P, Pol, Ins, M> BreakerStream<S, P, Pol, Ins, (), M> {
+    /// wait while the circuit breaker is open instead of returning rejections, so the pipeline
+    /// applies backpressure
+    ///
+    /// The stream is polled again once the delay of the open state expires, or periodically
+    /// when the delay is unknown, e.g. the circuit breaker is forced open. Delays are slept by
+    /// `sleep`, see the timers section of the `futures` module.
+    pub fn wait_while_open<Sleep, Slept>(
+        self,
+        sleep: Sleep,
+    ) -> BreakerStream<S, P, Pol, Ins, WaitWhileOpen<Sleep, Slept>, M>
+    where
+        Sleep: FnMut(Duration) -> Slept,
+        Slept: Future<Output = ()>,
+    {
+        BreakerStream {
+            breaker: self.breaker,
+            stream: self.stream,
+            predicate: self.predicate,
+            wait: WaitWhileOpen::new(sleep),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<S, P, Pol, Ins, W, M> BreakerStream<S, P, Pol, Ins, W, M> {
+    /// judge items by the provided function rather than by `Err`, e.g. a stream of HTTP
+    /// responses, where ones with the status 5xx count as failures, items are passed on as `Ok`
+    pub fn with_outcome<J>(self, judge: J) -> BreakerStream<S, J, Pol, Ins, W, ByOutcome>
+    where
+        S: Stream,
+        J: FnMut(&S::Item) -> Outcome,
+    {
+        BreakerStream {
+            breaker: self.breaker,
+            stream: self.stream,
+            predicate: judge,
+            wait: self.wait,
+            mode: PhantomData,
         }
     }
+
     /// return a reference to the underlying state machine
     pub fn state_machine(&self) -> &StateMachine<Pol, Ins> {
         &self.breaker
     }
+
+    /// return a reference to the wrapped stream
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// return a pinned mutable reference to the wrapped stream
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().stream
+    }
+}
+
+/// What `BreakerStream` does while the circuit breaker rejects polls.
+pub trait WhileOpen {
+    /// Polls waiting for the `delay`, or returns `None` to return the rejection instead.
+    fn poll_wait(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        delay: Duration,
+    ) -> Option<task::Poll<()>>;
+
+    /// Stops waiting once calls are permitted, e.g. after a transition woke the task early.
+    ///
+    /// The default implementation does nothing.
+    #[inline]
+    fn stop(self: std::pin::Pin<&mut Self>) {}
+}
+
+/// Returns rejections.
+impl WhileOpen for () {
+    #[inline]
+    fn poll_wait(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut task::Context<'_>,
+        _delay: Duration,
+    ) -> Option<task::Poll<()>> {
+        None
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Waits while the circuit breaker is open, see `BreakerStream::wait_while_open`.
+    #[derive(Debug, Clone)]
+    pub struct WaitWhileOpen<Sleep, Slept> {
+        sleep: Sleep,
+        #[pin]
+        sleeping: Option<Slept>,
+    }
+}
+
+impl<Sleep, Slept> WaitWhileOpen<Sleep, Slept> {
+    pub(crate) fn new(sleep: Sleep) -> Self {
+        WaitWhileOpen {
+            sleep,
+            sleeping: None,
+        }
+    }
+}
+
+impl<Sleep, Slept> WhileOpen for WaitWhileOpen<Sleep, Slept>
+where
+    Sleep: FnMut(Duration) -> Slept,
+    Slept: Future<Output = ()>,
+{
+    fn poll_wait(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        delay: Duration,
+    ) -> Option<task::Poll<()>> {
+        let mut this = self.project();
+        if this.sleeping.is_none() {
+            this.sleeping.set(Some((this.sleep)(delay)));
+        }
+
+        let sleeping = this
+            .sleeping
+            .as_mut()
+            .as_pin_mut()
+            .expect("sleep is started above");
+        let poll = sleeping.poll(cx);
+        if poll.is_ready() {
+            this.sleeping.set(None);
+        }
+        Some(poll)
+    }
+
+    #[inline]
+    fn stop(self: std::pin::Pin<&mut Self>) {
+        self.project().sleeping.set(None);
+    }
 }
 
-impl<T, E, S, P, Pol, Ins> Stream for BreakerStream<S, P, Pol, Ins>
+impl<T, E, S, P, Pol, Ins, W> Stream for BreakerStream<S, P, Pol, Ins, W>
 where
     S: Stream<Item = Result<T, E>>,
     P: FailurePredicate<E>,
     Pol: FailurePolicy,
-    Ins: crate::Instrument,
+    Ins: Instrument,
+    W: WhileOpen,
 {
     type Item = Result<T, crate::Error<E>>;
 
@@ -64,8 +225,8 @@ where
     ) -> task::Poll<Option<Self::Item>> {
         use task::Poll;
         let this = self.project();
-        if !this.breaker.is_call_permitted() {
-            return Poll::Ready(Some(Err(crate::Error::Rejected)));
+        if let Err(rejection) = ready!(poll_permit(this.breaker, this.wait, cx)) {
+            return Poll::Ready(Some(Err(crate::Error::Rejected(rejection))));
         }
 
         match this.stream.poll_next(cx) {
@@ -87,11 +248,267 @@ where
     }
 }
 
+impl<S, J, Pol, Ins, W> Stream for BreakerStream<S, J, Pol, Ins, W, ByOutcome>
+where
+    S: Stream,
+    J: FnMut(&S::Item) -> Outcome,
+    Pol: FailurePolicy,
+    Ins: Instrument,
+    W: WhileOpen,
+{
+    type Item = Result<S::Item, crate::Error<Infallible>>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        use task::Poll;
+        let this = self.project();
+        if let Err(rejection) = ready!(poll_permit(this.breaker, this.wait, cx)) {
+            return Poll::Ready(Some(Err(crate::Error::Rejected(rejection))));
+        }
+
+        match ready!(this.stream.poll_next(cx)) {
+            Some(item) => {
+                match (this.predicate)(&item) {
+                    Outcome::Success => this.breaker.on_success(),
+                    Outcome::Failure => this.breaker.on_error(),
+                    Outcome::Ignored => {}
+                }
+                Poll::Ready(Some(Ok(item)))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Waits for the permission to poll the stream, or returns the rejection, see `WhileOpen`.
+fn poll_permit<Pol, Ins, W>(
+    breaker: &StateMachine<Pol, Ins>,
+    mut wait: Pin<&mut W>,
+    cx: &mut task::Context<'_>,
+) -> task::Poll<Result<(), Rejection>>
+where
+    Pol: FailurePolicy,
+    Ins: Instrument,
+    W: WhileOpen,
+{
+    use task::Poll;
+    while let Err(rejection) = breaker.permit() {
+        let delay = match rejection.retry_after() {
+            Some(retry_after) => retry_after + WAIT_PRECISION,
+            None => WAIT_INTERVAL,
+        };
+        match wait.as_mut().poll_wait(cx, delay) {
+            Some(Poll::Ready(())) => {}
+            Some(Poll::Pending) => {
+                // A transition wakes the task before the delay expires, e.g. when the circuit
+                // breaker is reset or closed by a health check.
+                breaker.register_waker(cx.waker());
+                return Poll::Pending;
+            }
+            None => return Poll::Ready(Err(rejection)),
+        }
+    }
+    wait.stop();
+    Poll::Ready(Ok(()))
+}
+
+pin_project_lite::pin_project! {
+    /// Stream that recreates the underlying stream with backoff when it yields an error, e.g. a
+    /// reconnecting subscription.
+    ///
+    /// An error is passed on to the consumer, then the failed stream is dropped, and a new one is
+    /// created by the factory once the next delay of the backoff has elapsed. The backoff starts
+    /// over after every successful item. The retry stream ends when the underlying stream ends,
+    /// or when the backoff is exhausted, then the last error is the final item, `is_exhausted`
+    /// tells the two apart and `attempts` tells how many streams were created.
+    ///
+    /// Items which aren't `Result`s, or `Ok` ones which should be retried as well, are judged by
+    /// a function, see `with_outcome`.
+    ///
+    /// Delays are slept by `sleep`, see the timers section of the `futures` module.
+    ///
+    /// Wrapped into `BreakerStream`, errors of every attempt are recorded by the circuit breaker,
+    /// and reconnections stop while it's open.
+    ///
+    /// ```
+    /// # async {
+    /// use std::time::Duration;
+    /// use failsafe::{backoff, futures::stream::{BreakerStream, RetryStream}, Config};
+    /// use futures::{stream, StreamExt};
+    ///
+    /// // E.g. a subscription which may break.
+    /// fn subscribe() -> impl futures::Stream<Item = Result<u32, ()>> {
+    ///     stream::iter(vec![Ok(1), Ok(2), Err(())])
+    /// }
+    ///
+    /// let backoff = backoff::exponential(Duration::from_secs(1), Duration::from_secs(60));
+    /// let retry = RetryStream::new(subscribe, backoff.take(5), tokio::time::sleep);
+    /// let stream = BreakerStream::new(Config::new().build(), retry);
+    /// futures::pin_mut!(stream);
+    ///
+    /// while let Some(message) = stream.next().await {
+    ///     println!("{:?}", message);
+    /// }
+    /// # };
+    /// ```
+    #[derive(Debug)]
+    pub struct RetryStream<F, S, B, Sleep, Slept, J> {
+        connect: F,
+        #[pin]
+        stream: Option<S>,
+        backoff: B,
+        delays: B,
+        sleep: Sleep,
+        #[pin]
+        sleeping: Option<Slept>,
+        judge: J,
+        attempts: u32,
+        done: bool,
+        exhausted: bool,
+    }
+}
+
+impl<T, E, F, S, B, Sleep, Slept> RetryStream<F, S, B, Sleep, Slept, fn(&Result<T, E>) -> Outcome>
+where
+    F: FnMut() -> S,
+    S: Stream<Item = Result<T, E>>,
+    B: Iterator<Item = Duration> + Clone,
+    Sleep: FnMut(Duration) -> Slept,
+    Slept: Future<Output = ()>,
+{
+    /// create new retry stream, the first stream is created on the first poll
+    pub fn new(connect: F, backoff: B, sleep: Sleep) -> Self {
+        Self {
+            connect,
+            stream: None,
+            delays: backoff.clone(),
+            backoff,
+            sleep,
+            sleeping: None,
+            judge: err_is_failure,
+            attempts: 0,
+            done: false,
+            exhausted: false,
+        }
+    }
+}
+
+impl<F, S, B, Sleep, Slept, J> RetryStream<F, S, B, Sleep, Slept, J>
+where
+    F: FnMut() -> S,
+    S: Stream,
+    B: Iterator<Item = Duration> + Clone,
+    Sleep: FnMut(Duration) -> Slept,
+    Slept: Future<Output = ()>,
+{
+    /// judge items by the provided function rather than by `Err`, e.g. a response with the
+    /// status 429 or 503 may be judged as failure, so the stream is recreated after it
+    pub fn with_outcome<K>(self, judge: K) -> RetryStream<F, S, B, Sleep, Slept, K>
+    where
+        K: FnMut(&S::Item) -> Outcome,
+    {
+        RetryStream {
+            connect: self.connect,
+            stream: self.stream,
+            backoff: self.backoff,
+            delays: self.delays,
+            sleep: self.sleep,
+            sleeping: self.sleeping,
+            judge,
+            attempts: self.attempts,
+            done: self.done,
+            exhausted: self.exhausted,
+        }
+    }
+
+    /// return the number of streams created so far, including the current one
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// return `true` once the backoff is exhausted, then the last item yielded was the final
+    /// failure, rather than the end of the underlying stream
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+fn err_is_failure<T, E>(item: &Result<T, E>) -> Outcome {
+    match item {
+        Ok(_) => Outcome::Success,
+        Err(_) => Outcome::Failure,
+    }
+}
+
+impl<F, S, B, Sleep, Slept, J> Stream for RetryStream<F, S, B, Sleep, Slept, J>
+where
+    F: FnMut() -> S,
+    S: Stream,
+    B: Iterator<Item = Duration> + Clone,
+    Sleep: FnMut(Duration) -> Slept,
+    Slept: Future<Output = ()>,
+    J: FnMut(&S::Item) -> Outcome,
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Self::Item>> {
+        use task::Poll;
+        let mut this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        if let Some(sleeping) = this.sleeping.as_mut().as_pin_mut() {
+            ready!(sleeping.poll(cx));
+            this.sleeping.set(None);
+        }
+        if this.stream.is_none() {
+            this.stream.set(Some((this.connect)()));
+            *this.attempts += 1;
+        }
+
+        let stream = this
+            .stream
+            .as_mut()
+            .as_pin_mut()
+            .expect("stream is created above");
+        let item = match ready!(stream.poll_next(cx)) {
+            Some(item) => item,
+            None => {
+                *this.done = true;
+                return Poll::Ready(None);
+            }
+        };
+
+        match (this.judge)(&item) {
+            Outcome::Success => *this.delays = this.backoff.clone(),
+            Outcome::Failure => {
+                this.stream.set(None);
+                match this.delays.next() {
+                    Some(delay) => this.sleeping.set(Some((this.sleep)(delay))),
+                    None => {
+                        *this.done = true;
+                        *this.exhausted = true;
+                    }
+                }
+            }
+            Outcome::Ignored => {}
+        }
+        Poll::Ready(Some(item))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
     use std::time::Duration;
 
-    use futures::StreamExt;
+    use futures::{future, stream, StreamExt};
 
     use crate::{backoff, failure_policy, Config};
 
@@ -124,7 +541,7 @@ mod tests {
         assert!(!stream.state_machine().is_call_permitted());
 
         match stream.next().await {
-            Some(Err(crate::Error::Rejected)) => {}
+            Some(Err(crate::Error::Rejected(_))) => {}
             err => unreachable!("{:?}", err),
         }
         assert!(!stream.state_machine().is_call_permitted());
@@ -137,6 +554,162 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn wait_while_open() {
+        let stream = BreakerStream::new(
+            new_circuit_breaker(Duration::from_millis(100)),
+            futures::stream::iter(vec![Err::<(), ()>(()), Ok(())]),
+        )
+        .wait_while_open(tokio::time::sleep);
+        tokio::pin!(stream);
+
+        match stream.next().await {
+            Some(Err(crate::Error::Inner(_))) => {}
+            err => unreachable!("{:?}", err),
+        }
+        let started = std::time::Instant::now();
+        match stream.next().await {
+            Some(Ok(())) => {}
+            err => unreachable!("{:?}", err),
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn woken_by_transition() {
+        let stream = BreakerStream::new(
+            new_circuit_breaker(Duration::from_secs(60)),
+            futures::stream::iter(vec![Err::<(), ()>(()), Ok(())]),
+        )
+        .wait_while_open(|_| future::pending());
+        tokio::pin!(stream);
+
+        match stream.next().await {
+            Some(Err(crate::Error::Inner(_))) => {}
+            err => unreachable!("{:?}", err),
+        }
+
+        // The sleep never completes, only the transition wakes the stream.
+        let circuit_breaker = stream.state_machine().clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            circuit_breaker.reset();
+        });
+        match tokio::time::timeout(Duration::from_secs(5), stream.next()).await {
+            Ok(Some(Ok(()))) => {}
+            err => unreachable!("{:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn judge_outcome() {
+        let stream = BreakerStream::new(
+            new_circuit_breaker(Duration::from_secs(5)),
+            stream::iter(vec![Ok(404), Ok(200), Ok(503), Ok(200)]),
+        )
+        .with_outcome(|status: &Result<u16, ()>| match status {
+            Ok(404) => Outcome::Ignored,
+            Ok(status) if *status >= 500 => Outcome::Failure,
+            Ok(_) => Outcome::Success,
+            Err(_) => Outcome::Failure,
+        });
+        tokio::pin!(stream);
+
+        for expected in &[404, 200, 503] {
+            match stream.next().await {
+                Some(Ok(Ok(status))) => assert_eq!(*expected, status),
+                x => unreachable!("{:?}", x),
+            }
+        }
+        match stream.next().await {
+            Some(Err(crate::Error::Rejected(_))) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert_eq!(1, stream.state_machine().snapshot().successes);
+
+        // The rejected poll left the last item in the wrapped stream.
+        assert_eq!((1, Some(1)), stream.get_ref().size_hint());
+        assert_eq!(Some(Ok(200)), stream.as_mut().get_pin_mut().next().await);
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff() {
+        let connections = RefCell::new(0);
+        let delays = RefCell::new(Vec::new());
+        let stream = RetryStream::new(
+            || {
+                *connections.borrow_mut() += 1;
+                match *connections.borrow() {
+                    1 => stream::iter(vec![Ok(1), Err("broken")]),
+                    2 => stream::iter(vec![Err("refused")]),
+                    _ => stream::iter(vec![Ok(2), Ok(3)]),
+                }
+            },
+            vec![Duration::from_secs(1), Duration::from_secs(2)].into_iter(),
+            |delay| {
+                delays.borrow_mut().push(delay);
+                future::ready(())
+            },
+        );
+
+        tokio::pin!(stream);
+        let items = stream.as_mut().collect::<Vec<_>>().await;
+        assert_eq!(
+            vec![Ok(1), Err("broken"), Err("refused"), Ok(2), Ok(3)],
+            items
+        );
+        // The underlying stream has ended, the backoff isn't exhausted.
+        assert!(!stream.is_exhausted());
+        assert_eq!(3, *connections.borrow());
+        assert_eq!(
+            vec![Duration::from_secs(1), Duration::from_secs(2)],
+            *delays.borrow()
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_until_backoff_exhausted() {
+        let stream = RetryStream::new(
+            || stream::iter(vec![Err::<(), _>(())]),
+            backoff::constant(Duration::from_millis(1)).take(2),
+            tokio::time::sleep,
+        );
+        tokio::pin!(stream);
+
+        let mut errors = 0;
+        while let Some(Err(())) = stream.next().await {
+            errors += 1;
+            assert_eq!(errors == 3, stream.is_exhausted());
+        }
+        assert_eq!(3, errors);
+        assert_eq!(3, stream.attempts());
+    }
+
+    #[tokio::test]
+    async fn retry_on_outcome() {
+        let connections = RefCell::new(0);
+        let stream = RetryStream::new(
+            || {
+                *connections.borrow_mut() += 1;
+                match *connections.borrow() {
+                    1 => stream::iter(vec![Ok::<_, ()>(200), Ok(503)]),
+                    _ => stream::iter(vec![Ok(404), Ok(200)]),
+                }
+            },
+            backoff::constant(Duration::from_secs(1)),
+            |_| future::ready(()),
+        )
+        .with_outcome(|status: &Result<u16, ()>| match status {
+            Ok(503) => Outcome::Failure,
+            Ok(404) => Outcome::Ignored,
+            _ => Outcome::Success,
+        });
+
+        let items = stream.collect::<Vec<_>>().await;
+        assert_eq!(vec![Ok(200), Ok(503), Ok(404), Ok(200)], items);
+        assert_eq!(2, *connections.borrow());
+    }
+
     fn new_circuit_breaker(
         duration: Duration,
     ) -> StateMachine<failure_policy::ConsecutiveFailures<std::iter::Repeat<Duration>>, ()> {
@@ -0,0 +1,212 @@
+//! An adapter of a [governor](https://docs.rs/governor) rate limiter, requires the `governor`
+//! feature.
+//!
+//! `Governor` takes part in a composite circuit breaker, see `composite::all_of`, so calls are
+//! rate limited first and then checked by the circuit breaker. Rejections share the crate's
+//! `Error` type and are reported to the instrument.
+//!
+//! # Example
+//!
+//! ```
+//! use std::num::NonZeroU32;
+//! use failsafe::{composite, governor::Governor, CircuitBreaker, Config, Error};
+//! use governor::{Quota, RateLimiter};
+//!
+//! let quota = Quota::per_second(NonZeroU32::new(100).unwrap());
+//! let limit = Governor::new(RateLimiter::direct(quota)).name("api quota");
+//! let circuit_breaker = Config::new().build();
+//!
+//! match composite::all_of(&[&limit, &circuit_breaker]).call(|| Ok::<_, ()>(42)) {
+//!     Ok(_) => {}
+//!     Err(Error::Rejected(rejection)) => eprintln!("{}", rejection),
+//!     Err(Error::Inner(_)) => {}
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ::governor::clock::{Clock, DefaultClock};
+use ::governor::DefaultDirectRateLimiter;
+
+use super::clock;
+use super::composite::Member;
+use super::error::{Error, Rejection, RejectionReason};
+use super::instrument::Instrument;
+
+/// A rate limiter which takes part in a composite circuit breaker.
+///
+/// It permits calls while the quota of the wrapped limiter allows, results of calls are ignored.
+pub struct Governor<INSTRUMENT = ()> {
+    limiter: DefaultDirectRateLimiter,
+    instrument: INSTRUMENT,
+    name: Option<Arc<str>>,
+}
+
+impl Governor<()> {
+    /// Creates a new adapter of the `limiter`.
+    pub fn new(limiter: DefaultDirectRateLimiter) -> Self {
+        Self::with_instrument(limiter, ())
+    }
+}
+
+impl<INSTRUMENT> Governor<INSTRUMENT>
+where
+    INSTRUMENT: Instrument,
+{
+    /// Creates a new adapter of the `limiter`, the instrument is notified about rejected calls.
+    pub fn with_instrument(limiter: DefaultDirectRateLimiter, instrument: INSTRUMENT) -> Self {
+        Governor {
+            limiter,
+            instrument,
+            name: None,
+        }
+    }
+
+    /// Sets the name reported in rejections.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(Arc::from(name.into()));
+        self
+    }
+
+    /// Returns the wrapped rate limiter.
+    pub fn get_ref(&self) -> &DefaultDirectRateLimiter {
+        &self.limiter
+    }
+
+    /// Executes a given function if the quota allows it.
+    ///
+    /// Returns `Error::Rejected` when the quota is exhausted.
+    pub fn call<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        Member::permit(self).map_err(Error::Rejected)?;
+        f().map_err(Error::Inner)
+    }
+
+    /// Returns how long to wait until the quota allows a call, or `None` if it's allowed now.
+    fn check(&self) -> Option<Duration> {
+        match self.limiter.check() {
+            Ok(_) => None,
+            Err(not_until) => Some(not_until.wait_time_from(DefaultClock::default().now())),
+        }
+    }
+}
+
+impl<INSTRUMENT> Member for Governor<INSTRUMENT>
+where
+    INSTRUMENT: Instrument,
+{
+    fn is_call_permitted(&self) -> bool {
+        Member::permit(self).is_ok()
+    }
+
+    fn wait_permitted(&self, deadline: Instant) -> bool {
+        Member::wait_permit(self, deadline).is_ok()
+    }
+
+    #[inline]
+    fn on_latency(&self, _latency: Duration) {}
+
+    #[inline]
+    fn on_success_after(&self, _latency: Duration) {}
+
+    #[inline]
+    fn on_error_after(&self, _latency: Duration) {}
+
+    #[inline]
+    fn on_error_in(&self, _class: &'static str) {}
+
+    #[inline]
+    fn on_success_weighted(&self, _cost: u32) {}
+
+    #[inline]
+    fn on_error_weighted(&self, _cost: u32) {}
+
+    #[inline]
+    fn rejection(&self) -> Rejection {
+        Rejection::new(self.name.clone()).with_reason(RejectionReason::RateLimited)
+    }
+
+    /// A rejection carries the time until the quota allows a call as `retry_after`.
+    fn permit(&self) -> Result<(), Rejection> {
+        match self.check() {
+            None => Ok(()),
+            Some(wait) => {
+                self.instrument.on_call_rejected();
+                Err(self.rejection().with_retry_after(Some(wait)))
+            }
+        }
+    }
+
+    fn wait_permit(&self, deadline: Instant) -> Result<(), Rejection> {
+        loop {
+            let wait = match self.check() {
+                Some(wait) => wait,
+                None => return Ok(()),
+            };
+
+            let now = clock::now();
+            if now + wait > deadline {
+                self.instrument.on_call_rejected();
+                return Err(self.rejection().with_retry_after(Some(wait)));
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    #[inline]
+    fn on_error_with(&self, _reason: &dyn Display) {}
+}
+
+impl<INSTRUMENT> Debug for Governor<INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Governor");
+        if let Some(name) = &self.name {
+            debug.field("name", name);
+        }
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use ::governor::{Quota, RateLimiter};
+
+    use super::super::circuit_breaker::CircuitBreaker;
+    use super::super::{composite, Config};
+    use super::*;
+
+    #[test]
+    fn rate_limit_first() {
+        let quota = Quota::per_hour(NonZeroU32::new(2).unwrap());
+        let limit = Governor::new(RateLimiter::direct(quota)).name("quota");
+        let circuit_breaker = Config::new().build();
+        let members: [&dyn Member; 2] = [&limit, &circuit_breaker];
+        let all_of = composite::all_of(&members);
+
+        assert!(all_of.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(all_of.call(|| Err::<(), _>(())).is_err());
+        match all_of.call(|| Ok::<_, ()>(())) {
+            Err(Error::Rejected(rejection)) => {
+                assert_eq!(Some("quota"), rejection.name());
+                assert_eq!(RejectionReason::RateLimited, rejection.reason());
+                let retry_after = rejection.retry_after().unwrap();
+                assert!(retry_after > Duration::from_secs(60), "{:?}", retry_after);
+            }
+            x => unreachable!("{:?}", x),
+        }
+
+        // The rate limited call isn't seen by the circuit breaker.
+        let snapshot = circuit_breaker.snapshot();
+        assert_eq!(
+            (1, 1, 0),
+            (snapshot.successes, snapshot.failures, snapshot.rejections)
+        );
+    }
+}
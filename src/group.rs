@@ -0,0 +1,208 @@
+//! Groups of isolated circuit breakers with the same tuning, e.g. one per worker pool or shard.
+//!
+//! Members of a `Group` are built from one configuration, each with its own clone of the
+//! failure policy, so a failing shard doesn't trip the others. Events of all members are
+//! delivered to a single `GroupSink` along with the index of the member.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{group::Group, instrument::Event, Config};
+//!
+//! let group = Group::new(Config::new().name("pool"), 4, |index: usize, event: &Event| {
+//!     if let Event::Open { .. } = event {
+//!         eprintln!("pool {} is open", index);
+//!     }
+//! });
+//!
+//! let result = group.call(2, || Ok::<_, ()>("processed"));
+//! assert!(result.is_ok());
+//! ```
+
+use std::fmt::{self, Debug};
+use std::slice;
+use std::sync::Arc;
+
+use super::circuit_breaker::CircuitBreaker;
+use super::config::Config;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::instrument::{Event, EventSink, Events};
+use super::state_machine::StateMachine;
+
+/// Consumes events of members of a group, see `Group`.
+pub trait GroupSink {
+    /// Calls on every event of the member with the given `index`.
+    fn on_event(&self, index: usize, event: &Event);
+}
+
+impl<F> GroupSink for F
+where
+    F: Fn(usize, &Event),
+{
+    #[inline]
+    fn on_event(&self, index: usize, event: &Event) {
+        self(index, event)
+    }
+}
+
+/// A sink which ignores all events.
+impl GroupSink for () {
+    #[inline]
+    fn on_event(&self, _index: usize, _event: &Event) {}
+}
+
+/// The sink of a group seen by one of its members, it labels events with the member's index.
+pub struct Indexed<SINK> {
+    index: usize,
+    sink: Arc<SINK>,
+}
+
+impl<SINK> Indexed<SINK> {
+    /// Returns the index of the member.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<SINK> EventSink for Indexed<SINK>
+where
+    SINK: GroupSink,
+{
+    #[inline]
+    fn on_event(&self, event: &Event) {
+        self.sink.on_event(self.index, event)
+    }
+}
+
+impl<SINK> Debug for Indexed<SINK> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Indexed").field(&self.index).finish()
+    }
+}
+
+/// The instrument of members of a group.
+pub type Member<SINK> = Events<Indexed<SINK>>;
+
+/// A fixed number of isolated circuit breakers built from one configuration.
+pub struct Group<POLICY, SINK> {
+    members: Vec<StateMachine<POLICY, Member<SINK>>>,
+}
+
+impl<POLICY, SINK> Group<POLICY, SINK>
+where
+    POLICY: FailurePolicy + Clone,
+    SINK: GroupSink,
+{
+    /// Builds `size` members from the `config`, which events are delivered to the `sink`.
+    ///
+    /// When the configuration is named, members are named after it with their index, e.g.
+    /// `pool[2]`.
+    pub fn new(config: Config<POLICY, ()>, size: usize, sink: SINK) -> Self {
+        let sink = Arc::new(sink);
+        let name = config.settings.name.clone();
+
+        let members = (0..size)
+            .map(|index| {
+                let mut config = config.clone().instrument(Events::new(Indexed {
+                    index,
+                    sink: sink.clone(),
+                }));
+                if let Some(name) = &name {
+                    config = config.name(format!("{}[{}]", name, index));
+                }
+                config.build()
+            })
+            .collect();
+
+        Group { members }
+    }
+}
+
+impl<POLICY, SINK> Group<POLICY, SINK>
+where
+    POLICY: FailurePolicy,
+    SINK: GroupSink,
+{
+    /// Returns the member with the given `index`.
+    ///
+    /// # Panics
+    ///
+    /// When the `index` is out of bounds.
+    pub fn get(&self, index: usize) -> &StateMachine<POLICY, Member<SINK>> {
+        &self.members[index]
+    }
+
+    /// Executes a given function within the circuit breaker of the member with the given
+    /// `index`.
+    ///
+    /// # Panics
+    ///
+    /// When the `index` is out of bounds.
+    pub fn call<F, E, R>(&self, index: usize, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.get(index).call(f)
+    }
+
+    /// Returns the number of members.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Tells whether the group has no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Returns an iterator over members in the order of their indexes.
+    pub fn iter(&self) -> slice::Iter<'_, StateMachine<POLICY, Member<SINK>>> {
+        self.members.iter()
+    }
+}
+
+impl<POLICY, SINK> Debug for Group<POLICY, SINK> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Group")
+            .field("members", &self.members)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::super::{backoff, failure_policy};
+    use super::*;
+
+    #[test]
+    fn isolated_members() {
+        let backoff = backoff::constant(Duration::from_secs(30));
+        let policy = failure_policy::consecutive_failures(1, backoff);
+        let opened = Mutex::new(Vec::new());
+        let group = Group::new(
+            Config::new().failure_policy(policy).name("pool"),
+            3,
+            |index: usize, event: &Event| {
+                if let Event::Open { .. } = event {
+                    opened.lock().unwrap().push(index);
+                }
+            },
+        );
+
+        assert!(group.call(1, || Err::<(), _>(())).is_err());
+        assert!(!group.get(1).is_call_permitted());
+        assert!(group.get(0).is_call_permitted());
+        assert!(group.get(2).is_call_permitted());
+        assert_eq!(vec![1], *opened.lock().unwrap());
+
+        let names = group
+            .iter()
+            .map(|it| it.name().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["pool[0]", "pool[1]", "pool[2]"], names);
+    }
+}
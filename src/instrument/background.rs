@@ -0,0 +1,256 @@
+use std::fmt::{self, Debug, Display};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use super::super::snapshot::Snapshot;
+use super::{Instrument, Transition};
+
+/// An instrument which delivers events to the wrapped instrument on a background thread.
+///
+/// Events are passed over a bounded channel, so instruments doing I/O, e.g. posting to a webhook
+/// or writing to a channel with backpressure, never block the circuit breaker. When the channel
+/// is full, events are dropped and counted, see `dropped`.
+///
+/// The background thread exits once the adapter is dropped and all pending events are delivered.
+///
+/// ```
+/// use failsafe::{instrument::Background, Config, Instrument};
+///
+/// #[derive(Debug)]
+/// struct Webhook;
+///
+/// impl Instrument for Webhook {
+///     fn on_open(&self) {
+///         // A slow blocking call.
+///     }
+/// }
+///
+/// let circuit_breaker = Config::new()
+///     .instrument(Background::new(Webhook, 1024))
+///     .build();
+/// ```
+pub struct Background {
+    sender: SyncSender<Message>,
+    capacity: usize,
+    dropped: AtomicU64,
+}
+
+enum Message {
+    CallRejected,
+    Rejected(u64),
+    ProbesExhausted(u64),
+    Open,
+    HalfOpen,
+    Closed,
+    Success(Option<Duration>),
+    Failure(Option<Duration>),
+    FailureReason(String),
+    ReplayDropped,
+    Transition(Transition),
+    Shutdown(Box<Snapshot>, Sender<()>),
+}
+
+impl Background {
+    /// Spawns a thread which delivers events to the `instrument`, at most `capacity` events may
+    /// be pending.
+    ///
+    /// # Panics
+    ///
+    /// When the thread can't be spawned.
+    pub fn new<INSTRUMENT>(instrument: INSTRUMENT, capacity: usize) -> Self
+    where
+        INSTRUMENT: Instrument + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+
+        thread::Builder::new()
+            .name("failsafe-instrument".into())
+            .spawn(move || deliver(instrument, receiver))
+            .expect("failed to spawn the instrument thread");
+
+        Background {
+            sender,
+            capacity,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of events dropped because the channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    fn send(&self, message: Message) {
+        if self.sender.try_send(message).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn deliver<INSTRUMENT>(instrument: INSTRUMENT, receiver: Receiver<Message>)
+where
+    INSTRUMENT: Instrument,
+{
+    for message in receiver {
+        match message {
+            Message::CallRejected => instrument.on_call_rejected(),
+            Message::Rejected(rejections) => instrument.on_rejected(rejections),
+            Message::ProbesExhausted(rejections) => instrument.on_probes_exhausted(rejections),
+            Message::Open => instrument.on_open(),
+            Message::HalfOpen => instrument.on_half_open(),
+            Message::Closed => instrument.on_closed(),
+            Message::Success(latency) => instrument.on_success(latency),
+            Message::Failure(latency) => instrument.on_failure(latency),
+            Message::FailureReason(reason) => instrument.on_failure_reason(&reason),
+            Message::ReplayDropped => instrument.on_replay_dropped(),
+            Message::Transition(transition) => instrument.on_transition(&transition),
+            Message::Shutdown(snapshot, done) => {
+                instrument.on_shutdown(&snapshot);
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+impl Instrument for Background {
+    /// The buffer of pending events, which is allocated up front.
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.capacity * mem::size_of::<Message>()
+    }
+
+    #[inline]
+    fn on_call_rejected(&self) {
+        self.send(Message::CallRejected)
+    }
+
+    #[inline]
+    fn on_rejected(&self, rejections: u64) {
+        self.send(Message::Rejected(rejections))
+    }
+
+    #[inline]
+    fn on_probes_exhausted(&self, rejections: u64) {
+        self.send(Message::ProbesExhausted(rejections))
+    }
+
+    #[inline]
+    fn on_open(&self) {
+        self.send(Message::Open)
+    }
+
+    #[inline]
+    fn on_half_open(&self) {
+        self.send(Message::HalfOpen)
+    }
+
+    #[inline]
+    fn on_closed(&self) {
+        self.send(Message::Closed)
+    }
+
+    #[inline]
+    fn on_success(&self, latency: Option<Duration>) {
+        self.send(Message::Success(latency))
+    }
+
+    #[inline]
+    fn on_failure(&self, latency: Option<Duration>) {
+        self.send(Message::Failure(latency))
+    }
+
+    /// The reason is rendered to a string to be sent to the background thread.
+    #[inline]
+    fn on_failure_reason(&self, reason: &dyn Display) {
+        self.send(Message::FailureReason(reason.to_string()))
+    }
+
+    #[inline]
+    fn on_replay_dropped(&self) {
+        self.send(Message::ReplayDropped)
+    }
+
+    #[inline]
+    fn on_transition(&self, transition: &Transition) {
+        self.send(Message::Transition(*transition))
+    }
+
+    /// Waits until all pending events and the shutdown are delivered, it's never dropped.
+    fn on_shutdown(&self, snapshot: &Snapshot) {
+        let (done, delivered) = mpsc::channel();
+        if self
+            .sender
+            .send(Message::Shutdown(Box::new(snapshot.clone()), done))
+            .is_ok()
+        {
+            let _ = delivered.recv();
+        }
+    }
+}
+
+impl Debug for Background {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Background")
+            .field("capacity", &self.capacity)
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+    use std::time::Duration;
+
+    use super::super::super::Config;
+    use super::super::{Cause, State};
+    use super::*;
+
+    #[test]
+    fn deliver_in_background() {
+        let (sender, receiver) = mpsc::channel();
+        let background = Background::new(Observer(sender), 16);
+
+        background.on_closed();
+        background.on_call_rejected();
+        background.on_transition(&Transition::new(State::Closed, State::Open, Cause::Policy));
+
+        let timeout = Duration::from_secs(5);
+        for expected in &["closed", "rejected", "open"] {
+            assert_eq!(Ok(*expected), receiver.recv_timeout(timeout));
+        }
+        assert_eq!(0, background.dropped());
+
+        // Shutdown waits for delivery.
+        background.on_shutdown(&Config::new().build().snapshot());
+        assert_eq!(Ok("shutdown"), receiver.try_recv());
+
+        // The thread exits when the adapter is dropped.
+        drop(background);
+        assert!(receiver.recv_timeout(timeout).is_err());
+    }
+
+    struct Observer(Sender<&'static str>);
+
+    impl Instrument for Observer {
+        fn on_call_rejected(&self) {
+            self.0.send("rejected").unwrap();
+        }
+
+        fn on_open(&self) {
+            self.0.send("open").unwrap();
+        }
+
+        fn on_closed(&self) {
+            self.0.send("closed").unwrap();
+        }
+
+        fn on_shutdown(&self, _snapshot: &Snapshot) {
+            self.0.send("shutdown").unwrap();
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use super::super::error::RejectionReason;
+use super::super::snapshot::Snapshot;
+use super::{Cause, Instrument, State, Transition};
+
+/// An event of the circuit breaker, see `Events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The circuit breaker has opened.
+    Open {
+        /// The previous state.
+        from: State,
+        /// Why the circuit breaker has opened.
+        cause: Cause,
+    },
+    /// The circuit breaker has become half open.
+    HalfOpen,
+    /// The circuit breaker has closed, or was created.
+    Closed,
+    /// A call was rejected.
+    Rejected {
+        /// The number of calls rejected since the circuit breaker has opened, including this
+        /// one, if it's known.
+        rejections: Option<u64>,
+        /// Why the call was rejected, it's `Open` when unknown.
+        reason: RejectionReason,
+    },
+    /// A call succeeded.
+    Success {
+        /// The latency of the call, if it was measured.
+        latency: Option<Duration>,
+    },
+    /// A call failed.
+    Failure {
+        /// The latency of the call, if it was measured.
+        latency: Option<Duration>,
+    },
+    /// A replay queue dropped an operation because it was full.
+    ReplayDropped,
+    /// The application shuts down, no more events are expected.
+    Shutdown,
+}
+
+/// Consumes events of the circuit breaker, see `Events`.
+pub trait EventSink {
+    /// Calls on every event.
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> EventSink for F
+where
+    F: Fn(&Event),
+{
+    #[inline]
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// An instrument which delivers all events to a single `EventSink`.
+///
+/// It makes forwarding to channels, logs or metrics trivial, with an exhaustive match over
+/// `Event`.
+///
+/// ```
+/// use failsafe::{instrument::{Event, Events}, Config};
+///
+/// let circuit_breaker = Config::new()
+///     .instrument(Events::new(|event: &Event| match event {
+///         Event::Open { cause, .. } => eprintln!("open: {:?}", cause),
+///         Event::HalfOpen | Event::Closed => {}
+///         Event::Rejected { .. } => eprintln!("rejected"),
+///         Event::Success { .. } | Event::Failure { .. } | Event::ReplayDropped => {}
+///         Event::Shutdown => eprintln!("shutdown"),
+///     }))
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct Events<SINK> {
+    sink: SINK,
+}
+
+impl<SINK> Events<SINK>
+where
+    SINK: EventSink,
+{
+    /// Creates a new instrument which delivers events to the `sink`.
+    pub fn new(sink: SINK) -> Self {
+        Events { sink }
+    }
+
+    /// Returns a reference to the sink.
+    pub fn get_ref(&self) -> &SINK {
+        &self.sink
+    }
+}
+
+impl<SINK> Instrument for Events<SINK>
+where
+    SINK: EventSink,
+{
+    #[inline]
+    fn on_call_rejected(&self) {
+        self.sink.on_event(&Event::Rejected {
+            rejections: None,
+            reason: RejectionReason::Open,
+        })
+    }
+
+    #[inline]
+    fn on_rejected(&self, rejections: u64) {
+        self.sink.on_event(&Event::Rejected {
+            rejections: Some(rejections),
+            reason: RejectionReason::Open,
+        })
+    }
+
+    #[inline]
+    fn on_probes_exhausted(&self, rejections: u64) {
+        self.sink.on_event(&Event::Rejected {
+            rejections: Some(rejections),
+            reason: RejectionReason::ProbesExhausted,
+        })
+    }
+
+    /// The state machine reports transitions via `on_transition`, so a direct call is
+    /// considered forced.
+    #[inline]
+    fn on_open(&self) {
+        self.sink.on_event(&Event::Open {
+            from: State::Closed,
+            cause: Cause::Forced,
+        })
+    }
+
+    #[inline]
+    fn on_half_open(&self) {
+        self.sink.on_event(&Event::HalfOpen)
+    }
+
+    #[inline]
+    fn on_closed(&self) {
+        self.sink.on_event(&Event::Closed)
+    }
+
+    #[inline]
+    fn on_success(&self, latency: Option<Duration>) {
+        self.sink.on_event(&Event::Success { latency })
+    }
+
+    #[inline]
+    fn on_failure(&self, latency: Option<Duration>) {
+        self.sink.on_event(&Event::Failure { latency })
+    }
+
+    #[inline]
+    fn on_replay_dropped(&self) {
+        self.sink.on_event(&Event::ReplayDropped)
+    }
+
+    #[inline]
+    fn on_shutdown(&self, _snapshot: &Snapshot) {
+        self.sink.on_event(&Event::Shutdown)
+    }
+
+    #[inline]
+    fn on_transition(&self, transition: &Transition) {
+        let event = match transition.to {
+            State::Open => Event::Open {
+                from: transition.from,
+                cause: transition.cause,
+            },
+            State::HalfOpen => Event::HalfOpen,
+            State::Closed => Event::Closed,
+        };
+        self.sink.on_event(&event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::super::super::{backoff, clock, failure_policy, CircuitBreaker, Config};
+    use super::*;
+
+    #[test]
+    fn deliver_events() {
+        clock::freeze(|time| {
+            let events = Mutex::new(Vec::new());
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .instrument(Events::new(|event: &Event| {
+                    events.lock().unwrap().push(*event)
+                }))
+                .build();
+
+            let _ = circuit_breaker.call(|| {
+                time.advance(Duration::from_millis(20));
+                Err::<(), _>(())
+            });
+            let _ = circuit_breaker.call(|| Ok::<_, ()>(()));
+
+            assert_eq!(
+                vec![
+                    Event::Closed,
+                    Event::Failure {
+                        latency: Some(Duration::from_millis(20))
+                    },
+                    Event::Open {
+                        from: State::Closed,
+                        cause: Cause::Policy
+                    },
+                    Event::Rejected {
+                        rejections: Some(1),
+                        reason: RejectionReason::Open,
+                    },
+                ],
+                *events.lock().unwrap()
+            );
+        })
+    }
+}
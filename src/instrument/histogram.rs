@@ -0,0 +1,300 @@
+use std::fmt::{self, Debug, Display};
+use std::mem;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+
+use super::super::lock::{Lock, Mutex};
+use super::super::snapshot::Snapshot;
+use super::{Instrument, Transition};
+
+/// The highest trackable latency in microseconds, one hour, longer latencies are saturated.
+const MAX_MICROS: u64 = 3_600_000_000;
+
+/// Significant decimal digits of recorded latencies.
+const PRECISION: u8 = 3;
+
+/// A histogram of latencies of calls, it's a handle which clones share the same histogram.
+///
+/// Latencies are kept in microseconds with three significant digits, so queries are precise
+/// within 0.1%, in a constant memory of a few dozens of kilobytes. It may be used standalone,
+/// or fed by the `Latencies` instrument.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::instrument::LatencyHistogram;
+///
+/// let histogram = LatencyHistogram::new();
+/// for millis in 1..=100 {
+///     histogram.record(Duration::from_millis(millis));
+/// }
+///
+/// let p99 = histogram.percentile(0.99).unwrap();
+/// assert_eq!(100, histogram.count());
+/// assert_eq!(99, p99.as_millis());
+/// ```
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    histogram: Arc<Mutex<Histogram<u64>>>,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        let histogram = Histogram::new_with_bounds(1, MAX_MICROS, PRECISION)
+            .expect("bounds of the histogram are valid");
+
+        LatencyHistogram {
+            histogram: Arc::new(Mutex::new(histogram)),
+        }
+    }
+
+    /// Records the `latency` of a call.
+    #[inline]
+    pub fn record(&self, latency: Duration) {
+        let micros = latency.as_micros().min(u128::from(MAX_MICROS)) as u64;
+        self.histogram
+            .with(|histogram| histogram.saturating_record(micros));
+    }
+
+    /// Returns the latency below which the share `p` of calls fall, e.g. `0.99` for the 99th
+    /// percentile, or `None` if nothing was recorded.
+    ///
+    /// # Panics
+    ///
+    /// When `p` isn't in `[0.0, 1.0]` interval.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        assert!((0.0..=1.0).contains(&p), "percentile must be [0, 1]: {}", p);
+
+        self.histogram.with(|histogram| {
+            if histogram.is_empty() {
+                None
+            } else {
+                let micros = histogram.value_at_quantile(p).min(MAX_MICROS);
+                Some(Duration::from_micros(micros))
+            }
+        })
+    }
+
+    /// Returns the number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.histogram.with(|histogram| histogram.len())
+    }
+
+    /// Returns the sum of recorded latencies, it's approximated as the histogram does.
+    pub fn sum(&self) -> Duration {
+        self.histogram.with(|histogram| {
+            let micros = histogram.mean() * histogram.len() as f64;
+            Duration::from_micros(micros as u64)
+        })
+    }
+
+    /// Returns the number of bytes the histogram allocates on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.histogram
+            .with(|histogram| histogram.distinct_values() * mem::size_of::<u64>())
+    }
+
+    /// Erases all recorded latencies, e.g. after they are exported.
+    pub fn reset(&self) {
+        self.histogram.with(|histogram| histogram.reset());
+    }
+
+    /// Returns a copy of the underlying histogram of microseconds, e.g. to merge or serialize it.
+    pub fn to_histogram(&self) -> Histogram<u64> {
+        self.histogram.with(|histogram| histogram.clone())
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+/// An instrument which records latencies of measured calls into a `LatencyHistogram`, and
+/// delivers all events to the wrapped instrument.
+///
+/// Calls made by `call` and `call_async` are measured, as well as ones recorded by
+/// `on_success_after` and `on_error_after`. Latencies of successful and failed calls are
+/// recorded together.
+///
+/// ```
+/// use failsafe::{instrument::Latencies, CircuitBreaker, Config};
+///
+/// let latencies = Latencies::new(());
+/// let histogram = latencies.histogram();
+/// let circuit_breaker = Config::new().instrument(latencies).build();
+///
+/// circuit_breaker.call(|| Ok::<_, ()>(())).unwrap();
+/// assert_eq!(1, histogram.count());
+/// println!("p99 is {:?}", histogram.percentile(0.99));
+/// ```
+pub struct Latencies<INSTRUMENT> {
+    inner: INSTRUMENT,
+    histogram: LatencyHistogram,
+}
+
+impl<INSTRUMENT> Latencies<INSTRUMENT> {
+    /// Wraps the `inner` instrument, latencies are recorded into a new histogram.
+    pub fn new(inner: INSTRUMENT) -> Self {
+        Self::with_histogram(inner, LatencyHistogram::new())
+    }
+
+    /// Wraps the `inner` instrument, latencies are recorded into the `histogram`, which may be
+    /// shared by several circuit breakers.
+    pub fn with_histogram(inner: INSTRUMENT, histogram: LatencyHistogram) -> Self {
+        Latencies { inner, histogram }
+    }
+
+    /// Returns a handle of the histogram, it may be queried after the instrument is moved into
+    /// a circuit breaker.
+    pub fn histogram(&self) -> LatencyHistogram {
+        self.histogram.clone()
+    }
+
+    /// Returns a reference to the wrapped instrument.
+    pub fn get_ref(&self) -> &INSTRUMENT {
+        &self.inner
+    }
+}
+
+impl<INSTRUMENT> Instrument for Latencies<INSTRUMENT>
+where
+    INSTRUMENT: Instrument,
+{
+    #[inline]
+    fn on_call_rejected(&self) {
+        self.inner.on_call_rejected()
+    }
+
+    #[inline]
+    fn on_rejected(&self, rejections: u64) {
+        self.inner.on_rejected(rejections)
+    }
+
+    #[inline]
+    fn on_probes_exhausted(&self, rejections: u64) {
+        self.inner.on_probes_exhausted(rejections)
+    }
+
+    #[inline]
+    fn on_open(&self) {
+        self.inner.on_open()
+    }
+
+    #[inline]
+    fn on_half_open(&self) {
+        self.inner.on_half_open()
+    }
+
+    #[inline]
+    fn on_closed(&self) {
+        self.inner.on_closed()
+    }
+
+    #[inline]
+    fn on_success(&self, latency: Option<Duration>) {
+        if let Some(latency) = latency {
+            self.histogram.record(latency);
+        }
+        self.inner.on_success(latency)
+    }
+
+    #[inline]
+    fn on_failure(&self, latency: Option<Duration>) {
+        if let Some(latency) = latency {
+            self.histogram.record(latency);
+        }
+        self.inner.on_failure(latency)
+    }
+
+    #[inline]
+    fn on_failure_reason(&self, reason: &dyn Display) {
+        self.inner.on_failure_reason(reason)
+    }
+
+    #[inline]
+    fn on_replay_dropped(&self) {
+        self.inner.on_replay_dropped()
+    }
+
+    #[inline]
+    fn on_shutdown(&self, snapshot: &Snapshot) {
+        self.inner.on_shutdown(snapshot)
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.histogram.heap_size() + self.inner.heap_size()
+    }
+
+    #[inline]
+    fn on_transition(&self, transition: &Transition) {
+        self.inner.on_transition(transition)
+    }
+}
+
+impl<INSTRUMENT> Debug for Latencies<INSTRUMENT>
+where
+    INSTRUMENT: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Latencies")
+            .field("inner", &self.inner)
+            .field("histogram", &self.histogram)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{backoff, clock, failure_policy, CircuitBreaker, Config};
+    use super::*;
+
+    #[test]
+    fn record_measured_calls() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(3, backoff);
+            let latencies = Latencies::new(());
+            let histogram = latencies.histogram();
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .instrument(latencies)
+                .build();
+
+            for millis in &[10, 20, 30] {
+                let _ = circuit_breaker.call(|| {
+                    time.advance(Duration::from_millis(*millis));
+                    Err::<(), _>(())
+                });
+            }
+            // Neither rejected nor unmeasured calls are recorded.
+            assert!(circuit_breaker.call(|| Ok::<_, ()>(())).is_err());
+            circuit_breaker.on_success();
+
+            // Values are precise within 0.1%.
+            let close = |actual: Duration, millis: u64| {
+                let expected = Duration::from_millis(millis);
+                assert!(actual >= expected && actual <= expected + expected / 1000);
+            };
+            assert_eq!(3, histogram.count());
+            close(histogram.percentile(0.5).unwrap(), 20);
+            close(histogram.percentile(1.0).unwrap(), 30);
+            close(histogram.sum(), 60);
+
+            histogram.reset();
+            assert_eq!(None, histogram.percentile(0.5));
+        });
+    }
+}
@@ -0,0 +1,205 @@
+//! State machine instrumentation.
+
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use super::clock;
+use super::snapshot::Snapshot;
+
+mod background;
+mod events;
+#[cfg(feature = "histogram")]
+mod histogram;
+mod rate_limited;
+mod slo;
+
+pub use self::background::Background;
+pub use self::events::{Event, EventSink, Events};
+#[cfg(feature = "histogram")]
+pub use self::histogram::{Latencies, LatencyHistogram};
+pub use self::rate_limited::RateLimited;
+pub use self::slo::{Breach, Slo};
+
+/// Observable states of the circuit breaker.
+///
+/// The gradual recovery after a successful probe is a part of the closed state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    /// The circuit breaker permits calls.
+    Closed,
+    /// The circuit breaker rejects calls until the delay provided by the failure policy expires.
+    Open,
+    /// The circuit breaker permits probe calls to check whether the backend has recovered.
+    HalfOpen,
+}
+
+impl State {
+    /// Returns a string value for the state identifier.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open => "open",
+            State::HalfOpen => "half_open",
+        }
+    }
+
+    /// Returns a numeric value of the state for gauges: 0 is closed, 1 is half open, 2 is open.
+    ///
+    /// The value grows with the severity, so the maximum over several circuit breakers is the
+    /// worst of their states.
+    #[inline]
+    pub fn as_gauge(&self) -> u8 {
+        match self {
+            State::Closed => 0,
+            State::HalfOpen => 1,
+            State::Open => 2,
+        }
+    }
+}
+
+/// The reason of a state transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Cause {
+    /// The failure policy has detected that the backend died.
+    Policy,
+    /// A probe or a canary call has completed, its result decides the next state.
+    Probe,
+    /// The open state's delay has expired.
+    DelayExpired,
+    /// The transition was requested explicitly, e.g. by `StateMachine::reset`.
+    Forced,
+    /// No probe has completed in the half open state in time, see `Config::half_open_timeout`.
+    ProbeTimeout,
+}
+
+/// A change of the circuit breaker's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Transition {
+    /// The previous state.
+    pub from: State,
+    /// The new state.
+    pub to: State,
+    /// The moment of the transition.
+    pub at: Instant,
+    /// Why the transition happened.
+    pub cause: Cause,
+    /// The failure policy which has opened the circuit breaker when the cause is
+    /// `Cause::Policy`, e.g. a side of `FailurePolicy::or_else`, see `FailurePolicy::tripped_by`.
+    pub tripped_by: Option<&'static str>,
+}
+
+impl Transition {
+    #[inline]
+    pub(crate) fn new(from: State, to: State, cause: Cause) -> Self {
+        Transition {
+            from,
+            to,
+            at: clock::now(),
+            cause,
+            tripped_by: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn tripped_by(mut self, policy: &'static str) -> Self {
+        self.tripped_by = Some(policy);
+        self
+    }
+}
+
+/// Consumes the state machine events. May used for metrics and/or logs.
+///
+/// All methods do nothing by default, so implementations override only events they are
+/// interested in. Either override `on_transition` to receive every state change with its
+/// context, or the `on_open`, `on_half_open` and `on_closed` callbacks, which the default
+/// `on_transition` dispatches to.
+pub trait Instrument {
+    /// Calls when state machine reject a call.
+    #[inline]
+    fn on_call_rejected(&self) {}
+
+    /// Calls when state machine rejects a call, with the number of calls rejected since the
+    /// circuit breaker has opened, including this one.
+    ///
+    /// It allows summaries like "rejected 10k calls during this outage" without external
+    /// counters. The default implementation calls `on_call_rejected`.
+    #[inline]
+    fn on_rejected(&self, _rejections: u64) {
+        self.on_call_rejected()
+    }
+
+    /// Calls instead of `on_rejected` when a half open or recovering circuit breaker rejects a
+    /// call because it admits only some calls, see `RejectionReason::ProbesExhausted`.
+    ///
+    /// The default implementation calls `on_rejected`.
+    #[inline]
+    fn on_probes_exhausted(&self, rejections: u64) {
+        self.on_rejected(rejections)
+    }
+
+    /// Calls when the circuit breaker become to open state.
+    #[inline]
+    fn on_open(&self) {}
+
+    /// Calls when the circuit breaker become to half open state.
+    #[inline]
+    fn on_half_open(&self) {}
+
+    /// Calls when the circuit breaker become to closed state.
+    ///
+    /// It's also called once the state machine is created.
+    #[inline]
+    fn on_closed(&self) {}
+
+    /// Calls when a call succeeded, with its latency if the call was measured.
+    #[inline]
+    fn on_success(&self, _latency: Option<Duration>) {}
+
+    /// Calls when a call failed, with its latency if the call was measured.
+    #[inline]
+    fn on_failure(&self, _latency: Option<Duration>) {}
+
+    /// Calls with the reason of a failed call, when it's known: the error of a call made by
+    /// `CircuitBreaker::call_reported`, or the class of an error of `call_classified`.
+    ///
+    /// It's called before `on_failure` and the transition the failure may cause, so instruments
+    /// can log what kind of failure has opened the circuit breaker.
+    #[inline]
+    fn on_failure_reason(&self, _reason: &dyn Display) {}
+
+    /// Calls when a `replay::ReplayQueue` drops an operation because it's full.
+    #[inline]
+    fn on_replay_dropped(&self) {}
+
+    /// Calls once the application shuts down, see `StateMachine::shutdown`, with the final
+    /// snapshot of the circuit breaker.
+    ///
+    /// Instruments which buffer events should flush them before returning.
+    #[inline]
+    fn on_shutdown(&self, _snapshot: &Snapshot) {}
+
+    /// Returns the approximate number of bytes the instrument allocates on the heap, e.g. for
+    /// buffers of events, it's a part of `StateMachine::memory_footprint`.
+    ///
+    /// The default implementation returns zero.
+    #[inline]
+    fn heap_size(&self) -> usize {
+        0
+    }
+
+    /// Calls when the circuit breaker changes its state.
+    #[inline]
+    fn on_transition(&self, transition: &Transition) {
+        match transition.to {
+            State::Closed => self.on_closed(),
+            State::Open => self.on_open(),
+            State::HalfOpen => self.on_half_open(),
+        }
+    }
+}
+
+/// An instrumentation which does noting.
+impl Instrument for () {}
@@ -0,0 +1,276 @@
+use std::fmt::{self, Debug, Display};
+use std::time::{Duration, Instant};
+
+use super::super::clock;
+use super::super::lock::{Lock, Mutex};
+use super::super::snapshot::Snapshot;
+use super::{Instrument, State, Transition};
+
+const CALL_REJECTED: usize = 0;
+const CLOSED: usize = 1;
+const OPEN: usize = 2;
+const HALF_OPEN: usize = 3;
+const SUCCESS: usize = 4;
+const FAILURE: usize = 5;
+const REPLAY_DROPPED: usize = 6;
+const FAILURE_REASON: usize = 7;
+
+/// An instrument which delivers at most `max_events` identical events per `interval` to the
+/// wrapped instrument, the rest are suppressed.
+///
+/// It keeps log based instruments from producing millions of lines during a long outage, when
+/// every call is rejected. Transitions are limited by the new state, together with the
+/// corresponding `on_open`, `on_half_open` and `on_closed` events.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{instrument::RateLimited, Config, Instrument};
+///
+/// #[derive(Debug)]
+/// struct Log;
+///
+/// impl Instrument for Log {
+///     fn on_call_rejected(&self) {
+///         eprintln!("call rejected");
+///     }
+/// }
+///
+/// let circuit_breaker = Config::new()
+///     .instrument(RateLimited::new(Log, 10, Duration::from_secs(60)))
+///     .build();
+/// ```
+pub struct RateLimited<INSTRUMENT> {
+    inner: INSTRUMENT,
+    max_events: u32,
+    interval: Duration,
+    windows: [Mutex<Window>; 8],
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    events: u32,
+    suppressed: u64,
+}
+
+impl<INSTRUMENT> RateLimited<INSTRUMENT> {
+    /// Creates a new rate limited instrument which delivers at most `max_events` of each kind
+    /// per `interval` to the `inner` one.
+    pub fn new(inner: INSTRUMENT, max_events: u32, interval: Duration) -> Self {
+        let now = clock::now();
+        let window = || {
+            Mutex::new(Window {
+                started: now,
+                events: 0,
+                suppressed: 0,
+            })
+        };
+
+        RateLimited {
+            inner,
+            max_events,
+            interval,
+            windows: [
+                window(),
+                window(),
+                window(),
+                window(),
+                window(),
+                window(),
+                window(),
+                window(),
+            ],
+        }
+    }
+
+    /// Returns a reference to the wrapped instrument.
+    pub fn get_ref(&self) -> &INSTRUMENT {
+        &self.inner
+    }
+
+    /// Returns the total number of suppressed events.
+    pub fn suppressed(&self) -> u64 {
+        self.windows
+            .iter()
+            .map(|window| window.with(|it| it.suppressed))
+            .sum()
+    }
+
+    /// Counts an event of the given kind and returns `true` if it may be delivered.
+    #[inline]
+    fn admit(&self, kind: usize) -> bool {
+        let interval = self.interval;
+        let max_events = self.max_events;
+
+        self.windows[kind].with(|window| {
+            let now = clock::now();
+            if now - window.started >= interval {
+                window.started = now;
+                window.events = 0;
+            }
+
+            if window.events < max_events {
+                window.events += 1;
+                true
+            } else {
+                window.suppressed += 1;
+                false
+            }
+        })
+    }
+}
+
+impl<INSTRUMENT> Instrument for RateLimited<INSTRUMENT>
+where
+    INSTRUMENT: Instrument,
+{
+    #[inline]
+    fn on_call_rejected(&self) {
+        if self.admit(CALL_REJECTED) {
+            self.inner.on_call_rejected()
+        }
+    }
+
+    #[inline]
+    fn on_rejected(&self, rejections: u64) {
+        if self.admit(CALL_REJECTED) {
+            self.inner.on_rejected(rejections)
+        }
+    }
+
+    #[inline]
+    fn on_probes_exhausted(&self, rejections: u64) {
+        if self.admit(CALL_REJECTED) {
+            self.inner.on_probes_exhausted(rejections)
+        }
+    }
+
+    #[inline]
+    fn on_open(&self) {
+        if self.admit(OPEN) {
+            self.inner.on_open()
+        }
+    }
+
+    #[inline]
+    fn on_half_open(&self) {
+        if self.admit(HALF_OPEN) {
+            self.inner.on_half_open()
+        }
+    }
+
+    #[inline]
+    fn on_closed(&self) {
+        if self.admit(CLOSED) {
+            self.inner.on_closed()
+        }
+    }
+
+    #[inline]
+    fn on_success(&self, latency: Option<Duration>) {
+        if self.admit(SUCCESS) {
+            self.inner.on_success(latency)
+        }
+    }
+
+    #[inline]
+    fn on_failure(&self, latency: Option<Duration>) {
+        if self.admit(FAILURE) {
+            self.inner.on_failure(latency)
+        }
+    }
+
+    #[inline]
+    fn on_failure_reason(&self, reason: &dyn Display) {
+        if self.admit(FAILURE_REASON) {
+            self.inner.on_failure_reason(reason)
+        }
+    }
+
+    #[inline]
+    fn on_replay_dropped(&self) {
+        if self.admit(REPLAY_DROPPED) {
+            self.inner.on_replay_dropped()
+        }
+    }
+
+    /// Shutdown is never suppressed.
+    #[inline]
+    fn on_shutdown(&self, snapshot: &Snapshot) {
+        self.inner.on_shutdown(snapshot)
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.inner.heap_size()
+    }
+
+    #[inline]
+    fn on_transition(&self, transition: &Transition) {
+        let kind = match transition.to {
+            State::Closed => CLOSED,
+            State::Open => OPEN,
+            State::HalfOpen => HALF_OPEN,
+        };
+
+        if self.admit(kind) {
+            self.inner.on_transition(transition)
+        }
+    }
+}
+
+impl<INSTRUMENT> Debug for RateLimited<INSTRUMENT>
+where
+    INSTRUMENT: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RateLimited")
+            .field("inner", &self.inner)
+            .field("max_events", &self.max_events)
+            .field("interval", &self.interval)
+            .field("suppressed", &self.suppressed())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn suppress_identical_events() {
+        clock::freeze(|time| {
+            let instrument = RateLimited::new(Counter::default(), 2, Duration::from_secs(10));
+
+            for _ in 0..5 {
+                instrument.on_call_rejected();
+            }
+            instrument.on_open();
+            assert_eq!(2, instrument.get_ref().rejected.load(Ordering::SeqCst));
+            assert_eq!(1, instrument.get_ref().opened.load(Ordering::SeqCst));
+            assert_eq!(3, instrument.suppressed());
+
+            time.advance(Duration::from_secs(10));
+            instrument.on_call_rejected();
+            assert_eq!(3, instrument.get_ref().rejected.load(Ordering::SeqCst));
+        })
+    }
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        rejected: AtomicUsize,
+        opened: AtomicUsize,
+    }
+
+    impl Instrument for Counter {
+        fn on_call_rejected(&self) {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_open(&self) {
+            self.opened.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
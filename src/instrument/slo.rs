@@ -0,0 +1,250 @@
+use std::fmt::{self, Debug};
+use std::time::Duration;
+
+use super::super::failure_policy::DEFAULT_MINIMUM_REQUEST_THRESHOLD;
+use super::super::lock::{Lock, Mutex};
+use super::super::windowed_adder::WindowedAdder;
+use super::events::{Event, EventSink};
+
+/// The number of slices of rolling windows of rules.
+const SLICES: u8 = 10;
+
+/// A breached rule of `Slo`, along with the observed value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Breach {
+    /// The circuit breaker has opened more times than allowed, see `Slo::opened_more_than`.
+    Opened {
+        /// How many times the circuit breaker has opened within the window.
+        times: u64,
+        /// The window of the rule.
+        per: Duration,
+    },
+    /// The share of rejected calls is above the limit, see `Slo::rejection_rate_above`.
+    RejectionRate {
+        /// The share of rejected calls within the window.
+        rate: f64,
+        /// The window of the rule.
+        over: Duration,
+    },
+}
+
+/// An event sink which evaluates simple service level rules over rolling windows, and calls
+/// `on_breach` when a rule is breached.
+///
+/// The callback is called once per breach, a rule is armed again after it's satisfied. It's
+/// called outside of the sink's lock, e.g. to page someone or to post to a channel.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{instrument::{Breach, Events, Slo}, Config};
+///
+/// let slo = Slo::new(|breach: &Breach| eprintln!("SLO breached: {:?}", breach))
+///     .opened_more_than(3, Duration::from_secs(3600))
+///     .rejection_rate_above(0.1, Duration::from_secs(300));
+///
+/// let circuit_breaker = Config::new().instrument(Events::new(slo)).build();
+/// ```
+pub struct Slo<F> {
+    rules: Mutex<Vec<Rule>>,
+    on_breach: F,
+}
+
+#[derive(Debug)]
+enum Rule {
+    Opened {
+        limit: u64,
+        opened: WindowedAdder,
+        breached: bool,
+    },
+    RejectionRate {
+        limit: f64,
+        rejections: WindowedAdder,
+        calls: WindowedAdder,
+        breached: bool,
+    },
+}
+
+impl<F> Slo<F>
+where
+    F: Fn(&Breach),
+{
+    /// Creates a sink without rules, which calls `on_breach` when one of them is breached.
+    pub fn new(on_breach: F) -> Self {
+        Slo {
+            rules: Mutex::new(Vec::new()),
+            on_breach,
+        }
+    }
+
+    /// Adds a rule which is breached when the circuit breaker opens more than `times` within
+    /// the rolling window `per`.
+    pub fn opened_more_than(self, times: u64, per: Duration) -> Self {
+        self.rule(Rule::Opened {
+            limit: times,
+            opened: WindowedAdder::new(per, SLICES),
+            breached: false,
+        })
+    }
+
+    /// Adds a rule which is breached when the share of rejected calls is above the `rate`
+    /// within the rolling window `over`.
+    ///
+    /// The rate isn't evaluated until there are at least 5 calls within the window, including
+    /// rejected ones.
+    ///
+    /// # Panics
+    ///
+    /// When `rate` isn't in `[0.0, 1.0]` interval.
+    pub fn rejection_rate_above(self, rate: f64, over: Duration) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "rejection rate must be [0, 1]: {}",
+            rate
+        );
+
+        self.rule(Rule::RejectionRate {
+            limit: rate,
+            rejections: WindowedAdder::new(over, SLICES),
+            calls: WindowedAdder::new(over, SLICES),
+            breached: false,
+        })
+    }
+
+    fn rule(self, rule: Rule) -> Self {
+        self.rules.with(|rules| rules.push(rule));
+        self
+    }
+}
+
+impl Rule {
+    /// Counts the event and returns a breach if the rule has been breached just now.
+    fn evaluate(&mut self, event: &Event) -> Option<Breach> {
+        match self {
+            Rule::Opened {
+                limit,
+                opened,
+                breached,
+            } => {
+                if let Event::Open { .. } = event {
+                    opened.add(1);
+                }
+                let times = opened.sum() as u64;
+                let breach = Breach::Opened {
+                    times,
+                    per: opened.window(),
+                };
+                edge(breached, times > *limit, breach)
+            }
+            Rule::RejectionRate {
+                limit,
+                rejections,
+                calls,
+                breached,
+            } => {
+                match event {
+                    Event::Rejected { .. } => {
+                        rejections.add(1);
+                        calls.add(1);
+                    }
+                    Event::Success { .. } | Event::Failure { .. } => calls.add(1),
+                    _ => {}
+                }
+                let total = calls.sum();
+                let rate = rejections.sum() as f64 / total.max(1) as f64;
+                let breach = Breach::RejectionRate {
+                    rate,
+                    over: calls.window(),
+                };
+                let enough = total >= i64::from(DEFAULT_MINIMUM_REQUEST_THRESHOLD);
+                edge(breached, enough && rate > *limit, breach)
+            }
+        }
+    }
+}
+
+/// Returns the `breach` when the rule has become breached, and arms it again once satisfied.
+fn edge(breached: &mut bool, now: bool, breach: Breach) -> Option<Breach> {
+    let fired = now && !*breached;
+    *breached = now;
+    if fired {
+        Some(breach)
+    } else {
+        None
+    }
+}
+
+impl<F> EventSink for Slo<F>
+where
+    F: Fn(&Breach),
+{
+    fn on_event(&self, event: &Event) {
+        let breaches = self.rules.with(|rules| {
+            rules
+                .iter_mut()
+                .filter_map(|rule| rule.evaluate(event))
+                .collect::<Vec<_>>()
+        });
+
+        for breach in &breaches {
+            (self.on_breach)(breach);
+        }
+    }
+}
+
+impl<F> Debug for Slo<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.rules
+            .with(|rules| f.debug_struct("Slo").field("rules", rules).finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::super::super::{backoff, clock, failure_policy, CircuitBreaker, Config};
+    use super::super::Events;
+    use super::*;
+
+    #[test]
+    fn breach_once() {
+        clock::freeze(|time| {
+            let breaches = Mutex::new(Vec::new());
+            let slo = Slo::new(|breach: &Breach| breaches.lock().unwrap().push(*breach))
+                .opened_more_than(1, Duration::from_secs(3600))
+                .rejection_rate_above(0.5, Duration::from_secs(60));
+
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .instrument(Events::new(slo))
+                .build();
+
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            for _ in 0..10 {
+                assert!(circuit_breaker.call(|| Ok::<_, ()>(())).is_err());
+            }
+            assert_eq!(
+                vec![Breach::RejectionRate {
+                    rate: 0.8,
+                    over: Duration::from_secs(60)
+                }],
+                *breaches.lock().unwrap()
+            );
+
+            // The failed probe opens the circuit breaker the second time.
+            time.advance(Duration::from_secs(10));
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            assert_eq!(
+                Breach::Opened {
+                    times: 2,
+                    per: Duration::from_secs(3600)
+                },
+                breaches.lock().unwrap()[1]
+            );
+            assert_eq!(2, breaches.lock().unwrap().len());
+        });
+    }
+}
@@ -0,0 +1,203 @@
+use std::cell::Cell;
+use std::fmt::{self, Debug, Display};
+use std::time::Instant;
+
+use super::circuit_breaker::CircuitBreaker;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate::{FailureClassifier, FailurePredicate};
+use super::instrument::Instrument;
+use super::snapshot::LabelCounts;
+use super::state_machine::StateMachine;
+
+/// A circuit breaker which attributes calls to a label, see `StateMachine::labeled`.
+///
+/// Calls are permitted and recorded by the underlying state machine as usual, the label only
+/// adds its own counters to the snapshot.
+pub struct Labeled<'a, POLICY, INSTRUMENT> {
+    state_machine: &'a StateMachine<POLICY, INSTRUMENT>,
+    label: &'a str,
+}
+
+/// Remembers whether the wrapped predicate or classifier has judged an error as a failure.
+struct Judged<'c, T> {
+    inner: T,
+    failed: &'c Cell<bool>,
+}
+
+impl<'c, T, E> FailurePredicate<E> for Judged<'c, T>
+where
+    T: FailurePredicate<E>,
+{
+    fn is_err(&self, err: &E) -> bool {
+        let failed = self.inner.is_err(err);
+        self.failed.set(failed);
+        failed
+    }
+}
+
+impl<'c, T, E> FailureClassifier<E> for Judged<'c, T>
+where
+    T: FailureClassifier<E>,
+{
+    fn classify(&self, err: &E) -> Option<&'static str> {
+        let class = self.inner.classify(err);
+        self.failed.set(class.is_some());
+        class
+    }
+}
+
+impl<'a, POLICY, INSTRUMENT> Labeled<'a, POLICY, INSTRUMENT> {
+    pub(crate) fn new(state_machine: &'a StateMachine<POLICY, INSTRUMENT>, label: &'a str) -> Self {
+        Labeled {
+            state_machine,
+            label,
+        }
+    }
+
+    /// Returns the label of calls.
+    pub fn label(&self) -> &str {
+        self.label
+    }
+
+    /// Counts the result of a call, `failed` tells whether an error was recorded as failure.
+    fn count<R, E>(&self, res: Result<R, Error<E>>, failed: bool) -> Result<R, Error<E>> {
+        self.state_machine
+            .with_label(self.label, |counts: &mut LabelCounts| match res {
+                Ok(_) => counts.successes += 1,
+                Err(Error::Rejected(_)) => counts.rejections += 1,
+                Err(Error::Inner(_)) if failed => counts.failures += 1,
+                Err(Error::Inner(_)) => counts.successes += 1,
+            });
+        res
+    }
+}
+
+impl<'a, POLICY, INSTRUMENT> CircuitBreaker for Labeled<'a, POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    fn is_call_permitted(&self) -> bool {
+        let permitted = self.state_machine.is_call_permitted();
+        if !permitted {
+            self.state_machine
+                .with_label(self.label, |counts| counts.rejections += 1);
+        }
+        permitted
+    }
+
+    fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        let failed = Cell::new(false);
+        let predicate = Judged {
+            inner: predicate,
+            failed: &failed,
+        };
+        let res = self.state_machine.call_with(predicate, f);
+        self.count(res, failed.get())
+    }
+
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        let failed = Cell::new(false);
+        let classifier = Judged {
+            inner: classifier,
+            failed: &failed,
+        };
+        let res = self.state_machine.call_classified(classifier, f);
+        self.count(res, failed.get())
+    }
+
+    fn call_or_wait<F, E, R>(&self, deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        let res = self.state_machine.call_or_wait(deadline, f);
+        self.count(res, true)
+    }
+
+    fn call_weighted<F, E, R>(&self, cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        let res = self.state_machine.call_weighted(cost, f);
+        self.count(res, true)
+    }
+
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        let res = self.state_machine.call_reported(f);
+        self.count(res, true)
+    }
+}
+
+impl<'a, POLICY, INSTRUMENT> Debug for Labeled<'a, POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Labeled")
+            .field("label", &self.label)
+            .field("state_machine", self.state_machine)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn count_by_label() {
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let policy = failure_policy::consecutive_failures(3, backoff);
+        let circuit_breaker = Config::new().failure_policy(policy).build();
+
+        let _ = circuit_breaker.labeled("a").call(|| Ok::<_, ()>(()));
+        let _ = circuit_breaker
+            .labeled("a")
+            .call_with(|_: &bool| false, || Err::<(), _>(true));
+        for _ in 0..3 {
+            let _ = circuit_breaker.labeled("b").call(|| Err::<(), _>(()));
+        }
+        // The decision is global, the open state rejects calls of any label.
+        let _ = circuit_breaker.labeled("a").call(|| Ok::<_, ()>(()));
+
+        let snapshot = circuit_breaker.snapshot();
+        assert_eq!(
+            (2, 3, 1),
+            (snapshot.successes, snapshot.failures, snapshot.rejections)
+        );
+        assert_eq!(
+            vec![
+                (
+                    "a".to_owned(),
+                    LabelCounts {
+                        successes: 2,
+                        failures: 0,
+                        rejections: 1
+                    }
+                ),
+                (
+                    "b".to_owned(),
+                    LabelCounts {
+                        successes: 0,
+                        failures: 3,
+                        rejections: 0
+                    }
+                ),
+            ],
+            snapshot.labels.into_iter().collect::<Vec<_>>()
+        );
+    }
+}
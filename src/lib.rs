@@ -4,7 +4,8 @@
 //!
 //! # Links
 //!
-//! * Future aware circuit breaker's interface [futures::CircuitBreaker](futures/index.html).
+//! * Future aware circuit breaker's interface [futures::CircuitBreaker](futures/index.html), requires
+//!   the default `async` feature.
 //! * The state machine which is used [StateMachine](state_machine/StateMachine.t.html).
 //! * More about circuit breakers [https://martinfowler.com/bliki/CircuitBreaker.html](https://martinfowler.com/bliki/CircuitBreaker.html)
 //!
@@ -35,7 +36,7 @@
 //!     Err(Error::Inner(_)) => {
 //!       eprintln!("{}: fail", n);
 //!     },
-//!     Err(Error::Rejected) => {
+//!     Err(Error::Rejected(_)) => {
 //!        eprintln!("{}: rejected", n);
 //!        break;
 //!     },
@@ -73,23 +74,65 @@ mod config;
 mod ema;
 mod error;
 mod failure_predicate;
-mod instrument;
+mod labeled;
+mod lock;
+mod monitor;
+mod percentile;
+mod reservation;
+mod snapshot;
 mod state_machine;
 mod windowed_adder;
 
+pub mod adaptive;
+#[cfg(feature = "admin")]
+pub mod admin;
 pub mod backoff;
+pub mod balance;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod classify;
+pub mod composite;
 pub mod failure_policy;
-#[cfg(feature = "futures-support")]
+#[cfg(feature = "async")]
 pub mod futures;
+#[cfg(feature = "governor")]
+pub mod governor;
+pub mod group;
+pub mod instrument;
+pub mod outlier;
+pub mod partition;
+pub mod prometheus;
+pub mod publish;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+pub mod registry;
+pub mod replay;
+#[cfg(feature = "sqlx")]
+pub mod sqlx;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tower")]
+pub mod tower;
 
 #[doc(hidden)]
 pub mod clock;
 
-pub use self::circuit_breaker::CircuitBreaker;
-pub use self::config::Config;
-pub use self::error::Error;
+pub use self::circuit_breaker::{CircuitBreaker, SharedCircuitBreaker};
+pub use self::config::{Config, Preset};
+pub use self::error::{CallResult, Error, Rejection, RejectionReason};
 pub use self::failure_policy::FailurePolicy;
-pub use self::failure_predicate::{Any, FailurePredicate};
-pub use self::instrument::Instrument;
-pub use self::state_machine::StateMachine;
-pub use self::windowed_adder::WindowedAdder;
+pub use self::failure_predicate::{Any, FailureClassifier, FailurePredicate, Outcome};
+pub use self::instrument::{Cause, Instrument, State, Transition};
+pub use self::labeled::Labeled;
+pub use self::monitor::Monitor;
+pub use self::percentile::Percentile;
+#[cfg(feature = "global")]
+pub use self::registry::global;
+pub use self::reservation::Reservation;
+pub use self::snapshot::{
+    InstrumentOverhead, LabelCounts, LastError, Snapshot, SnapshotDiff, TransitionCounts,
+};
+pub use self::state_machine::{
+    CallContext, Decision, ProbeSelection, Scheduled, StateMachine, WeakStateMachine,
+};
+pub use self::windowed_adder::{WindowState, WindowedAdder};
@@ -0,0 +1,196 @@
+//! Internal synchronization primitives.
+//!
+//! The state machine doesn't depend on a particular mutex implementation, it uses the `Lock`
+//! trait instead. `parking_lot::Mutex` is used when the `parking_lot` feature is enabled (by
+//! default), otherwise the crate falls back to `std::sync::Mutex`.
+//!
+//! The state of a circuit breaker is guarded by `StateLock`, which is a reader-writer lock, so
+//! observers like names and `Display`, and decisions which don't change the state, e.g.
+//! rejections of the open state, don't exclude each other. Calls in the closed state take no
+//! lock at all, see `Published`.
+
+/// A minimal interface of a lock which protects a value of type `T`.
+pub(crate) trait Lock<T> {
+    /// Creates a new lock in an unlocked state.
+    fn new(value: T) -> Self;
+
+    /// Acquires the lock and calls `f` with the protected value.
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R;
+
+    /// Acquires the lock for reading and calls `f` with the protected value.
+    ///
+    /// Readers may share the lock if the implementation supports that.
+    fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R;
+}
+
+/// The default lock implementation.
+#[cfg(feature = "parking_lot")]
+pub(crate) type Mutex<T> = parking_lot::Mutex<T>;
+
+/// The default lock implementation.
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) type Mutex<T> = std::sync::Mutex<T>;
+
+/// The reader-writer lock implementation.
+#[cfg(feature = "parking_lot")]
+pub(crate) type RwLock<T> = parking_lot::RwLock<T>;
+
+/// The reader-writer lock implementation.
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) type RwLock<T> = std::sync::RwLock<T>;
+
+/// The lock of the state of a circuit breaker.
+pub(crate) type StateLock<T> = RwLock<T>;
+
+#[cfg(feature = "parking_lot")]
+impl<T> Lock<T> for parking_lot::Mutex<T> {
+    #[inline]
+    fn new(value: T) -> Self {
+        parking_lot::Mutex::new(value)
+    }
+
+    #[inline]
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.lock())
+    }
+
+    #[inline]
+    fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.lock())
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+impl<T> Lock<T> for parking_lot::RwLock<T> {
+    #[inline]
+    fn new(value: T) -> Self {
+        parking_lot::RwLock::new(value)
+    }
+
+    #[inline]
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.write())
+    }
+
+    #[inline]
+    fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.read())
+    }
+}
+
+impl<T> Lock<T> for std::sync::Mutex<T> {
+    #[inline]
+    fn new(value: T) -> Self {
+        std::sync::Mutex::new(value)
+    }
+
+    #[inline]
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        // A panic inside of an instrument or a policy must not make the circuit breaker unusable,
+        // so the poisoned state is ignored.
+        let mut guard = match self.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut guard)
+    }
+
+    #[inline]
+    fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.with(|it| f(it))
+    }
+}
+
+impl<T> Lock<T> for std::sync::RwLock<T> {
+    #[inline]
+    fn new(value: T) -> Self {
+        std::sync::RwLock::new(value)
+    }
+
+    #[inline]
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        // The poisoned state is ignored for the same reason as for the mutex.
+        let mut guard = match self.write() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut guard)
+    }
+
+    #[inline]
+    fn read_with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = match self.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&guard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std_mutex() {
+        let lock: std::sync::Mutex<u32> = Lock::new(1);
+        lock.with(|it| *it += 1);
+        assert_eq!(2, lock.with(|it| *it));
+    }
+
+    #[test]
+    fn default_mutex() {
+        let lock: Mutex<u32> = Lock::new(1);
+        lock.with(|it| *it += 1);
+        assert_eq!(2, lock.with(|it| *it));
+    }
+
+    #[test]
+    fn std_rwlock() {
+        let lock: std::sync::RwLock<u32> = Lock::new(1);
+        lock.with(|it| *it += 1);
+        assert_eq!(2, lock.read_with(|it| *it));
+    }
+
+    #[test]
+    fn state_lock() {
+        let lock: StateLock<u32> = Lock::new(1);
+        lock.with(|it| *it += 1);
+        assert_eq!(2, lock.read_with(|it| *it));
+    }
+
+    #[test]
+    fn shared_readers() {
+        let lock: StateLock<u32> = Lock::new(1);
+        let sum = lock.read_with(|a| lock.read_with(|b| a + b));
+        assert_eq!(2, sum);
+    }
+}
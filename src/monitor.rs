@@ -0,0 +1,86 @@
+use std::fmt::{self, Debug};
+use std::time::Duration;
+
+use super::clock;
+use super::instrument::State;
+use super::snapshot::Snapshot;
+use super::state_machine::StateMachine;
+
+/// A read only handle of a circuit breaker, see `StateMachine::monitor`.
+///
+/// It queries the state and counters without side effects, but can't record results of calls
+/// or force transitions, so it's suitable for dashboards and health endpoints.
+pub struct Monitor<POLICY, INSTRUMENT> {
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+}
+
+impl<POLICY, INSTRUMENT> Monitor<POLICY, INSTRUMENT> {
+    pub(crate) fn new(state_machine: StateMachine<POLICY, INSTRUMENT>) -> Self {
+        Monitor { state_machine }
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> State {
+        self.state_machine.state()
+    }
+
+    /// Returns a point in time view of the state and counters.
+    pub fn snapshot(&self) -> Snapshot {
+        self.state_machine.snapshot()
+    }
+
+    /// Returns the time left until the open state expires, if the circuit breaker is open.
+    ///
+    /// It's `None` when the circuit breaker is forced open, as it's open until reset.
+    pub fn remaining_open(&self) -> Option<Duration> {
+        let now = clock::now();
+        self.snapshot()
+            .open_until
+            .map(|until| until.saturating_duration_since(now))
+    }
+}
+
+impl<POLICY, INSTRUMENT> Clone for Monitor<POLICY, INSTRUMENT> {
+    fn clone(&self) -> Self {
+        Monitor {
+            state_machine: self.state_machine.clone(),
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Debug for Monitor<POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Monitor")
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{backoff, failure_policy, CircuitBreaker, Config};
+    use super::*;
+
+    #[test]
+    fn observe_without_side_effects() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+            let monitor = circuit_breaker.monitor();
+
+            assert_eq!(State::Closed, monitor.state());
+            assert_eq!(None, monitor.remaining_open());
+
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            time.advance(Duration::from_secs(2));
+            assert_eq!(State::Open, monitor.state());
+            assert_eq!(Some(Duration::from_secs(3)), monitor.remaining_open());
+
+            time.advance(Duration::from_secs(5));
+            assert_eq!(Some(Duration::from_secs(0)), monitor.remaining_open());
+            assert_eq!(State::Open, monitor.state());
+            assert_eq!(1, monitor.snapshot().failures);
+        })
+    }
+}
@@ -0,0 +1,314 @@
+//! Outlier ejection for a set of endpoints, e.g. for client side load balancing.
+//!
+//! `OutlierDetection` counts results of calls per endpoint. Periodically, `eject_outliers`
+//! compares the failure rate of every endpoint with the average of the set, and ejects the
+//! endpoints which deviate too much. An ejected endpoint returns after the ejection time, which
+//! grows with every repeated ejection.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use failsafe::outlier::OutlierDetection;
+//!
+//! let detection = OutlierDetection::new()
+//!     .deviation(0.2)
+//!     .max_ejection_percent(50)
+//!     .base_ejection_time(Duration::from_secs(30));
+//!
+//! for endpoint in &["a", "b", "c"] {
+//!     for _ in 0..10 {
+//!         if *endpoint == "c" {
+//!             detection.on_error(endpoint);
+//!         } else {
+//!             detection.on_success(endpoint);
+//!         }
+//!     }
+//! }
+//!
+//! // Periodically, e.g. from a timer.
+//! assert_eq!(vec!["c"], detection.eject_outliers());
+//! assert!(detection.is_ejected(&"c"));
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use super::clock;
+use super::lock::{Lock, Mutex};
+
+const DEFAULT_DEVIATION: f64 = 0.2;
+const DEFAULT_MIN_REQUESTS: u64 = 10;
+const DEFAULT_MAX_EJECTION_PERCENT: u8 = 10;
+const DEFAULT_BASE_EJECTION_TIME: Duration = Duration::from_secs(30);
+
+/// Ejects endpoints which failure rate deviates from the average of the set.
+pub struct OutlierDetection<K> {
+    deviation: f64,
+    min_requests: u64,
+    max_ejection_percent: u8,
+    base_ejection_time: Duration,
+    endpoints: Mutex<HashMap<K, Endpoint>>,
+}
+
+#[derive(Debug, Default)]
+struct Endpoint {
+    successes: u64,
+    failures: u64,
+    ejected_until: Option<Instant>,
+    ejections: u32,
+}
+
+impl Endpoint {
+    #[inline]
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until.map_or(false, |until| until > now)
+    }
+
+    #[inline]
+    fn failure_rate(&self) -> f64 {
+        self.failures as f64 / (self.successes + self.failures) as f64
+    }
+}
+
+impl<K> OutlierDetection<K>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Creates a new detection with the default settings: the deviation of `0.2`, `10`
+    /// minimum requests, at most `10` percent of ejected endpoints and `30` seconds of the base
+    /// ejection time.
+    pub fn new() -> Self {
+        OutlierDetection {
+            deviation: DEFAULT_DEVIATION,
+            min_requests: DEFAULT_MIN_REQUESTS,
+            max_ejection_percent: DEFAULT_MAX_EJECTION_PERCENT,
+            base_ejection_time: DEFAULT_BASE_EJECTION_TIME,
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how much the failure rate of an endpoint may exceed the average failure rate of the
+    /// set before the endpoint is ejected.
+    ///
+    /// # Panics
+    ///
+    /// When the deviation isn't in `[0.0, 1.0]` interval.
+    pub fn deviation(mut self, deviation: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&deviation),
+            "deviation must be [0, 1]: {}",
+            deviation
+        );
+        self.deviation = deviation;
+        self
+    }
+
+    /// Sets the minimum number of calls an endpoint must receive during an interval to take
+    /// part in the detection.
+    pub fn min_requests(mut self, min_requests: u64) -> Self {
+        self.min_requests = min_requests;
+        self
+    }
+
+    /// Sets the maximum share of the set, in percents, which may be ejected at the same time.
+    ///
+    /// The share is rounded up, so unless it's zero at least one endpoint may be ejected, even
+    /// of a small set.
+    ///
+    /// # Panics
+    ///
+    /// When the percentage is greater than `100`.
+    pub fn max_ejection_percent(mut self, percent: u8) -> Self {
+        assert!(percent <= 100, "percent must be [0, 100]: {}", percent);
+        self.max_ejection_percent = percent;
+        self
+    }
+
+    /// Sets the ejection time, it's multiplied by the number of times an endpoint was ejected.
+    pub fn base_ejection_time(mut self, base_ejection_time: Duration) -> Self {
+        self.base_ejection_time = base_ejection_time;
+        self
+    }
+
+    /// Records a successful call to the `endpoint`.
+    pub fn on_success(&self, endpoint: &K) {
+        self.record(endpoint, |it| it.successes += 1)
+    }
+
+    /// Records a failed call to the `endpoint`.
+    pub fn on_error(&self, endpoint: &K) {
+        self.record(endpoint, |it| it.failures += 1)
+    }
+
+    /// Tells whether the `endpoint` is ejected now.
+    pub fn is_ejected(&self, endpoint: &K) -> bool {
+        let now = clock::now();
+        self.endpoints.with(|endpoints| {
+            endpoints
+                .get(endpoint)
+                .map_or(false, |it| it.is_ejected(now))
+        })
+    }
+
+    /// Forgets the `endpoint`, e.g. when it's removed from the set.
+    pub fn remove(&self, endpoint: &K) {
+        self.endpoints.with(|endpoints| endpoints.remove(endpoint));
+    }
+
+    /// Ejects outliers and starts a new interval, it returns the newly ejected endpoints.
+    ///
+    /// Endpoints with at least `min_requests` calls, which aren't ejected already, take part in
+    /// the detection. An endpoint is an outlier when its failure rate exceeds the average by
+    /// more than the `deviation`. The worst outliers are ejected first, while the share of
+    /// ejected endpoints stays within `max_ejection_percent`.
+    pub fn eject_outliers(&self) -> Vec<K> {
+        let now = clock::now();
+        self.endpoints.with(|endpoints| {
+            let mut ejected = endpoints.values().filter(|it| it.is_ejected(now)).count();
+            let max_ejected = match self.max_ejection_percent as usize {
+                0 => 0,
+                percent => ((endpoints.len() * percent + 99) / 100).max(1),
+            };
+
+            let mut eligible = endpoints
+                .iter()
+                .filter(|(_, it)| {
+                    !it.is_ejected(now) && it.successes + it.failures >= self.min_requests.max(1)
+                })
+                .map(|(key, it)| (key.clone(), it.failure_rate()))
+                .collect::<Vec<_>>();
+
+            let mut outliers = Vec::new();
+            if !eligible.is_empty() {
+                let average =
+                    eligible.iter().map(|(_, rate)| rate).sum::<f64>() / eligible.len() as f64;
+                eligible.retain(|(_, rate)| *rate - average > self.deviation);
+                eligible.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+                for (key, _) in eligible {
+                    if ejected >= max_ejected {
+                        break;
+                    }
+                    if let Some(it) = endpoints.get_mut(&key) {
+                        it.ejections = it.ejections.saturating_add(1);
+                        it.ejected_until = Some(now + self.base_ejection_time * it.ejections);
+                    }
+                    ejected += 1;
+                    outliers.push(key);
+                }
+            }
+
+            for it in endpoints.values_mut() {
+                it.successes = 0;
+                it.failures = 0;
+            }
+            outliers
+        })
+    }
+
+    fn record<F>(&self, endpoint: &K, f: F)
+    where
+        F: FnOnce(&mut Endpoint),
+    {
+        self.endpoints.with(|endpoints| {
+            if let Some(it) = endpoints.get_mut(endpoint) {
+                return f(it);
+            }
+            let mut it = Endpoint::default();
+            f(&mut it);
+            endpoints.insert(endpoint.clone(), it);
+        })
+    }
+}
+
+impl<K> Default for OutlierDetection<K>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Debug for OutlierDetection<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OutlierDetection")
+            .field("deviation", &self.deviation)
+            .field("min_requests", &self.min_requests)
+            .field("max_ejection_percent", &self.max_ejection_percent)
+            .field("base_ejection_time", &self.base_ejection_time)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eject_outliers() {
+        clock::freeze(|time| {
+            let detection = OutlierDetection::new()
+                .min_requests(2)
+                .max_ejection_percent(25)
+                .base_ejection_time(Duration::from_secs(10));
+
+            let record = |endpoint: u32, failures: u32| {
+                for n in 0..4 {
+                    if n < failures {
+                        detection.on_error(&endpoint);
+                    } else {
+                        detection.on_success(&endpoint);
+                    }
+                }
+            };
+
+            for endpoint in 0..8 {
+                record(endpoint, if endpoint < 3 { 4 } else { 0 });
+            }
+            // Only 2 of 8 endpoints may be ejected, the third outlier stays.
+            let mut ejected = detection.eject_outliers();
+            ejected.sort_unstable();
+            assert_eq!(2, ejected.len());
+            assert!(ejected.iter().all(|it| *it < 3));
+            assert!(ejected.iter().all(|it| detection.is_ejected(it)));
+
+            // The ejection time grows with repeated ejections.
+            time.advance(Duration::from_secs(11));
+            assert!(!detection.is_ejected(&ejected[0]));
+            record(ejected[0], 4);
+            record(7, 0);
+            assert_eq!(vec![ejected[0]], detection.eject_outliers());
+            time.advance(Duration::from_secs(11));
+            assert!(detection.is_ejected(&ejected[0]));
+        });
+    }
+
+    #[test]
+    fn eject_one_of_small_set() {
+        let detection = OutlierDetection::new();
+        for endpoint in &["a", "b", "c"] {
+            for _ in 0..10 {
+                if *endpoint == "c" {
+                    detection.on_error(endpoint);
+                } else {
+                    detection.on_success(endpoint);
+                }
+            }
+        }
+
+        // 10% of 3 endpoints is rounded up to one.
+        assert_eq!(vec!["c"], detection.eject_outliers());
+
+        let detection = OutlierDetection::new().max_ejection_percent(0);
+        for _ in 0..10 {
+            detection.on_error(&"c");
+            detection.on_success(&"a");
+        }
+        assert!(detection.eject_outliers().is_empty());
+    }
+}
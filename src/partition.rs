@@ -0,0 +1,194 @@
+//! Circuit breakers per partition of a stream consumer, e.g. of Kafka topics.
+//!
+//! A failing partition shouldn't stop the whole consumer. `Partitioned` keeps a circuit breaker
+//! per partition, created on demand, and tells which partitions are tripped, so the consumer
+//! pauses only those and keeps processing the rest.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{partition::Partitioned, Config};
+//!
+//! let partitions = Partitioned::new(|| Config::new().build());
+//!
+//! let result = partitions.call(&("orders", 0), || Ok::<_, ()>("processed"));
+//! assert!(result.is_ok());
+//!
+//! // Periodically, e.g. from the poll loop.
+//! for (topic, partition) in partitions.paused() {
+//!     println!("pausing {}/{}", topic, partition);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+
+use super::circuit_breaker::CircuitBreaker;
+use super::config::Config;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::instrument::Instrument;
+use super::lock::{Lock, Mutex};
+use super::state_machine::StateMachine;
+
+type Factory<K, POLICY, INSTRUMENT> =
+    Box<dyn Fn(&K) -> StateMachine<POLICY, INSTRUMENT> + Send + Sync>;
+
+/// Circuit breakers keyed by partition, see the module documentation.
+pub struct Partitioned<K, POLICY, INSTRUMENT> {
+    factory: Factory<K, POLICY, INSTRUMENT>,
+    partitions: Mutex<HashMap<K, StateMachine<POLICY, INSTRUMENT>>>,
+}
+
+impl<K, POLICY, INSTRUMENT> Partitioned<K, POLICY, INSTRUMENT>
+where
+    K: Hash + Eq + Clone,
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Creates an empty set, circuit breakers of partitions are created by the `factory`.
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn() -> StateMachine<POLICY, INSTRUMENT> + Send + Sync + 'static,
+    {
+        Partitioned {
+            factory: Box::new(move |_: &K| factory()),
+            partitions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates an empty set, circuit breakers are built from configurations returned by the
+    /// `factory` for each partition, so some partitions may be tuned differently.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use failsafe::{backoff, failure_policy, partition::Partitioned, Config};
+    ///
+    /// let partitions = Partitioned::with_config(|host: &&str| {
+    ///     // A known flaky host trips sooner.
+    ///     let failures = if *host == "flaky.local" { 1 } else { 5 };
+    ///     let backoff = backoff::constant(Duration::from_secs(10));
+    ///     Config::new().failure_policy(failure_policy::consecutive_failures(failures, backoff))
+    /// });
+    ///
+    /// assert!(partitions.call(&"flaky.local", || Err::<(), _>(())).is_err());
+    /// assert!(partitions.is_paused(&"flaky.local"));
+    /// ```
+    pub fn with_config<F>(factory: F) -> Self
+    where
+        F: Fn(&K) -> Config<POLICY, INSTRUMENT> + Send + Sync + 'static,
+    {
+        Partitioned {
+            factory: Box::new(move |partition: &K| factory(partition).build()),
+            partitions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the circuit breaker of the `partition`, it's created on first use.
+    pub fn get(&self, partition: &K) -> StateMachine<POLICY, INSTRUMENT> {
+        self.partitions.with(|partitions| {
+            if let Some(state_machine) = partitions.get(partition) {
+                return state_machine.clone();
+            }
+            let state_machine = (self.factory)(partition);
+            partitions.insert(partition.clone(), state_machine.clone());
+            state_machine
+        })
+    }
+
+    /// Processes a message of the `partition` within its circuit breaker.
+    pub fn call<F, E, R>(&self, partition: &K, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.get(partition).call(f)
+    }
+
+    /// Tells whether processing of the `partition` should be paused.
+    ///
+    /// It has no side effects, see `StateMachine::peek_permitted`, so the partition is resumed
+    /// once the delay of the open state expires, and the next message acts as a probe.
+    pub fn is_paused(&self, partition: &K) -> bool {
+        self.partitions.with(|partitions| {
+            partitions
+                .get(partition)
+                .map_or(false, |it| !it.peek_permitted())
+        })
+    }
+
+    /// Returns partitions which circuit breakers are tripped.
+    pub fn paused(&self) -> Vec<K> {
+        self.partitions.with(|partitions| {
+            partitions
+                .iter()
+                .filter(|(_, it)| !it.peek_permitted())
+                .map(|(partition, _)| partition.clone())
+                .collect()
+        })
+    }
+
+    /// Forgets the `partition`, e.g. when it's revoked by a rebalance.
+    pub fn remove(&self, partition: &K) {
+        self.partitions
+            .with(|partitions| partitions.remove(partition));
+    }
+}
+
+impl<K, POLICY, INSTRUMENT> Debug for Partitioned<K, POLICY, INSTRUMENT>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.partitions.with(|partitions| {
+            f.debug_struct("Partitioned")
+                .field("partitions", &partitions.keys().collect::<Vec<_>>())
+                .finish()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, clock, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn pause_tripped_partitions() {
+        clock::freeze(|time| {
+            let partitions = Partitioned::new(|| {
+                let backoff = backoff::constant(Duration::from_secs(5));
+                let policy = failure_policy::consecutive_failures(1, backoff);
+                Config::new().failure_policy(policy).build()
+            });
+
+            assert!(partitions.call(&0, || Ok::<_, ()>(())).is_ok());
+            assert!(partitions.call(&1, || Err::<(), _>(())).is_err());
+            assert_eq!(vec![1], partitions.paused());
+            assert!(partitions.is_paused(&1));
+            assert!(!partitions.is_paused(&0));
+            assert!(!partitions.is_paused(&2));
+
+            time.advance(Duration::from_secs(10));
+            assert!(partitions.paused().is_empty());
+
+            partitions.remove(&1);
+            assert!(partitions.get(&1).is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn override_per_partition() {
+        let partitions = Partitioned::with_config(|partition: &u32| {
+            let failures = if *partition == 0 { 1 } else { 3 };
+            let backoff = backoff::constant(Duration::from_secs(5));
+            Config::new().failure_policy(failure_policy::consecutive_failures(failures, backoff))
+        });
+
+        assert!(partitions.call(&0, || Err::<(), _>(())).is_err());
+        assert!(partitions.call(&1, || Err::<(), _>(())).is_err());
+        assert_eq!(vec![0], partitions.paused());
+    }
+}
@@ -0,0 +1,203 @@
+/// A streaming estimator of a percentile, e.g. of latencies, in constant memory.
+///
+/// It implements the P² algorithm by Jain and Chlamtac, which keeps five markers, the minimum,
+/// the maximum, the estimated percentile and two in between, and adjusts their heights by a
+/// piecewise-parabolic interpolation on every observation. Until five values are observed the
+/// estimate is exact.
+///
+/// ```
+/// use failsafe::Percentile;
+///
+/// let mut p99 = Percentile::new(0.99);
+/// for latency in 1..=1000 {
+///     p99.observe(f64::from(latency));
+/// }
+///
+/// let estimate = p99.estimate().unwrap();
+/// assert!((estimate - 990.0).abs() < 10.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Percentile {
+    p: f64,
+    count: u64,
+    /// Heights of markers, or observed values until there are five of them.
+    heights: [f64; 5],
+    /// Actual positions of markers, 1-based.
+    positions: [f64; 5],
+    /// Desired positions of markers.
+    desired: [f64; 5],
+    /// Increments of desired positions per observation.
+    increments: [f64; 5],
+}
+
+impl Percentile {
+    /// Creates an estimator of the percentile `p`, e.g. `0.99` for the 99th percentile.
+    ///
+    /// # Panics
+    ///
+    /// When `p` isn't in `[0.0, 1.0]` interval.
+    pub fn new(p: f64) -> Self {
+        assert!((0.0..=1.0).contains(&p), "percentile must be [0, 1]: {}", p);
+
+        Percentile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    /// Returns the estimated percentile.
+    pub fn percentile(&self) -> f64 {
+        self.p
+    }
+
+    /// Returns the number of observed values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// `true` if no values were observed.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Adds the `value` to the estimate, NaNs and infinities are ignored.
+    pub fn observe(&mut self, value: f64) {
+        // An infinite marker turns the interpolation of its neighbours into NaNs.
+        if !value.is_finite() {
+            return;
+        }
+
+        if self.count < 5 {
+            self.heights[self.count as usize] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            }
+            return;
+        }
+        self.count += 1;
+
+        let heights = &mut self.heights;
+        // The cell of the value, heights at the ends are extended to include it.
+        let cell = if value < heights[0] {
+            heights[0] = value;
+            0
+        } else if value >= heights[4] {
+            heights[4] = value;
+            3
+        } else {
+            (1..5).find(|&i| value < heights[i]).unwrap() - 1
+        };
+
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(&self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let drift = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (drift >= 1.0 && n[i + 1] - n[i] > 1.0) || (drift <= -1.0 && n[i - 1] - n[i] < -1.0)
+            {
+                let step = drift.signum();
+                let parabolic = self.parabolic(i, step);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, step)
+                    };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    /// Returns the estimate, or `None` if no values were observed.
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            count if count < 5 => {
+                let mut observed = self.heights;
+                let observed = &mut observed[..count as usize];
+                observed.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let rank = (self.p * (count - 1) as f64).round() as usize;
+                Some(observed[rank])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+
+    /// Erases all observations.
+    pub fn reset(&mut self) {
+        *self = Percentile::new(self.p);
+    }
+
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + step / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        let j = if step > 0.0 { i + 1 } else { i - 1 };
+        q[i] + step * (q[j] - q[i]) / (n[j] - n[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_shuffled() {
+        let mut p50 = Percentile::new(0.5);
+        let mut p99 = Percentile::new(0.99);
+
+        // Visits every value of 0..10000 once, in a scattered order.
+        for i in 0..10_000u32 {
+            let value = f64::from(i * 7919 % 10_000);
+            p50.observe(value);
+            p99.observe(value);
+        }
+
+        assert_eq!(10_000, p50.count());
+        assert!((p50.estimate().unwrap() - 5_000.0).abs() < 100.0);
+        assert!((p99.estimate().unwrap() - 9_900.0).abs() < 50.0);
+    }
+
+    #[test]
+    fn exact_until_five_values() {
+        let mut median = Percentile::new(0.5);
+        assert_eq!(None, median.estimate());
+
+        for value in &[30.0, f64::NAN, 10.0, f64::INFINITY, 20.0] {
+            median.observe(*value);
+        }
+        assert_eq!(3, median.count());
+        assert_eq!(Some(20.0), median.estimate());
+
+        median.reset();
+        assert!(median.is_empty());
+    }
+
+    #[test]
+    fn ignore_infinities() {
+        let mut p90 = Percentile::new(0.9);
+        for value in 0..100 {
+            p90.observe(f64::from(value));
+            p90.observe(f64::INFINITY);
+            p90.observe(f64::NEG_INFINITY);
+        }
+
+        assert_eq!(100, p90.count());
+        assert!((p90.estimate().unwrap() - 90.0).abs() < 5.0);
+    }
+}
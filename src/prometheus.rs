@@ -0,0 +1,241 @@
+//! Renders snapshots of circuit breakers in the Prometheus text exposition format.
+//!
+//! It lets applications without a metrics library expose `/metrics` in a few lines.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{prometheus, Config};
+//!
+//! let redis = Config::new().build();
+//! let postgres = Config::new().build();
+//!
+//! let body = prometheus::render(vec![
+//!     ("redis", redis.snapshot()),
+//!     ("postgres", postgres.snapshot()),
+//! ]);
+//! assert!(body.contains("failsafe_state{breaker=\"redis\"} 0"));
+//! ```
+
+use std::fmt::{self, Write};
+
+#[cfg(feature = "histogram")]
+use super::instrument::LatencyHistogram;
+use super::snapshot::Snapshot;
+
+/// Renders named snapshots into a string, see `write`.
+pub fn render<I, N>(snapshots: I) -> String
+where
+    I: IntoIterator<Item = (N, Snapshot)>,
+    N: AsRef<str>,
+{
+    let mut out = String::new();
+    write(&mut out, snapshots).expect("writing to a String never fails");
+    out
+}
+
+/// Writes named snapshots in the Prometheus text exposition format.
+///
+/// The following metrics are written, labeled by the `breaker` name:
+///
+/// * `failsafe_state` - gauge of the state: 0 is closed, 1 is half open, 2 is open.
+/// * `failsafe_calls_total` - counter of calls by the `outcome`: success, failure or rejected.
+/// * `failsafe_opened_total` - counter of transitions to the open state.
+/// * `failsafe_transitions_total` - counter of transitions by the `from` and `to` states.
+pub fn write<W, I, N>(out: &mut W, snapshots: I) -> fmt::Result
+where
+    W: Write,
+    I: IntoIterator<Item = (N, Snapshot)>,
+    N: AsRef<str>,
+{
+    let snapshots: Vec<_> = snapshots.into_iter().collect();
+
+    writeln!(
+        out,
+        "# HELP failsafe_state The state of the circuit breaker: 0 is closed, 1 is half open, 2 is open."
+    )?;
+    writeln!(out, "# TYPE failsafe_state gauge")?;
+    for (name, snapshot) in &snapshots {
+        writeln!(
+            out,
+            "failsafe_state{{breaker=\"{}\"}} {}",
+            Escaped(name.as_ref()),
+            snapshot.state.as_gauge()
+        )?;
+    }
+
+    writeln!(
+        out,
+        "# HELP failsafe_calls_total The number of calls by outcome."
+    )?;
+    writeln!(out, "# TYPE failsafe_calls_total counter")?;
+    for (name, snapshot) in &snapshots {
+        let outcomes = [
+            ("success", snapshot.successes),
+            ("failure", snapshot.failures),
+            ("rejected", snapshot.rejections),
+        ];
+        for (outcome, value) in outcomes.iter() {
+            writeln!(
+                out,
+                "failsafe_calls_total{{breaker=\"{}\",outcome=\"{}\"}} {}",
+                Escaped(name.as_ref()),
+                outcome,
+                value
+            )?;
+        }
+    }
+
+    writeln!(
+        out,
+        "# HELP failsafe_opened_total The number of transitions to the open state."
+    )?;
+    writeln!(out, "# TYPE failsafe_opened_total counter")?;
+    for (name, snapshot) in &snapshots {
+        writeln!(
+            out,
+            "failsafe_opened_total{{breaker=\"{}\"}} {}",
+            Escaped(name.as_ref()),
+            snapshot.opened
+        )?;
+    }
+
+    writeln!(
+        out,
+        "# HELP failsafe_transitions_total The number of transitions by the previous and the new state."
+    )?;
+    writeln!(out, "# TYPE failsafe_transitions_total counter")?;
+    for (name, snapshot) in &snapshots {
+        for (from, to, value) in snapshot.transitions.iter() {
+            writeln!(
+                out,
+                "failsafe_transitions_total{{breaker=\"{}\",from=\"{}\",to=\"{}\"}} {}",
+                Escaped(name.as_ref()),
+                from.as_str(),
+                to.as_str(),
+                value
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quantiles of latencies written by `write_latencies`.
+#[cfg(feature = "histogram")]
+const QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+/// Writes named histograms of latencies as the `failsafe_latency_seconds` summary, labeled by
+/// the `breaker` name, with the median, the 90th, 99th and 99.9th percentiles.
+///
+/// ```
+/// use failsafe::{instrument::Latencies, prometheus, CircuitBreaker, Config};
+///
+/// let latencies = Latencies::new(());
+/// let histogram = latencies.histogram();
+/// let redis = Config::new().instrument(latencies).build();
+/// redis.call(|| Ok::<_, ()>(())).unwrap();
+///
+/// let mut body = String::new();
+/// prometheus::write_latencies(&mut body, vec![("redis", &histogram)]).unwrap();
+/// assert!(body.contains("failsafe_latency_seconds_count{breaker=\"redis\"} 1"));
+/// ```
+#[cfg(feature = "histogram")]
+pub fn write_latencies<'a, W, I, N>(out: &mut W, histograms: I) -> fmt::Result
+where
+    W: Write,
+    I: IntoIterator<Item = (N, &'a LatencyHistogram)>,
+    N: AsRef<str>,
+{
+    writeln!(out, "# HELP failsafe_latency_seconds Latencies of calls.")?;
+    writeln!(out, "# TYPE failsafe_latency_seconds summary")?;
+    for (name, histogram) in histograms {
+        let name = Escaped(name.as_ref());
+        for quantile in QUANTILES.iter() {
+            let latency = histogram.percentile(*quantile).unwrap_or_default();
+            writeln!(
+                out,
+                "failsafe_latency_seconds{{breaker=\"{}\",quantile=\"{}\"}} {}",
+                name,
+                quantile,
+                latency.as_secs_f64()
+            )?;
+        }
+        writeln!(
+            out,
+            "failsafe_latency_seconds_sum{{breaker=\"{}\"}} {}",
+            name,
+            histogram.sum().as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            "failsafe_latency_seconds_count{{breaker=\"{}\"}} {}",
+            name,
+            histogram.count()
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Escapes a label value.
+struct Escaped<'a>(&'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ch in self.0.chars() {
+            match ch {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                ch => f.write_char(ch)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, clock, failure_policy, CircuitBreaker, Config};
+    use super::*;
+
+    #[test]
+    fn render_snapshots() {
+        clock::freeze(|_| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+
+            circuit_breaker.call(|| Ok::<_, ()>(())).unwrap();
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            let _ = circuit_breaker.call(|| Ok::<_, ()>(()));
+
+            let body = render(vec![("db \"main\"", circuit_breaker.snapshot())]);
+            let expected = "\
+# HELP failsafe_state The state of the circuit breaker: 0 is closed, 1 is half open, 2 is open.
+# TYPE failsafe_state gauge
+failsafe_state{breaker=\"db \\\"main\\\"\"} 2
+# HELP failsafe_calls_total The number of calls by outcome.
+# TYPE failsafe_calls_total counter
+failsafe_calls_total{breaker=\"db \\\"main\\\"\",outcome=\"success\"} 1
+failsafe_calls_total{breaker=\"db \\\"main\\\"\",outcome=\"failure\"} 1
+failsafe_calls_total{breaker=\"db \\\"main\\\"\",outcome=\"rejected\"} 1
+# HELP failsafe_opened_total The number of transitions to the open state.
+# TYPE failsafe_opened_total counter
+failsafe_opened_total{breaker=\"db \\\"main\\\"\"} 1
+# HELP failsafe_transitions_total The number of transitions by the previous and the new state.
+# TYPE failsafe_transitions_total counter
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"closed\",to=\"half_open\"} 0
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"closed\",to=\"open\"} 1
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"half_open\",to=\"closed\"} 0
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"half_open\",to=\"open\"} 0
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"open\",to=\"closed\"} 0
+failsafe_transitions_total{breaker=\"db \\\"main\\\"\",from=\"open\",to=\"half_open\"} 0
+";
+            assert_eq!(expected, body);
+        })
+    }
+}
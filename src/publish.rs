@@ -0,0 +1,243 @@
+//! Guarding of fire-and-forget publishers, e.g. of a message queue, with bounded local buffering.
+//!
+//! Publishing is often fire-and-forget: results are ignored, so the circuit breaker never sees
+//! failures, and messages rejected by an open circuit breaker are silently lost. `Buffered`
+//! records the result of every publication, and keeps rejected or failed messages in a bounded
+//! buffer, which is drained in order once the circuit breaker permits calls again.
+//!
+//! Publishers are passed as functions, so any client fits, e.g. `lapin::Channel::basic_publish`
+//! with `Buffered::publish_async`.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{publish::{Buffered, Delivery}, Config};
+//!
+//! let mut publisher = Buffered::new(Config::new().build(), 1024);
+//! let send = |message: &String| -> Result<(), ()> {
+//!     // e.g. publish to a queue
+//!     Ok(())
+//! };
+//!
+//! match publisher.publish("hello".to_owned(), send) {
+//!     Delivery::Sent | Delivery::Buffered => {}
+//!     Delivery::Dropped(message) => eprintln!("buffer is full, dropped {}", message),
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+#[cfg(feature = "async")]
+use std::future::Future;
+
+use super::circuit_breaker::CircuitBreaker;
+use super::failure_policy::FailurePolicy;
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// What happened to a message passed to `Buffered::publish`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery<M> {
+    /// The message was published.
+    Sent,
+    /// The message was rejected or failed, it's kept in the buffer and published later.
+    Buffered,
+    /// The message was rejected or failed while the buffer is full, it's returned as is.
+    Dropped(M),
+}
+
+/// A publisher guarded by a circuit breaker, which buffers messages it can't publish now.
+///
+/// Buffered messages are published before new ones, so the order is kept. Methods take
+/// `&mut self`, so a publisher shared between tasks is wrapped into a mutex.
+pub struct Buffered<M, POLICY, INSTRUMENT> {
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+    buffer: VecDeque<M>,
+    capacity: usize,
+}
+
+impl<M, POLICY, INSTRUMENT> Buffered<M, POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Creates a publisher which buffers at most `capacity` messages.
+    pub fn new(state_machine: StateMachine<POLICY, INSTRUMENT>, capacity: usize) -> Self {
+        Buffered {
+            state_machine,
+            buffer: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Publishes the `message` by `f` within the circuit breaker, after the buffered ones.
+    pub fn publish<F, E>(&mut self, message: M, mut f: F) -> Delivery<M>
+    where
+        F: FnMut(&M) -> Result<(), E>,
+    {
+        self.flush(&mut f);
+        if !self.buffer.is_empty() {
+            return self.push(message);
+        }
+
+        match self.state_machine.call(|| f(&message)) {
+            Ok(()) => Delivery::Sent,
+            Err(_) => self.push(message),
+        }
+    }
+
+    /// Publishes buffered messages by `f` in order, until a message is rejected or fails.
+    ///
+    /// Returns the number of published messages. It's meant to be called periodically, when
+    /// there are no new messages to publish.
+    pub fn flush<F, E>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&M) -> Result<(), E>,
+    {
+        let mut sent = 0;
+        while let Some(message) = self.buffer.front() {
+            if self.state_machine.call(|| f(message)).is_err() {
+                break;
+            }
+            self.buffer.pop_front();
+            sent += 1;
+        }
+        sent
+    }
+
+    /// Returns the number of buffered messages.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Tells whether there are no buffered messages.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Returns the circuit breaker.
+    pub fn state_machine(&self) -> &StateMachine<POLICY, INSTRUMENT> {
+        &self.state_machine
+    }
+
+    fn push(&mut self, message: M) -> Delivery<M> {
+        if self.buffer.len() >= self.capacity {
+            return Delivery::Dropped(message);
+        }
+        self.buffer.push_back(message);
+        Delivery::Buffered
+    }
+}
+
+#[cfg(feature = "async")]
+impl<M, POLICY, INSTRUMENT> Buffered<M, POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy + Send + Sync,
+    INSTRUMENT: Instrument + Send + Sync,
+{
+    /// Publishes the `message` by the future returned from `f`, see `publish`.
+    pub async fn publish_async<F, FUT, E>(&mut self, message: M, mut f: F) -> Delivery<M>
+    where
+        F: FnMut(&M) -> FUT,
+        FUT: Future<Output = Result<(), E>>,
+    {
+        self.flush_async(&mut f).await;
+        if !self.buffer.is_empty() {
+            return self.push(message);
+        }
+
+        let res = super::futures::CircuitBreaker::call(&self.state_machine, f(&message)).await;
+        match res {
+            Ok(()) => Delivery::Sent,
+            Err(_) => self.push(message),
+        }
+    }
+
+    /// Publishes buffered messages by futures returned from `f`, see `flush`.
+    pub async fn flush_async<F, FUT, E>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&M) -> FUT,
+        FUT: Future<Output = Result<(), E>>,
+    {
+        let mut sent = 0;
+        while let Some(message) = self.buffer.front() {
+            let res = super::futures::CircuitBreaker::call(&self.state_machine, f(message)).await;
+            if res.is_err() {
+                break;
+            }
+            self.buffer.pop_front();
+            sent += 1;
+        }
+        sent
+    }
+}
+
+impl<M, POLICY, INSTRUMENT> Debug for Buffered<M, POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Buffered")
+            .field("state_machine", &self.state_machine)
+            .field("buffered", &self.buffer.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    use super::super::{backoff, clock, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn buffer_while_open() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let mut publisher = Buffered::new(Config::new().failure_policy(policy).build(), 2);
+
+            let sent = RefCell::new(Vec::new());
+            let broken = RefCell::new(true);
+            let send = |message: &u32| {
+                if *broken.borrow() {
+                    return Err(());
+                }
+                sent.borrow_mut().push(*message);
+                Ok(())
+            };
+
+            // The failure opens the circuit breaker, the next messages are rejected.
+            assert_eq!(Delivery::Buffered, publisher.publish(1, send));
+            assert_eq!(Delivery::Buffered, publisher.publish(2, send));
+            assert_eq!(Delivery::Dropped(3), publisher.publish(3, send));
+            assert_eq!(2, publisher.len());
+
+            time.advance(Duration::from_secs(10));
+            *broken.borrow_mut() = false;
+            assert_eq!(Delivery::Sent, publisher.publish(4, send));
+            assert!(publisher.is_empty());
+            assert_eq!(vec![1, 2, 4], *sent.borrow());
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn flush_async() {
+        let mut publisher = Buffered::new(Config::new().build(), 8);
+        assert_eq!(
+            Delivery::Buffered,
+            futures::executor::block_on(
+                publisher.publish_async(1, |_| futures::future::ready(Err::<(), _>(())))
+            )
+        );
+
+        let sent = RefCell::new(Vec::new());
+        let flushed = futures::executor::block_on(publisher.flush_async(|message| {
+            sent.borrow_mut().push(*message);
+            futures::future::ready(Ok::<_, ()>(()))
+        }));
+        assert_eq!(1, flushed);
+        assert_eq!(vec![1], *sent.borrow());
+    }
+}
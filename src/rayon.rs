@@ -0,0 +1,176 @@
+//! Guarding of [rayon](https://docs.rs/rayon) parallel iterators, requires the `rayon` feature.
+//!
+//! `ParallelCall::call_each` runs a function over items of a parallel iterator within a shared
+//! circuit breaker. Each item requests the permission before the function is executed, and
+//! records the result, so once the circuit breaker opens the remaining items are rejected
+//! without calling the failing backend, while threads of the pool contend only for the state of
+//! a single circuit breaker.
+//!
+//! # Example
+//!
+//! ```
+//! use rayon::prelude::*;
+//! use failsafe::{rayon::ParallelCall, Config};
+//!
+//! let circuit_breaker = Config::new().build();
+//!
+//! let results: Vec<_> = (0..100)
+//!     .into_par_iter()
+//!     .call_each(&circuit_breaker, |n| Ok::<_, ()>(n * 2))
+//!     .collect();
+//! assert_eq!(100, results.len());
+//! ```
+
+use std::fmt::{self, Debug};
+
+use ::rayon::iter::plumbing::{Consumer, ProducerCallback, UnindexedConsumer};
+use ::rayon::iter::{IndexedParallelIterator, Map, ParallelIterator};
+
+use super::circuit_breaker::CircuitBreaker;
+use super::error::Error;
+
+/// An extension of parallel iterators which calls go through a circuit breaker.
+pub trait ParallelCall: ParallelIterator {
+    /// Executes a given function for each item within the `circuit_breaker`, see
+    /// `CircuitBreaker::call`.
+    ///
+    /// Unlike `CircuitBreaker::call_each` items are processed in parallel, so processing doesn't
+    /// stop at the first rejection, but each of the remaining items is rejected as well until
+    /// the circuit breaker permits calls again.
+    fn call_each<CB, F, E, R>(self, circuit_breaker: CB, f: F) -> CallEach<Self, CB, F>
+    where
+        CB: CircuitBreaker + Send + Sync,
+        F: Fn(Self::Item) -> Result<R, E> + Send + Sync,
+        E: Send,
+        R: Send,
+    {
+        CallEach {
+            base: self,
+            circuit_breaker,
+            f,
+        }
+    }
+}
+
+impl<I> ParallelCall for I where I: ParallelIterator {}
+
+/// A parallel iterator over results of calls within a circuit breaker, see
+/// `ParallelCall::call_each`.
+pub struct CallEach<I, CB, F> {
+    base: I,
+    circuit_breaker: CB,
+    f: F,
+}
+
+impl<I, CB, F, E, R> CallEach<I, CB, F>
+where
+    I: ParallelIterator,
+    CB: CircuitBreaker + Send + Sync,
+    F: Fn(I::Item) -> Result<R, E> + Send + Sync,
+    E: Send,
+    R: Send,
+{
+    fn calls(self) -> Map<I, impl Fn(I::Item) -> Result<R, Error<E>> + Send + Sync> {
+        let CallEach {
+            base,
+            circuit_breaker,
+            f,
+        } = self;
+        base.map(move |item| circuit_breaker.call(|| f(item)))
+    }
+}
+
+impl<I, CB, F, E, R> ParallelIterator for CallEach<I, CB, F>
+where
+    I: ParallelIterator,
+    CB: CircuitBreaker + Send + Sync,
+    F: Fn(I::Item) -> Result<R, E> + Send + Sync,
+    E: Send,
+    R: Send,
+{
+    type Item = Result<R, Error<E>>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.calls().drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        self.base.opt_len()
+    }
+}
+
+impl<I, CB, F, E, R> IndexedParallelIterator for CallEach<I, CB, F>
+where
+    I: IndexedParallelIterator,
+    CB: CircuitBreaker + Send + Sync,
+    F: Fn(I::Item) -> Result<R, E> + Send + Sync,
+    E: Send,
+    R: Send,
+{
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        self.calls().drive(consumer)
+    }
+
+    fn with_producer<P>(self, callback: P) -> P::Output
+    where
+        P: ProducerCallback<Self::Item>,
+    {
+        self.calls().with_producer(callback)
+    }
+}
+
+impl<I, CB, F> Debug for CallEach<I, CB, F>
+where
+    I: Debug,
+    CB: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallEach")
+            .field("base", &self.base)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rayon::prelude::*;
+
+    use super::super::Config;
+    use super::*;
+
+    #[test]
+    fn record_and_reject() {
+        let circuit_breaker = Config::new().build();
+
+        let results: Vec<_> = (0..64u32)
+            .into_par_iter()
+            .call_each(
+                &circuit_breaker,
+                |n| if n % 2 == 0 { Ok(n) } else { Err(n) },
+            )
+            .collect();
+        assert!(matches!(results[2], Ok(2)));
+        assert!(matches!(results[3], Err(Error::Inner(3))));
+        let snapshot = circuit_breaker.snapshot();
+        assert_eq!((32, 32), (snapshot.successes, snapshot.failures));
+
+        circuit_breaker.force_open();
+        let rejected = (0..64u32)
+            .into_par_iter()
+            .call_each(&circuit_breaker, Ok::<_, ()>)
+            .filter(|res| matches!(res, Err(Error::Rejected(_))))
+            .count();
+        assert_eq!(64, rejected);
+    }
+}
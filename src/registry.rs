@@ -0,0 +1,322 @@
+//! A registry of named circuit breakers.
+//!
+//! Circuit breakers with different failure policies and instruments are kept together behind the
+//! object safe `Managed` trait, which exposes operational actions and snapshots.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{registry::Registry, Config, State};
+//!
+//! let registry = Registry::new();
+//! registry.register("redis", Config::new().build());
+//!
+//! let redis = registry.get("redis").unwrap();
+//! redis.force_open();
+//! assert_eq!(State::Open, redis.snapshot().state);
+//! ```
+//!
+//! Small applications may skip the configuration, `Registry::breaker` creates a circuit breaker
+//! with defaults on first use, or returns the one registered with defaults, and with the `global` feature there is a process-global registry:
+//!
+//! ```
+//! # #[cfg(feature = "global")]
+//! # {
+//! use failsafe::CircuitBreaker;
+//!
+//! let result = failsafe::global().breaker("redis").call(|| Ok::<_, ()>("pong"));
+//! assert!(result.is_ok());
+//! # }
+//! ```
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+#[cfg(feature = "global")]
+use once_cell::sync::Lazy;
+
+use super::config::Config;
+use super::failure_policy::{DefaultPolicy, FailurePolicy};
+use super::instrument::Instrument;
+use super::lock::{Lock, Mutex};
+use super::snapshot::Snapshot;
+use super::state_machine::StateMachine;
+
+/// An object safe interface of a circuit breaker for operational tooling.
+pub trait Managed: Send + Sync {
+    /// Returns a point in time view of the circuit breaker.
+    fn snapshot(&self) -> Snapshot;
+
+    /// Opens the circuit breaker until `reset`.
+    fn force_open(&self);
+
+    /// Closes the circuit breaker until `reset`.
+    fn force_closed(&self);
+
+    /// Resets the circuit breaker to the closed state.
+    fn reset(&self);
+
+    /// Names the circuit breaker unless it already has a name, the registry calls it on
+    /// registration.
+    ///
+    /// The default implementation does nothing.
+    #[inline]
+    fn name_if_unset(&self, _name: &str) {}
+}
+
+impl<POLICY, INSTRUMENT> Managed for StateMachine<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    // The policy needs to be `Sync` as well, the state is guarded by a reader-writer lock.
+    Self: Send + Sync,
+{
+    #[inline]
+    fn snapshot(&self) -> Snapshot {
+        StateMachine::snapshot(self)
+    }
+
+    #[inline]
+    fn force_open(&self) {
+        StateMachine::force_open(self)
+    }
+
+    #[inline]
+    fn force_closed(&self) {
+        StateMachine::force_closed(self)
+    }
+
+    #[inline]
+    fn reset(&self) {
+        StateMachine::reset(self)
+    }
+
+    #[inline]
+    fn name_if_unset(&self, name: &str) {
+        StateMachine::name_if_unset(self, name)
+    }
+}
+
+/// A collection of named circuit breakers.
+#[derive(Default)]
+pub struct Registry {
+    breakers: Mutex<BTreeMap<String, Entry>>,
+}
+
+/// A registered circuit breaker, along with its typed handle when it has the default policy and
+/// instrument, so `Registry::breaker` returns it.
+struct Entry {
+    managed: Arc<dyn Managed>,
+    default: Option<StateMachine<DefaultPolicy, ()>>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the circuit breaker under the `name`, returns the previously registered one.
+    ///
+    /// The state machine is cheap to clone, so keep a clone to make calls. An anonymous circuit
+    /// breaker is named after the `name`, see `Config::name`.
+    pub fn register<B>(&self, name: impl Into<String>, breaker: B) -> Option<Arc<dyn Managed>>
+    where
+        B: Managed + 'static,
+    {
+        let name = name.into();
+        breaker.name_if_unset(&name);
+        let default = (&breaker as &dyn Any)
+            .downcast_ref::<StateMachine<DefaultPolicy, ()>>()
+            .cloned();
+        let entry = Entry {
+            managed: Arc::new(breaker),
+            default,
+        };
+        self.breakers
+            .with(|breakers| breakers.insert(name, entry))
+            .map(|it| it.managed)
+    }
+
+    /// Returns the circuit breaker with defaults registered under the `name`, it's created by
+    /// `Config::new` on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics when a circuit breaker with another policy or instrument is registered under the
+    /// `name`, see `try_breaker`.
+    pub fn breaker(&self, name: &str) -> StateMachine<DefaultPolicy, ()> {
+        match self.try_breaker(name) {
+            Some(state_machine) => state_machine,
+            None => panic!(
+                "circuit breaker {:?} is registered with another policy or instrument",
+                name
+            ),
+        }
+    }
+
+    /// Returns the circuit breaker with defaults registered under the `name`, it's created by
+    /// `Config::new` on first use, or `None` when a circuit breaker with another policy or
+    /// instrument is registered under the `name`.
+    pub fn try_breaker(&self, name: &str) -> Option<StateMachine<DefaultPolicy, ()>> {
+        self.breakers.with(|breakers| {
+            if let Some(entry) = breakers.get(name) {
+                return entry.default.clone();
+            }
+            let state_machine = Config::new().name(name).build();
+            let entry = Entry {
+                managed: Arc::new(state_machine.clone()),
+                default: Some(state_machine.clone()),
+            };
+            breakers.insert(name.to_owned(), entry);
+            Some(state_machine)
+        })
+    }
+
+    /// Returns the circuit breaker registered under the `name`.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Managed>> {
+        self.breakers
+            .with(|breakers| breakers.get(name).map(|it| it.managed.clone()))
+    }
+
+    /// Removes the circuit breaker registered under the `name`.
+    pub fn remove(&self, name: &str) -> Option<Arc<dyn Managed>> {
+        self.breakers
+            .with(|breakers| breakers.remove(name))
+            .map(|it| it.managed)
+    }
+
+    /// Returns names of registered circuit breakers in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        self.breakers
+            .with(|breakers| breakers.keys().cloned().collect())
+    }
+
+    /// Returns snapshots of registered circuit breakers in alphabetical order of names.
+    ///
+    /// Snapshots are taken outside of the registry's lock.
+    pub fn snapshots(&self) -> Vec<(String, Snapshot)> {
+        let breakers: Vec<_> = self.breakers.with(|breakers| {
+            breakers
+                .iter()
+                .map(|(name, entry)| (name.clone(), entry.managed.clone()))
+                .collect()
+        });
+
+        breakers
+            .into_iter()
+            .map(|(name, breaker)| (name, breaker.snapshot()))
+            .collect()
+    }
+}
+
+/// Returns the process-global registry, requires the `global` feature.
+///
+/// It's created on first use, so applications which don't need several registries don't have to
+/// pass one around.
+#[cfg(feature = "global")]
+pub fn global() -> &'static Registry {
+    static GLOBAL: Lazy<Registry> = Lazy::new(Registry::new);
+    &GLOBAL
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("names", &self.names())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::failure_policy::consecutive_failures;
+    use super::super::instrument::State;
+    use super::super::{backoff, Config};
+    use super::*;
+
+    #[test]
+    fn register_and_control() {
+        let registry = Registry::new();
+        let redis = Config::new().build();
+
+        assert!(registry.register("redis", redis.clone()).is_none());
+        registry.register("postgres", Config::new().build());
+        assert_eq!(vec!["postgres", "redis"], registry.names());
+
+        registry.get("redis").unwrap().force_open();
+        assert!(!redis.is_call_permitted());
+        assert_eq!(Some("redis"), redis.name().as_deref());
+        assert!(format!("{:?}", redis)
+            .starts_with("StateMachine { name: \"redis\", state: \"forced_open\", in_state: "));
+
+        let states: Vec<_> = registry
+            .snapshots()
+            .into_iter()
+            .map(|(name, snapshot)| (name, snapshot.state))
+            .collect();
+        assert_eq!(
+            vec![
+                ("postgres".to_string(), State::Closed),
+                ("redis".to_string(), State::Open)
+            ],
+            states
+        );
+
+        registry.get("redis").unwrap().reset();
+        assert!(redis.is_call_permitted());
+
+        assert!(registry.remove("redis").is_some());
+        assert!(registry.get("redis").is_none());
+    }
+
+    #[test]
+    fn breakers_with_defaults() {
+        let registry = Registry::new();
+
+        let redis = registry.breaker("redis");
+        redis.force_open();
+        assert!(!registry.breaker("redis").is_call_permitted());
+        assert_eq!(Some("redis"), redis.name().as_deref());
+        assert_eq!(State::Open, registry.get("redis").unwrap().snapshot().state);
+
+        // A circuit breaker registered with defaults is returned as is.
+        let postgres = Config::new().build();
+        registry.register("postgres", postgres.clone());
+        postgres.force_open();
+        assert!(!registry.breaker("postgres").is_call_permitted());
+
+        registry.remove("redis");
+        assert!(registry.get("redis").is_none());
+        assert_eq!(State::Closed, registry.breaker("redis").snapshot().state);
+    }
+
+    #[test]
+    fn breakers_of_other_types() {
+        let registry = Registry::new();
+        let backoff = backoff::constant(Duration::from_secs(5));
+        let redis = Config::new()
+            .failure_policy(consecutive_failures(3, backoff))
+            .build();
+        registry.register("redis", redis.clone());
+
+        assert!(registry.try_breaker("redis").is_none());
+        redis.force_open();
+        assert_eq!(State::Open, registry.get("redis").unwrap().snapshot().state);
+    }
+
+    #[cfg(feature = "global")]
+    #[test]
+    fn global_registry() {
+        let breaker = global().breaker("registry::tests::global_registry");
+        breaker.force_open();
+        assert!(!global()
+            .breaker("registry::tests::global_registry")
+            .is_call_permitted());
+        assert!(std::ptr::eq(global(), global()));
+    }
+}
@@ -0,0 +1,212 @@
+//! Buffering of rejected operations for fire-and-forget write paths.
+//!
+//! A `ReplayQueue` executes operations within the circuit breaker when it permits calls, and
+//! keeps rejected ones, up to the capacity, to execute them once the circuit breaker closes.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{replay::{ReplayQueue, Submitted}, Config};
+//!
+//! let queue = ReplayQueue::new(Config::new().build(), 1024);
+//!
+//! match queue.submit(|| Ok::<_, ()>(println!("written"))) {
+//!     Submitted::Completed(_) => {}
+//!     Submitted::Queued => {}
+//!     Submitted::Dropped => eprintln!("the queue is full"),
+//! }
+//!
+//! // Periodically, e.g. from a timer.
+//! queue.replay();
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::circuit_breaker::call_permitted;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate;
+use super::instrument::{Instrument, State};
+use super::lock::{Lock, Mutex};
+use super::state_machine::StateMachine;
+
+type Job<E> = Box<dyn FnOnce() -> Result<(), E> + Send>;
+
+/// The result of `ReplayQueue::submit`.
+#[derive(Debug)]
+pub enum Submitted<E> {
+    /// The operation was executed at once, with the given result.
+    Completed(Result<(), Error<E>>),
+    /// The circuit breaker rejected the operation, it's queued for replay.
+    Queued,
+    /// The circuit breaker rejected the operation and the queue is full, so it's dropped.
+    Dropped,
+}
+
+/// A bounded queue of operations, which are replayed once the circuit breaker closes.
+pub struct ReplayQueue<POLICY, INSTRUMENT, E> {
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+    capacity: usize,
+    jobs: Mutex<VecDeque<Job<E>>>,
+    dropped: AtomicU64,
+}
+
+impl<POLICY, INSTRUMENT, E> ReplayQueue<POLICY, INSTRUMENT, E>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Creates a new queue of at most `capacity` operations, which are executed within the
+    /// `state_machine`.
+    pub fn new(state_machine: StateMachine<POLICY, INSTRUMENT>, capacity: usize) -> Self {
+        ReplayQueue {
+            state_machine,
+            capacity,
+            jobs: Mutex::new(VecDeque::new()),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Executes the operation if the circuit breaker permits it, otherwise queues it.
+    ///
+    /// Pending operations are replayed first, so they keep the order of submission.
+    pub fn submit<F>(&self, job: F) -> Submitted<E>
+    where
+        F: FnOnce() -> Result<(), E> + Send + 'static,
+    {
+        self.replay();
+
+        let pending = self.jobs.with(|jobs| !jobs.is_empty());
+        if !pending && self.state_machine.is_call_permitted() {
+            return Submitted::Completed(call_permitted(
+                &self.state_machine,
+                failure_predicate::Any,
+                job,
+            ));
+        }
+
+        let queued = self.jobs.with(|jobs| {
+            if jobs.len() < self.capacity {
+                jobs.push_back(Box::new(job));
+                true
+            } else {
+                false
+            }
+        });
+
+        if queued {
+            Submitted::Queued
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            self.state_machine.instrumented(|it| it.on_replay_dropped());
+            Submitted::Dropped
+        }
+    }
+
+    /// Executes queued operations while the circuit breaker is closed and permits calls,
+    /// returns the number of executed operations.
+    ///
+    /// Results of replayed operations are recorded by the circuit breaker and discarded.
+    pub fn replay(&self) -> usize {
+        let mut replayed = 0;
+
+        while self.state_machine.snapshot().state == State::Closed {
+            let job = match self.jobs.with(|jobs| jobs.pop_front()) {
+                Some(job) => job,
+                None => break,
+            };
+
+            if !self.state_machine.is_call_permitted() {
+                self.jobs.with(|jobs| jobs.push_front(job));
+                break;
+            }
+
+            let _ = call_permitted(&self.state_machine, failure_predicate::Any, job);
+            replayed += 1;
+        }
+
+        replayed
+    }
+}
+
+impl<POLICY, INSTRUMENT, E> ReplayQueue<POLICY, INSTRUMENT, E> {
+    /// Returns the number of queued operations.
+    pub fn len(&self) -> usize {
+        self.jobs.with(|jobs| jobs.len())
+    }
+
+    /// Returns `true` if there are no queued operations.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of operations dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<POLICY, INSTRUMENT, E> Debug for ReplayQueue<POLICY, INSTRUMENT, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReplayQueue")
+            .field("state_machine", &self.state_machine)
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::super::{backoff, clock, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn replay_when_closed() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let state_machine = Config::new().failure_policy(policy).build();
+            let queue = ReplayQueue::new(state_machine.clone(), 1);
+            let written = Arc::new(AtomicUsize::new(0));
+            let write = || {
+                let written = written.clone();
+                move || {
+                    written.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, ()>(())
+                }
+            };
+
+            assert!(matches!(
+                queue.submit(|| Err(())),
+                Submitted::Completed(Err(Error::Inner(())))
+            ));
+            assert!(matches!(queue.submit(write()), Submitted::Queued));
+            assert!(matches!(queue.submit(write()), Submitted::Dropped));
+            assert_eq!((1, 1), (queue.len(), queue.dropped()));
+
+            // Probes in the half open state don't replay operations.
+            time.advance(Duration::from_secs(10));
+            assert!(state_machine.is_call_permitted());
+            assert_eq!(0, queue.replay());
+
+            state_machine.on_success();
+            assert_eq!(1, queue.replay());
+            assert!(queue.is_empty());
+            assert_eq!(1, written.load(Ordering::SeqCst));
+
+            assert!(matches!(
+                queue.submit(write()),
+                Submitted::Completed(Ok(()))
+            ));
+            assert_eq!(2, written.load(Ordering::SeqCst));
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt::{self, Debug};
+use std::time::Instant;
+
+use super::circuit_breaker::call_permitted;
+use super::clock;
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate::{self, FailurePredicate};
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// A permission to call which must be used before it expires, see `StateMachine::try_reserve`.
+///
+/// In the half open state it holds the probe slot, which is returned once the call is made, or
+/// the reservation is dropped or expires.
+pub struct Reservation<POLICY, INSTRUMENT> {
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+    slot: Option<u64>,
+    expires: Instant,
+}
+
+impl<POLICY, INSTRUMENT> Reservation<POLICY, INSTRUMENT> {
+    pub(crate) fn new(
+        state_machine: StateMachine<POLICY, INSTRUMENT>,
+        slot: Option<u64>,
+        expires: Instant,
+    ) -> Self {
+        Reservation {
+            state_machine,
+            slot,
+            expires,
+        }
+    }
+
+    /// Returns the moment the reservation expires.
+    pub fn expires_at(&self) -> Instant {
+        self.expires
+    }
+
+    /// Tells whether the reservation has expired, so the call would be rejected.
+    pub fn is_expired(&self) -> bool {
+        clock::now() > self.expires
+    }
+
+    /// Tells whether the reservation holds the probe slot of the half open state.
+    pub fn is_probe(&self) -> bool {
+        self.slot.is_some()
+    }
+}
+
+impl<POLICY, INSTRUMENT> Reservation<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Executes a given function within the circuit breaker, unless the reservation has
+    /// expired, see `CircuitBreaker::call`.
+    pub fn call<F, E, R>(self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.call_with(failure_predicate::Any, f)
+    }
+
+    /// Executes a given function within the circuit breaker, unless the reservation has
+    /// expired, errors are checked by the `predicate`, see `CircuitBreaker::call_with`.
+    pub fn call_with<P, F, E, R>(self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        if self.is_expired() {
+            return Err(Error::Rejected(self.state_machine.current_rejection()));
+        }
+
+        // The slot is returned once the result is recorded, when the reservation is dropped.
+        call_permitted(&self.state_machine, predicate, f)
+    }
+}
+
+impl<POLICY, INSTRUMENT> Drop for Reservation<POLICY, INSTRUMENT> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            self.state_machine.release(slot);
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Debug for Reservation<POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reservation")
+            .field("probe", &self.is_probe())
+            .field("expires", &self.expires)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn expired_probe_slot_is_returned() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+            let ttl = Duration::from_secs(2);
+
+            circuit_breaker.on_error();
+            assert!(circuit_breaker.try_reserve(ttl).is_none());
+
+            // The reservation holds the only probe slot.
+            time.advance(Duration::from_secs(10));
+            let crashed = circuit_breaker.try_reserve(ttl).unwrap();
+            assert!(crashed.is_probe());
+            assert!(circuit_breaker.try_reserve(ttl).is_none());
+            assert!(!circuit_breaker.is_call_permitted());
+
+            // The slot is taken by the next caller once the reservation expires.
+            time.advance(Duration::from_secs(3));
+            let probe = circuit_breaker.try_reserve(ttl).unwrap();
+            assert!(matches!(
+                crashed.call(|| Ok::<_, ()>(())),
+                Err(Error::Rejected(_))
+            ));
+            assert!(!circuit_breaker.is_call_permitted());
+
+            assert!(probe.call(|| Ok::<_, ()>(())).is_ok());
+            let reservation = circuit_breaker.try_reserve(ttl).unwrap();
+            assert!(!reservation.is_probe());
+        });
+    }
+
+    #[test]
+    fn dropped_reservation_returns_probe_slot() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+
+            circuit_breaker.on_error();
+            time.advance(Duration::from_secs(10));
+            drop(circuit_breaker.try_reserve(Duration::from_secs(60)));
+            assert!(circuit_breaker.is_call_permitted());
+        });
+    }
+}
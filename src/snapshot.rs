@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::instrument::State;
+
+/// A point in time view of a circuit breaker, see `StateMachine::snapshot`.
+///
+/// Counters are totals since the circuit breaker was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The current state.
+    pub state: State,
+    /// The moment the circuit breaker entered the current state.
+    pub since: Instant,
+    /// The moment the open state expires, if the circuit breaker is open.
+    pub open_until: Option<Instant>,
+    /// The number of successful calls.
+    pub successes: u64,
+    /// The number of failed calls.
+    pub failures: u64,
+    /// The number of rejected calls.
+    pub rejections: u64,
+    /// How many times the circuit breaker has opened.
+    pub opened: u64,
+    /// Counters of transitions by their edge.
+    pub transitions: TransitionCounts,
+    /// Counters of labeled calls by label, see `StateMachine::labeled`.
+    pub labels: BTreeMap<String, LabelCounts>,
+    /// The failure which caused the most recent open transition, if it's captured, see
+    /// `Config::capture_last_error`.
+    pub last_error: Option<LastError>,
+    /// The time spent in the instrument, if it's measured, see `Config::measure_instrument`.
+    pub instrument_overhead: Option<InstrumentOverhead>,
+}
+
+impl Snapshot {
+    /// Returns what has changed since the `previous` snapshot of the same circuit breaker.
+    ///
+    /// It lets periodic pollers, e.g. a metrics scraper, emit only changes, and notice
+    /// transitions which happened between polls even when the state is the same again.
+    ///
+    /// ```
+    /// use failsafe::Config;
+    ///
+    /// let circuit_breaker = Config::new().build();
+    /// let previous = circuit_breaker.snapshot();
+    ///
+    /// circuit_breaker.on_success();
+    /// let diff = circuit_breaker.snapshot().diff(&previous);
+    /// assert_eq!(1, diff.successes);
+    /// assert!(!diff.transitioned);
+    /// ```
+    pub fn diff(&self, previous: &Snapshot) -> SnapshotDiff {
+        let labels = self
+            .labels
+            .iter()
+            .filter_map(|(label, counts)| {
+                let before = previous.labels.get(label).copied().unwrap_or_default();
+                let delta = LabelCounts {
+                    successes: counts.successes.saturating_sub(before.successes),
+                    failures: counts.failures.saturating_sub(before.failures),
+                    rejections: counts.rejections.saturating_sub(before.rejections),
+                };
+                if delta == LabelCounts::default() {
+                    None
+                } else {
+                    Some((label.clone(), delta))
+                }
+            })
+            .collect();
+
+        SnapshotDiff {
+            from: previous.state,
+            to: self.state,
+            transitioned: self.since != previous.since,
+            successes: self.successes.saturating_sub(previous.successes),
+            failures: self.failures.saturating_sub(previous.failures),
+            rejections: self.rejections.saturating_sub(previous.rejections),
+            opened: self.opened.saturating_sub(previous.opened),
+            transitions: self.transitions.delta(&previous.transitions),
+            labels,
+            last_error: if self.last_error != previous.last_error {
+                self.last_error.clone()
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Changes between two snapshots of a circuit breaker, see `Snapshot::diff`.
+///
+/// Counters are deltas, they saturate at zero if the previous snapshot is newer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SnapshotDiff {
+    /// The state of the previous snapshot.
+    pub from: State,
+    /// The state of the current snapshot.
+    pub to: State,
+    /// Whether the circuit breaker has changed its state at least once in between, it may be
+    /// `true` when `from` and `to` are the same.
+    pub transitioned: bool,
+    /// The number of successful calls in between.
+    pub successes: u64,
+    /// The number of failed calls in between.
+    pub failures: u64,
+    /// The number of rejected calls in between.
+    pub rejections: u64,
+    /// How many times the circuit breaker has opened in between.
+    pub opened: u64,
+    /// Transitions in between by their edge.
+    pub transitions: TransitionCounts,
+    /// Deltas of labels which counters have changed.
+    pub labels: BTreeMap<String, LabelCounts>,
+    /// The failure which caused the most recent open transition, if it has changed.
+    pub last_error: Option<LastError>,
+}
+
+impl SnapshotDiff {
+    /// Tells whether nothing has changed.
+    pub fn is_empty(&self) -> bool {
+        !self.transitioned
+            && self.successes == 0
+            && self.failures == 0
+            && self.rejections == 0
+            && self.opened == 0
+            && self.transitions.is_empty()
+            && self.labels.is_empty()
+            && self.last_error.is_none()
+    }
+}
+
+/// A summary of the failure which opened the circuit breaker, see `Snapshot::last_error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LastError {
+    /// The formatted reason of the failure, see `Instrument::on_failure_reason`.
+    pub message: String,
+    /// The moment of the failure.
+    pub at: Instant,
+}
+
+/// The time spent in callbacks of the instrument, see `Config::measure_instrument`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InstrumentOverhead {
+    /// The number of measured callbacks, a call or a transition may notify several callbacks
+    /// at once.
+    pub calls: u64,
+    /// The total time spent in callbacks.
+    pub total: Duration,
+    /// The longest time spent in a single call.
+    pub max: Duration,
+}
+
+/// Counters of transitions by their edge, e.g. from closed to open, see `Snapshot::transitions`.
+///
+/// ```
+/// use failsafe::{instrument::State, Config};
+///
+/// let circuit_breaker = Config::new().build();
+/// circuit_breaker.force_open();
+///
+/// let transitions = circuit_breaker.snapshot().transitions;
+/// assert_eq!(1, transitions.get(State::Closed, State::Open));
+/// assert_eq!(6, transitions.iter().count());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransitionCounts {
+    /// Counters indexed by gauge values of the previous and the new states.
+    counts: [[u64; 3]; 3],
+}
+
+/// Observable states in the order of their gauge values.
+const STATES: [State; 3] = [State::Closed, State::HalfOpen, State::Open];
+
+impl TransitionCounts {
+    pub(crate) fn new(counts: [[u64; 3]; 3]) -> Self {
+        TransitionCounts { counts }
+    }
+
+    /// Returns the number of transitions from the state `from` to the state `to`.
+    pub fn get(&self, from: State, to: State) -> u64 {
+        self.counts[usize::from(from.as_gauge())][usize::from(to.as_gauge())]
+    }
+
+    /// Returns an iterator over every edge between distinct states along with its counter,
+    /// including edges without transitions, so time series don't appear on the first one.
+    pub fn iter(&self) -> impl Iterator<Item = (State, State, u64)> + '_ {
+        STATES.iter().flat_map(move |from| {
+            STATES
+                .iter()
+                .filter(move |to| *to != from)
+                .map(move |to| (*from, *to, self.get(*from, *to)))
+        })
+    }
+
+    fn delta(&self, previous: &TransitionCounts) -> TransitionCounts {
+        let mut counts = self.counts;
+        for (row, before) in counts.iter_mut().zip(&previous.counts) {
+            for (count, before) in row.iter_mut().zip(before) {
+                *count = count.saturating_sub(*before);
+            }
+        }
+        TransitionCounts { counts }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counts.iter().flatten().all(|count| *count == 0)
+    }
+}
+
+/// Totals of calls with the same label, see `StateMachine::labeled`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LabelCounts {
+    /// The number of successful calls.
+    pub successes: u64,
+    /// The number of failed calls.
+    pub failures: u64,
+    /// The number of rejected calls.
+    pub rejections: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{backoff, clock, failure_policy, CircuitBreaker, Config};
+    use super::*;
+
+    #[test]
+    fn diff_missed_transitions() {
+        clock::freeze(|time| {
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new().failure_policy(policy).build();
+
+            let previous = circuit_breaker.snapshot();
+            assert!(circuit_breaker.snapshot().diff(&previous).is_empty());
+
+            // Opens and closes again between polls.
+            let _ = circuit_breaker.labeled("get").call(|| Err::<(), _>(()));
+            time.advance(Duration::from_secs(10));
+            assert!(circuit_breaker.is_call_permitted());
+            circuit_breaker.on_success();
+
+            let diff = circuit_breaker.snapshot().diff(&previous);
+            assert_eq!(State::Closed, diff.from);
+            assert_eq!(State::Closed, diff.to);
+            assert!(diff.transitioned);
+            assert_eq!(1, diff.opened);
+            assert_eq!(1, diff.transitions.get(State::Closed, State::Open));
+            assert_eq!(1, diff.transitions.get(State::Open, State::HalfOpen));
+            assert_eq!(1, diff.transitions.get(State::HalfOpen, State::Closed));
+            assert_eq!(0, diff.transitions.get(State::HalfOpen, State::Open));
+            assert_eq!((1, 1), (diff.successes, diff.failures));
+            assert_eq!(1, diff.labels["get"].failures);
+            assert!(!diff.is_empty());
+        });
+    }
+}
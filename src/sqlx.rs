@@ -0,0 +1,160 @@
+//! Guarding of [sqlx](https://docs.rs/sqlx) executors, requires the `sqlx` feature.
+//!
+//! `Guarded` threads queries through a circuit breaker, and `Infrastructure` tells which
+//! errors count as failures: only infrastructure errors, like a pool timeout or a reset
+//! connection, trip the circuit breaker, while application errors, like constraint violations
+//! or a missing row, are returned as is without being recorded as failures.
+//!
+//! # Example
+//!
+//! ```
+//! # async fn count_users<DB>(_: &DB) -> Result<i64, sqlx::Error> { Ok(1) }
+//! # async {
+//! # let pool = ();
+//! use failsafe::{sqlx::Guarded, Config, Error};
+//!
+//! // `pool` is e.g. a `sqlx::PgPool`.
+//! let db = Guarded::new(pool, Config::new().build());
+//!
+//! // `count_users` runs a query on the pool, e.g. `sqlx::query_scalar(..).fetch_one(pool)`.
+//! match db.run(|pool| count_users(pool)).await {
+//!     Ok(count) => println!("{} users", count),
+//!     Err(Error::Rejected(rejection)) => eprintln!("{}", rejection),
+//!     Err(Error::Inner(err)) => eprintln!("{}", err),
+//! }
+//! # };
+//! ```
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+
+use ::sqlx::Error as SqlxError;
+
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::failure_predicate::FailurePredicate;
+use super::futures::CircuitBreaker;
+use super::instrument::Instrument;
+use super::state_machine::StateMachine;
+
+/// SQLSTATE classes of infrastructure errors: connection exception, insufficient resources,
+/// operator intervention and system error.
+const INFRASTRUCTURE_CLASSES: &[&str] = &["08", "53", "57", "58"];
+
+/// A predicate which counts only infrastructure errors as failures.
+///
+/// I/O, TLS and protocol errors, pool timeouts and closed pools are infrastructure errors, as
+/// well as database errors which SQLSTATE is of a connection, resource or operator class, e.g.
+/// too many connections or an administrator shutdown. Other errors are application errors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Infrastructure;
+
+impl FailurePredicate<SqlxError> for Infrastructure {
+    fn is_err(&self, err: &SqlxError) -> bool {
+        match err {
+            SqlxError::Io(_)
+            | SqlxError::Tls(_)
+            | SqlxError::Protocol(_)
+            | SqlxError::PoolTimedOut
+            | SqlxError::PoolClosed
+            | SqlxError::WorkerCrashed
+            | SqlxError::BeginFailed => true,
+            SqlxError::Database(err) => err.code().map_or(false, |code| {
+                INFRASTRUCTURE_CLASSES
+                    .iter()
+                    .any(|class| code.starts_with(class))
+            }),
+            _ => false,
+        }
+    }
+}
+
+/// An executor, e.g. a pool, which queries run through a circuit breaker.
+pub struct Guarded<DB, POLICY, INSTRUMENT> {
+    executor: DB,
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+}
+
+impl<DB, POLICY, INSTRUMENT> Guarded<DB, POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy + Send + Sync,
+    INSTRUMENT: Instrument + Send + Sync,
+{
+    /// Guards the `executor` by the `state_machine`.
+    pub fn new(executor: DB, state_machine: StateMachine<POLICY, INSTRUMENT>) -> Self {
+        Guarded {
+            executor,
+            state_machine,
+        }
+    }
+
+    /// Runs the query returned by `f` within the circuit breaker, errors are judged by
+    /// `Infrastructure`.
+    pub async fn run<'a, F, FUT, R>(&'a self, f: F) -> Result<R, Error<SqlxError>>
+    where
+        F: FnOnce(&'a DB) -> FUT,
+        FUT: Future<Output = Result<R, SqlxError>> + 'a,
+    {
+        self.state_machine
+            .call_with(Infrastructure, f(&self.executor))
+            .await
+    }
+
+    /// Returns the underlying executor, queries run on it bypass the circuit breaker.
+    pub fn get_ref(&self) -> &DB {
+        &self.executor
+    }
+
+    /// Returns the circuit breaker.
+    pub fn state_machine(&self) -> &StateMachine<POLICY, INSTRUMENT> {
+        &self.state_machine
+    }
+}
+
+impl<DB, POLICY, INSTRUMENT> Debug for Guarded<DB, POLICY, INSTRUMENT>
+where
+    DB: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Guarded")
+            .field("executor", &self.executor)
+            .field("state_machine", &self.state_machine)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use futures::{executor::block_on, future};
+
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let is_err = |err| Infrastructure.is_err(&err);
+        assert!(is_err(SqlxError::PoolTimedOut));
+        assert!(is_err(SqlxError::Io(io::ErrorKind::ConnectionReset.into())));
+        assert!(!is_err(SqlxError::RowNotFound));
+        assert!(!is_err(SqlxError::ColumnNotFound("id".to_owned())));
+    }
+
+    #[test]
+    fn trip_on_infrastructure_errors() {
+        let backoff = backoff::constant(Duration::from_secs(30));
+        let policy = failure_policy::consecutive_failures(1, backoff);
+        let db = Guarded::new((), Config::new().failure_policy(policy).build());
+
+        let res = block_on(db.run(|_| future::ready(Err::<(), _>(SqlxError::RowNotFound))));
+        assert!(matches!(res, Err(Error::Inner(SqlxError::RowNotFound))));
+        assert!(db.state_machine().is_call_permitted());
+
+        let res = block_on(db.run(|_| future::ready(Err::<(), _>(SqlxError::PoolTimedOut))));
+        assert!(matches!(res, Err(Error::Inner(SqlxError::PoolTimedOut))));
+        let res = block_on(db.run(|_| future::ready(Ok::<_, SqlxError>(()))));
+        assert!(matches!(res, Err(Error::Rejected(_))));
+    }
+}
@@ -1,17 +1,43 @@
-use std::fmt::{self, Debug};
-use std::sync::Arc;
+use std::any;
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Display, Write as _};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::Waker;
 use std::time::{Duration, Instant};
 
-use parking_lot::Mutex;
-
+use super::backoff::{GenRange, ThreadLocalGenRange};
+use super::circuit_breaker::call_permitted;
 use super::clock;
-use super::failure_policy::FailurePolicy;
-use super::instrument::Instrument;
+use super::error::{Error, Rejection, RejectionReason};
+use super::failure_policy::{self, CallRecord, FailurePolicy};
+use super::failure_predicate::{self, Outcome};
+use super::instrument::{self, Cause, Instrument, Transition};
+use super::labeled::Labeled;
+use super::lock::{Lock, Mutex, StateLock};
+use super::monitor::Monitor;
+use super::reservation::Reservation;
+use super::snapshot::{InstrumentOverhead, LabelCounts, LastError, Snapshot, TransitionCounts};
+
+/// How often a waiting caller asks for the permission, unless the circuit breaker is open.
+pub(crate) const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+/// A margin added to the open state's deadline, so a waiting caller wakes up after it.
+pub(crate) const WAIT_PRECISION: Duration = Duration::from_millis(1);
+/// How many tasks waiting for the permission are woken by a transition, see `register_waker`.
+#[cfg(feature = "async")]
+const MAX_WAKERS: usize = 64;
 
-const ON_CLOSED: u8 = 0b0000_0001;
-const ON_HALF_OPEN: u8 = 0b0000_0010;
-const ON_REJECTED: u8 = 0b0000_0100;
-const ON_OPEN: u8 = 0b0000_1000;
+/// Codes of published states, kept in the upper bits of the packed word.
+const PUBLISHED_CLOSED: u64 = 0;
+const PUBLISHED_OPEN: u64 = 1;
+const PUBLISHED_HALF_OPEN: u64 = 2;
+const PUBLISHED_FORCED_OPEN: u64 = 3;
+const PUBLISHED_SHIFT: u32 = 62;
+/// The label which counts calls of labels over the limit, see `Config::max_labels`.
+const OVERFLOW_LABEL: &str = "other";
+/// The lower bits of the packed word keep nanoseconds since the epoch.
+const PUBLISHED_NANOS: u64 = (1 << PUBLISHED_SHIFT) - 1;
 
 /// States of the state machine.
 #[derive(Debug)]
@@ -21,18 +47,313 @@ enum State {
     /// An open breaker has tripped and will not allow requests through until an interval expired.
     Open(Instant, Duration),
     /// A half open breaker has completed its wait interval and will allow requests. The state keeps
-    /// the previous duration in an open state.
-    HalfOpen(Duration),
+    /// the moment of transition and the previous duration in an open state.
+    HalfOpen(Instant, Duration),
+    /// A recovering breaker has received a successful probe and admits a growing share of calls,
+    /// from the moment of transition, before becoming fully closed.
+    Recovering(Instant),
+    /// An operator has opened the breaker, it rejects all calls until reset.
+    ForcedOpen,
+    /// An operator has closed the breaker, it permits all calls and ignores the failure policy
+    /// until reset.
+    ForcedClosed,
+}
+
+/// The outcome of a request for permission.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Permit {
+    Rejected(RejectionReason),
+    Permitted,
+    /// Admitted as a probe of the half open state, which follows an open state of the delay.
+    Probe(Duration),
+}
+
+/// Tunables of the state machine which aren't a part of the failure policy.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Settings {
+    /// Spreads the transition to the half open state and admission of probes over the interval.
+    pub(crate) half_open_jitter: Option<Duration>,
+    /// Ramps traffic up after a successful probe instead of closing at once.
+    pub(crate) recovery: Option<Recovery>,
+    /// A share of calls permitted in the open state.
+    pub(crate) canary_ratio: Option<f64>,
+    /// The name reported in rejections and the debug output.
+    pub(crate) name: Option<Arc<str>>,
+    /// Which callers probe the backend in the half open state.
+    pub(crate) probe_selection: ProbeSelection,
+    /// Tells whether a scheduled window is active.
+    pub(crate) schedule: Option<Schedule>,
+    /// Whether the open state is left only by a passed health check, see `on_health_check`.
+    pub(crate) background_probing: bool,
+    /// Whether the reason of the failure which opened the circuit breaker is kept.
+    pub(crate) capture_last_error: bool,
+    /// Receives every transition along with the instrument.
+    pub(crate) transition_hook: Option<TransitionHook>,
+    /// Records one in the given number of successes in the closed state, with that weight.
+    pub(crate) success_sampling: Option<u32>,
+    /// Whether the time spent in the instrument is measured.
+    pub(crate) measure_instrument: bool,
+    /// Overrides decisions for calls, e.g. to inject failures in tests.
+    pub(crate) interceptor: Option<Interceptor>,
+    /// Falls back to the open state when no probe completes in the half open state in time.
+    pub(crate) half_open_timeout: Option<Duration>,
+    /// The maximum number of labels with their own counters.
+    pub(crate) max_labels: Option<usize>,
+}
+
+/// A mode of the circuit breaker during a scheduled window, see `Config::schedule`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduled {
+    /// All calls are rejected, e.g. during planned maintenance of the backend.
+    ForcedOpen,
+    /// All calls are permitted and recorded, but the circuit breaker never rejects them.
+    Shadow,
+}
+
+/// A user supplied callback which returns the active scheduled window.
+#[derive(Clone)]
+pub(crate) struct Schedule(pub(crate) Arc<dyn Fn() -> Option<Scheduled> + Send + Sync>);
+
+impl Debug for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Schedule")
+    }
+}
+
+/// Details of a call seen by an interceptor, see `Config::interceptor`.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct CallContext<'a> {
+    /// The name of the circuit breaker, see `Config::name`.
+    pub name: Option<&'a str>,
+    /// The current state of the circuit breaker.
+    pub state: instrument::State,
+    /// The outcome of the completed call about to be recorded, or `None` when the call
+    /// requests the permission.
+    pub outcome: Option<Outcome>,
+}
+
+/// An override of an interceptor, see `Config::interceptor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The call is permitted whatever the state is, when it requests the permission.
+    Permit,
+    /// The call is rejected whatever the state is, when it requests the permission.
+    Reject,
+    /// The completed call is recorded with the given outcome instead of the actual one.
+    Record(Outcome),
+}
+
+/// A user supplied callback which overrides decisions for calls.
+pub(crate) type InterceptFn = dyn Fn(&CallContext) -> Option<Decision> + Send + Sync;
+
+/// An interceptor of calls, see `Config::interceptor`.
+#[derive(Clone)]
+pub(crate) struct Interceptor(pub(crate) Arc<InterceptFn>);
+
+impl Debug for Interceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Interceptor")
+    }
+}
+
+/// A user supplied callback of transitions.
+pub(crate) type TransitionFn = dyn Fn(Transition) + Send + Sync;
+
+/// A callback of transitions, see `Config::on_transition`.
+#[derive(Clone)]
+pub(crate) struct TransitionHook(pub(crate) Arc<TransitionFn>);
+
+impl Debug for TransitionHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("TransitionHook")
+    }
+}
+
+/// Defines which callers probe the backend in the half open state, see
+/// `Config::probe_selection`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeSelection {
+    /// All callers are admitted, the first results decide the next state. This is the default
+    /// behaviour.
+    FirstCome,
+    /// A share of callers given by the ratio is admitted, the rest are rejected.
+    Sampled(f64),
+    /// Only callers which opted in via `StateMachine::call_as_probe` or
+    /// `StateMachine::is_probe_permitted` are admitted, so critical traffic isn't used to test
+    /// the recovery.
+    OptIn,
+}
+
+impl Default for ProbeSelection {
+    fn default() -> Self {
+        ProbeSelection::FirstCome
+    }
+}
+
+/// Steps of the gradual recovery.
+#[derive(Debug, Clone)]
+pub(crate) struct Recovery {
+    /// Shares of admitted calls for each step.
+    pub(crate) steps: Vec<f64>,
+    /// How long each step lasts.
+    pub(crate) step_duration: Duration,
 }
 
 struct Shared<POLICY> {
     state: State,
     failure_policy: POLICY,
+    /// A number of canary calls permitted in the current open state, which results are pending,
+    /// see `is_canary`.
+    canaries: u32,
+    /// The moment of the last observable state change.
+    changed_at: Instant,
+    /// A number of calls rejected since the circuit breaker has opened, it's atomic so
+    /// rejections take the read lock only, see `permit_unchanged`.
+    rejections: AtomicU64,
+    /// The id and the expiry of the reservation which holds the probe slot of the half open
+    /// state, see `StateMachine::try_reserve`.
+    reserved: Option<(u64, Instant)>,
+    /// The id of the last reservation of the probe slot.
+    reservations: u64,
+}
+
+/// Totals since the state machine was created.
+#[derive(Default)]
+struct Counters {
+    successes: AtomicU64,
+    failures: AtomicU64,
+    rejections: AtomicU64,
+    opened: AtomicU64,
+    /// Transitions indexed by gauge values of the previous and the new states.
+    transitions: [[AtomicU64; 3]; 3],
+    /// Calls of the instrument and the time spent in them, when it's measured.
+    instrument_calls: AtomicU64,
+    instrument_nanos: AtomicU64,
+    instrument_max_nanos: AtomicU64,
+    /// Successes in the closed state which the policy hasn't seen yet, and the moment of the
+    /// last one in nanoseconds since the epoch of the published state, see
+    /// `Config::success_sampling`.
+    pending_successes: AtomicU64,
+    pending_at: AtomicU64,
+}
+
+impl Counters {
+    fn transitions(&self) -> [[u64; 3]; 3] {
+        let mut transitions = [[0; 3]; 3];
+        for (row, counters) in transitions.iter_mut().zip(&self.transitions) {
+            for (count, counter) in row.iter_mut().zip(counters) {
+                *count = counter.load(Ordering::Relaxed);
+            }
+        }
+        transitions
+    }
+}
+
+/// A copy of the state for observers, which read it without contending with the lock.
+///
+/// It's updated under the lock after every change. The state is packed with the deadline of
+/// the open state into a single word, both moments are kept as nanoseconds since the epoch.
+struct Published {
+    epoch: Instant,
+    state: AtomicU64,
+    since: AtomicU64,
+}
+
+impl Published {
+    fn new(epoch: Instant) -> Self {
+        Published {
+            epoch,
+            state: AtomicU64::new(PUBLISHED_CLOSED << PUBLISHED_SHIFT),
+            since: AtomicU64::new(0),
+        }
+    }
+
+    fn store(&self, state: &State, changed_at: Instant) {
+        let packed = match *state {
+            State::Closed | State::ForcedClosed | State::Recovering(_) => {
+                PUBLISHED_CLOSED << PUBLISHED_SHIFT
+            }
+            State::Open(until, _) => (PUBLISHED_OPEN << PUBLISHED_SHIFT) | self.nanos(until),
+            State::HalfOpen(_, _) => PUBLISHED_HALF_OPEN << PUBLISHED_SHIFT,
+            State::ForcedOpen => PUBLISHED_FORCED_OPEN << PUBLISHED_SHIFT,
+        };
+
+        // Stores are skipped when nothing has changed, so readers' cache lines stay valid.
+        let since = self.nanos(changed_at);
+        if self.since.load(Ordering::Relaxed) != since {
+            self.since.store(since, Ordering::Relaxed);
+        }
+        if self.state.load(Ordering::Relaxed) != packed {
+            self.state.store(packed, Ordering::Release);
+        }
+    }
+
+    /// Returns the code of the state.
+    #[inline]
+    fn code(&self) -> u64 {
+        self.state.load(Ordering::Acquire) >> PUBLISHED_SHIFT
+    }
+
+    /// Returns the code of the state and the deadline of the open state.
+    fn load(&self) -> (u64, Option<Instant>) {
+        let packed = self.state.load(Ordering::Acquire);
+        let code = packed >> PUBLISHED_SHIFT;
+        let until = if code == PUBLISHED_OPEN {
+            Some(self.epoch + Duration::from_nanos(packed & PUBLISHED_NANOS))
+        } else {
+            None
+        };
+        (code, until)
+    }
+
+    fn since(&self) -> Instant {
+        self.instant(self.since.load(Ordering::Relaxed))
+    }
+
+    fn instant(&self, nanos: u64) -> Instant {
+        self.epoch + Duration::from_nanos(nanos)
+    }
+
+    fn nanos(&self, at: Instant) -> u64 {
+        let nanos = at.saturating_duration_since(self.epoch).as_nanos();
+        nanos.min(u128::from(PUBLISHED_NANOS)) as u64
+    }
+
+    fn observable(code: u64) -> instrument::State {
+        match code {
+            PUBLISHED_CLOSED => instrument::State::Closed,
+            PUBLISHED_HALF_OPEN => instrument::State::HalfOpen,
+            _ => instrument::State::Open,
+        }
+    }
+}
+
+/// A failed call to record into the failure policy.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Failure {
+    /// A failure without details.
+    Plain,
+    /// A failure with an error of the given class.
+    Classified(&'static str),
+    /// A failure of a call of the given cost.
+    Weighted(u32),
 }
 
 struct Inner<POLICY, INSTRUMENT> {
-    shared: Mutex<Shared<POLICY>>,
+    shared: StateLock<Shared<POLICY>>,
     instrument: INSTRUMENT,
+    settings: Settings,
+    counters: Counters,
+    published: Published,
+    labels: StateLock<BTreeMap<String, LabelCounts>>,
+    name: StateLock<Option<Arc<str>>>,
+    last_error: StateLock<Option<LastError>>,
+    /// Tasks waiting for the circuit breaker to permit calls.
+    wakers: Mutex<Vec<Waker>>,
+    /// Applies an expired half open timeout, it's set by the constructor which knows the policy
+    /// and the instrument, so observers like `state` apply it without these bounds.
+    expire_half_open: fn(&StateMachine<POLICY, INSTRUMENT>),
 }
 
 /// A circuit breaker implementation backed by state machine.
@@ -50,282 +371,2581 @@ struct Inner<POLICY, INSTRUMENT> {
 /// calls to see if the backend is still unavailable or has become available again. If the circuit
 /// breaker receives a failure on the next call, the state will change back to `Open`. Otherwise
 /// it changes to `Closed`.
+///
+/// A state machine is a handle of a shared state: a clone is a single reference count increment,
+/// and all clones act as one circuit breaker. With the default policy and instrument it's
+/// `Send + Sync + Clone + 'static`, so it may be kept in statics and moved to other threads and
+/// tasks, as well as `SharedCircuitBreaker`, `WeakStateMachine` and `Monitor`. The state is
+/// guarded by a reader-writer lock, so a custom failure policy has to be `Send + Sync` to share
+/// the state machine between threads.
 pub struct StateMachine<POLICY, INSTRUMENT> {
     inner: Arc<Inner<POLICY, INSTRUMENT>>,
 }
 
-impl State {
-    /// Returns a string value for the state identifier.
-    #[inline]
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            State::Open(_, _) => "open",
-            State::Closed => "closed",
-            State::HalfOpen(_) => "half_open",
-        }
+/// A weak handle of a state machine, which doesn't keep it alive, see `StateMachine::downgrade`.
+pub struct WeakStateMachine<POLICY, INSTRUMENT> {
+    inner: Weak<Inner<POLICY, INSTRUMENT>>,
+}
+
+impl State {
+    /// Returns a string value for the state identifier.
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Open(_, _) => "open",
+            State::Closed => "closed",
+            State::HalfOpen(_, _) => "half_open",
+            State::Recovering(_) => "recovering",
+            State::ForcedOpen => "forced_open",
+            State::ForcedClosed => "forced_closed",
+        }
+    }
+
+    /// Returns the state as seen by instruments, the recovery is a part of the closed state.
+    #[inline]
+    fn observable(&self) -> instrument::State {
+        match self {
+            State::Closed | State::Recovering(_) | State::ForcedClosed => instrument::State::Closed,
+            State::Open(_, _) | State::ForcedOpen => instrument::State::Open,
+            State::HalfOpen(_, _) => instrument::State::HalfOpen,
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Debug for StateMachine<POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.shared.read_with(|shared| shared.state.as_str());
+        let snapshot = self.snapshot();
+        let now = clock::now();
+
+        let mut debug = f.debug_struct("StateMachine");
+        if let Some(name) = self.name() {
+            debug.field("name", &name);
+        }
+        debug
+            .field("state", &state)
+            .field("in_state", &now.saturating_duration_since(snapshot.since));
+        if let Some(until) = snapshot.open_until {
+            debug.field("open_for", &until.saturating_duration_since(now));
+        }
+        debug
+            .field(
+                "policy",
+                &failure_policy::short_type_name(any::type_name::<POLICY>()),
+            )
+            .field("successes", &snapshot.successes)
+            .field("failures", &snapshot.failures)
+            .field("rejections", &snapshot.rejections)
+            .field("opened", &snapshot.opened)
+            .finish()
+    }
+}
+
+/// A one line summary, e.g. "circuit breaker `redis` is open for 1.5s, policy
+/// consecutive_failures(5), 10 successes, 5 failures, 2 rejections, opened 1 times".
+impl<POLICY, INSTRUMENT> Display for StateMachine<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Writes the policy's description by `FailurePolicy::describe`.
+        struct Describe<'a, POLICY>(&'a POLICY);
+
+        impl<'a, POLICY> Display for Describe<'a, POLICY>
+        where
+            POLICY: FailurePolicy,
+        {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.describe(f)
+            }
+        }
+
+        let (state, policy) = self.inner.shared.read_with(|shared| {
+            let policy = Describe(&shared.failure_policy).to_string();
+            (shared.state.as_str(), policy)
+        });
+        let snapshot = self.snapshot();
+
+        f.write_str("circuit breaker ")?;
+        if let Some(name) = self.name() {
+            write!(f, "`{}` ", name)?;
+        }
+        write!(
+            f,
+            "is {} for {:?}, policy {}, {} successes, {} failures, {} rejections, opened {} times",
+            state,
+            clock::now().saturating_duration_since(snapshot.since),
+            policy,
+            snapshot.successes,
+            snapshot.failures,
+            snapshot.rejections,
+            snapshot.opened
+        )
+    }
+}
+
+impl<POLICY, INSTRUMENT> StateMachine<POLICY, INSTRUMENT> {
+    /// Creates a weak handle of the state machine.
+    ///
+    /// Long lived observers, like metric exporters, may keep it without keeping the state
+    /// machine alive after all callers are gone.
+    pub fn downgrade(&self) -> WeakStateMachine<POLICY, INSTRUMENT> {
+        WeakStateMachine {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Returns a read only handle, which queries the state and counters but can't record
+    /// results of calls or force transitions.
+    pub fn monitor(&self) -> Monitor<POLICY, INSTRUMENT> {
+        Monitor::new(self.clone())
+    }
+
+    /// Returns the name of the circuit breaker, see `Config::name`.
+    pub fn name(&self) -> Option<Arc<str>> {
+        self.inner.name.read_with(|name| name.clone())
+    }
+
+    /// Names the circuit breaker, unless it already has a name.
+    ///
+    /// The registry calls it on registration, so anonymous circuit breakers are named after
+    /// their keys.
+    pub(crate) fn name_if_unset(&self, name: &str) {
+        self.inner.name.with(|current| {
+            if current.is_none() {
+                *current = Some(Arc::from(name));
+            }
+        })
+    }
+
+    /// Returns the probe slot held by the reservation `slot`, unless it's expired and taken by
+    /// another reservation.
+    pub(crate) fn release(&self, slot: u64) {
+        self.update(|shared| {
+            if shared.reserved.map(|(id, _)| id) == Some(slot) {
+                shared.reserved = None;
+            }
+        })
+    }
+
+    /// Returns details of a call rejected for the `reason`, including the remaining delay of the
+    /// open state.
+    pub(crate) fn rejection(&self, reason: RejectionReason) -> Rejection {
+        let (_, until) = self.inner.published.load();
+        let retry_after = until.and_then(|until| until.checked_duration_since(clock::now()));
+        Rejection::new(self.name())
+            .with_retry_after(retry_after)
+            .with_reason(reason)
+    }
+
+    /// Returns the current state.
+    ///
+    /// Unlike `is_call_permitted` it has no side effects and doesn't take the lock, so an open state with the expired
+    /// delay is reported as open until the next call is requested. The only exception is an
+    /// expired `Config::half_open_timeout`, which opens the circuit breaker again.
+    pub fn state(&self) -> instrument::State {
+        self.observe_half_open_timeout();
+        Published::observable(self.inner.published.load().0)
+    }
+
+    /// Tells whether a call would be permitted now, without side effects.
+    ///
+    /// It's meant for observers like health checks: it neither transits the state, nor counts
+    /// rejections, nor notifies the instrument. The random admission of half open jitter,
+    /// recovery and canary calls is not taken into account, so these states report `true` and
+    /// an open state reports `false` until the delay expires.
+    pub fn peek_permitted(&self) -> bool {
+        match self.inner.settings.scheduled() {
+            Some(Scheduled::ForcedOpen) => return false,
+            Some(Scheduled::Shadow) => return true,
+            None => {}
+        }
+
+        match self.inner.published.load() {
+            (PUBLISHED_FORCED_OPEN, _) => false,
+            (_, Some(until)) => !self.inner.settings.background_probing && clock::now() > until,
+            (_, None) => true,
+        }
+    }
+
+    /// Applies an expired `Config::half_open_timeout` for observers of the state, the lock is
+    /// taken only when the published state is half open for longer than the timeout.
+    fn observe_half_open_timeout(&self) {
+        let settings = &self.inner.settings;
+        let published = &self.inner.published;
+        let expired = settings.half_open_timeout.map_or(false, |timeout| {
+            published.code() == PUBLISHED_HALF_OPEN
+                && clock::now().saturating_duration_since(published.since()) > timeout
+        });
+        if expired {
+            (self.inner.expire_half_open)(self);
+        }
+    }
+
+    /// Runs `f` under the lock and publishes the resulting state for lock free readers.
+    fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Shared<POLICY>) -> R,
+    {
+        self.inner.shared.with(|shared| {
+            let res = f(shared);
+            self.inner.published.store(&shared.state, shared.changed_at);
+            res
+        })
+    }
+
+    /// Returns a point in time view of the state and counters of the state machine.
+    ///
+    /// Unlike `is_call_permitted` it has no side effects, and it never waits for the lock
+    /// taken by recording of calls. The only exception is an expired
+    /// `Config::half_open_timeout`, which opens the circuit breaker again, so exporters don't
+    /// report a stale half open state.
+    pub fn snapshot(&self) -> Snapshot {
+        self.observe_half_open_timeout();
+        let published = &self.inner.published;
+        let (code, open_until) = published.load();
+        let counters = &self.inner.counters;
+
+        Snapshot {
+            state: Published::observable(code),
+            since: published.since(),
+            open_until,
+            successes: counters.successes.load(Ordering::Relaxed),
+            failures: counters.failures.load(Ordering::Relaxed),
+            rejections: counters.rejections.load(Ordering::Relaxed),
+            opened: counters.opened.load(Ordering::Relaxed),
+            transitions: TransitionCounts::new(counters.transitions()),
+            labels: self.inner.labels.read_with(|labels| labels.clone()),
+            last_error: self.inner.last_error.read_with(|it| it.clone()),
+            instrument_overhead: if self.inner.settings.measure_instrument {
+                Some(InstrumentOverhead {
+                    calls: counters.instrument_calls.load(Ordering::Relaxed),
+                    total: Duration::from_nanos(counters.instrument_nanos.load(Ordering::Relaxed)),
+                    max: Duration::from_nanos(
+                        counters.instrument_max_nanos.load(Ordering::Relaxed),
+                    ),
+                })
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns a circuit breaker which attributes calls to the `label`, e.g. a tenant or an
+    /// endpoint.
+    ///
+    /// The decision to permit a call remains global, but each label keeps its own counters of
+    /// successes, failures and rejections in the snapshot, which helps to identify who drives
+    /// failures. Labels are kept until the state machine is dropped, so use a bounded set, or
+    /// limit their number by `Config::max_labels`.
+    ///
+    /// ```
+    /// use failsafe::{CircuitBreaker, Config};
+    ///
+    /// let circuit_breaker = Config::new().build();
+    /// let _ = circuit_breaker.labeled("tenant-1").call(|| Err::<(), _>(()));
+    ///
+    /// let snapshot = circuit_breaker.snapshot();
+    /// assert_eq!(1, snapshot.labels["tenant-1"].failures);
+    /// ```
+    pub fn labeled<'a>(&'a self, label: &'a str) -> Labeled<'a, POLICY, INSTRUMENT> {
+        Labeled::new(self, label)
+    }
+
+    /// Updates counters of the `label`.
+    pub(crate) fn with_label<F>(&self, label: &str, f: F)
+    where
+        F: FnOnce(&mut LabelCounts),
+    {
+        let max_labels = self.inner.settings.max_labels;
+        self.inner.labels.with(|labels| {
+            if let Some(counts) = labels.get_mut(label) {
+                return f(counts);
+            }
+            let label = match max_labels {
+                Some(max) if labels.len() >= max => OVERFLOW_LABEL,
+                _ => label,
+            };
+            f(labels.entry(label.to_owned()).or_default())
+        })
+    }
+}
+
+impl<POLICY, INSTRUMENT> WeakStateMachine<POLICY, INSTRUMENT> {
+    /// Returns the state machine, if it's still alive.
+    pub fn upgrade(&self) -> Option<StateMachine<POLICY, INSTRUMENT>> {
+        self.inner.upgrade().map(|inner| StateMachine { inner })
+    }
+}
+
+impl<POLICY, INSTRUMENT> Clone for WeakStateMachine<POLICY, INSTRUMENT> {
+    fn clone(&self) -> Self {
+        WeakStateMachine {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Debug for WeakStateMachine<POLICY, INSTRUMENT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.upgrade() {
+            Some(state_machine) => f
+                .debug_tuple("WeakStateMachine")
+                .field(&state_machine)
+                .finish(),
+            None => f.write_str("WeakStateMachine(dropped)"),
+        }
+    }
+}
+
+impl<POLICY, INSTRUMENT> Clone for StateMachine<POLICY, INSTRUMENT> {
+    fn clone(&self) -> Self {
+        StateMachine {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<POLICY> Shared<POLICY>
+where
+    POLICY: FailurePolicy,
+{
+    #[inline]
+    fn transit_to_closed(&mut self) {
+        self.state = State::Closed;
+        self.changed_at = clock::now();
+        self.canaries = 0;
+        self.failure_policy.revived();
+    }
+
+    #[inline]
+    fn transit_to_recovering(&mut self) {
+        self.changed_at = clock::now();
+        self.state = State::Recovering(self.changed_at);
+        self.canaries = 0;
+        self.failure_policy.revived();
+    }
+
+    /// Handles a successful probe, the call permitted in the half open state or a canary call.
+    #[inline]
+    fn transit_on_probe_success(&mut self, settings: &Settings) {
+        if settings.recovery.is_some() {
+            self.transit_to_recovering();
+        } else {
+            self.transit_to_closed();
+        }
+    }
+
+    #[inline]
+    fn transit_to_half_open(&mut self, delay: Duration) {
+        self.changed_at = clock::now();
+        self.state = State::HalfOpen(self.changed_at, delay);
+        self.reserved = None;
+    }
+
+    /// Tells whether the outcome of a call which took `latency` and completed `at` (now by
+    /// default) is one of pending canary calls.
+    ///
+    /// Canaries are the only calls permitted in the open state, so these are calls started
+    /// after the circuit breaker has opened, calls admitted before it may still complete. An
+    /// unmeasured call can't be told apart, so it never counts as a canary.
+    #[inline]
+    fn is_canary(&self, latency: Option<Duration>, at: Option<Instant>) -> bool {
+        let started =
+            latency.and_then(|latency| at.unwrap_or_else(clock::now).checked_sub(latency));
+        self.canaries > 0 && started.map_or(false, |started| started >= self.changed_at)
+    }
+
+    /// Passes the `latency` of a completed call to the policy in the closed state, returns the
+    /// transition to the open state if it trips the policy.
+    #[inline]
+    fn record_latency(
+        &mut self,
+        latency: Option<Duration>,
+        settings: &Settings,
+    ) -> Option<Transition> {
+        let latency = match self.state {
+            State::Closed | State::Recovering(_) => latency?,
+            _ => return None,
+        };
+        let delay = self.failure_policy.record_latency(latency)?;
+        self.transit_to_open(delay, settings);
+        Some(
+            Transition::new(
+                instrument::State::Closed,
+                instrument::State::Open,
+                Cause::Policy,
+            )
+            .tripped_by(self.failure_policy.tripped_by()),
+        )
+    }
+
+    /// Returns the reason a call is rejected in the current state, a closed state rejects calls
+    /// only when it's overridden by a schedule or an interceptor.
+    #[inline]
+    fn rejection_reason(&self) -> RejectionReason {
+        match self.state {
+            State::HalfOpen(_, _) | State::Recovering(_) => RejectionReason::ProbesExhausted,
+            _ => RejectionReason::Open,
+        }
+    }
+
+    /// Decides on a call when the decision leaves the state as it is, so it's made under the
+    /// read lock, or returns `None` when it may change the state, e.g. an expired delay of the
+    /// open state, or a half open state which may time out.
+    fn permit_unchanged(
+        &self,
+        settings: &Settings,
+        intercepted: Option<Decision>,
+        scheduled: Option<Scheduled>,
+    ) -> Option<Permit> {
+        let rejected = Permit::Rejected(self.rejection_reason());
+        match self.state {
+            State::HalfOpen(_, _) | State::Recovering(_) => None,
+            _ if intercepted == Some(Decision::Reject) => Some(rejected),
+            _ if intercepted == Some(Decision::Permit) => Some(Permit::Permitted),
+            _ if scheduled == Some(Scheduled::ForcedOpen) => Some(rejected),
+            State::Closed | State::ForcedClosed => Some(Permit::Permitted),
+            State::ForcedOpen => Some(rejected),
+            State::Open(until, _) => {
+                let expired = clock::now() > until && !settings.background_probing;
+                if expired || settings.canary_ratio.is_some() {
+                    None
+                } else {
+                    Some(rejected)
+                }
+            }
+        }
+    }
+
+    /// Counts a rejection of the `permit`, unless the shadow mode lets the call proceed, and
+    /// returns the permit along with the number of rejections since the circuit breaker opened.
+    fn settle(
+        &self,
+        permit: Permit,
+        scheduled: Option<Scheduled>,
+    ) -> (Permit, Option<(u64, RejectionReason)>) {
+        match permit {
+            Permit::Rejected(_) if scheduled == Some(Scheduled::Shadow) => {
+                (Permit::Permitted, None)
+            }
+            Permit::Rejected(reason) => {
+                let rejections = self.rejections.fetch_add(1, Ordering::Relaxed) + 1;
+                (permit, Some((rejections, reason)))
+            }
+            permit => (permit, None),
+        }
+    }
+
+    /// Opens the circuit breaker again with the previous delay, as of the moment the half open
+    /// timeout expired, when no probe has arrived in time.
+    fn expire_half_open(&mut self, settings: &Settings) -> Option<Transition> {
+        if let (&State::HalfOpen(since, delay), Some(timeout)) =
+            (&self.state, settings.half_open_timeout)
+        {
+            if clock::now() - since > timeout {
+                self.transit_to_open_at(since + timeout, delay, settings);
+                return Some(Transition::new(
+                    instrument::State::HalfOpen,
+                    instrument::State::Open,
+                    Cause::ProbeTimeout,
+                ));
+            }
+        }
+        None
+    }
+
+    /// Tells whether an unexpired reservation holds the probe slot.
+    #[inline]
+    fn is_reserved(&self) -> bool {
+        self.reserved
+            .map_or(false, |(_, expires)| clock::now() <= expires)
+    }
+
+    #[inline]
+    fn transit_to_open(&mut self, delay: Duration, settings: &Settings) {
+        self.transit_to_open_at(clock::now(), delay, settings);
+    }
+
+    /// Opens the circuit breaker as of the moment `at`, which may be in the past.
+    #[inline]
+    fn transit_to_open_at(&mut self, at: Instant, delay: Duration, settings: &Settings) {
+        if self.state.observable() == instrument::State::Closed {
+            self.rejections.store(0, Ordering::Relaxed);
+        }
+        self.changed_at = at;
+        let until = self.changed_at + delay + settings.pick_half_open_jitter();
+        self.state = State::Open(until, delay);
+        self.canaries = 0;
+    }
+}
+
+impl Settings {
+    /// Returns the active scheduled window.
+    #[inline]
+    fn scheduled(&self) -> Option<Scheduled> {
+        self.schedule.as_ref().and_then(|schedule| (schedule.0)())
+    }
+
+    /// Returns a random part of the half open jitter, which is added to the open state's deadline.
+    #[inline]
+    fn pick_half_open_jitter(&self) -> Duration {
+        match self.half_open_jitter_millis() {
+            Some(jitter) => Duration::from_millis(ThreadLocalGenRange.gen_range(0, jitter)),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// Decides whether a call is admitted in the half open state entered `elapsed` time ago. The
+    /// chance grows linearly and reaches 100% when the jitter interval has passed.
+    #[inline]
+    fn admit_in_half_open(&self, elapsed: Duration) -> bool {
+        match self.half_open_jitter_millis() {
+            Some(jitter) => {
+                let elapsed = millis(elapsed);
+                elapsed >= jitter || ThreadLocalGenRange.gen_range(0, jitter) < elapsed
+            }
+            None => true,
+        }
+    }
+
+    /// Decides whether a caller, which hasn't volunteered, probes the backend in the half open
+    /// state entered `elapsed` time ago, or `None` for the caller which has just entered it.
+    #[inline]
+    fn admit_probe(&self, elapsed: Option<Duration>) -> bool {
+        match self.probe_selection {
+            ProbeSelection::FirstCome => elapsed.map_or(true, |it| self.admit_in_half_open(it)),
+            ProbeSelection::Sampled(ratio) => {
+                elapsed.map_or(true, |it| self.admit_in_half_open(it)) && admit_with_ratio(ratio)
+            }
+            ProbeSelection::OptIn => false,
+        }
+    }
+
+    /// Tells whether the published closed state is enough to permit a call.
+    ///
+    /// The gradual recovery is published as closed, and a scheduled window overrides the state,
+    /// so both need the lock.
+    #[inline]
+    fn lock_free_closed(&self) -> bool {
+        self.recovery.is_none() && self.schedule.is_none() && self.interceptor.is_none()
+    }
+
+    /// Returns the share of admitted calls for the recovery started `elapsed` time ago, or `None`
+    /// when the recovery is over.
+    #[inline]
+    fn recovery_ratio(&self, elapsed: Duration) -> Option<f64> {
+        let recovery = self.recovery.as_ref()?;
+        let step_millis = millis(recovery.step_duration).max(1);
+        let step = (millis(elapsed) / step_millis) as usize;
+        recovery.steps.get(step).cloned()
+    }
+
+    #[inline]
+    fn half_open_jitter_millis(&self) -> Option<u64> {
+        self.half_open_jitter.map(millis).filter(|it| *it > 0)
+    }
+}
+
+/// Admits a call with the given probability.
+#[inline]
+fn admit_with_ratio(ratio: f64) -> bool {
+    const SCALE: u64 = 10_000;
+    ThreadLocalGenRange.gen_range(0, SCALE) < (ratio * SCALE as f64) as u64
+}
+
+/// Records `count` successes put off by `Config::success_sampling`, as they happened by the
+/// moment `at`, so the failure policy sees every success.
+fn record_pending_successes<POLICY>(policy: &mut POLICY, count: u64, at: Instant)
+where
+    POLICY: FailurePolicy,
+{
+    if count > 0 {
+        clock::at(at, || {
+            let record = CallRecord::new(at);
+            for _ in 0..count {
+                policy.record_success_with(&record);
+            }
+        });
+    }
+}
+
+/// Runs `f` at the time an outcome happened, if it's given, see `StateMachine::on_success_at`.
+#[inline]
+fn happened_at<F, R>(at: Option<Instant>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    match at {
+        Some(at) => clock::at(at, f),
+        None => f(),
+    }
+}
+
+#[inline]
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000 + u64::from(duration.subsec_millis())
+}
+
+impl<POLICY, INSTRUMENT> StateMachine<POLICY, INSTRUMENT>
+where
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    /// Creates a new state machine with given failure policy and instrument.
+    pub fn new(failure_policy: POLICY, instrument: INSTRUMENT) -> Self {
+        Self::with_settings(failure_policy, instrument, Settings::default())
+    }
+
+    /// Creates a new state machine with given failure policy, instrument and settings.
+    pub(crate) fn with_settings(
+        failure_policy: POLICY,
+        instrument: INSTRUMENT,
+        settings: Settings,
+    ) -> Self {
+        instrument.on_closed();
+
+        let now = clock::now();
+        let name = settings.name.clone();
+        StateMachine {
+            inner: Arc::new(Inner {
+                shared: Lock::new(Shared {
+                    state: State::Closed,
+                    failure_policy,
+                    canaries: 0,
+                    changed_at: now,
+                    rejections: AtomicU64::new(0),
+                    reserved: None,
+                    reservations: 0,
+                }),
+                instrument,
+                settings,
+                counters: Counters::default(),
+                published: Published::new(now),
+                labels: Lock::new(BTreeMap::new()),
+                name: Lock::new(name),
+                last_error: Lock::new(None),
+                wakers: Lock::new(Vec::new()),
+                expire_half_open: Self::expire_half_open,
+            }),
+        }
+    }
+
+    fn expire_half_open(&self) {
+        let timed_out = self.update(|shared| shared.expire_half_open(&self.inner.settings));
+        self.notify(timed_out);
+    }
+
+    /// Requests permission to call.
+    ///
+    /// It returns `true` if a call is allowed, or `false` if prohibited. It's an acting check,
+    /// meant to be followed by the call and its outcome: it may transit an open state with the
+    /// expired delay to half open, admit a canary and count a rejection, notifying the
+    /// instrument. Observers should use `peek_permitted` or `state` instead.
+    pub fn is_call_permitted(&self) -> bool {
+        self.permit().is_ok()
+    }
+
+    /// Requests permission to call, see `is_call_permitted`, a rejection carries the reason
+    /// decided along with the permission.
+    pub(crate) fn permit(&self) -> Result<(), Rejection> {
+        match self.request_permission(false) {
+            Permit::Rejected(reason) => Err(self.rejection(reason)),
+            Permit::Permitted | Permit::Probe(_) => Ok(()),
+        }
+    }
+
+    /// Returns details of a call rejected in the current state, when no permission was
+    /// requested, e.g. because a reservation has expired.
+    pub(crate) fn current_rejection(&self) -> Rejection {
+        let reason = self
+            .inner
+            .shared
+            .read_with(|shared| shared.rejection_reason());
+        self.rejection(reason)
+    }
+
+    /// Requests permission to call, volunteering to be a probe in the half open state.
+    ///
+    /// Volunteers are always admitted in the half open state, whatever `ProbeSelection` is
+    /// configured, otherwise it's the same as `is_call_permitted`.
+    pub fn is_probe_permitted(&self) -> bool {
+        !matches!(self.request_permission(true), Permit::Rejected(_))
+    }
+
+    /// Requests permission to call within the `ttl`, see `is_call_permitted`.
+    ///
+    /// In the half open state the returned reservation holds the probe slot: other callers are
+    /// rejected until it's used, dropped, or the `ttl` expires, so the next caller probes the
+    /// backend when the reserved call never happens, e.g. because its caller has crashed. An
+    /// expired reservation rejects the call.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use failsafe::Config;
+    ///
+    /// let circuit_breaker = Config::new().build();
+    /// if let Some(reservation) = circuit_breaker.try_reserve(Duration::from_secs(5)) {
+    ///     // Prepare the request, e.g. take a connection from a pool.
+    ///     let res = reservation.call(|| Ok::<_, ()>("fetched"));
+    ///     assert!(res.is_ok());
+    /// }
+    /// ```
+    pub fn try_reserve(&self, ttl: Duration) -> Option<Reservation<POLICY, INSTRUMENT>> {
+        if let Permit::Rejected(_) = self.request_permission(false) {
+            return None;
+        }
+
+        let expires = clock::now() + ttl;
+        let slot = self.update(|shared| match shared.state {
+            // Another reservation has taken the slot after the permission was granted.
+            State::HalfOpen(_, _) if shared.is_reserved() => None,
+            State::HalfOpen(_, _) => {
+                shared.reservations += 1;
+                shared.reserved = Some((shared.reservations, expires));
+                Some(Some(shared.reservations))
+            }
+            _ => Some(None),
+        })?;
+
+        Some(Reservation::new(self.clone(), slot, expires))
+    }
+
+    /// Executes a given function within circuit breaker, volunteering to be a probe in the half
+    /// open state, see `is_probe_permitted`.
+    ///
+    /// Depending on function result value, the call will be recorded as success or failure.
+    /// When it's admitted in the half open state, its result decides the next state even if
+    /// other probes complete first, so a dedicated health check drives the recovery rather
+    /// than user traffic: a passed check closes the circuit breaker reopened by a failed probe,
+    /// and a failed one reopens the circuit breaker closed by a successful probe.
+    pub fn call_as_probe<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        let delay = match self.request_permission(true) {
+            Permit::Rejected(reason) => return Err(Error::Rejected(self.rejection(reason))),
+            Permit::Permitted => None,
+            Permit::Probe(delay) => Some(delay),
+        };
+
+        let res = call_permitted(self, failure_predicate::Any, f);
+        if let Some(delay) = delay {
+            self.settle_probe(res.is_ok(), delay);
+        }
+        res
+    }
+
+    /// Makes the state follow the result of a probe, which other probes may have overtaken.
+    fn settle_probe(&self, passed: bool, delay: Duration) {
+        let settings = &self.inner.settings;
+        let transition = self.update(|shared| {
+            let from = shared.state.observable();
+            match shared.state {
+                State::Open(_, _) | State::HalfOpen(_, _) if passed => {
+                    shared.transit_on_probe_success(settings);
+                    Some(Transition::new(
+                        from,
+                        instrument::State::Closed,
+                        Cause::Probe,
+                    ))
+                }
+                State::Closed | State::Recovering(_) if !passed => {
+                    shared.transit_to_open(delay, settings);
+                    Some(Transition::new(from, instrument::State::Open, Cause::Probe))
+                }
+                _ => None,
+            }
+        });
+
+        self.notify(transition);
+    }
+
+    fn request_permission(&self, volunteer: bool) -> Permit {
+        let settings = &self.inner.settings;
+        // The closed state permits calls without the lock, and without notifying the
+        // instrument, so it costs an atomic load.
+        if settings.lock_free_closed() && self.inner.published.code() == PUBLISHED_CLOSED {
+            return Permit::Permitted;
+        }
+
+        let mut timed_out = None;
+        let mut transition = None;
+
+        let scheduled = settings.scheduled();
+        let intercepted = self.intercept(None);
+        // Rejections of the open state, and permits of the closed one, share the read lock, the
+        // write lock is taken only when the decision may change the state.
+        let unchanged = self.inner.shared.read_with(|shared| {
+            shared
+                .permit_unchanged(settings, intercepted, scheduled)
+                .map(|permit| shared.settle(permit, scheduled))
+        });
+        let (res, rejections) = unchanged.unwrap_or_else(|| {
+            self.update(|shared| {
+                timed_out = shared.expire_half_open(settings);
+
+                let rejected = Permit::Rejected(shared.rejection_reason());
+                // Probes are admitted in the half open state only, the one entered from the open.
+                let probe = |delay, admitted| {
+                    if admitted {
+                        Permit::Probe(delay)
+                    } else {
+                        Permit::Rejected(RejectionReason::ProbesExhausted)
+                    }
+                };
+                let permit = match shared.state {
+                    _ if intercepted == Some(Decision::Reject) => rejected,
+                    _ if intercepted == Some(Decision::Permit) => Permit::Permitted,
+                    _ if scheduled == Some(Scheduled::ForcedOpen) => rejected,
+                    State::Closed | State::ForcedClosed => Permit::Permitted,
+                    State::ForcedOpen => rejected,
+                    State::HalfOpen(_, _) if shared.is_reserved() => rejected,
+                    State::HalfOpen(since, delay) => probe(
+                        delay,
+                        volunteer || settings.admit_probe(Some(clock::now() - since)),
+                    ),
+                    State::Recovering(since) => match settings.recovery_ratio(clock::now() - since)
+                    {
+                        Some(ratio) if admit_with_ratio(ratio) => Permit::Permitted,
+                        Some(_) => rejected,
+                        None => {
+                            shared.state = State::Closed;
+                            Permit::Permitted
+                        }
+                    },
+                    State::Open(until, delay) => {
+                        if clock::now() > until && !settings.background_probing {
+                            shared.transit_to_half_open(delay);
+                            transition = Some(Transition::new(
+                                instrument::State::Open,
+                                instrument::State::HalfOpen,
+                                Cause::DelayExpired,
+                            ));
+                            probe(delay, volunteer || settings.admit_probe(None))
+                        } else if settings.canary_ratio.map_or(false, admit_with_ratio) {
+                            shared.canaries += 1;
+                            Permit::Permitted
+                        } else {
+                            rejected
+                        }
+                    }
+                };
+
+                shared.settle(permit, scheduled)
+            })
+        });
+
+        self.notify(timed_out);
+        self.notify(transition);
+
+        if let Some((rejections, reason)) = rejections {
+            self.inner
+                .counters
+                .rejections
+                .fetch_add(1, Ordering::Relaxed);
+            match reason {
+                RejectionReason::ProbesExhausted => {
+                    self.instrumented(|it| it.on_probes_exhausted(rejections))
+                }
+                _ => self.instrumented(|it| it.on_rejected(rejections)),
+            }
+        }
+
+        res
+    }
+
+    /// Wakes the task of the `waker` on the next transition, so tasks waiting while the circuit
+    /// breaker is open don't rely on timers alone.
+    ///
+    /// At most `MAX_WAKERS` wakers are kept, on overflow all of them are woken, so wakers of
+    /// dropped tasks are released even if the state never changes, e.g. it's forced open.
+    #[cfg(feature = "async")]
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        let overflow = self.inner.wakers.with(|wakers| {
+            if wakers.iter().any(|it| it.will_wake(waker)) {
+                return Vec::new();
+            }
+            let overflow = if wakers.len() >= MAX_WAKERS {
+                mem::take(wakers)
+            } else {
+                Vec::new()
+            };
+            wakers.push(waker.clone());
+            overflow
+        });
+        overflow.into_iter().for_each(Waker::wake);
+    }
+
+    /// Wakes all registered tasks, wakers may poll synchronously, so they are woken without the
+    /// lock.
+    fn wake_all(&self) {
+        let wakers = self.inner.wakers.with(mem::take);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Blocks the current thread until a call is permitted or the `deadline` expires.
+    ///
+    /// While the circuit breaker is open it sleeps until the open state expires, otherwise it
+    /// asks for the permission periodically. It returns the last rejection if no call is allowed.
+    pub(crate) fn wait_permitted(&self, deadline: Instant) -> Result<(), Rejection> {
+        // The wait is bounded by the slept time rather than by the deadline, so it ends even if
+        // the clock doesn't follow sleeps, e.g. a paused one.
+        let mut remaining = deadline.saturating_duration_since(clock::now());
+        loop {
+            let now = clock::now();
+            let open_until = self.snapshot().open_until.filter(|until| *until >= now);
+            let expired = remaining == Duration::from_secs(0);
+            if open_until.is_none() {
+                match self.permit() {
+                    Err(rejection) if expired => return Err(rejection),
+                    Err(_) => {}
+                    Ok(()) => return Ok(()),
+                }
+            } else if expired {
+                // Ask for the last time, so the rejection is reported.
+                return self.permit();
+            }
+
+            let wait = match open_until {
+                Some(until) => until - now + WAIT_PRECISION,
+                None => WAIT_INTERVAL,
+            };
+            let wait = wait.min(remaining);
+            clock::sleep(wait);
+            remaining -= wait;
+        }
+    }
+
+    /// Reset state machine to Closed
+    ///
+    /// It also ends the forced open or closed state, see `force_open` and `force_closed`.
+    pub fn reset(&self) {
+        let transition = self.update(|shared| {
+            let from = shared.state.observable();
+            match shared.state {
+                State::HalfOpen(_, _) | State::Open(_, _) | State::ForcedOpen => {
+                    shared.transit_to_closed();
+                    Some(Transition::new(
+                        from,
+                        instrument::State::Closed,
+                        Cause::Forced,
+                    ))
+                }
+                State::Recovering(_) | State::ForcedClosed => {
+                    shared.state = State::Closed;
+                    None
+                }
+                State::Closed => None,
+            }
+        });
+
+        self.notify(transition);
+    }
+
+    /// Opens the circuit breaker until `reset`, all calls are rejected.
+    ///
+    /// It's useful for operators to take a misbehaving backend out of rotation.
+    pub fn force_open(&self) {
+        let transition = self.update(|shared| {
+            let from = shared.state.observable();
+            shared.state = State::ForcedOpen;
+            shared.canaries = 0;
+            if from == instrument::State::Closed {
+                shared.rejections.store(0, Ordering::Relaxed);
+            }
+            if from == instrument::State::Open {
+                return None;
+            }
+
+            shared.changed_at = clock::now();
+            Some(Transition::new(
+                from,
+                instrument::State::Open,
+                Cause::Forced,
+            ))
+        });
+
+        self.notify(transition);
+    }
+
+    /// Closes the circuit breaker until `reset`, all calls are permitted and the failure
+    /// policy never opens it.
+    pub fn force_closed(&self) {
+        let transition = self.update(|shared| {
+            let from = shared.state.observable();
+            if from == instrument::State::Closed {
+                shared.state = State::ForcedClosed;
+                return None;
+            }
+
+            shared.transit_to_closed();
+            shared.state = State::ForcedClosed;
+            Some(Transition::new(
+                from,
+                instrument::State::Closed,
+                Cause::Forced,
+            ))
+        });
+
+        self.notify(transition);
+    }
+
+    /// Returns how long to wait before the next health check, or `None` unless the circuit
+    /// breaker is open, see `Config::background_probing`.
+    pub fn health_check_delay(&self) -> Option<Duration> {
+        match self.inner.published.load() {
+            (PUBLISHED_OPEN, Some(until)) => Some(until.saturating_duration_since(clock::now())),
+            _ => None,
+        }
+    }
+
+    /// Records the result of a health check made while the circuit breaker is open, see
+    /// `Config::background_probing`.
+    ///
+    /// A passed check closes the circuit breaker, or starts the gradual recovery. A failed one
+    /// keeps it open for another delay. Results are ignored in other states.
+    pub fn on_health_check(&self, passed: bool) {
+        let settings = &self.inner.settings;
+        let transition = self.update(|shared| match shared.state {
+            State::Open(_, _) if passed => {
+                shared.transit_on_probe_success(settings);
+                Some(Transition::new(
+                    instrument::State::Open,
+                    instrument::State::Closed,
+                    Cause::Probe,
+                ))
+            }
+            State::Open(_, delay) => {
+                shared.transit_to_open(delay, settings);
+                None
+            }
+            _ => None,
+        });
+
+        self.notify(transition);
+    }
+
+    /// Returns the approximate number of bytes the circuit breaker takes, including windows of
+    /// the failure policy, buffers of the instrument, labels and the captured last error.
+    ///
+    /// It helps to budget memory of applications which embed thousands of circuit breakers.
+    /// Keyed counters are bounded by `Config::max_labels` and `PerClass::max_classes`.
+    ///
+    /// ```
+    /// use failsafe::Config;
+    ///
+    /// let circuit_breaker = Config::new().build();
+    /// assert!(circuit_breaker.memory_footprint() > 0);
+    /// ```
+    pub fn memory_footprint(&self) -> usize {
+        let inner = &self.inner;
+        let policy = inner
+            .shared
+            .read_with(|shared| shared.failure_policy.heap_size());
+        // Entries of a B-tree are counted without nodes' overhead.
+        let labels = inner.labels.read_with(|labels| {
+            labels
+                .keys()
+                .map(|label| label.capacity() + mem::size_of::<(String, LabelCounts)>())
+                .sum::<usize>()
+        });
+        let name = inner
+            .name
+            .read_with(|name| name.as_ref().map_or(0, |it| it.len()));
+        let last_error = inner
+            .last_error
+            .read_with(|it| it.as_ref().map_or(0, |it| it.message.capacity()));
+        let wakers = inner
+            .wakers
+            .with(|wakers| wakers.capacity() * mem::size_of::<Waker>());
+
+        mem::size_of::<Inner<POLICY, INSTRUMENT>>()
+            + policy
+            + inner.instrument.heap_size()
+            + labels
+            + name
+            + last_error
+            + wakers
+    }
+
+    /// Emits the final snapshot to the instrument via `Instrument::on_shutdown`, which flushes
+    /// its buffered state.
+    ///
+    /// Call it when the application shuts down, so metrics exporters and event channels
+    /// terminate cleanly. The circuit breaker keeps working after it, but events recorded later
+    /// may be lost by instruments which have terminated.
+    pub fn shutdown(&self) {
+        let snapshot = self.snapshot();
+        self.instrumented(|it| it.on_shutdown(&snapshot));
+    }
+
+    /// Records a successful call.
+    ///
+    /// This method must be invoked when a call was success.
+    pub fn on_success(&self) {
+        self.record_success(1, None)
+    }
+
+    /// Records a successful call which took `latency`.
+    ///
+    /// It's `on_latency` followed by `on_success` at once, which also passes the latency to the
+    /// instrument.
+    pub fn on_success_after(&self, latency: Duration) {
+        self.record_success(1, Some(latency))
+    }
+
+    /// Records a successful call of the given `cost`.
+    ///
+    /// The failure policy counts it as `cost` calls, so one expensive call, e.g. a bulk
+    /// request, weighs as much as many small ones.
+    pub fn on_success_weighted(&self, cost: u32) {
+        self.record_success(cost, None)
+    }
+
+    /// Records a successful call which completed `at` the given time, e.g. one replayed from
+    /// a completion queue.
+    ///
+    /// Windowed policies attribute it to the time slice of `at`, or ignore it when `at` is out
+    /// of their window, while transitions it causes happen now. `at` shouldn't be in the future.
+    pub fn on_success_at(&self, at: Instant) {
+        self.record_success_at(1, None, Some(at))
+    }
+
+    pub(crate) fn record_success(&self, cost: u32, latency: Option<Duration>) {
+        self.record_success_at(cost, latency, None)
+    }
+
+    fn record_success_at(&self, cost: u32, latency: Option<Duration>, at: Option<Instant>) {
+        match self.intercept(Some(Outcome::Success)) {
+            Some(Decision::Record(Outcome::Failure)) => {
+                let failure = if cost == 1 {
+                    Failure::Plain
+                } else {
+                    Failure::Weighted(cost)
+                };
+                self.apply_error_at(failure, latency, None, at)
+            }
+            Some(Decision::Record(Outcome::Ignored)) => {}
+            _ => self.apply_success_at(cost, latency, at),
+        }
+    }
+
+    fn apply_success_at(&self, cost: u32, latency: Option<Duration>, at: Option<Instant>) {
+        let settings = &self.inner.settings;
+        let counters = &self.inner.counters;
+        let pending = match settings.success_sampling {
+            // Successes in other states may cause transitions, so they are always recorded.
+            Some(rate) if cost == 1 && self.inner.published.code() == PUBLISHED_CLOSED => {
+                let pending = counters.pending_successes.fetch_add(1, Ordering::Relaxed) + 1;
+                let batch = if pending < u64::from(rate) {
+                    let at = self.inner.published.nanos(at.unwrap_or_else(clock::now));
+                    counters.pending_at.store(at, Ordering::Relaxed);
+                    None
+                } else {
+                    // The batch is full, it's recorded along with this success, unless another
+                    // thread has just taken it.
+                    Some(self.take_pending_successes()).filter(|(count, _)| *count > 0)
+                };
+                if batch.is_none() {
+                    counters.successes.fetch_add(1, Ordering::Relaxed);
+                    self.instrumented(|it| it.on_success(latency));
+                    return;
+                }
+                batch
+            }
+            _ => None,
+        };
+
+        let transition = self.update(|shared| {
+            // A slow call may open the circuit breaker, then the success is recorded as usual.
+            let tripped = shared.record_latency(latency, settings);
+            let from = shared.state.observable();
+            let transition = match shared.state {
+                State::HalfOpen(_, _) => true,
+                State::Open(_, _) => shared.is_canary(latency, at),
+                _ => false,
+            };
+            if transition {
+                shared.transit_on_probe_success(settings);
+            }
+            let policy = &mut shared.failure_policy;
+            if let Some((count, pending_at)) = pending {
+                record_pending_successes(policy, count - 1, pending_at);
+            }
+            happened_at(at, || {
+                let record = CallRecord::new(clock::now())
+                    .with_latency(latency)
+                    .with_weight(cost);
+                policy.record_success_with(&record);
+            });
+
+            if transition {
+                Some(Transition::new(
+                    from,
+                    instrument::State::Closed,
+                    Cause::Probe,
+                ))
+            } else {
+                tripped
+            }
+        });
+
+        counters.successes.fetch_add(1, Ordering::Relaxed);
+        self.instrumented(|it| it.on_success(latency));
+        self.notify(transition);
+    }
+
+    /// Takes successes put off by `Config::success_sampling`, returns their number and the
+    /// moment of the last one.
+    fn take_pending_successes(&self) -> (u64, Instant) {
+        let counters = &self.inner.counters;
+        let count = counters.pending_successes.swap(0, Ordering::Relaxed);
+        let at = counters.pending_at.load(Ordering::Relaxed);
+        (count, self.inner.published.instant(at))
+    }
+
+    /// Records the latency of a completed call.
+    ///
+    /// This method should be invoked before `on_success` or `on_error`, so latency aware
+    /// policies can open the circuit breaker even when calls succeed. See also `on_success_after`
+    /// and `on_error_after`.
+    pub fn on_latency(&self, latency: Duration) {
+        let settings = &self.inner.settings;
+        let transition = self.update(|shared| shared.record_latency(Some(latency), settings));
+        self.notify(transition);
+    }
+
+    /// Records a failed call.
+    ///
+    /// This method must be invoked when a call failed.
+    pub fn on_error(&self) {
+        self.record_error(Failure::Plain, None, None)
+    }
+
+    /// Records a failed call which took `latency`.
+    ///
+    /// It's `on_latency` followed by `on_error` at once, which also passes the latency to the
+    /// instrument.
+    pub fn on_error_after(&self, latency: Duration) {
+        self.record_error(Failure::Plain, Some(latency), None)
+    }
+
+    /// Records a failed call with an error of the given class.
+    ///
+    /// Class aware policies, like `failure_policy::per_class`, accrue failures of each class
+    /// independently; other policies treat it as `on_error`.
+    pub fn on_error_in(&self, class: &'static str) {
+        self.record_error(Failure::Classified(class), None, Some(&class))
+    }
+
+    /// Records a failed call of the given `cost`.
+    ///
+    /// The failure policy counts it as `cost` calls, see `on_success_weighted`.
+    pub fn on_error_weighted(&self, cost: u32) {
+        self.record_error(Failure::Weighted(cost), None, None)
+    }
+
+    /// Records a failed call which error is the `reason`.
+    ///
+    /// The instrument receives the reason before the transition the failure may cause, see
+    /// `Instrument::on_failure_reason`.
+    pub fn on_error_with(&self, reason: &dyn Display) {
+        self.record_error(Failure::Plain, None, Some(reason))
+    }
+
+    /// Records a failed call which completed `at` the given time, see `on_success_at`.
+    pub fn on_error_at(&self, at: Instant) {
+        self.record_error_at(Failure::Plain, None, None, Some(at))
+    }
+
+    pub(crate) fn record_error(
+        &self,
+        failure: Failure,
+        latency: Option<Duration>,
+        reason: Option<&dyn Display>,
+    ) {
+        self.record_error_at(failure, latency, reason, None)
+    }
+
+    fn record_error_at(
+        &self,
+        failure: Failure,
+        latency: Option<Duration>,
+        reason: Option<&dyn Display>,
+        at: Option<Instant>,
+    ) {
+        match self.intercept(Some(Outcome::Failure)) {
+            Some(Decision::Record(Outcome::Success)) => {
+                let cost = match failure {
+                    Failure::Weighted(cost) => cost,
+                    Failure::Plain | Failure::Classified(_) => 1,
+                };
+                self.apply_success_at(cost, latency, at)
+            }
+            Some(Decision::Record(Outcome::Ignored)) => {}
+            _ => self.apply_error_at(failure, latency, reason, at),
+        }
+    }
+
+    fn apply_error_at(
+        &self,
+        failure: Failure,
+        latency: Option<Duration>,
+        reason: Option<&dyn Display>,
+        at: Option<Instant>,
+    ) {
+        let settings = &self.inner.settings;
+        // Pending successes precede the failure, e.g. they end a streak of failures.
+        let pending = settings
+            .success_sampling
+            .map(|_| self.take_pending_successes());
+        let mark_dead = |policy: &mut POLICY| {
+            if let Some((count, pending_at)) = pending {
+                record_pending_successes(policy, count, pending_at);
+            }
+            happened_at(at, || {
+                let record = CallRecord::new(clock::now()).with_latency(latency);
+                let record = match failure {
+                    Failure::Plain => record,
+                    Failure::Classified(class) => record.with_class(class),
+                    Failure::Weighted(cost) => record.with_weight(cost),
+                };
+                policy.mark_dead_on_failure_with(&record)
+            })
+        };
+        let transition = self.update(|shared| {
+            // A slow call may trip the policy before the failure is recorded.
+            if let Some(tripped) = shared.record_latency(latency, settings) {
+                return Some(tripped);
+            }
+            match shared.state {
+                State::Closed | State::Recovering(_) => {
+                    let delay = mark_dead(&mut shared.failure_policy)?;
+                    shared.transit_to_open(delay, settings);
+                    Some(
+                        Transition::new(
+                            instrument::State::Closed,
+                            instrument::State::Open,
+                            Cause::Policy,
+                        )
+                        .tripped_by(shared.failure_policy.tripped_by()),
+                    )
+                }
+                State::HalfOpen(_, delay_in_half_open) => {
+                    // Pick up the next open state's delay from the policy, if policy returns Some(_)
+                    // use it, otherwise reuse the delay from the current state.
+                    let delay = mark_dead(&mut shared.failure_policy).unwrap_or(delay_in_half_open);
+                    shared.transit_to_open(delay, settings);
+                    Some(Transition::new(
+                        instrument::State::HalfOpen,
+                        instrument::State::Open,
+                        Cause::Probe,
+                    ))
+                }
+                State::Open(_, _) => {
+                    // A failed canary call, the backend is still unavailable.
+                    if shared.is_canary(latency, at) {
+                        shared.canaries -= 1;
+                    }
+                    None
+                }
+                State::ForcedOpen | State::ForcedClosed => None,
+            }
+        });
+
+        self.inner.counters.failures.fetch_add(1, Ordering::Relaxed);
+        if transition.is_some() {
+            self.capture_last_error(reason);
+        }
+        self.instrumented(|it| {
+            if let Some(reason) = reason {
+                it.on_failure_reason(reason);
+            }
+            it.on_failure(latency);
+        });
+        self.notify(transition);
+    }
+
+    /// Keeps the reason of the failure which has opened the circuit breaker, if it's enabled.
+    fn capture_last_error(&self, reason: Option<&dyn Display>) {
+        if !self.inner.settings.capture_last_error {
+            return;
+        }
+
+        let at = clock::now();
+        // The buffer of the previous message is reused, so repeated openings don't allocate.
+        self.inner
+            .last_error
+            .with(|it| match (it.as_mut(), reason) {
+                (Some(last_error), Some(reason)) => {
+                    last_error.message.clear();
+                    let _ = write!(last_error.message, "{}", reason);
+                    last_error.at = at;
+                }
+                (None, Some(reason)) => {
+                    *it = Some(LastError {
+                        message: reason.to_string(),
+                        at,
+                    })
+                }
+                (_, None) => *it = None,
+            });
+    }
+
+    /// Returns a reference to the instrument.
+    #[cfg(test)]
+    #[inline]
+    pub(crate) fn instrument(&self) -> &INSTRUMENT {
+        &self.inner.instrument
+    }
+
+    /// Asks the interceptor for a decision about the call, see `Config::interceptor`.
+    #[inline]
+    fn intercept(&self, outcome: Option<Outcome>) -> Option<Decision> {
+        let Interceptor(interceptor) = self.inner.settings.interceptor.as_ref()?;
+        let name = self.name();
+        interceptor(&CallContext {
+            name: name.as_deref(),
+            state: Published::observable(self.inner.published.code()),
+            outcome,
+        })
+    }
+
+    /// Calls the instrument, measuring the time spent in it if it's enabled, see
+    /// `Config::measure_instrument`.
+    #[inline]
+    pub(crate) fn instrumented<F>(&self, f: F)
+    where
+        F: FnOnce(&INSTRUMENT),
+    {
+        if !self.inner.settings.measure_instrument {
+            return f(&self.inner.instrument);
+        }
+
+        // The real time is measured, a mocked clock doesn't advance within callbacks.
+        let started = Instant::now();
+        f(&self.inner.instrument);
+        let nanos = started.elapsed().as_nanos().min(u128::from(u64::MAX)) as u64;
+
+        let counters = &self.inner.counters;
+        counters.instrument_calls.fetch_add(1, Ordering::Relaxed);
+        counters
+            .instrument_nanos
+            .fetch_add(nanos, Ordering::Relaxed);
+        counters
+            .instrument_max_nanos
+            .fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Reports the transition to the instrument, it must be called outside of the lock.
+    #[inline]
+    fn notify(&self, transition: Option<Transition>) {
+        if let Some(transition) = transition {
+            let counters = &self.inner.counters;
+            if transition.to == instrument::State::Open {
+                counters.opened.fetch_add(1, Ordering::Relaxed);
+            }
+            counters.transitions[usize::from(transition.from.as_gauge())]
+                [usize::from(transition.to.as_gauge())]
+            .fetch_add(1, Ordering::Relaxed);
+            self.instrumented(|it| {
+                it.on_transition(&transition);
+                if let Some(TransitionHook(hook)) = &self.inner.settings.transition_hook {
+                    hook(transition);
+                }
+            });
+            // Every transition drains the wakers, so ones of dropped tasks don't pile up while
+            // the circuit breaker stays open, the tasks which still wait register again.
+            self.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::super::circuit_breaker::CircuitBreaker;
+    use super::super::failure_policy::DefaultPolicy;
+    use super::super::failure_policy::{consecutive_failures, max_failures_in_window};
+    use super::super::monitor::Monitor;
+    use super::super::{backoff, failure_policy, Config, SharedCircuitBreaker};
+    use super::*;
+
+    /// Perform `Closed` -> `Open` -> `HalfOpen` -> `Open` -> `HalfOpen` -> `Closed` transitions.
+    #[test]
+    fn state_machine() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::exponential(5.seconds(), 300.seconds());
+            let policy = consecutive_failures(3, backoff);
+            let state_machine = StateMachine::new(policy, observe.clone());
+
+            assert!(state_machine.is_call_permitted());
+
+            // Perform success requests. the circuit breaker must be closed.
+            for _i in 0..10 {
+                assert!(state_machine.is_call_permitted());
+                state_machine.on_success();
+                assert!(observe.is_closed());
+            }
+
+            // Perform failed requests, the circuit breaker still closed.
+            for _i in 0..2 {
+                assert!(state_machine.is_call_permitted());
+                state_machine.on_error();
+                assert!(observe.is_closed());
+            }
+
+            // Perform a failed request and transit to the open state for 5s.
+            assert!(state_machine.is_call_permitted());
+            state_machine.on_error();
+            assert!(observe.is_open());
+
+            // Reject call attempts, the circuit breaker in open state.
+            for i in 0..10 {
+                assert!(!state_machine.is_call_permitted());
+                assert_eq!(i + 1, observe.rejected_calls());
+            }
+
+            // Wait 2s, the circuit breaker still open.
+            time.advance(2.seconds());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_open());
+
+            clock::now();
+
+            // Wait 4s (6s total), the circuit breaker now in the half open state.
+            time.advance(4.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+
+            // Perform a failed request and transit back to the open state for 10s.
+            state_machine.on_error();
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_open());
+
+            // Wait 5s, the circuit breaker still open.
+            time.advance(5.seconds());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_open());
+
+            // Wait 6s (11s total), the circuit breaker now in the half open state.
+            time.advance(6.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+
+            // Perform a success request and transit to the closed state.
+            state_machine.on_success();
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_closed());
+
+            // Perform success requests.
+            for _i in 0..10 {
+                assert!(state_machine.is_call_permitted());
+                state_machine.on_success();
+            }
+
+            // Perform failed request and transit back to open state
+            for _i in 0..3 {
+                state_machine.on_error();
+            }
+            assert!(observe.is_open());
+
+            // Reset state machine
+            state_machine.reset();
+            assert!(observe.is_closed());
+        });
+    }
+
+    #[test]
+    fn half_open_jitter() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                half_open_jitter: Some(10.seconds()),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            state_machine.on_error();
+            assert!(observe.is_open());
+
+            // The open state lasts for 5s, but may be extended by up to 10s of jitter.
+            time.advance(5.seconds());
+            assert!(!state_machine.is_call_permitted());
+
+            time.advance(11.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+
+            // Other callers are admitted gradually after the transition.
+            assert!(!state_machine.is_call_permitted());
+            time.advance(10.seconds());
+            assert!(state_machine.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn gradual_recovery() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                recovery: Some(Recovery {
+                    steps: vec![0.0, 1.0],
+                    step_duration: 10.seconds(),
+                }),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            state_machine.on_error();
+            time.advance(6.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+
+            // A successful probe starts the recovery, the first step admits nothing.
+            state_machine.on_success();
+            assert!(observe.is_closed());
+            assert_eq!(
+                "recovering",
+                state_machine.inner.shared.with(|it| it.state.as_str())
+            );
+            assert!(!state_machine.is_call_permitted());
+
+            // The second step admits everything.
+            time.advance(10.seconds());
+            assert!(state_machine.is_call_permitted());
+
+            // The recovery is over.
+            time.advance(10.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert_eq!(
+                "closed",
+                state_machine.inner.shared.with(|it| it.state.as_str())
+            );
+        });
+    }
+
+    #[test]
+    fn canary_traffic() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                canary_ratio: Some(1.0),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            state_machine.on_error();
+            assert!(observe.is_open());
+
+            // A failed canary keeps the circuit breaker open.
+            assert!(state_machine.is_call_permitted());
+            time.advance(1.seconds());
+            state_machine.on_error_after(1.seconds());
+            assert!(observe.is_open());
+
+            // A successful canary closes the circuit breaker before the open state expires.
+            assert!(state_machine.is_call_permitted());
+            time.advance(1.seconds());
+            state_machine.on_success_after(1.seconds());
+            assert!(observe.is_closed());
+        });
+    }
+
+    #[test]
+    fn canaries_ignore_calls_admitted_before_opening() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                canary_ratio: Some(1.0),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            // A slow call is in flight when the circuit breaker opens.
+            assert!(state_machine.is_call_permitted());
+            time.advance(1.seconds());
+            state_machine.on_error();
+            assert!(observe.is_open());
+
+            // It completes while a canary is pending, but it isn't the canary.
+            assert!(state_machine.is_call_permitted());
+            time.advance(1.seconds());
+            state_machine.on_success_after(2.seconds());
+            assert!(observe.is_open());
+            state_machine.on_success();
+            assert!(observe.is_open());
+
+            state_machine.on_success_after(1.seconds());
+            assert!(observe.is_closed());
+        });
+    }
+
+    #[test]
+    fn opt_in_probes() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                probe_selection: ProbeSelection::OptIn,
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            state_machine.on_error();
+            assert!(!state_machine.is_probe_permitted());
+
+            // Regular callers are rejected in the half open state.
+            time.advance(10.seconds());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+            assert!(!state_machine.is_call_permitted());
+
+            assert!(state_machine.call_as_probe(|| Ok::<_, ()>(())).is_ok());
+            assert!(observe.is_closed());
+            assert!(state_machine.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn probe_decides() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, observe.clone());
+
+            // A failed probe of user traffic overtakes the passed health check.
+            state_machine.on_error();
+            time.advance(10.seconds());
+            let res = state_machine.call_as_probe(|| {
+                assert!(state_machine.is_call_permitted());
+                state_machine.on_error();
+                assert!(observe.is_open());
+                Ok::<_, ()>(())
+            });
+            assert!(res.is_ok());
+            assert!(observe.is_closed());
+
+            // A successful probe of user traffic overtakes the failed health check.
+            state_machine.on_error();
+            time.advance(10.seconds());
+            let res = state_machine.call_as_probe(|| {
+                assert!(state_machine.is_call_permitted());
+                state_machine.on_success();
+                assert!(observe.is_closed());
+                Err::<(), _>(())
+            });
+            assert!(res.is_err());
+            assert!(observe.is_open());
+            assert!(!state_machine.is_call_permitted());
+
+            // Checks in the closed state are recorded as usual calls.
+            time.advance(10.seconds());
+            assert!(state_machine.call_as_probe(|| Ok::<_, ()>(())).is_ok());
+            assert!(state_machine.call_as_probe(|| Ok::<_, ()>(())).is_ok());
+            assert!(observe.is_closed());
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn wakers_are_released() {
+        use std::sync::atomic::AtomicUsize;
+
+        use futures::task::{waker, ArcWake};
+
+        struct Counter(AtomicUsize);
+
+        impl ArcWake for Counter {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let woken = |counters: &[Arc<Counter>]| -> usize {
+            counters.iter().map(|it| it.0.load(Ordering::Relaxed)).sum()
+        };
+        let counters: Vec<_> = (0..=MAX_WAKERS)
+            .map(|_| Arc::new(Counter(AtomicUsize::new(0))))
+            .collect();
+
+        // A transition to the open state wakes the registered tasks as well.
+        let state_machine =
+            StateMachine::new(consecutive_failures(1, backoff::constant(5.seconds())), ());
+        state_machine.register_waker(&waker(counters[0].clone()));
+        state_machine.on_error();
+        assert_eq!(1, woken(&counters));
+
+        // Wakers of tasks which never come back are released once there are too many.
+        state_machine.force_open();
+        for counter in &counters[..MAX_WAKERS] {
+            state_machine.register_waker(&waker(counter.clone()));
+        }
+        assert_eq!(1, woken(&counters));
+        state_machine.register_waker(&waker(counters[MAX_WAKERS].clone()));
+        assert_eq!(1 + MAX_WAKERS, woken(&counters));
+        assert_eq!(1, state_machine.inner.wakers.with(|it| it.len()));
+    }
+
+    #[test]
+    fn handles_are_shareable() {
+        fn shareable<T: Send + Sync + Clone + 'static>() {}
+
+        shareable::<StateMachine<DefaultPolicy, ()>>();
+        shareable::<WeakStateMachine<DefaultPolicy, ()>>();
+        shareable::<SharedCircuitBreaker>();
+        shareable::<Monitor<DefaultPolicy, ()>>();
+        shareable::<StateMachine<DefaultPolicy, Observer>>();
+    }
+
+    #[test]
+    fn memory_footprint() {
+        let backoff = backoff::constant(5.seconds());
+        let policy = max_failures_in_window(3, 10.seconds(), backoff);
+        let settings = Settings {
+            max_labels: Some(2),
+            ..Settings::default()
+        };
+        let state_machine = StateMachine::with_settings(policy, (), settings);
+        let empty = state_machine.memory_footprint();
+        assert!(empty > mem::size_of::<Inner<(), ()>>());
+
+        for tenant in 0..100 {
+            let label = format!("tenant-{}", tenant);
+            let _ = state_machine.labeled(&label).call(|| Ok::<_, ()>(()));
+        }
+
+        let labels = state_machine.snapshot().labels;
+        assert_eq!(
+            vec!["other", "tenant-0", "tenant-1"],
+            labels.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(98, labels["other"].successes);
+        assert!(state_machine.memory_footprint() - empty < 1024);
+    }
+
+    #[test]
+    fn half_open_timeout() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                half_open_timeout: Some(10.seconds()),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            // The probe is admitted, but its result is never recorded.
+            state_machine.on_error();
+            time.advance(6.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+
+            // Falls back to the open state of 5s once the 10s timeout has expired.
+            time.advance(11.seconds());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_open());
+
+            time.advance(5.seconds());
+            assert!(state_machine.is_call_permitted());
+            assert!(observe.is_half_open());
+            state_machine.on_success();
+            assert!(observe.is_closed());
+        });
+    }
+
+    #[test]
+    fn half_open_timeout_observed() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                half_open_timeout: Some(10.seconds()),
+                probe_selection: ProbeSelection::OptIn,
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            // The caller isn't a volunteer, and no probe arrives after it.
+            let start = clock::now();
+            state_machine.on_error();
+            time.advance(6.seconds());
+            assert!(!state_machine.is_call_permitted());
+            assert_eq!(instrument::State::HalfOpen, state_machine.state());
+
+            // Observers see the open state of 5s after the timeout without any calls.
+            time.advance(11.seconds());
+            assert_eq!(instrument::State::Open, state_machine.state());
+            assert!(observe.is_open());
+            let snapshot = state_machine.snapshot();
+            assert_eq!(instrument::State::Open, snapshot.state);
+            assert_eq!(Some(start + 21.seconds()), snapshot.open_until);
+        });
+    }
+
+    #[test]
+    fn rejection_reasons() {
+        use super::super::instrument::{Event, Events};
+        use std::sync::Mutex as StdMutex;
+
+        clock::freeze(move |time| {
+            let reasons = Arc::new(StdMutex::new(Vec::new()));
+            let sink = reasons.clone();
+            let events = Events::new(move |event: &Event| {
+                if let Event::Rejected { reason, .. } = event {
+                    sink.lock().unwrap().push(*reason);
+                }
+            });
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                probe_selection: ProbeSelection::OptIn,
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, events, settings);
+            let rejected = |res: Result<(), Error<()>>| match res {
+                Err(Error::Rejected(rejection)) => rejection.reason(),
+                res => unreachable!("{:?}", res),
+            };
+
+            state_machine.on_error();
+            assert_eq!(
+                RejectionReason::Open,
+                rejected(state_machine.call(|| Ok(())))
+            );
+
+            // The half open state admits only volunteers, others may retry shortly.
+            time.advance(10.seconds());
+            assert_eq!(
+                RejectionReason::ProbesExhausted,
+                rejected(state_machine.call(|| Ok(())))
+            );
+            assert_eq!(
+                vec![RejectionReason::Open, RejectionReason::ProbesExhausted],
+                *reasons.lock().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn rejections_share_the_lock() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let backoff = backoff::constant(5.seconds());
+        let policy = consecutive_failures(1, backoff);
+        let state_machine = StateMachine::new(policy, ());
+        state_machine.on_error();
+
+        // A reader holds the lock, a rejection of the open state doesn't wait for it.
+        let (tx, rx) = mpsc::channel();
+        let handle = state_machine.clone();
+        let rejected = state_machine.inner.shared.read_with(|_| {
+            thread::spawn(move || tx.send(handle.is_call_permitted()).unwrap());
+            rx.recv_timeout(Duration::from_secs(5))
+        });
+        assert_eq!(Ok(false), rejected);
+        assert_eq!(1, state_machine.snapshot().rejections);
+    }
+
+    #[test]
+    fn overridden_closed_state_rejections() {
+        use super::super::instrument::{Event, Events};
+        use std::sync::atomic::AtomicU8;
+        use std::sync::Mutex as StdMutex;
+
+        clock::freeze(move |_| {
+            let reasons = Arc::new(StdMutex::new(Vec::new()));
+            let sink = reasons.clone();
+            let events = Events::new(move |event: &Event| {
+                if let Event::Rejected { reason, .. } = event {
+                    sink.lock().unwrap().push(*reason);
+                }
+            });
+            let window = Arc::new(AtomicU8::new(0));
+            let active = window.clone();
+            let rejecting = window.clone();
+            let settings = Settings {
+                schedule: Some(Schedule(Arc::new(move || {
+                    match active.load(Ordering::Relaxed) {
+                        1 => Some(Scheduled::ForcedOpen),
+                        _ => None,
+                    }
+                }))),
+                interceptor: Some(Interceptor(Arc::new(
+                    move |_: &CallContext| match rejecting.load(Ordering::Relaxed) {
+                        2 => Some(Decision::Reject),
+                        _ => None,
+                    },
+                ))),
+                ..Settings::default()
+            };
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::with_settings(policy, events, settings);
+            let rejected = |res: Result<(), Error<()>>| match res {
+                Err(Error::Rejected(rejection)) => rejection.reason(),
+                res => unreachable!("{:?}", res),
+            };
+
+            // The closed state rejects calls as if it was open, both in errors and events.
+            window.store(1, Ordering::Relaxed);
+            assert_eq!(
+                RejectionReason::Open,
+                rejected(state_machine.call(|| Ok(())))
+            );
+            window.store(2, Ordering::Relaxed);
+            assert_eq!(
+                RejectionReason::Open,
+                rejected(state_machine.call(|| Ok(())))
+            );
+            assert_eq!(instrument::State::Closed, state_machine.snapshot().state);
+            assert_eq!(
+                vec![RejectionReason::Open, RejectionReason::Open],
+                *reasons.lock().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn scheduled_windows() {
+        use std::sync::atomic::AtomicU8;
+
+        clock::freeze(move |_| {
+            let window = Arc::new(AtomicU8::new(0));
+            let active = window.clone();
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                schedule: Some(Schedule(Arc::new(move || {
+                    match active.load(Ordering::Relaxed) {
+                        1 => Some(Scheduled::ForcedOpen),
+                        2 => Some(Scheduled::Shadow),
+                        _ => None,
+                    }
+                }))),
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            window.store(1, Ordering::Relaxed);
+            assert!(!state_machine.peek_permitted());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_closed());
+            assert_eq!(1, state_machine.snapshot().rejections);
+
+            // The shadow mode permits calls of the open circuit breaker.
+            window.store(2, Ordering::Relaxed);
+            state_machine.on_error();
+            assert!(observe.is_open());
+            assert!(state_machine.is_call_permitted());
+            assert_eq!(1, state_machine.snapshot().rejections);
+
+            window.store(0, Ordering::Relaxed);
+            assert!(!state_machine.is_call_permitted());
+        });
+    }
+
+    #[test]
+    fn background_probing() {
+        clock::freeze(move |time| {
+            let observe = Observer::new();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                background_probing: true,
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, observe.clone(), settings);
+
+            assert_eq!(None, state_machine.health_check_delay());
+            state_machine.on_error();
+            assert_eq!(Some(5.seconds()), state_machine.health_check_delay());
+
+            // User traffic isn't used as probes.
+            time.advance(10.seconds());
+            assert_eq!(Some(0.seconds()), state_machine.health_check_delay());
+            assert!(!state_machine.peek_permitted());
+            assert!(!state_machine.is_call_permitted());
+            assert!(observe.is_open());
+
+            state_machine.on_health_check(false);
+            assert_eq!(Some(5.seconds()), state_machine.health_check_delay());
+            assert!(observe.is_open());
+
+            state_machine.on_health_check(true);
+            assert!(observe.is_closed());
+            assert!(state_machine.is_call_permitted());
+        });
     }
-}
 
-impl<POLICY, INSTRUMENT> Debug for StateMachine<POLICY, INSTRUMENT> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let shared = self.inner.shared.lock();
-        f.debug_struct("StateMachine")
-            .field("state", &(shared.state.as_str()))
-            .finish()
-    }
-}
+    #[test]
+    fn measure_instrument() {
+        #[derive(Debug)]
+        struct Blocking;
 
-impl<POLICY, INSTRUMENT> Clone for StateMachine<POLICY, INSTRUMENT> {
-    fn clone(&self) -> Self {
-        StateMachine {
-            inner: self.inner.clone(),
+        impl Instrument for Blocking {
+            fn on_failure(&self, _latency: Option<Duration>) {
+                thread::sleep(Duration::from_millis(2));
+            }
         }
-    }
-}
 
-impl<POLICY> Shared<POLICY>
-where
-    POLICY: FailurePolicy,
-{
-    #[inline]
-    fn transit_to_closed(&mut self) {
-        self.state = State::Closed;
-        self.failure_policy.revived();
-    }
+        let backoff = backoff::constant(5.seconds());
+        let policy = consecutive_failures(2, backoff);
+        assert_eq!(
+            None,
+            StateMachine::new(policy.clone(), Blocking)
+                .snapshot()
+                .instrument_overhead
+        );
 
-    #[inline]
-    fn transit_to_half_open(&mut self, delay: Duration) {
-        self.state = State::HalfOpen(delay);
-    }
+        let settings = Settings {
+            measure_instrument: true,
+            ..Settings::default()
+        };
+        let state_machine = StateMachine::with_settings(policy, Blocking, settings);
+        state_machine.on_success();
+        state_machine.on_error();
 
-    #[inline]
-    fn transit_to_open(&mut self, delay: Duration) {
-        let until = clock::now() + delay;
-        self.state = State::Open(until, delay);
+        let overhead = state_machine.snapshot().instrument_overhead.unwrap();
+        assert_eq!(2, overhead.calls);
+        assert!(overhead.max >= Duration::from_millis(2));
+        assert!(overhead.total >= overhead.max);
     }
-}
 
-impl<POLICY, INSTRUMENT> StateMachine<POLICY, INSTRUMENT>
-where
-    POLICY: FailurePolicy,
-    INSTRUMENT: Instrument,
-{
-    /// Creates a new state machine with given failure policy and instrument.
-    pub fn new(failure_policy: POLICY, instrument: INSTRUMENT) -> Self {
-        instrument.on_closed();
+    #[test]
+    fn capture_last_error() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let settings = Settings {
+                capture_last_error: true,
+                ..Settings::default()
+            };
+            let state_machine = StateMachine::with_settings(policy, (), settings);
 
-        StateMachine {
-            inner: Arc::new(Inner {
-                shared: Mutex::new(Shared {
-                    state: State::Closed,
-                    failure_policy,
-                }),
-                instrument,
-            }),
-        }
-    }
+            state_machine.on_error_with(&"connection refused");
+            let last_error = state_machine.snapshot().last_error.unwrap();
+            assert_eq!("connection refused", last_error.message);
+            assert_eq!(clock::now(), last_error.at);
 
-    /// Requests permission to call.
-    ///
-    /// It returns `true` if a call is allowed, or `false` if prohibited.
-    pub fn is_call_permitted(&self) -> bool {
-        let mut instrument: u8 = 0;
+            // Failures which don't open the circuit breaker are ignored.
+            time.advance(10.seconds());
+            assert!(state_machine.is_call_permitted());
+            state_machine.on_success();
+            state_machine.on_error_with(&"timeout");
+            assert_eq!(
+                "timeout",
+                state_machine.snapshot().last_error.unwrap().message
+            );
+        });
+    }
 
-        let res = {
-            let mut shared = self.inner.shared.lock();
+    #[test]
+    fn no_allocations_on_transitions() {
+        fn allocations<POLICY>(policy: POLICY) -> usize
+        where
+            POLICY: FailurePolicy,
+        {
+            clock::freeze(move |time| {
+                let state_machine = Config::new()
+                    .failure_policy(policy)
+                    .name("redis")
+                    .half_open_jitter(1.seconds())
+                    .capture_last_error()
+                    .build();
 
-            match shared.state {
-                State::Closed => true,
-                State::HalfOpen(_) => true,
-                State::Open(until, delay) => {
-                    if clock::now() > until {
-                        shared.transit_to_half_open(delay);
-                        instrument |= ON_HALF_OPEN;
-                        true
-                    } else {
-                        instrument |= ON_REJECTED;
-                        false
+                let mut cycle = || {
+                    // Open, fail the probe, and close after a successful one.
+                    for _ in 0..100 {
+                        time.advance(1.seconds());
+                        state_machine.on_error_with(&"connection refused");
+                        if state_machine.state() == instrument::State::Open {
+                            break;
+                        }
                     }
-                }
-            }
-        };
-
-        if instrument & ON_HALF_OPEN != 0 {
-            self.inner.instrument.on_half_open();
-        }
+                    time.advance(3600.seconds());
+                    assert!(state_machine.is_call_permitted());
+                    state_machine.on_error_with(&"connection refused");
+                    time.advance(3600.seconds());
+                    assert!(state_machine.is_call_permitted());
+                    state_machine.on_success();
+                    assert!(state_machine.is_call_permitted());
+                    state_machine.on_success();
+                };
 
-        if instrument & ON_REJECTED != 0 {
-            self.inner.instrument.on_call_rejected();
+                // The first cycle initializes thread locals and buffers.
+                cycle();
+                let before = ALLOCATIONS.with(Cell::get);
+                for _ in 0..10 {
+                    cycle();
+                }
+                ALLOCATIONS.with(Cell::get) - before
+            })
         }
 
-        res
+        let backoff = backoff::exponential(1.seconds(), 60.seconds());
+        assert_eq!(0, allocations(consecutive_failures(3, backoff.clone())));
+        assert_eq!(
+            0,
+            allocations(max_failures_in_window(3, 10.seconds(), backoff.clone()))
+        );
+        assert_eq!(
+            0,
+            allocations(failure_policy::success_rate_over_time_window(
+                0.5,
+                1,
+                10.seconds(),
+                backoff
+            ))
+        );
+        assert_eq!(
+            0,
+            allocations(
+                failure_policy::SuccessRateOverTimeWindow::default()
+                    .or_else(failure_policy::ConsecutiveFailures::default())
+            )
+        );
     }
 
-    /// Reset state machine to Closed
-    ///
-    pub fn reset(&self) {
-        let mut shared = self.inner.shared.lock();
-        match shared.state {
-            State::HalfOpen(_) => {
-                shared.transit_to_closed();
-                self.inner.instrument.on_closed();
-            }
-            State::Open(_, _) => {
-                shared.transit_to_closed();
-                self.inner.instrument.on_closed();
-            }
-            _ => {}
-        }
+    #[test]
+    fn debug_and_display() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(3, backoff.clone()).or_else(max_failures_in_window(
+                10,
+                60.seconds(),
+                backoff,
+            ));
+            let state_machine = StateMachine::new(policy, ());
+            state_machine.on_success();
+            state_machine.on_error();
+            time.advance(1.seconds());
+
+            assert_eq!(
+                "StateMachine { state: \"closed\", in_state: 1s, policy: \"OrElse\", \
+                 successes: 1, failures: 1, rejections: 0, opened: 0 }",
+                format!("{:?}", state_machine)
+            );
+            assert_eq!(
+                "circuit breaker is closed for 1s, policy consecutive_failures(3) or \
+                 max_failures_in_window(10), 1 successes, 1 failures, 0 rejections, opened 0 times",
+                state_machine.to_string()
+            );
+        });
     }
 
-    /// Records a successful call.
-    ///
-    /// This method must be invoked when a call was success.
-    pub fn on_success(&self) {
-        let mut instrument: u8 = 0;
-        {
-            let mut shared = self.inner.shared.lock();
-            if let State::HalfOpen(_) = shared.state {
-                shared.transit_to_closed();
-                instrument |= ON_CLOSED;
+    #[test]
+    fn shutdown() {
+        #[derive(Debug, Default)]
+        struct Exporter(Mutex<Option<Snapshot>>);
+
+        impl Instrument for Exporter {
+            fn on_shutdown(&self, snapshot: &Snapshot) {
+                self.0.with(|it| *it = Some(snapshot.clone()));
             }
-            shared.failure_policy.record_success()
         }
 
-        if instrument & ON_CLOSED != 0 {
-            self.inner.instrument.on_closed();
-        }
+        let backoff = backoff::constant(5.seconds());
+        let policy = consecutive_failures(1, backoff);
+        let state_machine = StateMachine::new(policy, Exporter::default());
+
+        state_machine.on_error();
+        state_machine.shutdown();
+
+        let snapshot = state_machine.inner.instrument.0.with(|it| it.take());
+        assert_eq!(Some(state_machine.snapshot()), snapshot);
     }
 
-    /// Records a failed call.
-    ///
-    /// This method must be invoked when a call failed.
-    pub fn on_error(&self) {
-        let mut instrument: u8 = 0;
-        {
-            let mut shared = self.inner.shared.lock();
-            match shared.state {
-                State::Closed => {
-                    if let Some(delay) = shared.failure_policy.mark_dead_on_failure() {
-                        shared.transit_to_open(delay);
-                        instrument |= ON_OPEN;
-                    }
-                }
-                State::HalfOpen(delay_in_half_open) => {
-                    // Pick up the next open state's delay from the policy, if policy returns Some(_)
-                    // use it, otherwise reuse the delay from the current state.
-                    let delay = shared
-                        .failure_policy
-                        .mark_dead_on_failure()
-                        .unwrap_or(delay_in_half_open);
-                    shared.transit_to_open(delay);
-                    instrument |= ON_OPEN;
-                }
-                _ => {}
-            }
-        }
+    #[test]
+    fn transitions() {
+        use super::super::instrument::State::*;
 
-        if instrument & ON_OPEN != 0 {
-            self.inner.instrument.on_open();
-        }
+        clock::freeze(move |time| {
+            let observe = TransitionObserver::default();
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, observe.clone());
+            let started = clock::now();
+
+            state_machine.on_error();
+            time.advance(10.seconds());
+            assert!(state_machine.is_call_permitted());
+            state_machine.on_error();
+            state_machine.reset();
+            state_machine.reset();
+
+            let transitions = observe.0.lock().unwrap().clone();
+            assert_eq!(started + 10.seconds(), transitions[3].at);
+
+            let transitions: Vec<_> = transitions
+                .iter()
+                .map(|it| (it.from, it.to, it.cause))
+                .collect();
+            assert_eq!(
+                vec![
+                    (Closed, Open, Cause::Policy),
+                    (Open, HalfOpen, Cause::DelayExpired),
+                    (HalfOpen, Open, Cause::Probe),
+                    (Open, Closed, Cause::Forced),
+                ],
+                transitions
+            );
+        });
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::{Arc, Mutex};
+    #[test]
+    fn snapshot() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, ());
 
-    use super::super::backoff;
-    use super::super::failure_policy::consecutive_failures;
-    use super::*;
+            time.advance(1.seconds());
+            state_machine.on_error();
+
+            let snapshot = state_machine.snapshot();
+            assert_eq!(instrument::State::Open, snapshot.state);
+            assert_eq!(clock::now(), snapshot.since);
+            assert_eq!(Some(clock::now() + 5.seconds()), snapshot.open_until);
+            assert_eq!(
+                (0, 1, 0, 1),
+                (
+                    snapshot.successes,
+                    snapshot.failures,
+                    snapshot.rejections,
+                    snapshot.opened
+                )
+            );
+
+            // Taking a snapshot doesn't move the state machine to the half open state.
+            time.advance(10.seconds());
+            assert_eq!(instrument::State::Open, state_machine.snapshot().state);
+        });
+    }
 
-    /// Perform `Closed` -> `Open` -> `HalfOpen` -> `Open` -> `HalfOpen` -> `Closed` transitions.
     #[test]
-    fn state_machine() {
+    fn peek_permitted() {
         clock::freeze(move |time| {
             let observe = Observer::new();
-            let backoff = backoff::exponential(5.seconds(), 300.seconds());
-            let policy = consecutive_failures(3, backoff);
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
             let state_machine = StateMachine::new(policy, observe.clone());
 
+            assert!(state_machine.peek_permitted());
+            state_machine.on_error();
+            assert!(!state_machine.peek_permitted());
+            assert_eq!(0, state_machine.snapshot().rejections);
+
+            // Peeking doesn't move the state machine to the half open state.
+            time.advance(10.seconds());
+            assert!(state_machine.peek_permitted());
+            assert_eq!(instrument::State::Open, state_machine.state());
+            assert!(!observe.is_half_open());
+
             assert!(state_machine.is_call_permitted());
+            assert_eq!(instrument::State::HalfOpen, state_machine.state());
+            assert!(observe.is_half_open());
+        });
+    }
 
-            // Perform success requests. the circuit breaker must be closed.
-            for _i in 0..10 {
-                assert!(state_machine.is_call_permitted());
-                state_machine.on_success();
-                assert!(observe.is_closed());
-            }
+    #[test]
+    fn retry_after() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, ());
 
-            // Perform failed requests, the circuit breaker still closed.
-            for _i in 0..2 {
-                assert!(state_machine.is_call_permitted());
-                state_machine.on_error();
-                assert!(observe.is_closed());
+            fn retry_after<POLICY: FailurePolicy>(
+                state_machine: &StateMachine<POLICY, ()>,
+            ) -> Option<Duration> {
+                match state_machine.call(|| Ok::<_, ()>(())) {
+                    Err(Error::Rejected(rejection)) => rejection.retry_after(),
+                    x => unreachable!("{:?}", x),
+                }
             }
 
-            // Perform a failed request and transit to the open state for 5s.
-            assert!(state_machine.is_call_permitted());
             state_machine.on_error();
-            assert!(observe.is_open());
+            assert_eq!(Some(5.seconds()), retry_after(&state_machine));
+            time.advance(2.seconds());
+            assert_eq!(Some(3.seconds()), retry_after(&state_machine));
 
-            // Reject call attempts, the circuit breaker in open state.
-            for i in 0..10 {
-                assert!(!state_machine.is_call_permitted());
-                assert_eq!(i + 1, observe.rejected_calls());
-            }
+            // The circuit breaker may stay forced open for an unknown time.
+            state_machine.force_open();
+            assert_eq!(None, retry_after(&state_machine));
+        });
+    }
 
-            // Wait 2s, the circuit breaker still open.
-            time.advance(2.seconds());
-            assert!(!state_machine.is_call_permitted());
-            assert!(observe.is_open());
+    #[test]
+    fn replayed_outcomes() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = max_failures_in_window(2, 10.seconds(), backoff);
+            let state_machine = StateMachine::new(policy, ());
+            let start = clock::now();
 
-            clock::now();
+            time.advance(20.seconds());
+            // Out of the window.
+            state_machine.on_error_at(start);
+            state_machine.on_error();
+            assert_eq!(instrument::State::Closed, state_machine.state());
 
-            // Wait 4s (6s total), the circuit breaker now in the half open state.
-            time.advance(4.seconds());
-            assert!(state_machine.is_call_permitted());
-            assert!(observe.is_half_open());
+            state_machine.on_error_at(clock::now() - 1.seconds());
+            assert_eq!(instrument::State::Open, state_machine.state());
+            // The transition happens now.
+            assert_eq!(
+                Some(clock::now() + 5.seconds()),
+                state_machine.snapshot().open_until
+            );
+
+            // The moving average needs monotonic timestamps, older ones count as the latest.
+            let policy = failure_policy::success_rate_over_time_window(
+                0.5,
+                1,
+                10.seconds(),
+                backoff::constant(5.seconds()),
+            );
+            let state_machine = StateMachine::new(policy, ());
+            state_machine.on_success();
+            state_machine.on_success_at(start);
+            state_machine.on_error_at(start);
+            assert_eq!(instrument::State::Closed, state_machine.state());
+        });
+    }
+
+    #[test]
+    fn rejections_during_outage() {
+        #[derive(Debug, Default)]
+        struct Outage(Mutex<Vec<u64>>);
+
+        impl Instrument for Outage {
+            fn on_rejected(&self, rejections: u64) {
+                self.0.with(|it| it.push(rejections));
+            }
+        }
+
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, Outage::default());
 
-            // Perform a failed request and transit back to the open state for 10s.
             state_machine.on_error();
             assert!(!state_machine.is_call_permitted());
-            assert!(observe.is_open());
-
-            // Wait 5s, the circuit breaker still open.
-            time.advance(5.seconds());
             assert!(!state_machine.is_call_permitted());
-            assert!(observe.is_open());
 
-            // Wait 6s (11s total), the circuit breaker now in the half open state.
-            time.advance(6.seconds());
+            // A failed probe continues the outage.
+            time.advance(10.seconds());
             assert!(state_machine.is_call_permitted());
-            assert!(observe.is_half_open());
+            state_machine.on_error();
+            assert!(!state_machine.is_call_permitted());
 
-            // Perform a success request and transit to the closed state.
-            state_machine.on_success();
+            // The next outage starts over.
+            time.advance(10.seconds());
             assert!(state_machine.is_call_permitted());
-            assert!(observe.is_closed());
+            state_machine.on_success();
+            state_machine.on_error();
+            assert!(!state_machine.is_call_permitted());
 
-            // Perform success requests.
-            for _i in 0..10 {
-                assert!(state_machine.is_call_permitted());
-                state_machine.on_success();
-            }
+            assert_eq!(
+                vec![1, 2, 3, 1],
+                state_machine.instrument().0.with(|it| it.clone())
+            );
+        });
+    }
 
-            // Perform failed request and transit back to open state
-            for _i in 0..3 {
-                state_machine.on_error();
-            }
-            assert!(observe.is_open());
+    #[test]
+    fn lock_free_reads() {
+        clock::freeze(move |time| {
+            let backoff = backoff::constant(5.seconds());
+            let policy = consecutive_failures(1, backoff);
+            let state_machine = StateMachine::new(policy, ());
+            state_machine.on_error();
 
-            // Reset state machine
+            // Observers don't take the lock, so they answer while it's held.
+            state_machine.inner.shared.with(|_| {
+                assert_eq!(instrument::State::Open, state_machine.state());
+                assert!(!state_machine.peek_permitted());
+                let snapshot = state_machine.snapshot();
+                assert_eq!(Some(clock::now() + 5.seconds()), snapshot.open_until);
+
+                time.advance(10.seconds());
+                assert!(state_machine.peek_permitted());
+            });
+
+            state_machine.force_open();
+            assert!(!state_machine.peek_permitted());
             state_machine.reset();
-            assert!(observe.is_closed());
+            assert_eq!(instrument::State::Closed, state_machine.state());
+            assert_eq!(clock::now(), state_machine.snapshot().since);
         });
     }
 
+    #[test]
+    fn forced_states() {
+        let observe = Observer::new();
+        let backoff = backoff::constant(5.seconds());
+        let policy = consecutive_failures(1, backoff);
+        let state_machine = StateMachine::new(policy, observe.clone());
+
+        state_machine.force_open();
+        assert!(observe.is_open());
+        assert!(!state_machine.is_call_permitted());
+        assert_eq!(None, state_machine.snapshot().open_until);
+
+        // The failure policy doesn't open the forced closed circuit breaker.
+        state_machine.force_closed();
+        assert!(observe.is_closed());
+        state_machine.on_error();
+        assert!(state_machine.is_call_permitted());
+
+        state_machine.reset();
+        state_machine.on_error();
+        assert!(observe.is_open());
+    }
+
+    #[test]
+    fn downgrade() {
+        let backoff = backoff::constant(5.seconds());
+        let state_machine = StateMachine::new(consecutive_failures(1, backoff), ());
+        let weak = state_machine.downgrade();
+
+        weak.upgrade().unwrap().on_error();
+        assert!(!state_machine.is_call_permitted());
+
+        drop(state_machine);
+        assert!(weak.upgrade().is_none());
+        assert_eq!("WeakStateMachine(dropped)", format!("{:?}", weak));
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct TransitionObserver(Arc<Mutex<Vec<Transition>>>);
+
+    impl Instrument for TransitionObserver {
+        fn on_transition(&self, transition: &Transition) {
+            self.0.lock().unwrap().push(*transition);
+        }
+    }
+
     #[derive(Debug)]
     enum State {
         Open,
@@ -397,4 +3017,23 @@ mod tests {
             Duration::from_secs(self)
         }
     }
+
+    thread_local!(static ALLOCATIONS: Cell<usize> = const { Cell::new(0) });
+
+    /// Counts allocations per thread, so tests running in parallel don't interfere.
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|it| it.set(it.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
 }
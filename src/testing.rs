@@ -0,0 +1,434 @@
+//! Utilities for testing code which uses circuit breakers, requires the `testing` feature.
+//!
+//! * `MockCircuitBreaker` permits or rejects calls by a script, so handling of rejections can
+//!   be tested without real time and failure policies.
+//! * `Spy` is an instrument which records events of a real circuit breaker, and asserts the
+//!   sequence of its states.
+//!
+//! # Example
+//!
+//! ```
+//! use failsafe::{testing::MockCircuitBreaker, CircuitBreaker, Error};
+//!
+//! fn fetch<C: CircuitBreaker>(circuit_breaker: &C) -> &'static str {
+//!     match circuit_breaker.call(|| Ok::<_, ()>("fresh")) {
+//!         Ok(value) => value,
+//!         Err(Error::Rejected(_)) => "cached",
+//!         Err(Error::Inner(_)) => "error",
+//!     }
+//! }
+//!
+//! let circuit_breaker = MockCircuitBreaker::new().script([true, false]);
+//! assert_eq!("fresh", fetch(&circuit_breaker));
+//! assert_eq!("cached", fetch(&circuit_breaker));
+//! assert_eq!(1, circuit_breaker.rejections());
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::circuit_breaker::CircuitBreaker;
+use super::error::{Error, Rejection, RejectionReason};
+use super::failure_predicate::{FailureClassifier, FailurePredicate};
+use super::instrument::{Cause, Event, Instrument, State, Transition};
+use super::lock::{Lock, Mutex};
+use super::snapshot::Snapshot;
+
+/// A circuit breaker which permits or rejects calls by a script, and counts their results.
+///
+/// Permissions of the script are taken one per call, once it's exhausted the circuit breaker
+/// falls back to permitting, or rejecting if created by `rejecting`. Clones share the script
+/// and the counters.
+#[derive(Clone)]
+pub struct MockCircuitBreaker {
+    inner: Arc<Mutex<Script>>,
+}
+
+#[derive(Debug)]
+struct Script {
+    permits: VecDeque<bool>,
+    otherwise: bool,
+    successes: u64,
+    failures: u64,
+    rejections: u64,
+}
+
+impl MockCircuitBreaker {
+    /// Creates a circuit breaker which permits all calls.
+    pub fn new() -> Self {
+        Self::with_default(true)
+    }
+
+    /// Creates a circuit breaker which rejects all calls.
+    pub fn rejecting() -> Self {
+        Self::with_default(false)
+    }
+
+    fn with_default(otherwise: bool) -> Self {
+        MockCircuitBreaker {
+            inner: Arc::new(Mutex::new(Script {
+                permits: VecDeque::new(),
+                otherwise,
+                successes: 0,
+                failures: 0,
+                rejections: 0,
+            })),
+        }
+    }
+
+    /// Appends permissions to the script, `true` permits a call and `false` rejects it.
+    pub fn script<I>(self, permits: I) -> Self
+    where
+        I: IntoIterator<Item = bool>,
+    {
+        self.inner.with(|script| script.permits.extend(permits));
+        self
+    }
+
+    /// Returns the number of calls recorded as success.
+    pub fn successes(&self) -> u64 {
+        self.inner.with(|script| script.successes)
+    }
+
+    /// Returns the number of calls recorded as failure.
+    pub fn failures(&self) -> u64 {
+        self.inner.with(|script| script.failures)
+    }
+
+    /// Returns the number of rejected calls.
+    pub fn rejections(&self) -> u64 {
+        self.inner.with(|script| script.rejections)
+    }
+
+    fn record(&self, is_failure: bool) {
+        self.inner.with(|script| {
+            if is_failure {
+                script.failures += 1;
+            } else {
+                script.successes += 1;
+            }
+        })
+    }
+
+    fn run<F, E, R, J>(&self, judge: J, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        J: FnOnce(&E) -> bool,
+    {
+        if !self.is_call_permitted() {
+            return Err(Error::Rejected(Rejection::default()));
+        }
+
+        match f() {
+            Ok(ok) => {
+                self.record(false);
+                Ok(ok)
+            }
+            Err(err) => {
+                self.record(judge(&err));
+                Err(Error::Inner(err))
+            }
+        }
+    }
+}
+
+impl Default for MockCircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for MockCircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner
+            .with(|script| f.debug_tuple("MockCircuitBreaker").field(script).finish())
+    }
+}
+
+impl CircuitBreaker for MockCircuitBreaker {
+    fn is_call_permitted(&self) -> bool {
+        self.inner.with(|script| {
+            let permitted = script.permits.pop_front().unwrap_or(script.otherwise);
+            if !permitted {
+                script.rejections += 1;
+            }
+            permitted
+        })
+    }
+
+    fn call_with<P, F, E, R>(&self, predicate: P, f: F) -> Result<R, Error<E>>
+    where
+        P: FailurePredicate<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.run(|err| predicate.is_err(err), f)
+    }
+
+    fn call_classified<C, F, E, R>(&self, classifier: C, f: F) -> Result<R, Error<E>>
+    where
+        C: FailureClassifier<E>,
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.run(|err| classifier.classify(err).is_some(), f)
+    }
+
+    /// Takes the next permission of the script at once, it never waits.
+    fn call_or_wait<F, E, R>(&self, _deadline: Instant, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.run(|_| true, f)
+    }
+
+    fn call_reported<F, E, R>(&self, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+        E: Display,
+    {
+        self.run(|_| true, f)
+    }
+
+    /// Counts the call once, whatever its cost.
+    fn call_weighted<F, E, R>(&self, _cost: u32, f: F) -> Result<R, Error<E>>
+    where
+        F: FnOnce() -> Result<R, E>,
+    {
+        self.run(|_| true, f)
+    }
+}
+
+/// An instrument which records events of the circuit breaker for assertions.
+///
+/// Clones share the recorded events, so keep one to inspect the circuit breaker built with
+/// another.
+///
+/// ```
+/// use std::time::Duration;
+/// use failsafe::{backoff, failure_policy, instrument::State, testing::Spy, CircuitBreaker, Config};
+///
+/// let spy = Spy::new();
+/// let backoff = backoff::constant(Duration::from_secs(30));
+/// let circuit_breaker = Config::new()
+///     .failure_policy(failure_policy::consecutive_failures(1, backoff))
+///     .instrument(spy.clone())
+///     .build();
+///
+/// let _ = circuit_breaker.call(|| Err::<(), _>(()));
+/// spy.assert_states(&[State::Closed, State::Open]);
+/// ```
+#[derive(Clone, Default)]
+pub struct Spy {
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Spy {
+    /// Creates a new spy without recorded events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all recorded events in order.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.with(|events| events.clone())
+    }
+
+    /// Returns the sequence of states the circuit breaker went through, starting with the
+    /// closed state it was created in.
+    pub fn states(&self) -> Vec<State> {
+        self.events.with(|events| {
+            events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Open { .. } => Some(State::Open),
+                    Event::HalfOpen => Some(State::HalfOpen),
+                    Event::Closed => Some(State::Closed),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the number of rejected calls.
+    pub fn rejections(&self) -> usize {
+        self.count(|event| matches!(event, Event::Rejected { .. }))
+    }
+
+    /// Returns the number of successful calls.
+    pub fn successes(&self) -> usize {
+        self.count(|event| matches!(event, Event::Success { .. }))
+    }
+
+    /// Returns the number of failed calls.
+    pub fn failures(&self) -> usize {
+        self.count(|event| matches!(event, Event::Failure { .. }))
+    }
+
+    /// Forgets all recorded events.
+    pub fn clear(&self) {
+        self.events.with(|events| events.clear())
+    }
+
+    /// Asserts the circuit breaker went through exactly the `expected` states, see `states`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with both sequences if they differ.
+    #[track_caller]
+    pub fn assert_states(&self, expected: &[State]) {
+        let actual = self.states();
+        assert!(
+            actual == expected,
+            "unexpected transitions of the circuit breaker\n  expected: {:?}\n    actual: {:?}",
+            expected,
+            actual
+        );
+    }
+
+    fn count<F>(&self, f: F) -> usize
+    where
+        F: Fn(&Event) -> bool,
+    {
+        self.events
+            .with(|events| events.iter().filter(|event| f(event)).count())
+    }
+
+    fn push(&self, event: Event) {
+        self.events.with(|events| events.push(event))
+    }
+}
+
+impl Debug for Spy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Spy").field(&self.events()).finish()
+    }
+}
+
+impl Instrument for Spy {
+    fn on_call_rejected(&self) {
+        self.push(Event::Rejected {
+            rejections: None,
+            reason: RejectionReason::Open,
+        })
+    }
+
+    fn on_rejected(&self, rejections: u64) {
+        self.push(Event::Rejected {
+            rejections: Some(rejections),
+            reason: RejectionReason::Open,
+        })
+    }
+
+    fn on_probes_exhausted(&self, rejections: u64) {
+        self.push(Event::Rejected {
+            rejections: Some(rejections),
+            reason: RejectionReason::ProbesExhausted,
+        })
+    }
+
+    /// The state machine reports transitions via `on_transition`, so a direct call is
+    /// considered forced.
+    fn on_open(&self) {
+        self.push(Event::Open {
+            from: State::Closed,
+            cause: Cause::Forced,
+        })
+    }
+
+    fn on_half_open(&self) {
+        self.push(Event::HalfOpen)
+    }
+
+    fn on_closed(&self) {
+        self.push(Event::Closed)
+    }
+
+    fn on_success(&self, latency: Option<Duration>) {
+        self.push(Event::Success { latency })
+    }
+
+    fn on_failure(&self, latency: Option<Duration>) {
+        self.push(Event::Failure { latency })
+    }
+
+    fn on_replay_dropped(&self) {
+        self.push(Event::ReplayDropped)
+    }
+
+    fn on_shutdown(&self, _snapshot: &Snapshot) {
+        self.push(Event::Shutdown)
+    }
+
+    fn on_transition(&self, transition: &Transition) {
+        match transition.to {
+            State::Open => self.push(Event::Open {
+                from: transition.from,
+                cause: transition.cause,
+            }),
+            State::HalfOpen => self.on_half_open(),
+            State::Closed => self.on_closed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{backoff, clock, failure_policy, Config};
+    use super::*;
+
+    #[test]
+    fn scripted_permissions() {
+        let circuit_breaker = MockCircuitBreaker::rejecting().script(vec![true, false, true]);
+        let handle = circuit_breaker.clone();
+
+        assert!(circuit_breaker.call(|| Ok::<_, ()>(())).is_ok());
+        match circuit_breaker.call(|| Ok::<_, ()>(())) {
+            Err(Error::Rejected(_)) => {}
+            x => unreachable!("{:?}", x),
+        }
+        match circuit_breaker.call_with(|_: &()| false, || Err::<(), _>(())) {
+            Err(Error::Inner(())) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert!(!circuit_breaker.is_call_permitted());
+
+        assert_eq!(
+            (2, 0, 2),
+            (handle.successes(), handle.failures(), handle.rejections())
+        );
+    }
+
+    #[test]
+    fn spy_transitions() {
+        clock::freeze(|time| {
+            let spy = Spy::new();
+            let backoff = backoff::constant(Duration::from_secs(5));
+            let policy = failure_policy::consecutive_failures(1, backoff);
+            let circuit_breaker = Config::new()
+                .failure_policy(policy)
+                .instrument(spy.clone())
+                .build();
+
+            let _ = circuit_breaker.call(|| Err::<(), _>(()));
+            let _ = circuit_breaker.call(|| Ok::<_, ()>(()));
+            time.advance(Duration::from_secs(10));
+            let _ = circuit_breaker.call(|| Ok::<_, ()>(()));
+
+            spy.assert_states(&[State::Closed, State::Open, State::HalfOpen, State::Closed]);
+            assert_eq!(
+                (1, 1, 1),
+                (spy.successes(), spy.failures(), spy.rejections())
+            );
+
+            spy.clear();
+            assert!(spy.events().is_empty());
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected transitions")]
+    fn spy_assert_states() {
+        Spy::new().assert_states(&[State::Closed]);
+    }
+}
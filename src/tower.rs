@@ -0,0 +1,269 @@
+//! Guarding of [tower](https://docs.rs/tower) services, requires the `tower` feature.
+//!
+//! `Guarded` wraps a service into a circuit breaker. By default `poll_ready` returns a rejection
+//! while the circuit breaker is open. In the waiting mode, see `Guarded::wait_while_open`, it
+//! returns `Pending` instead, and the task is woken once calls are permitted again, so
+//! load balancers which pick ready services route around open ones rather than failing.
+//!
+//! # Example
+//!
+//! ```
+//! # use std::{future::{ready, Ready}, task::{Context, Poll}};
+//! # struct Backend;
+//! # impl tower_service::Service<&'static str> for Backend {
+//! #     type Response = &'static str;
+//! #     type Error = ();
+//! #     type Future = Ready<Result<&'static str, ()>>;
+//! #     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> { Poll::Ready(Ok(())) }
+//! #     fn call(&mut self, req: &'static str) -> Self::Future { ready(Ok(req)) }
+//! # }
+//! # async {
+//! use failsafe::{tower::Guarded, Config};
+//!
+//! // `Backend` is a `tower_service::Service`, e.g. a client of an endpoint.
+//! let service = Guarded::new(Backend, Config::new().build()).wait_while_open(tokio::time::sleep);
+//! # };
+//! ```
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::ready;
+use tower_service::Service;
+
+use super::error::Error;
+use super::failure_policy::FailurePolicy;
+use super::futures::stream::{WaitWhileOpen, WhileOpen};
+use super::instrument::Instrument;
+use super::state_machine::{StateMachine, WAIT_INTERVAL, WAIT_PRECISION};
+
+/// A service which calls go through a circuit breaker.
+///
+/// The permission to call is requested by `poll_ready`, so a half open circuit breaker admits
+/// only the services which probes are in flight.
+pub struct Guarded<S, POLICY, INSTRUMENT, W = ()> {
+    service: S,
+    state_machine: StateMachine<POLICY, INSTRUMENT>,
+    wait: Pin<Box<W>>,
+    permitted: bool,
+}
+
+impl<S, POLICY, INSTRUMENT> Guarded<S, POLICY, INSTRUMENT> {
+    /// Guards the `service` by the `state_machine`.
+    pub fn new(service: S, state_machine: StateMachine<POLICY, INSTRUMENT>) -> Self {
+        Guarded {
+            service,
+            state_machine,
+            wait: Box::pin(()),
+            permitted: false,
+        }
+    }
+
+    /// Makes `poll_ready` return `Pending` while the circuit breaker is open instead of a
+    /// rejection.
+    ///
+    /// The task is woken by a transition to the half open or closed state, or once the delay
+    /// of the open state expires, slept by `sleep`, see the timers section of the `futures`
+    /// module.
+    pub fn wait_while_open<Sleep, Slept>(
+        self,
+        sleep: Sleep,
+    ) -> Guarded<S, POLICY, INSTRUMENT, WaitWhileOpen<Sleep, Slept>>
+    where
+        Sleep: FnMut(Duration) -> Slept,
+        Slept: Future<Output = ()>,
+    {
+        Guarded {
+            service: self.service,
+            state_machine: self.state_machine,
+            wait: Box::pin(WaitWhileOpen::new(sleep)),
+            permitted: self.permitted,
+        }
+    }
+}
+
+impl<S, POLICY, INSTRUMENT, W> Guarded<S, POLICY, INSTRUMENT, W> {
+    /// Returns the underlying service, calls made on it bypass the circuit breaker.
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Returns the circuit breaker.
+    pub fn state_machine(&self) -> &StateMachine<POLICY, INSTRUMENT> {
+        &self.state_machine
+    }
+}
+
+impl<S, REQUEST, POLICY, INSTRUMENT, W> Service<REQUEST> for Guarded<S, POLICY, INSTRUMENT, W>
+where
+    S: Service<REQUEST>,
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+    W: WhileOpen,
+{
+    type Response = S::Response;
+    type Error = Error<S::Error>;
+    type Future = ResponseFuture<S::Future, POLICY, INSTRUMENT>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        while !self.permitted {
+            let rejection = match self.state_machine.permit() {
+                Ok(()) => {
+                    self.permitted = true;
+                    self.wait.as_mut().stop();
+                    break;
+                }
+                Err(rejection) => rejection,
+            };
+            let delay = match rejection.retry_after() {
+                Some(retry_after) => retry_after + WAIT_PRECISION,
+                None => WAIT_INTERVAL,
+            };
+            match self.wait.as_mut().poll_wait(cx, delay) {
+                Some(Poll::Ready(())) => {}
+                Some(Poll::Pending) => {
+                    self.state_machine.register_waker(cx.waker());
+                    return Poll::Pending;
+                }
+                None => return Poll::Ready(Err(Error::Rejected(rejection))),
+            }
+        }
+
+        self.service.poll_ready(cx).map_err(Error::Inner)
+    }
+
+    fn call(&mut self, request: REQUEST) -> Self::Future {
+        // The permission requested by `poll_ready` is spent by this call.
+        self.permitted = false;
+        ResponseFuture {
+            future: self.service.call(request),
+            state_machine: self.state_machine.clone(),
+        }
+    }
+}
+
+impl<S, POLICY, INSTRUMENT, W> Debug for Guarded<S, POLICY, INSTRUMENT, W>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Guarded")
+            .field("service", &self.service)
+            .field("state_machine", &self.state_machine)
+            .finish()
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// The future of a call of `Guarded`, it records the result into the circuit breaker.
+    #[allow(missing_debug_implementations)]
+    pub struct ResponseFuture<FUTURE, POLICY, INSTRUMENT> {
+        #[pin]
+        future: FUTURE,
+        state_machine: StateMachine<POLICY, INSTRUMENT>,
+    }
+}
+
+impl<FUTURE, T, E, POLICY, INSTRUMENT> Future for ResponseFuture<FUTURE, POLICY, INSTRUMENT>
+where
+    FUTURE: Future<Output = Result<T, E>>,
+    POLICY: FailurePolicy,
+    INSTRUMENT: Instrument,
+{
+    type Output = Result<T, Error<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        match ready!(this.future.poll(cx)) {
+            Ok(ok) => {
+                this.state_machine.on_success();
+                Poll::Ready(Ok(ok))
+            }
+            Err(err) => {
+                this.state_machine.on_error();
+                Poll::Ready(Err(Error::Inner(err)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{pending, ready, Ready};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+    use futures::task::{waker, ArcWake};
+
+    use super::super::{backoff, failure_policy, Config};
+    use super::*;
+
+    struct Backend;
+
+    impl Service<bool> for Backend {
+        type Response = ();
+        type Error = ();
+        type Future = Ready<Result<(), ()>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, ok: bool) -> Self::Future {
+            ready(if ok { Ok(()) } else { Err(()) })
+        }
+    }
+
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+
+    impl ArcWake for Wakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn new_state_machine() -> StateMachine<impl FailurePolicy, ()> {
+        let backoff = backoff::constant(Duration::from_secs(60));
+        let policy = failure_policy::consecutive_failures(1, backoff);
+        Config::new().failure_policy(policy).build()
+    }
+
+    #[test]
+    fn reject_while_open() {
+        let mut service = Guarded::new(Backend, new_state_machine());
+
+        block_on(poll_fn(|cx| service.poll_ready(cx))).unwrap();
+        assert!(matches!(
+            block_on(service.call(false)),
+            Err(Error::Inner(()))
+        ));
+        assert!(matches!(
+            block_on(poll_fn(|cx| service.poll_ready(cx))),
+            Err(Error::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn pending_while_open() {
+        let mut service = Guarded::new(Backend, new_state_machine()).wait_while_open(|_| pending());
+        block_on(poll_fn(|cx| service.poll_ready(cx))).unwrap();
+        assert!(block_on(service.call(false)).is_err());
+
+        let wakes = Arc::new(Wakes::default());
+        let waker = waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(service.poll_ready(&mut cx).is_pending());
+
+        // A transition wakes the task, and the service becomes ready.
+        service.state_machine().reset();
+        assert_eq!(1, wakes.0.load(Ordering::SeqCst));
+        assert!(matches!(service.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+        assert!(block_on(service.call(true)).is_ok());
+    }
+}
@@ -1,9 +1,21 @@
+use std::mem;
 use std::time::{Duration, Instant};
 
 use super::clock;
 
+/// A saved state of a `WindowedAdder`, see `WindowedAdder::save`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct WindowState {
+    /// Values of slices, from the oldest to the current one.
+    pub slices: Vec<i64>,
+    /// The time since the current slice has started.
+    pub age: Duration,
+}
+
 /// Time windowed counter.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WindowedAdder {
     window: u64,
     slices: Vec<i64>,
@@ -36,6 +48,45 @@ impl WindowedAdder {
         }
     }
 
+    /// Returns the number of bytes the counter allocates on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.slices.capacity() * mem::size_of::<i64>()
+    }
+
+    /// Returns the range of time kept in the counter.
+    ///
+    /// It's a multiple of the slice duration, so it may be a bit shorter than the requested one.
+    pub fn window(&self) -> Duration {
+        self.slice_duration() * self.slices.len() as u32
+    }
+
+    /// Returns the range of time of a single slice, that's the resolution of the counter.
+    pub fn slice_duration(&self) -> Duration {
+        Duration::from_millis(self.window)
+    }
+
+    /// Returns the number of slices.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.slices.len()
+    }
+
+    /// Changes the `window` and the number of `slices`, the counter is reset.
+    ///
+    /// # Panics
+    ///
+    /// * When `slices` isn't in range [1;10].
+    pub fn resize(&mut self, window: Duration, slices: u8) {
+        assert!(slices <= 10);
+        assert!(slices > 1);
+
+        self.window = window.millis() / u64::from(slices);
+        self.slices.clear();
+        self.slices.resize(slices as usize, 0);
+        self.index = 0;
+        self.elapsed = clock::now();
+    }
+
     /// Purge outdated slices.
     pub fn expire(&mut self) {
         let now = clock::now();
@@ -64,6 +115,31 @@ impl WindowedAdder {
         //println!("inc {} vec={:?}", idx, self.slices);
     }
 
+    /// Returns the values of slices with their age, which `restore` puts back.
+    pub fn save(&self) -> WindowState {
+        let len = self.slices.len();
+        WindowState {
+            slices: (1..=len)
+                .map(|i| self.slices[(self.index + i) % len])
+                .collect(),
+            age: clock::now().saturating_duration_since(self.elapsed),
+        }
+    }
+
+    /// Restores values saved by `save`, e.g. by another process, slices keep their age.
+    ///
+    /// When the `state` has more slices than the counter, the oldest ones are dropped.
+    pub fn restore(&mut self, state: &WindowState) {
+        let len = self.slices.len();
+        self.reset();
+        for (slice, value) in self.slices.iter_mut().rev().zip(state.slices.iter().rev()) {
+            *slice = *value;
+        }
+        self.index = len - 1;
+        let now = clock::now();
+        self.elapsed = now.checked_sub(state.age).unwrap_or(now);
+    }
+
     /// Resets state of the counter.
     pub fn reset(&mut self) {
         self.slices.iter_mut().for_each(|it| *it = 0);
@@ -71,9 +147,22 @@ impl WindowedAdder {
     }
 
     /// Increments counter by `value`.
+    ///
+    /// When the current time is behind the current slice, e.g. for a replayed observation, the
+    /// value goes into the slice of that time, or it's dropped if that's out of the window.
     pub fn add(&mut self, value: i64) {
         self.expire();
-        self.slices[self.index] += value;
+
+        let age = self
+            .elapsed
+            .saturating_duration_since(clock::now())
+            .millis();
+        let back = ((age + self.window - 1) / self.window) as usize;
+        let len = self.slices.len();
+        if back >= len {
+            return;
+        }
+        self.slices[(self.index + len - back) % len] += value;
         //println!("add {} {:?}", value, self.slices);
     }
 
@@ -252,10 +341,72 @@ mod tests {
         });
     }
 
+    #[test]
+    fn add_to_past_slices() {
+        clock::freeze(|time| {
+            let mut adder = new_windowed_adder();
+            let start = clock::now();
+
+            time.advance(2.seconds());
+            adder.add(1);
+            clock::at(start, || adder.add(2));
+            assert_eq!(3, adder.sum());
+
+            // The observation from the first slice expires first.
+            time.advance(2.seconds());
+            assert_eq!(1, adder.sum());
+
+            // Observations older than the window are dropped.
+            clock::at(start, || adder.add(5));
+            assert_eq!(1, adder.sum());
+        });
+    }
+
+    #[test]
+    fn resize() {
+        clock::freeze(|_| {
+            let mut adder = WindowedAdder::new(10.seconds(), 3);
+            assert_eq!(3, adder.len());
+            assert_eq!(Duration::from_millis(3333), adder.slice_duration());
+            assert_eq!(Duration::from_millis(9999), adder.window());
+
+            adder.add(1);
+            adder.resize(20.seconds(), 5);
+            assert_eq!(0, adder.sum());
+            assert_eq!(5, adder.len());
+            assert_eq!(4.seconds(), adder.slice_duration());
+            assert_eq!(20.seconds(), adder.window());
+        });
+    }
+
     fn new_windowed_adder() -> WindowedAdder {
         WindowedAdder::new(3.seconds(), 3)
     }
 
+    #[test]
+    fn save_and_restore() {
+        clock::freeze(|time| {
+            let mut adder = new_windowed_adder();
+            adder.add(1);
+            time.advance(1.seconds());
+            adder.add(2);
+            time.advance(Duration::from_millis(500));
+
+            let state = adder.save();
+            assert_eq!(vec![0, 1, 2], state.slices);
+            assert_eq!(Duration::from_millis(500), state.age);
+
+            let mut restored = new_windowed_adder();
+            restored.restore(&state);
+            assert_eq!(3, restored.sum());
+
+            // The oldest value leaves the window at the same time as in the original.
+            time.advance(2.seconds());
+            assert_eq!(adder.sum(), restored.sum());
+            assert_eq!(2, restored.sum());
+        });
+    }
+
     trait IntoDuration {
         fn seconds(self) -> Duration;
     }